@@ -0,0 +1,55 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
+use serde::Deserialize;
+use smiles_parser::flat::{parse_flat, parse_flat_into, FlatChain};
+use smiles_parser::parse;
+
+#[derive(Deserialize)]
+struct WikidataItem {
+    smiles: String,
+}
+
+/// SMILES from the wikidata test set that both parsers accept.
+fn molecules() -> Vec<String> {
+    let contents = std::fs::read_to_string("./tests/wikidata_molecules.json").unwrap();
+    let items: Vec<WikidataItem> = serde_json::from_str(&contents).unwrap();
+    items
+        .into_iter()
+        .map(|item| item.smiles)
+        .filter(|smiles| parse(smiles.as_bytes()).is_ok() && parse_flat(smiles.as_bytes()).is_ok())
+        .collect()
+}
+
+fn bench_parse(c: &mut Criterion) {
+    let molecules = molecules();
+    let bytes: usize = molecules.iter().map(|smiles| smiles.len()).sum();
+
+    let mut group = c.benchmark_group("wikidata");
+    group.throughput(Throughput::Bytes(bytes as u64));
+    group.bench_function("chain", |b| {
+        b.iter(|| {
+            for smiles in &molecules {
+                black_box(parse(smiles.as_bytes()).unwrap());
+            }
+        })
+    });
+    group.bench_function("flat", |b| {
+        b.iter(|| {
+            for smiles in &molecules {
+                black_box(parse_flat(smiles.as_bytes()).unwrap());
+            }
+        })
+    });
+    group.bench_function("flat_reused", |b| {
+        let mut flat = FlatChain::default();
+        b.iter(|| {
+            for smiles in &molecules {
+                parse_flat_into(smiles.as_bytes(), &mut flat).unwrap();
+                black_box(&flat);
+            }
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_parse);
+criterion_main!(benches);
@@ -0,0 +1,394 @@
+//! Command-line tool to validate, convert and inspect SMILES strings.
+//!
+//! Every subcommand takes SMILES strings or `.smi` files as arguments, or reads SMILES from
+//! standard input (one per line, optionally followed by a title) if there are none.
+
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Write};
+use std::process::ExitCode;
+
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use petgraph::visit::EdgeRef;
+
+use smiles_parser::canon::canonical_smiles;
+use smiles_parser::conformer::{embed, write_pdb, write_xyz};
+use smiles_parser::depict::{to_dot, to_svg};
+use smiles_parser::descriptors::molecular_weight;
+use smiles_parser::flat::parse_flat;
+use smiles_parser::graph::MoleculeGraph;
+use smiles_parser::identifier::molecular_formula;
+use smiles_parser::molfile::write_v2000;
+use smiles_parser::reader::SmilesReader;
+use smiles_parser::sdf::SdfWriter;
+use smiles_parser::visit::{walk_chain, Visitor};
+use smiles_parser::{parse, Bond, Branch, BranchedAtom, Chain, Dot, ParseError, RingBond};
+
+#[derive(Parser)]
+#[command(
+    name = "smiles",
+    version,
+    about = "Validate, convert and inspect SMILES strings"
+)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Check that the inputs parse, printing the position of every error
+    Validate(Input),
+    /// Print the canonical SMILES (Kekulé and aromatic input give different output)
+    Canon(Input),
+    /// Print the molecular formula
+    Formula(Input),
+    /// Print the molecular weight
+    Mass(Input),
+    /// Convert to another format
+    Convert {
+        /// Output format
+        #[arg(long, value_enum)]
+        to: Format,
+        #[command(flatten)]
+        input: Input,
+    },
+    /// Print the atoms, bonds and rings
+    Info(Input),
+}
+
+#[derive(Args)]
+struct Input {
+    /// SMILES strings or `.smi` files (standard input if none are given)
+    inputs: Vec<String>,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Format {
+    /// V2000 Molfile
+    Mol,
+    /// SD file
+    Sdf,
+    /// JSON serialization of the parsed chain
+    Json,
+    /// Graphviz DOT graph
+    Dot,
+    /// SVG depiction
+    Svg,
+    /// XYZ file with 3D coordinates
+    Xyz,
+    /// PDB file with 3D coordinates
+    Pdb,
+}
+
+/// A parsed input: where it came from, its title and the parse result.
+struct Record {
+    source: String,
+    title: Option<String>,
+    result: Result<Chain, ParseError>,
+}
+
+/// Finds what [`parse`] accepts but [`parse_flat`] doesn't: ring bonds that are never closed, and
+/// bonds and dots that are not followed by an atom.
+#[derive(Default)]
+struct Completeness {
+    open_rings: HashSet<u8>,
+    dangling: bool,
+    incomplete: bool,
+}
+
+impl Completeness {
+    fn of(chain: &Chain) -> bool {
+        let mut completeness = Completeness::default();
+        walk_chain(&mut completeness, chain);
+        !completeness.incomplete && !completeness.dangling && completeness.open_rings.is_empty()
+    }
+}
+
+impl Visitor for Completeness {
+    fn visit_atom(&mut self, _atom: &BranchedAtom, _depth: usize) {
+        self.dangling = false;
+    }
+
+    fn visit_bond(&mut self, _bond: &Bond, _depth: usize) {
+        self.dangling = true;
+    }
+
+    fn visit_dot(&mut self, _dot: &Dot, _depth: usize) {
+        self.dangling = true;
+    }
+
+    fn visit_ring_bond(&mut self, ring_bond: &RingBond, _depth: usize) {
+        if !self.open_rings.remove(&ring_bond.ring_number) {
+            self.open_rings.insert(ring_bond.ring_number);
+        }
+    }
+
+    fn leave_branch(&mut self, _branch: &Branch, _depth: usize) {
+        self.incomplete |= self.dangling;
+    }
+}
+
+/// Parse a SMILES string, also rejecting ring bonds and branches that are never closed.
+///
+/// Valid input is only parsed once. [`parse`] just reports where it stopped, so invalid input
+/// is parsed again with [`parse_flat`] to report the kind and position of the first error.
+fn parse_strict(smiles: &[u8]) -> Result<Chain, ParseError> {
+    match parse(smiles) {
+        Ok(chain) if Completeness::of(&chain) => Ok(chain),
+        result => {
+            parse_flat(smiles)?;
+            result
+        }
+    }
+}
+
+/// Read the records of a `.smi` file one at a time.
+fn read_smi<'a, R: BufRead + 'a>(
+    name: &'a str,
+    reader: R,
+) -> impl Iterator<Item = io::Result<Record>> + 'a {
+    SmilesReader::with_parser(reader, parse_strict).map(move |record| {
+        let (line_number, title, result) = record?;
+        Ok(Record {
+            source: format!("{}:{}", name, line_number),
+            title,
+            result,
+        })
+    })
+}
+
+/// Call `process` with every record of the inputs, in order, without reading ahead.
+fn for_each_record(
+    input: &Input,
+    mut process: impl FnMut(Record) -> Result<(), Box<dyn std::error::Error>>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if input.inputs.is_empty() {
+        for record in read_smi("<stdin>", io::stdin().lock()) {
+            process(record?)?;
+        }
+    }
+    for argument in &input.inputs {
+        if argument.ends_with(".smi") {
+            let file = File::open(argument)
+                .map_err(|err| io::Error::new(err.kind(), format!("{}: {}", argument, err)))?;
+            for record in read_smi(argument, BufReader::new(file)) {
+                process(record?)?;
+            }
+        } else {
+            process(Record {
+                source: argument.clone(),
+                title: None,
+                result: parse_strict(argument.as_bytes()),
+            })?;
+        }
+    }
+    Ok(())
+}
+
+/// Append the title of a record to a line of output, separated by a tab.
+fn with_title(line: String, title: &Option<String>) -> String {
+    match title {
+        Some(title) => format!("{}\t{}", line, title),
+        None => line,
+    }
+}
+
+fn bond_name(bond: Bond) -> &'static str {
+    match bond {
+        Bond::Single => "single",
+        Bond::Double => "double",
+        Bond::Triple => "triple",
+        Bond::Quadruple => "quadruple",
+        Bond::Aromatic => "aromatic",
+        Bond::Up => "single (up)",
+        Bond::Down => "single (down)",
+    }
+}
+
+fn write_info(out: &mut impl Write, graph: &MoleculeGraph) -> io::Result<()> {
+    let atoms: Vec<_> = graph
+        .node_indices()
+        .filter(|&node| !graph.is_attached_hydrogen(node))
+        .collect();
+    writeln!(out, "atoms: {}", atoms.len())?;
+    for &node in &atoms {
+        let atom = &graph[node];
+        let mut symbol = atom.element().map_or_else(
+            || "*".to_string(),
+            |element| element.get_symbol().to_string(),
+        );
+        if atom.is_aromatic() {
+            symbol = symbol.to_lowercase();
+        }
+        write!(out, "  {:>4} {:<2}", node.index(), symbol)?;
+        let hydrogens = graph.hydrogen_count(node);
+        if hydrogens > 0 {
+            write!(out, " H{}", hydrogens)?;
+        }
+        if atom.charge() != 0 {
+            write!(out, " charge {:+}", atom.charge())?;
+        }
+        if let Some(isotope) = atom.isotope() {
+            write!(out, " isotope {}", isotope)?;
+        }
+        if let Some(chirality) = atom.chirality() {
+            write!(out, " {:?}", chirality)?;
+        }
+        writeln!(out)?;
+    }
+
+    let bonds: Vec<_> = graph
+        .edge_references()
+        .filter(|edge| {
+            !graph.is_attached_hydrogen(edge.source()) && !graph.is_attached_hydrogen(edge.target())
+        })
+        .collect();
+    writeln!(out, "bonds: {}", bonds.len())?;
+    for edge in bonds {
+        writeln!(
+            out,
+            "  {:>4}-{:<4} {}",
+            edge.source().index(),
+            edge.target().index(),
+            bond_name(*edge.weight())
+        )?;
+    }
+
+    let rings = graph.rings();
+    writeln!(out, "rings: {}", rings.len())?;
+    for ring in rings {
+        let ring: Vec<String> = ring.iter().map(|atom| atom.index().to_string()).collect();
+        writeln!(out, "  {} atoms: {}", ring.len(), ring.join(" "))?;
+    }
+    Ok(())
+}
+
+/// The output of a command for a parsed record. Errors are those of the conversion of the record,
+/// not of writing the output.
+fn record_output(
+    command: &Command,
+    chain: Chain,
+    source: &str,
+    title: Option<String>,
+) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let mut out = Vec::new();
+    let molfile_title = title.as_deref().unwrap_or_default();
+
+    match command {
+        Command::Validate(_) => {}
+        Command::Convert {
+            to: Format::Json, ..
+        } => {
+            writeln!(out, "{}", serde_json::to_string(&chain)?)?;
+        }
+        Command::Convert {
+            to: Format::Sdf, ..
+        } => {
+            let mut graph = MoleculeGraph::from_chain(chain);
+            graph.compute_2d_coords();
+            SdfWriter::new(&mut out).write_graph(&graph, molfile_title, std::iter::empty())?;
+        }
+        Command::Convert {
+            to: Format::Dot, ..
+        } => {
+            out.write_all(to_dot(&MoleculeGraph::from_chain(chain)).as_bytes())?;
+        }
+        Command::Convert {
+            to: Format::Svg, ..
+        } => {
+            out.write_all(to_svg(&MoleculeGraph::from_chain(chain)).as_bytes())?;
+        }
+        Command::Convert {
+            to: Format::Mol, ..
+        } => {
+            let mut graph = MoleculeGraph::from_chain(chain);
+            graph.compute_2d_coords();
+            out.write_all(write_v2000(&graph, molfile_title)?.as_bytes())?;
+        }
+        Command::Convert {
+            to: Format::Xyz, ..
+        } => {
+            let graph = MoleculeGraph::from_chain(chain);
+            let conformer = embed(&graph, 0)?;
+            out.write_all(write_xyz(&graph, &conformer, molfile_title).as_bytes())?;
+        }
+        Command::Convert {
+            to: Format::Pdb, ..
+        } => {
+            let graph = MoleculeGraph::from_chain(chain);
+            let conformer = embed(&graph, 0)?;
+            out.write_all(write_pdb(&graph, &conformer, molfile_title).as_bytes())?;
+        }
+        Command::Canon(_) => {
+            let smiles = canonical_smiles(&MoleculeGraph::from_chain(chain));
+            writeln!(out, "{}", with_title(smiles, &title))?;
+        }
+        Command::Formula(_) => {
+            let formula = molecular_formula(&MoleculeGraph::from_chain(chain));
+            writeln!(out, "{}", with_title(formula, &title))?;
+        }
+        Command::Mass(_) => {
+            let mass = molecular_weight(&MoleculeGraph::from_chain(chain));
+            writeln!(out, "{}", with_title(format!("{:.4}", mass), &title))?;
+        }
+        Command::Info(_) => {
+            writeln!(out, "{}", with_title(source.to_string(), &title))?;
+            write_info(&mut out, &MoleculeGraph::from_chain(chain))?;
+        }
+    }
+    Ok(out)
+}
+
+fn run(command: Command) -> Result<bool, Box<dyn std::error::Error>> {
+    let input = match &command {
+        Command::Validate(input)
+        | Command::Canon(input)
+        | Command::Formula(input)
+        | Command::Mass(input)
+        | Command::Info(input)
+        | Command::Convert { input, .. } => input,
+    };
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    let mut valid = true;
+    for_each_record(input, |record| {
+        let Record {
+            source,
+            title,
+            result,
+        } = record;
+        let chain = match result {
+            Ok(chain) => chain,
+            Err(err) => {
+                valid = false;
+                match command {
+                    Command::Validate(_) => writeln!(out, "{}: {}", source, err)?,
+                    _ => eprintln!("{}: {}", source, err),
+                }
+                return Ok(());
+            }
+        };
+        match record_output(&command, chain, &source, title) {
+            Ok(output) => out.write_all(&output)?,
+            Err(err) => {
+                valid = false;
+                eprintln!("{}: {}", source, err);
+            }
+        }
+        Ok(())
+    })?;
+    Ok(valid)
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    match run(cli.command) {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::FAILURE,
+        Err(err) => {
+            eprintln!("error: {}", err);
+            ExitCode::from(2)
+        }
+    }
+}
@@ -0,0 +1,722 @@
+//! Canonical ranking of the atoms of a [`MoleculeGraph`].
+//!
+//! Atoms are first partitioned by their invariants, the size of their component and the size of
+//! the smallest ring they are part of, and the partition is refined by the classes of their
+//! neighbors until it no longer changes. The resulting classes are the symmetry classes of the
+//! atoms. Remaining ties are then broken one at a time, each time refining again, until every
+//! atom has a distinct rank. Every atom of a tied class is tried in turn, and the ranking that
+//! gives the smallest result is kept, so the ranks don't depend on the order of the atoms.
+
+use std::cmp::Ordering;
+use std::collections::{HashSet, VecDeque};
+
+use petgraph::graph::NodeIndex;
+use petgraph::visit::EdgeRef;
+
+use crate::graph::{bond_order, MoleculeGraph};
+use crate::writer::write_smiles_ranked;
+use crate::{Bond, Chirality};
+
+/// Symmetry classes and canonical ranks of a set of atoms.
+#[derive(Clone)]
+pub(crate) struct Ranking {
+    /// Rank of every atom after refinement, equal for atoms that can't be told apart.
+    pub classes: Vec<usize>,
+    /// Distinct rank of every atom.
+    pub ranks: Vec<usize>,
+}
+
+/// Dense ranks of a list of keys: the rank of a key is the number of distinct smaller keys.
+fn dense_ranks<K: Ord>(keys: &[K]) -> Vec<usize> {
+    let mut order: Vec<usize> = (0..keys.len()).collect();
+    order.sort_by(|&a, &b| keys[a].cmp(&keys[b]));
+
+    let mut ranks = vec![0; keys.len()];
+    let mut rank = 0;
+    for (position, &index) in order.iter().enumerate() {
+        if position > 0 && keys[order[position - 1]] != keys[index] {
+            rank += 1;
+        }
+        ranks[index] = rank;
+    }
+    ranks
+}
+
+fn class_count(ranks: &[usize]) -> usize {
+    ranks.iter().max().map_or(0, |max| max + 1)
+}
+
+fn refine(neighbors: &[Vec<(usize, u8)>], mut ranks: Vec<usize>) -> Vec<usize> {
+    loop {
+        let keys: Vec<(usize, Vec<(usize, u8)>)> = neighbors
+            .iter()
+            .enumerate()
+            .map(|(atom, neighbors)| {
+                let mut neighbor_ranks: Vec<(usize, u8)> = neighbors
+                    .iter()
+                    .map(|&(neighbor, label)| (ranks[neighbor], label))
+                    .collect();
+                neighbor_ranks.sort_unstable();
+                (ranks[atom], neighbor_ranks)
+            })
+            .collect();
+        let refined = dense_ranks(&keys);
+        if class_count(&refined) == class_count(&ranks) {
+            return ranks;
+        }
+        ranks = refined;
+    }
+}
+
+/// Connected component of every atom, as the index of its first atom.
+fn component_ids(neighbors: &[Vec<(usize, u8)>]) -> Vec<usize> {
+    let mut components = vec![usize::MAX; neighbors.len()];
+    for start in 0..neighbors.len() {
+        if components[start] != usize::MAX {
+            continue;
+        }
+        components[start] = start;
+        let mut stack = vec![start];
+        while let Some(atom) = stack.pop() {
+            for &(neighbor, _) in &neighbors[atom] {
+                if components[neighbor] == usize::MAX {
+                    components[neighbor] = start;
+                    stack.push(neighbor);
+                }
+            }
+        }
+    }
+    components
+}
+
+/// Size of the smallest ring that an atom is part of, or 0 if it isn't in a ring.
+fn smallest_ring(neighbors: &[Vec<(usize, u8)>], atom: usize) -> usize {
+    // Breadth-first search that remembers through which neighbor of `atom` every atom was
+    // reached. A bond between atoms that were reached through different neighbors closes a ring.
+    let mut distances = vec![usize::MAX; neighbors.len()];
+    let mut branches = vec![usize::MAX; neighbors.len()];
+    distances[atom] = 0;
+    let mut queue = VecDeque::from(vec![atom]);
+    let mut smallest = usize::MAX;
+    while let Some(current) = queue.pop_front() {
+        // Rings found from here on have at least this size.
+        if 2 * distances[current] + 1 >= smallest {
+            break;
+        }
+        for &(neighbor, _) in &neighbors[current] {
+            if neighbor == atom {
+                continue;
+            }
+            if distances[neighbor] == usize::MAX {
+                distances[neighbor] = distances[current] + 1;
+                branches[neighbor] = if current == atom {
+                    neighbor
+                } else {
+                    branches[current]
+                };
+                queue.push_back(neighbor);
+            } else if current != atom && branches[neighbor] != branches[current] {
+                smallest = smallest.min(distances[current] + distances[neighbor] + 1);
+            }
+        }
+    }
+    if smallest == usize::MAX {
+        0
+    } else {
+        smallest
+    }
+}
+
+/// Classes of atoms that refinement can't tell apart, by their invariants, the size of their
+/// component and the size of their smallest ring.
+fn initial_classes<I: Ord>(neighbors: &[Vec<(usize, u8)>], invariants: &[I]) -> Vec<usize> {
+    let components = component_ids(neighbors);
+    let mut sizes = vec![0; neighbors.len()];
+    for &component in &components {
+        sizes[component] += 1;
+    }
+    let keys: Vec<(&I, usize, usize)> = invariants
+        .iter()
+        .enumerate()
+        .map(|(atom, invariant)| {
+            (
+                invariant,
+                sizes[components[atom]],
+                smallest_ring(neighbors, atom),
+            )
+        })
+        .collect();
+    refine(neighbors, dense_ranks(&keys))
+}
+
+/// The lowest class with more than one atom, if any.
+fn lowest_tie(ranks: &[usize]) -> Option<usize> {
+    let mut counts = vec![0; ranks.len()];
+    for &rank in ranks {
+        counts[rank] += 1;
+    }
+    (0..counts.len()).find(|&rank| counts[rank] > 1)
+}
+
+/// The atoms of a tied class that are tried as the first of their class. Atoms with a single
+/// neighbor are interchangeable with the atoms of the class that share it, like the hydrogens of
+/// a methyl group, so only one of them is tried.
+fn tie_candidates(neighbors: &[Vec<(usize, u8)>], ranks: &[usize], tied: usize) -> Vec<usize> {
+    let mut shared = HashSet::new();
+    (0..ranks.len())
+        .filter(|&atom| ranks[atom] == tied)
+        .filter(|&atom| match neighbors[atom].as_slice() {
+            [single] => shared.insert(*single),
+            _ => true,
+        })
+        .collect()
+}
+
+/// The most complete rankings that are scored when breaking ties. Beyond it, the remaining ties
+/// are broken in favor of the atom with the lowest index, which only makes a difference for
+/// atoms that refinement can't tell apart although they aren't symmetric.
+const MAX_RANKINGS: usize = 256;
+
+/// Rank atoms given as an adjacency list of `(neighbor, bond label)` pairs and their invariants.
+///
+/// Ties are broken by trying every atom of the lowest tied class as the first of its class, and
+/// of all complete rankings the one with the smallest `score` is returned.
+pub(crate) fn rank_atoms<I: Ord, S: Ord>(
+    neighbors: &[Vec<(usize, u8)>],
+    invariants: &[I],
+    mut score: impl FnMut(&Ranking) -> S,
+) -> Ranking {
+    let classes = initial_classes(neighbors, invariants);
+
+    let mut best: Option<(S, Ranking)> = None;
+    let mut scored = 0;
+    // Partial rankings, with the atom that is ranked first of its class next. Atoms with lower
+    // indices are tried first.
+    let mut stack = vec![(classes.clone(), None)];
+    while let Some((ranks, chosen)) = stack.pop() {
+        if scored == MAX_RANKINGS {
+            break;
+        }
+        let ranks = match chosen {
+            Some(chosen) => {
+                let keys: Vec<(usize, bool)> = ranks
+                    .iter()
+                    .enumerate()
+                    .map(|(atom, &rank)| (rank, atom != chosen))
+                    .collect();
+                refine(neighbors, dense_ranks(&keys))
+            }
+            None => ranks,
+        };
+        match lowest_tie(&ranks) {
+            Some(tied) => {
+                for candidate in tie_candidates(neighbors, &ranks, tied).into_iter().rev() {
+                    stack.push((ranks.clone(), Some(candidate)));
+                }
+            }
+            None => {
+                scored += 1;
+                let ranking = Ranking {
+                    classes: classes.clone(),
+                    ranks,
+                };
+                let ranking_score = score(&ranking);
+                if best.as_ref().is_none_or(|(best, _)| ranking_score < *best) {
+                    best = Some((ranking_score, ranking));
+                }
+            }
+        }
+    }
+
+    best.unwrap().1
+}
+
+fn atom_neighbors(graph: &MoleculeGraph) -> Vec<Vec<(usize, u8)>> {
+    graph
+        .node_indices()
+        .map(|node| {
+            graph
+                .edges(node)
+                .map(|edge| {
+                    let neighbor = graph.other_end(edge.id(), node);
+                    (neighbor.index(), bond_order(*edge.weight()))
+                })
+                .collect()
+        })
+        .collect()
+}
+
+type AtomInvariant = (Option<usize>, Option<u16>, i8, bool, usize, u8);
+
+fn atom_invariants(graph: &MoleculeGraph, neighbors: &[Vec<(usize, u8)>]) -> Vec<AtomInvariant> {
+    graph
+        .node_indices()
+        .map(|node| {
+            let atom = &graph[node];
+            (
+                atom.element().map(|element| element.get_atomic_number()),
+                atom.isotope(),
+                atom.charge(),
+                atom.is_aromatic(),
+                neighbors[node.index()].len(),
+                graph.bond_order_sum(node),
+            )
+        })
+        .collect()
+}
+
+/// Canonical ranks of all atoms of a graph, indexed by node index.
+///
+/// Two graphs of the same molecule, with the atoms and bonds in any order, get the same ranks
+/// for corresponding atoms (up to symmetry). Atoms are ranked by element, isotope, charge,
+/// aromaticity, number of neighbors, sum of bond orders, size of their component and size of
+/// their smallest ring, and bonds by their order. Of the ways to break the remaining ties, the
+/// one whose SMILES sorts first is chosen.
+pub fn canonical_ranks(graph: &MoleculeGraph) -> Vec<usize> {
+    let neighbors = atom_neighbors(graph);
+    let invariants = atom_invariants(graph, &neighbors);
+    rank_atoms(&neighbors, &invariants, |ranking| {
+        write_smiles_ranked(graph, &ranking.ranks)
+    })
+    .ranks
+}
+
+/// Symmetry classes of all atoms of a graph, indexed by node index, without stereo.
+pub(crate) fn symmetry_classes(graph: &MoleculeGraph) -> Vec<usize> {
+    let neighbors = atom_neighbors(graph);
+    let invariants = atom_invariants(graph, &neighbors);
+    initial_classes(&neighbors, &invariants)
+}
+
+/// Whether a tetrahedral center is anticlockwise relative to its neighbors ordered by class, or
+/// `None` if it has no tetrahedral chirality or two of its neighbors are in the same class.
+fn tetrahedral_parity(graph: &MoleculeGraph, atom: NodeIndex, classes: &[usize]) -> Option<bool> {
+    let anticlockwise = match graph[atom].chirality()? {
+        Chirality::Anticlockwise => true,
+        Chirality::Clockwise => false,
+        _ => return None,
+    };
+    // The chirality of the graph is relative to the neighbors in node order.
+    let mut neighbors: Vec<NodeIndex> = graph.neighbors(atom).collect();
+    neighbors.sort_unstable();
+    let neighbor_classes: Vec<usize> = neighbors
+        .iter()
+        .map(|neighbor| classes[neighbor.index()])
+        .collect();
+    let mut inversions = 0;
+    for (i, a) in neighbor_classes.iter().enumerate() {
+        for b in &neighbor_classes[i + 1..] {
+            match a.cmp(b) {
+                Ordering::Equal => return None,
+                Ordering::Greater => inversions += 1,
+                Ordering::Less => {}
+            }
+        }
+    }
+    Some(anticlockwise == (inversions % 2 == 0))
+}
+
+/// Whether the substituent of `atom` with the highest class is above the double bond to
+/// `other`, or `None` if the substituents have no direction or are in the same class.
+fn double_bond_side(
+    graph: &MoleculeGraph,
+    atom: NodeIndex,
+    other: NodeIndex,
+    classes: &[usize],
+) -> Option<bool> {
+    let substituents: Vec<NodeIndex> = graph
+        .neighbors(atom)
+        .filter(|&neighbor| neighbor != other)
+        .collect();
+    match substituents.as_slice() {
+        [_] => {}
+        [a, b] if classes[a.index()] != classes[b.index()] => {}
+        _ => return None,
+    }
+    let (substituent, above) = substituents.iter().find_map(|&substituent| {
+        let edge = graph.find_edge(atom, substituent).unwrap();
+        // `/` means that the later atom is above the earlier one.
+        let up = match graph[edge] {
+            Bond::Up => true,
+            Bond::Down => false,
+            _ => return None,
+        };
+        let (source, _) = graph.edge_endpoints(edge).unwrap();
+        Some((substituent, (source == atom) == up))
+    })?;
+    let reference = substituents
+        .iter()
+        .max_by_key(|substituent| classes[substituent.index()])
+        .unwrap();
+    Some((*reference == substituent) == above)
+}
+
+/// Whether a double bond is trans relative to the substituents with the highest class at both
+/// ends, or `None` if it has no stereo.
+fn double_bond_parity(
+    graph: &MoleculeGraph,
+    a: NodeIndex,
+    b: NodeIndex,
+    classes: &[usize],
+) -> Option<bool> {
+    let first = double_bond_side(graph, a, b, classes)?;
+    let second = double_bond_side(graph, b, a, classes)?;
+    Some(first != second)
+}
+
+/// Stereo centers that the configuration of `atom` is relative to when it has two substituents
+/// in the same class, like the other end of a 1,4-disubstituted ring. These are the atoms marked
+/// in `stereo` that can be reached from such substituents without passing through `atom`.
+pub(crate) fn para_partners(
+    graph: &MoleculeGraph,
+    atom: NodeIndex,
+    classes: &[usize],
+    stereo: &[bool],
+) -> Vec<NodeIndex> {
+    let neighbors: Vec<NodeIndex> = graph.neighbors(atom).collect();
+    let mut visited = vec![false; graph.node_count()];
+    visited[atom.index()] = true;
+    let mut stack: Vec<NodeIndex> = neighbors
+        .iter()
+        .filter(|&&neighbor| {
+            let class = classes[neighbor.index()];
+            neighbors
+                .iter()
+                .filter(|other| classes[other.index()] == class)
+                .count()
+                > 1
+        })
+        .copied()
+        .collect();
+    let mut partners = Vec::new();
+    while let Some(node) = stack.pop() {
+        if std::mem::replace(&mut visited[node.index()], true) {
+            continue;
+        }
+        if stereo[node.index()] {
+            partners.push(node);
+        }
+        stack.extend(graph.neighbors(node));
+    }
+    partners.sort_unstable();
+    partners
+}
+
+fn has_tetrahedral_chirality(graph: &MoleculeGraph, atom: NodeIndex) -> bool {
+    matches!(
+        graph[atom].chirality(),
+        Some(Chirality::Anticlockwise) | Some(Chirality::Clockwise)
+    )
+}
+
+/// A graph with its stereo descriptors reduced to those that make a difference.
+struct StereoForm {
+    graph: MoleculeGraph,
+    /// Keys by which the atoms are ranked.
+    keys: Vec<Vec<usize>>,
+    /// Groups of centers whose configurations are only defined relative to each other, so
+    /// inverting all centers of a group gives the same molecule.
+    para_groups: Vec<Vec<NodeIndex>>,
+}
+
+/// Refine the symmetry classes with the configurations of the stereo centers and double bonds,
+/// until no more of them can be told apart.
+fn stereo_form(graph: &MoleculeGraph) -> StereoForm {
+    let neighbors = atom_neighbors(graph);
+    let mut classes = symmetry_classes(graph);
+    let double_bonds: Vec<(NodeIndex, NodeIndex)> = graph
+        .edge_references()
+        .filter(|edge| *edge.weight() == Bond::Double)
+        .map(|edge| (edge.source(), edge.target()))
+        .collect();
+    let (mut centers, mut bonds);
+    loop {
+        centers = graph
+            .node_indices()
+            .map(|atom| tetrahedral_parity(graph, atom, &classes))
+            .collect::<Vec<_>>();
+        bonds = double_bonds
+            .iter()
+            .map(|&(a, b)| double_bond_parity(graph, a, b, &classes))
+            .collect::<Vec<_>>();
+        let mut keys: Vec<Vec<usize>> = classes.iter().map(|&class| vec![class]).collect();
+        for (atom, parity) in centers.iter().enumerate() {
+            keys[atom].push(parity.map_or(0, |parity| 1 + parity as usize));
+        }
+        for (&(a, b), parity) in double_bonds.iter().zip(&bonds) {
+            if let Some(parity) = parity {
+                keys[a.index()].push(3 + *parity as usize);
+                keys[b.index()].push(3 + *parity as usize);
+            }
+        }
+        let refined = refine(&neighbors, dense_ranks(&keys));
+        if class_count(&refined) == class_count(&classes) {
+            break;
+        }
+        classes = refined;
+    }
+
+    // Centers with two equal substituents only keep their configuration if it is relative to
+    // another such center.
+    let unresolved: Vec<bool> = graph
+        .node_indices()
+        .map(|atom| has_tetrahedral_chirality(graph, atom) && centers[atom.index()].is_none())
+        .collect();
+    let mut group = (0..graph.node_count()).collect::<Vec<_>>();
+    fn root(group: &mut [usize], mut atom: usize) -> usize {
+        while group[atom] != atom {
+            group[atom] = group[group[atom]];
+            atom = group[atom];
+        }
+        atom
+    }
+    let mut para = vec![false; graph.node_count()];
+    for atom in graph.node_indices().filter(|atom| unresolved[atom.index()]) {
+        for partner in para_partners(graph, atom, &classes, &unresolved) {
+            para[atom.index()] = true;
+            let (a, b) = (
+                root(&mut group, atom.index()),
+                root(&mut group, partner.index()),
+            );
+            group[a] = b;
+        }
+    }
+    let mut para_groups: Vec<Vec<NodeIndex>> = Vec::new();
+    let mut group_of_root = vec![usize::MAX; graph.node_count()];
+    let mut stripped = graph.clone();
+    for atom in graph.node_indices().filter(|atom| unresolved[atom.index()]) {
+        if para[atom.index()] {
+            let root = root(&mut group, atom.index());
+            if group_of_root[root] == usize::MAX {
+                group_of_root[root] = para_groups.len();
+                para_groups.push(Vec::new());
+            }
+            para_groups[group_of_root[root]].push(atom);
+        } else {
+            let (isotope, charge) = (graph[atom].isotope(), graph[atom].charge());
+            stripped.rebuild_atom(atom, isotope, None, charge);
+        }
+    }
+    let mut stereo_bond_atoms = vec![false; graph.node_count()];
+    for (&(a, b), parity) in double_bonds.iter().zip(&bonds) {
+        if parity.is_some() {
+            stereo_bond_atoms[a.index()] = true;
+            stereo_bond_atoms[b.index()] = true;
+        }
+    }
+    for edge in graph.edge_references() {
+        let directional = matches!(edge.weight(), Bond::Up | Bond::Down);
+        if directional
+            && !stereo_bond_atoms[edge.source().index()]
+            && !stereo_bond_atoms[edge.target().index()]
+        {
+            stripped[edge.id()] = Bond::Single;
+        }
+    }
+
+    let keys = classes
+        .iter()
+        .enumerate()
+        .map(|(atom, &class)| {
+            let mut key = vec![class, centers[atom].map_or(0, |parity| 1 + parity as usize)];
+            for (&(a, b), parity) in double_bonds.iter().zip(&bonds) {
+                if let Some(parity) = parity {
+                    if a.index() == atom || b.index() == atom {
+                        key.push(3 + *parity as usize);
+                    }
+                }
+            }
+            key
+        })
+        .collect();
+    StereoForm {
+        graph: stripped,
+        keys,
+        para_groups,
+    }
+}
+
+/// The most groups of relative centers whose inversions are tried one by one.
+const MAX_PARA_GROUPS: usize = 8;
+
+/// Canonical SMILES of a graph, written in the order of the [`canonical_ranks`] refined by
+/// stereo.
+///
+/// Atoms are first ranked without stereo. The configurations of stereo centers and double bonds
+/// whose neighbors can be told apart are then added to the atom invariants, and the ranks are
+/// refined again until nothing changes, so stereoisomers that are the same molecule, like the
+/// two ways to write a meso compound, get the same SMILES. Stereo descriptors of atoms and
+/// bonds that don't make a difference, like those of a center with two equal substituents, are
+/// dropped. Centers whose configurations are only relative to each other, like the ends of a
+/// cis or trans 1,4-disubstituted ring, are kept, and of the equivalent ways to write them and
+/// the markers of double bond stereo, the one that sorts first is chosen.
+///
+/// Every component is canonicalized on its own, and the components are written in the order of
+/// their SMILES.
+///
+/// The Kekulé and the aromatic form of a molecule give different SMILES, see
+/// [aromaticity](crate::graph#aromaticity).
+pub fn canonical_smiles(graph: &MoleculeGraph) -> String {
+    let components = component_ids(&atom_neighbors(graph));
+    let mut starts: Vec<usize> = components.clone();
+    starts.sort_unstable();
+    starts.dedup();
+    if starts.len() < 2 {
+        return canonical_component_smiles(graph);
+    }
+    let mut parts: Vec<String> = starts
+        .into_iter()
+        .map(|start| {
+            let keep: Vec<bool> = components
+                .iter()
+                .map(|&component| component == start)
+                .collect();
+            canonical_component_smiles(&graph.subgraph(&keep))
+        })
+        .collect();
+    parts.sort_unstable();
+    parts.join(".")
+}
+
+fn canonical_component_smiles(graph: &MoleculeGraph) -> String {
+    let StereoForm {
+        graph,
+        keys,
+        mut para_groups,
+    } = stereo_form(graph);
+    if para_groups.len() > MAX_PARA_GROUPS {
+        para_groups = vec![para_groups.concat()];
+    }
+    let neighbors = atom_neighbors(&graph);
+    let ranking = rank_atoms(&neighbors, &keys, |ranking| {
+        smallest_smiles(&graph, &ranking.ranks, &para_groups)
+    });
+    smallest_smiles(&graph, &ranking.ranks, &para_groups)
+}
+
+/// The SMILES that sorts first of those written in the order of the given ranks, with every
+/// combination of inverted groups of relative centers and both ways to mark double bond stereo.
+fn smallest_smiles(
+    graph: &MoleculeGraph,
+    ranks: &[usize],
+    para_groups: &[Vec<NodeIndex>],
+) -> String {
+    let mut graph = graph.clone();
+    let mut best: Option<String> = None;
+    for mask in 0..1usize << para_groups.len() {
+        if mask > 0 {
+            // In Gray code order, one group is inverted per step.
+            for &atom in &para_groups[mask.trailing_zeros() as usize] {
+                let chirality = match graph[atom].chirality() {
+                    Some(Chirality::Anticlockwise) => Chirality::Clockwise,
+                    _ => Chirality::Anticlockwise,
+                };
+                let (isotope, charge) = (graph[atom].isotope(), graph[atom].charge());
+                graph.rebuild_atom(atom, isotope, Some(chirality), charge);
+            }
+        }
+        let smiles = write_smiles_ranked(&graph, ranks);
+        let flipped: String = smiles
+            .chars()
+            .map(|c| match c {
+                '/' => '\\',
+                '\\' => '/',
+                c => c,
+            })
+            .collect();
+        let smiles = smiles.min(flipped);
+        if best.as_ref().is_none_or(|best| smiles < *best) {
+            best = Some(smiles);
+        }
+    }
+    best.unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graph::molecule;
+
+    fn ranks(smiles: &str) -> Vec<usize> {
+        canonical_ranks(&molecule(smiles))
+    }
+
+    #[test]
+    fn ranks_are_distinct() {
+        let mut ranks = ranks("c1ccc2ccccc2c1");
+        ranks.sort_unstable();
+        assert_eq!((0..18).collect::<Vec<_>>(), ranks);
+    }
+
+    #[test]
+    fn ranks_do_not_depend_on_atom_order() {
+        // Heavy atoms come first, in SMILES order.
+        let ethanol = ranks("CCO");
+        let reversed = ranks("OCC");
+        assert_eq!(ethanol[..3], [reversed[2], reversed[1], reversed[0]]);
+
+        let acid = ranks("CC(=O)O");
+        let reordered = ranks("OC(C)=O");
+        assert_eq!(
+            acid[..4],
+            [reordered[2], reordered[1], reordered[3], reordered[0]]
+        );
+    }
+
+    /// SMILES of a molecule with its atoms in other orders: rotated by every offset and reversed.
+    fn reordered(smiles: &str) -> Vec<String> {
+        let graph = molecule(smiles);
+        let count = graph.node_count();
+        let mut orders: Vec<Vec<usize>> = (0..count)
+            .map(|offset| (0..count).map(|atom| (atom + offset) % count).collect())
+            .collect();
+        orders.push((0..count).rev().collect());
+        orders
+            .iter()
+            .map(|ranks| write_smiles_ranked(&graph, ranks))
+            .collect()
+    }
+
+    #[test]
+    fn canonical_smiles_do_not_depend_on_atom_order() {
+        for smiles in &[
+            "C1CCCCC1.C1CCCC1",
+            "C1CCCCC1.C1CC1.C1CC1",
+            "C1CC1C1CCCCC1C1CC1",
+            "C12C3C4C1C5C2C3C45",
+            "c1ccc2ccccc2c1",
+            "CC(C)(C)c1ccc(O)cc1.[Na+].[Cl-]",
+            "N[C@@H](C)C(=O)O",
+            "C[C@H]1CC[C@@H](C)CC1",
+            "F/C=C/C=C\\Cl",
+        ] {
+            let expected = canonical_smiles(&molecule(smiles));
+            for reordered in reordered(smiles) {
+                let graph = molecule(&reordered);
+                assert_eq!(expected, canonical_smiles(&graph), "{}", reordered);
+            }
+        }
+    }
+
+    #[test]
+    fn symmetry_classes() {
+        let graph = molecule("CC(C)O");
+        let neighbors: Vec<Vec<(usize, u8)>> = vec![
+            vec![(1, 1)],
+            vec![(0, 1), (2, 1), (3, 1)],
+            vec![(1, 1)],
+            vec![(1, 1)],
+        ];
+        let invariants: Vec<_> = (0..4)
+            .map(|index| {
+                graph[petgraph::graph::NodeIndex::new(index)]
+                    .element()
+                    .map(|element| element.get_atomic_number())
+            })
+            .collect();
+        let ranking = rank_atoms(&neighbors, &invariants, |ranking| ranking.ranks.clone());
+        assert_eq!(ranking.classes[0], ranking.classes[2]);
+        assert_ne!(ranking.ranks[0], ranking.ranks[2]);
+        assert_ne!(ranking.classes[0], ranking.classes[3]);
+    }
+}
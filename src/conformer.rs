@@ -0,0 +1,997 @@
+//! 3D conformer embedding by distance geometry, and XYZ and PDB output.
+//!
+//! Lower and upper bounds for the distances between all atoms are derived from bond lengths, bond
+//! angles and torsions, and smoothed with the triangle inequality. Distances picked at random
+//! between these bounds are embedded in three dimensions from the largest eigenvectors of the
+//! metric matrix. The coordinates are then cleaned up by minimizing a simple force field of bound
+//! violations, tetrahedral chirality and planarity of double bonds and sp2 atoms. Conformers
+//! with the wrong chirality are mirrored, and embedded again if that doesn't help.
+//!
+//! Bond lengths are estimated from covalent radii and bond orders, and bond angles from the
+//! hybridization of the atoms and the size of the rings they are in. This is good enough as a
+//! starting point for docking or a force field minimization, but not a replacement for either.
+//! All atoms, including attached hydrogens, get coordinates in ångström.
+
+use std::collections::HashMap;
+use std::f64::consts::PI;
+use std::fmt::Write as _;
+
+use petgraph::graph::NodeIndex;
+use petgraph::visit::EdgeRef;
+use ptable::Element;
+
+use crate::graph::MoleculeGraph;
+use crate::minimize::minimize;
+use crate::{Bond, Chirality};
+
+/// Largest number of embeddings that are tried.
+const ATTEMPTS: usize = 10;
+/// Tolerance of the bounds of bond lengths and bond angles, in ångström.
+const TOLERANCE: f64 = 0.01;
+const ANGLE_TOLERANCE: f64 = 0.04;
+/// Lower bounds of other atom pairs are this fraction of the sum of their van der Waals radii.
+const VDW_SCALE: f64 = 0.7;
+/// Embeddings with a higher final energy are tried again.
+const MAX_ENERGY_PER_ATOM: f64 = 0.01;
+/// Smallest signed volume of a stereo center that counts as correct.
+const MIN_VOLUME: f64 = 0.5;
+
+#[derive(Debug, PartialEq, Eq, Copy, Clone, Hash)]
+pub enum EmbedError {
+    /// No conformer with the specified tetrahedral chirality was found.
+    StereoMismatch,
+}
+
+impl std::fmt::Display for EmbedError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EmbedError::StereoMismatch => write!(f, "no conformer with the specified chirality"),
+        }
+    }
+}
+
+impl std::error::Error for EmbedError {}
+
+/// 3D coordinates for all atoms of a [`MoleculeGraph`], in ångström.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Conformer {
+    positions: Vec<[f64; 3]>,
+}
+
+impl Conformer {
+    /// A conformer with the given coordinates by node index.
+    pub fn new(positions: Vec<[f64; 3]>) -> Self {
+        Conformer { positions }
+    }
+
+    /// Coordinates by node index.
+    pub fn positions(&self) -> &[[f64; 3]] {
+        &self.positions
+    }
+
+    pub fn position(&self, atom: NodeIndex) -> [f64; 3] {
+        self.positions[atom.index()]
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+enum Hybridization {
+    Linear,
+    Planar,
+    Tetrahedral,
+    Octahedral,
+}
+
+fn hybridization(graph: &MoleculeGraph, atom: NodeIndex) -> Hybridization {
+    let degree = graph.neighbors(atom).count();
+    let (mut doubles, mut triple, mut aromatic) = (0, false, false);
+    for edge in graph.edges(atom) {
+        match edge.weight() {
+            Bond::Double => doubles += 1,
+            Bond::Triple | Bond::Quadruple => triple = true,
+            Bond::Aromatic => aromatic = true,
+            _ => {}
+        }
+    }
+    if degree <= 2 && (triple || doubles >= 2) {
+        Hybridization::Linear
+    } else if degree <= 3 && (doubles > 0 || aromatic || graph[atom].is_aromatic()) {
+        Hybridization::Planar
+    } else if degree >= 5 {
+        Hybridization::Octahedral
+    } else {
+        Hybridization::Tetrahedral
+    }
+}
+
+fn ideal_angle(hybridization: Hybridization) -> f64 {
+    match hybridization {
+        Hybridization::Linear => PI,
+        Hybridization::Planar => 2.0 * PI / 3.0,
+        Hybridization::Tetrahedral => (-1.0f64 / 3.0).acos(),
+        Hybridization::Octahedral => PI / 2.0,
+    }
+}
+
+fn covalent_radius(element: Option<Element>) -> f64 {
+    match element {
+        Some(Element::Hydrogen) => 0.33,
+        Some(Element::Boron) => 0.84,
+        Some(Element::Carbon) => 0.76,
+        Some(Element::Nitrogen) => 0.71,
+        Some(Element::Oxygen) => 0.66,
+        Some(Element::Fluorine) => 0.57,
+        Some(Element::Silicon) => 1.11,
+        Some(Element::Phosphorus) => 1.07,
+        Some(Element::Sulfur) => 1.05,
+        Some(Element::Chlorine) => 1.02,
+        Some(Element::Selenium) => 1.20,
+        Some(Element::Bromine) => 1.20,
+        Some(Element::Iodine) => 1.39,
+        Some(element) if element.get_atomic_radius() > 0 => {
+            f64::from(element.get_atomic_radius()) / 100.0
+        }
+        _ => 1.5,
+    }
+}
+
+fn vdw_radius(element: Option<Element>) -> f64 {
+    match element {
+        Some(Element::Hydrogen) => 1.2,
+        Some(Element::Carbon) => 1.7,
+        Some(Element::Nitrogen) => 1.55,
+        Some(Element::Oxygen) => 1.52,
+        Some(Element::Fluorine) => 1.47,
+        Some(Element::Phosphorus) | Some(Element::Sulfur) => 1.8,
+        Some(Element::Chlorine) => 1.75,
+        Some(Element::Bromine) => 1.85,
+        Some(Element::Iodine) => 1.98,
+        _ => 2.0,
+    }
+}
+
+fn bond_length(graph: &MoleculeGraph, a: NodeIndex, b: NodeIndex, bond: Bond) -> f64 {
+    let shortening = match bond {
+        Bond::Aromatic => 0.1,
+        Bond::Double => 0.2,
+        Bond::Triple => 0.34,
+        Bond::Quadruple => 0.4,
+        Bond::Single | Bond::Up | Bond::Down => 0.0,
+    };
+    covalent_radius(graph[a].element()) + covalent_radius(graph[b].element()) - shortening
+}
+
+/// Side of the substituents of one atom of a double bond, as `true` for "above", if the double
+/// bond has a specified configuration.
+fn double_bond_sides(
+    graph: &MoleculeGraph,
+    atom: NodeIndex,
+    other: NodeIndex,
+) -> Option<Vec<(NodeIndex, bool)>> {
+    let substituents: Vec<NodeIndex> = graph
+        .neighbors(atom)
+        .filter(|&neighbor| neighbor != other)
+        .collect();
+    let (marked, above) = substituents.iter().find_map(|&substituent| {
+        let edge = graph.find_edge(atom, substituent).unwrap();
+        // `/` means that the later atom is above the earlier one.
+        let up = match graph[edge] {
+            Bond::Up => true,
+            Bond::Down => false,
+            _ => return None,
+        };
+        let (source, _) = graph.edge_endpoints(edge).unwrap();
+        Some((substituent, (source == atom) == up))
+    })?;
+    Some(
+        substituents
+            .into_iter()
+            .map(|substituent| (substituent, (substituent == marked) == above))
+            .collect(),
+    )
+}
+
+/// Distance between the ends of a path a-b-c-d with the given bond lengths and angles at b and
+/// c, for a torsion of zero (cis) or 180° (trans).
+fn torsion_distance(lengths: [f64; 3], angles: [f64; 2], cis: bool) -> f64 {
+    let a = [lengths[0] * angles[0].cos(), lengths[0] * angles[0].sin()];
+    let side = if cis { 1.0 } else { -1.0 };
+    let d = [
+        lengths[1] - lengths[2] * angles[1].cos(),
+        side * lengths[2] * angles[1].sin(),
+    ];
+    (a[0] - d[0]).hypot(a[1] - d[1])
+}
+
+/// Lower and upper distance bounds between all pairs of atoms.
+struct Bounds {
+    count: usize,
+    lower: Vec<f64>,
+    upper: Vec<f64>,
+    fixed: Vec<bool>,
+}
+
+impl Bounds {
+    fn new(count: usize) -> Self {
+        Bounds {
+            count,
+            lower: vec![0.0; count * count],
+            upper: vec![f64::INFINITY; count * count],
+            fixed: vec![false; count * count],
+        }
+    }
+
+    fn is_fixed(&self, a: usize, b: usize) -> bool {
+        self.fixed[a * self.count + b]
+    }
+
+    /// Set the bounds of a pair of atoms unless they are already set.
+    fn set(&mut self, a: usize, b: usize, lower: f64, upper: f64) {
+        if a == b || self.is_fixed(a, b) {
+            return;
+        }
+        for (i, j) in [(a, b), (b, a)] {
+            self.lower[i * self.count + j] = lower;
+            self.upper[i * self.count + j] = upper;
+            self.fixed[i * self.count + j] = true;
+        }
+    }
+
+    fn lower(&self, a: usize, b: usize) -> f64 {
+        self.lower[a * self.count + b]
+    }
+
+    fn upper(&self, a: usize, b: usize) -> f64 {
+        self.upper[a * self.count + b]
+    }
+
+    /// Tighten the bounds with the triangle inequality.
+    fn smooth(&mut self) {
+        let n = self.count;
+        for k in 0..n {
+            for i in 0..n {
+                let (upper_ik, lower_ik) = (self.upper(i, k), self.lower(i, k));
+                for j in (i + 1)..n {
+                    let (upper_kj, lower_kj) = (self.upper(k, j), self.lower(k, j));
+                    let mut upper = self.upper(i, j);
+                    if upper_ik + upper_kj < upper {
+                        upper = upper_ik + upper_kj;
+                    }
+                    let mut lower = self.lower(i, j);
+                    lower = lower.max(lower_ik - upper_kj).max(lower_kj - upper_ik);
+                    if lower > upper {
+                        // Inconsistent bounds, keep the upper bound which comes from bonds.
+                        lower = upper;
+                    }
+                    for (a, b) in [(i, j), (j, i)] {
+                        self.upper[a * n + b] = upper;
+                        self.lower[a * n + b] = lower;
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Terms of the force field besides the distance bounds.
+struct Geometry {
+    bounds: Bounds,
+    /// Signed volumes of stereo centers, as four atoms and the sign the volume should have.
+    chiral: Vec<([usize; 4], f64)>,
+    /// Quadruples of atoms that should be in one plane.
+    planar: Vec<[usize; 4]>,
+}
+
+impl Geometry {
+    fn new(graph: &MoleculeGraph) -> Self {
+        let count = graph.node_count();
+        let hybridizations: Vec<Hybridization> = graph
+            .node_indices()
+            .map(|atom| hybridization(graph, atom))
+            .collect();
+        let neighbors: Vec<Vec<NodeIndex>> = graph
+            .node_indices()
+            .map(|atom| {
+                let mut neighbors: Vec<NodeIndex> = graph.neighbors(atom).collect();
+                neighbors.sort_unstable();
+                neighbors
+            })
+            .collect();
+
+        let rings = graph.rings();
+        // Size of the smallest ring in which a-b-c are consecutive, by (min(a, c), b, max(a, c)).
+        let mut ring_angles: HashMap<(NodeIndex, NodeIndex, NodeIndex), usize> = HashMap::new();
+        for ring in &rings {
+            for index in 0..ring.len() {
+                let a = ring[(index + ring.len() - 1) % ring.len()];
+                let (b, c) = (ring[index], ring[(index + 1) % ring.len()]);
+                let size = ring_angles
+                    .entry((a.min(c), b, a.max(c)))
+                    .or_insert(ring.len());
+                *size = (*size).min(ring.len());
+            }
+        }
+        let planar_rings: Vec<&Vec<NodeIndex>> = rings
+            .iter()
+            .filter(|ring| {
+                ring.iter()
+                    .all(|atom| hybridizations[atom.index()] == Hybridization::Planar)
+            })
+            .collect();
+
+        let mut bounds = Bounds::new(count);
+        let mut lengths = HashMap::new();
+        for edge in graph.edge_references() {
+            let (a, b) = (edge.source(), edge.target());
+            let length = bond_length(graph, a, b, *edge.weight());
+            lengths.insert((a, b), length);
+            lengths.insert((b, a), length);
+            bounds.set(a.index(), b.index(), length - TOLERANCE, length + TOLERANCE);
+        }
+
+        // Bond angles at every atom.
+        let mut angles: HashMap<(NodeIndex, NodeIndex, NodeIndex), f64> = HashMap::new();
+        for center in graph.node_indices() {
+            let around = &neighbors[center.index()];
+            let hybridization = hybridizations[center.index()];
+            let mut known = Vec::new();
+            for (i, &a) in around.iter().enumerate() {
+                for &c in &around[i + 1..] {
+                    let ring = ring_angles.get(&(a, center, c)).copied();
+                    let angle = match ring {
+                        Some(size) if hybridization == Hybridization::Planar || size <= 5 => {
+                            Some((size - 2) as f64 * PI / size as f64)
+                        }
+                        _ => None,
+                    };
+                    known.push((a, c, angle));
+                }
+            }
+            // The angles around a planar atom add up to a full circle.
+            let fill = if hybridization == Hybridization::Planar && around.len() == 3 {
+                let sum: f64 = known.iter().filter_map(|(_, _, angle)| *angle).sum();
+                let unknown = known.iter().filter(|(_, _, angle)| angle.is_none()).count();
+                if unknown > 0 && sum > 0.0 {
+                    (2.0 * PI - sum) / unknown as f64
+                } else {
+                    ideal_angle(hybridization)
+                }
+            } else {
+                ideal_angle(hybridization)
+            };
+            for (a, c, angle) in known {
+                let angle = angle.unwrap_or(fill);
+                angles.insert((a, center, c), angle);
+                angles.insert((c, center, a), angle);
+                let (ab, cb) = (lengths[&(a, center)], lengths[&(c, center)]);
+                let distance = (ab * ab + cb * cb - 2.0 * ab * cb * angle.cos()).sqrt();
+                bounds.set(
+                    a.index(),
+                    c.index(),
+                    distance - ANGLE_TOLERANCE,
+                    distance + ANGLE_TOLERANCE,
+                );
+            }
+        }
+
+        // Torsions: fixed for bonds in planar rings and stereo double bonds, otherwise anything
+        // between cis and trans. Fixed torsions are set first so that they take precedence.
+        let mut ranges = Vec::new();
+        let mut planar = Vec::new();
+        for edge in graph.edge_references() {
+            let (b, c) = (edge.source(), edge.target());
+            let ring = planar_rings
+                .iter()
+                .filter(|ring| ring.contains(&b) && ring.contains(&c))
+                .min_by_key(|ring| ring.len());
+            let stereo = if *edge.weight() == Bond::Double && ring.is_none() {
+                double_bond_sides(graph, b, c).zip(double_bond_sides(graph, c, b))
+            } else {
+                None
+            };
+            if *edge.weight() == Bond::Double {
+                let a = neighbors[b.index()].iter().find(|&&a| a != c);
+                let d = neighbors[c.index()].iter().find(|&&d| d != b);
+                if let (Some(a), Some(d)) = (a, d) {
+                    planar.push([b.index(), a.index(), c.index(), d.index()]);
+                }
+            }
+
+            for &a in neighbors[b.index()].iter().filter(|&&a| a != c) {
+                for &d in neighbors[c.index()].iter().filter(|&&d| d != b && d != a) {
+                    let lengths = [lengths[&(a, b)], lengths[&(b, c)], lengths[&(c, d)]];
+                    let angles = [angles[&(a, b, c)], angles[&(b, c, d)]];
+                    let cis = match (ring, &stereo) {
+                        (Some(ring), _) => Some(ring.contains(&a) == ring.contains(&d)),
+                        (None, Some((first, second))) => {
+                            let side = |sides: &Vec<(NodeIndex, bool)>, atom| {
+                                sides
+                                    .iter()
+                                    .find(|(substituent, _)| *substituent == atom)
+                                    .map(|(_, above)| *above)
+                            };
+                            side(first, a).zip(side(second, d)).map(|(a, d)| a == d)
+                        }
+                        _ => None,
+                    };
+                    match cis {
+                        Some(cis) => {
+                            planar.push([b.index(), a.index(), c.index(), d.index()]);
+                            let distance = torsion_distance(lengths, angles, cis);
+                            bounds.set(
+                                a.index(),
+                                d.index(),
+                                distance - ANGLE_TOLERANCE,
+                                distance + ANGLE_TOLERANCE,
+                            );
+                        }
+                        None => ranges.push((
+                            a.index(),
+                            d.index(),
+                            torsion_distance(lengths, angles, true),
+                            torsion_distance(lengths, angles, false),
+                        )),
+                    }
+                }
+            }
+        }
+        for (a, d, cis, trans) in ranges {
+            bounds.set(a, d, cis.min(trans), cis.max(trans));
+        }
+
+        for a in 0..count {
+            for b in (a + 1)..count {
+                if !bounds.is_fixed(a, b) {
+                    let radii = vdw_radius(graph[NodeIndex::new(a)].element())
+                        + vdw_radius(graph[NodeIndex::new(b)].element());
+                    bounds.set(a, b, VDW_SCALE * radii, f64::INFINITY);
+                }
+            }
+        }
+        bounds.smooth();
+        // Atoms in different components are kept close to each other.
+        for index in 0..count * count {
+            if bounds.upper[index].is_infinite() {
+                bounds.upper[index] = bounds.lower[index] + 4.0;
+            }
+        }
+
+        // Stereo centers: the signed volume of the neighbors after the first one is negative for
+        // `@`. With three neighbors, the implicit lone pair takes the place of the last one.
+        let mut chiral = Vec::new();
+        for atom in graph.node_indices() {
+            let anticlockwise = match graph[atom].chirality() {
+                Some(Chirality::Anticlockwise) => true,
+                Some(Chirality::Clockwise) => false,
+                _ => continue,
+            };
+            let around = &neighbors[atom.index()];
+            let sign = if anticlockwise { -1.0 } else { 1.0 };
+            match around.len() {
+                4 => chiral.push((
+                    [
+                        atom.index(),
+                        around[1].index(),
+                        around[2].index(),
+                        around[3].index(),
+                    ],
+                    sign,
+                )),
+                3 => chiral.push((
+                    [
+                        atom.index(),
+                        around[0].index(),
+                        around[1].index(),
+                        around[2].index(),
+                    ],
+                    -sign,
+                )),
+                _ => {}
+            }
+        }
+
+        for atom in graph.node_indices() {
+            let around = &neighbors[atom.index()];
+            if hybridizations[atom.index()] == Hybridization::Planar && around.len() == 3 {
+                planar.push([
+                    atom.index(),
+                    around[0].index(),
+                    around[1].index(),
+                    around[2].index(),
+                ]);
+            }
+        }
+
+        Geometry {
+            bounds,
+            chiral,
+            planar,
+        }
+    }
+
+    /// Value and gradient of the force field for flat coordinates, optionally without the
+    /// planarity terms.
+    fn energy(&self, x: &[f64], gradient: &mut [f64], planarity: bool) -> f64 {
+        gradient.iter_mut().for_each(|g| *g = 0.0);
+        let n = self.bounds.count;
+        let mut energy = 0.0;
+        for i in 0..n {
+            for j in (i + 1)..n {
+                let delta = [
+                    x[3 * i] - x[3 * j],
+                    x[3 * i + 1] - x[3 * j + 1],
+                    x[3 * i + 2] - x[3 * j + 2],
+                ];
+                let squared = delta[0] * delta[0] + delta[1] * delta[1] + delta[2] * delta[2];
+                let (lower, upper) = (self.bounds.lower(i, j), self.bounds.upper(i, j));
+                // Derivative of the energy by the squared distance.
+                let derivative = if squared > upper * upper {
+                    let term = squared / (upper * upper) - 1.0;
+                    energy += term * term;
+                    2.0 * term / (upper * upper)
+                } else if squared < lower * lower {
+                    let sum = lower * lower + squared;
+                    let term = 2.0 * lower * lower / sum - 1.0;
+                    energy += term * term;
+                    -4.0 * term * lower * lower / (sum * sum)
+                } else {
+                    continue;
+                };
+                for k in 0..3 {
+                    gradient[3 * i + k] += 2.0 * derivative * delta[k];
+                    gradient[3 * j + k] -= 2.0 * derivative * delta[k];
+                }
+            }
+        }
+
+        for (atoms, sign) in &self.chiral {
+            let volume = volume(x, atoms);
+            let deficit = sign * volume - MIN_VOLUME;
+            if deficit < 0.0 {
+                energy += deficit * deficit;
+                volume_gradient(x, atoms, 2.0 * deficit * sign, gradient);
+            }
+        }
+        for atoms in self.planar.iter().filter(|_| planarity) {
+            let volume = volume(x, atoms);
+            energy += volume * volume;
+            volume_gradient(x, atoms, 2.0 * volume, gradient);
+        }
+        energy
+    }
+
+    fn chirality_is_correct(&self, x: &[f64]) -> bool {
+        self.chiral
+            .iter()
+            .all(|(atoms, sign)| sign * volume(x, atoms) > 0.0)
+    }
+}
+
+fn point(x: &[f64], atom: usize) -> [f64; 3] {
+    [x[3 * atom], x[3 * atom + 1], x[3 * atom + 2]]
+}
+
+fn difference(a: [f64; 3], b: [f64; 3]) -> [f64; 3] {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+fn cross(a: [f64; 3], b: [f64; 3]) -> [f64; 3] {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
+
+fn dot(a: [f64; 3], b: [f64; 3]) -> f64 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+/// Signed volume spanned by the last three atoms, relative to the first.
+fn volume(x: &[f64], atoms: &[usize; 4]) -> f64 {
+    let origin = point(x, atoms[0]);
+    let [a, b, c] = [1, 2, 3].map(|index| difference(point(x, atoms[index]), origin));
+    dot(a, cross(b, c))
+}
+
+/// Add the gradient of the signed volume, times `factor`, to `gradient`.
+fn volume_gradient(x: &[f64], atoms: &[usize; 4], factor: f64, gradient: &mut [f64]) {
+    let origin = point(x, atoms[0]);
+    let [a, b, c] = [1, 2, 3].map(|index| difference(point(x, atoms[index]), origin));
+    let partials = [cross(b, c), cross(c, a), cross(a, b)];
+    for (atom, partial) in atoms[1..].iter().zip(&partials) {
+        for k in 0..3 {
+            gradient[3 * atom + k] += factor * partial[k];
+            gradient[3 * atoms[0] + k] -= factor * partial[k];
+        }
+    }
+}
+
+/// A small deterministic random number generator (SplitMix64).
+struct Random(u64);
+
+impl Random {
+    fn next_f64(&mut self) -> f64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^= z >> 31;
+        (z >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+/// Embed random distances between the bounds in 3D, as flat coordinates.
+fn embed_distances(bounds: &Bounds, random: &mut Random) -> Vec<f64> {
+    let n = bounds.count;
+    let mut squared = vec![0.0; n * n];
+    for i in 0..n {
+        for j in (i + 1)..n {
+            let (lower, upper) = (bounds.lower(i, j), bounds.upper(i, j));
+            let distance = lower + random.next_f64() * (upper - lower);
+            squared[i * n + j] = distance * distance;
+            squared[j * n + i] = distance * distance;
+        }
+    }
+
+    // Metric matrix of the distances from the centroid.
+    let total: f64 = squared.iter().sum::<f64>() / (2.0 * (n * n) as f64);
+    let from_centroid: Vec<f64> = (0..n)
+        .map(|i| squared[i * n..(i + 1) * n].iter().sum::<f64>() / n as f64 - total)
+        .collect();
+    let mut metric = vec![0.0; n * n];
+    for i in 0..n {
+        for j in 0..n {
+            metric[i * n + j] = (from_centroid[i] + from_centroid[j] - squared[i * n + j]) / 2.0;
+        }
+    }
+    // Shift the spectrum so that the largest eigenvalues also have the largest magnitude.
+    let shift = (0..n)
+        .map(|i| {
+            let off_diagonal: f64 = (0..n)
+                .filter(|&j| j != i)
+                .map(|j| metric[i * n + j].abs())
+                .sum();
+            off_diagonal - metric[i * n + i]
+        })
+        .fold(0.0f64, f64::max);
+
+    // Subspace iteration for the three largest eigenvectors.
+    let mut vectors: Vec<Vec<f64>> = (0..3)
+        .map(|_| (0..n).map(|_| random.next_f64() - 0.5).collect())
+        .collect();
+    let multiply = |vector: &[f64]| -> Vec<f64> {
+        (0..n)
+            .map(|i| {
+                let row = &metric[i * n..(i + 1) * n];
+                row.iter().zip(vector).map(|(m, v)| m * v).sum::<f64>() + shift * vector[i]
+            })
+            .collect()
+    };
+    for _ in 0..100 {
+        vectors = vectors.iter().map(|vector| multiply(vector)).collect();
+        for k in 0..3 {
+            for l in 0..k {
+                let projection: f64 = vectors[k].iter().zip(&vectors[l]).map(|(a, b)| a * b).sum();
+                let previous = vectors[l].clone();
+                for (value, other) in vectors[k].iter_mut().zip(previous) {
+                    *value -= projection * other;
+                }
+            }
+            let norm = vectors[k].iter().map(|v| v * v).sum::<f64>().sqrt();
+            if norm > 1e-12 {
+                vectors[k].iter_mut().for_each(|v| *v /= norm);
+            }
+        }
+    }
+
+    let mut x = vec![0.0; 3 * n];
+    for (k, vector) in vectors.iter().enumerate() {
+        let product = multiply(vector);
+        let eigenvalue = vector.iter().zip(&product).map(|(a, b)| a * b).sum::<f64>() - shift;
+        for i in 0..n {
+            x[3 * i + k] = if eigenvalue > 1e-6 {
+                vector[i] * eigenvalue.sqrt()
+            } else {
+                random.next_f64() - 0.5
+            };
+        }
+    }
+    x
+}
+
+/// Compute 3D coordinates for all atoms of a graph by distance geometry.
+///
+/// Embedding is repeated a few times if the bounds can't be met, keeping the best conformer. The
+/// same `seed` always gives the same conformer. Fails if no conformer with the tetrahedral
+/// chirality of the graph was found, which can happen for strained ring systems.
+pub fn embed(graph: &MoleculeGraph, seed: u64) -> Result<Conformer, EmbedError> {
+    let count = graph.node_count();
+    if count == 0 {
+        return Ok(Conformer {
+            positions: Vec::new(),
+        });
+    }
+    let geometry = Geometry::new(graph);
+    let mut random = Random(seed);
+
+    // The conformer with the lowest energy of the attempts with the right chirality.
+    let mut best: Option<(f64, Vec<f64>)> = None;
+    for _ in 0..ATTEMPTS {
+        let mut x = embed_distances(&geometry.bounds, &mut random);
+        let wrong = geometry
+            .chiral
+            .iter()
+            .filter(|(atoms, sign)| sign * volume(&x, atoms) < 0.0)
+            .count();
+        if 2 * wrong > geometry.chiral.len() {
+            for i in 0..count {
+                x[3 * i + 2] = -x[3 * i + 2];
+            }
+        }
+        // Planarity is only enforced once the atoms are roughly in place, as it keeps atoms from
+        // passing through rings.
+        for planarity in [false, true] {
+            minimize(&mut x, 1000, 1e-4, 0.5, |x, gradient| {
+                geometry.energy(x, gradient, planarity)
+            });
+        }
+        if !geometry.chirality_is_correct(&x) {
+            continue;
+        }
+        let energy = geometry.energy(&x, &mut vec![0.0; x.len()], true);
+        if best
+            .as_ref()
+            .is_none_or(|(best_energy, _)| energy < *best_energy)
+        {
+            best = Some((energy, x));
+        }
+        if energy < MAX_ENERGY_PER_ATOM * count as f64 {
+            break;
+        }
+    }
+
+    let (_, x) = best.ok_or(EmbedError::StereoMismatch)?;
+    let mut center = [0.0; 3];
+    for i in 0..count {
+        for k in 0..3 {
+            center[k] += x[3 * i + k] / count as f64;
+        }
+    }
+    let positions = (0..count)
+        .map(|i| [0, 1, 2].map(|k| x[3 * i + k] - center[k]))
+        .collect();
+    Ok(Conformer { positions })
+}
+
+fn symbol(graph: &MoleculeGraph, atom: NodeIndex) -> &'static str {
+    graph[atom]
+        .element()
+        .map_or("X", |element| element.get_symbol())
+}
+
+/// Write a conformer as an XYZ file: the number of atoms, the title and a line with the symbol
+/// and coordinates of every atom.
+pub fn write_xyz(graph: &MoleculeGraph, conformer: &Conformer, title: &str) -> String {
+    let title: String = title.chars().filter(|c| *c != '\n' && *c != '\r').collect();
+    let mut out = format!("{}\n{}\n", graph.node_count(), title);
+    for atom in graph.node_indices() {
+        let [x, y, z] = conformer.position(atom);
+        writeln!(
+            out,
+            "{:<2} {:>12.6} {:>12.6} {:>12.6}",
+            symbol(graph, atom),
+            x,
+            y,
+            z
+        )
+        .unwrap();
+    }
+    out
+}
+
+/// Write a conformer as a PDB file with `HETATM` records of a single residue named `UNL`, and
+/// `CONECT` records for the bonds.
+///
+/// Atoms are named by their symbol and a number counting the atoms of each element.
+pub fn write_pdb(graph: &MoleculeGraph, conformer: &Conformer, title: &str) -> String {
+    let mut out = String::new();
+    let title: String = title
+        .chars()
+        .filter(|c| *c != '\n' && *c != '\r')
+        .take(70)
+        .collect();
+    if !title.is_empty() {
+        writeln!(out, "COMPND    {}", title).unwrap();
+    }
+
+    let mut counts: HashMap<&str, usize> = HashMap::new();
+    for atom in graph.node_indices() {
+        let symbol = symbol(graph, atom);
+        let number = counts.entry(symbol).or_insert(0);
+        *number += 1;
+        let name = format!("{}{}", symbol.to_uppercase(), number);
+        // Names of atoms with one letter symbols start in the second column of the name field.
+        let name = if symbol.len() == 1 && name.len() < 4 {
+            format!(" {:<3}", name)
+        } else {
+            format!("{:<4}", name)
+        };
+        let charge = match graph[atom].charge() {
+            0 => String::new(),
+            charge if charge > 0 => format!("{}+", charge),
+            charge => format!("{}-", -charge),
+        };
+        let [x, y, z] = conformer.position(atom);
+        writeln!(
+            out,
+            "HETATM{:>5} {} UNL     1    {:>8.3}{:>8.3}{:>8.3}  1.00  0.00          {:>2}{:<2}",
+            atom.index() + 1,
+            name,
+            x,
+            y,
+            z,
+            symbol.to_uppercase(),
+            charge
+        )
+        .unwrap();
+    }
+
+    for atom in graph.node_indices() {
+        let mut neighbors: Vec<usize> = graph
+            .neighbors(atom)
+            .map(|neighbor| neighbor.index() + 1)
+            .collect();
+        neighbors.sort_unstable();
+        for chunk in neighbors.chunks(4) {
+            write!(out, "CONECT{:>5}", atom.index() + 1).unwrap();
+            for neighbor in chunk {
+                write!(out, "{:>5}", neighbor).unwrap();
+            }
+            out.push('\n');
+        }
+    }
+    out.push_str("END\n");
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graph::molecule;
+
+    fn conformer(smiles: &str) -> (MoleculeGraph, Conformer) {
+        let graph = molecule(smiles);
+        let conformer = embed(&graph, 42).unwrap();
+        (graph, conformer)
+    }
+
+    fn distance(conformer: &Conformer, a: usize, b: usize) -> f64 {
+        let delta = difference(conformer.positions[a], conformer.positions[b]);
+        dot(delta, delta).sqrt()
+    }
+
+    #[test]
+    fn bond_lengths() {
+        for smiles in &[
+            "CC",
+            "C=CC#N",
+            "c1ccccc1O",
+            "CC(=O)Oc1ccccc1C(=O)O",
+            "Cn1cnc2c1c(=O)n(C)c(=O)n2C",
+            "C1CC2CCC1CC2",
+        ] {
+            let (graph, conformer) = conformer(smiles);
+            for edge in graph.edge_references() {
+                let (a, b) = (edge.source(), edge.target());
+                let expected = bond_length(&graph, a, b, *edge.weight());
+                let actual = distance(&conformer, a.index(), b.index());
+                assert!(
+                    (expected - actual).abs() < 0.1,
+                    "{}: bond {}-{} of length {} instead of {}",
+                    smiles,
+                    a.index(),
+                    b.index(),
+                    actual,
+                    expected
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn aromatic_rings_are_planar() {
+        for smiles in &["c1ccccc1", "c1ccc2ccccc2c1", "c1ccc2[nH]ccc2c1"] {
+            let (graph, conformer) = conformer(smiles);
+            let p = &conformer.positions;
+            let normal = cross(difference(p[2], p[0]), difference(p[4], p[0]));
+            let normal_length = dot(normal, normal).sqrt();
+            for atom in graph.node_indices() {
+                let height = dot(difference(p[atom.index()], p[0]), normal) / normal_length;
+                assert!(
+                    height.abs() < 0.1,
+                    "{}: atom {:?} out of plane",
+                    smiles,
+                    atom
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn chirality() {
+        for smiles in &["N[C@@H](C)C(=O)O", "N[C@H](C)C(=O)O", "F[C@](Cl)(Br)I"] {
+            let (graph, conformer) = conformer(smiles);
+            let geometry = Geometry::new(&graph);
+            let x: Vec<f64> = conformer.positions.iter().flatten().copied().collect();
+            assert!(geometry.chirality_is_correct(&x), "{}", smiles);
+        }
+
+        // The enantiomers are mirror images: the signed volume of the heavy neighbors of the
+        // stereo center has opposite signs.
+        let volumes: Vec<f64> = ["N[C@@H](C)C(=O)O", "N[C@H](C)C(=O)O"]
+            .iter()
+            .map(|smiles| {
+                let (_, conformer) = conformer(smiles);
+                let x: Vec<f64> = conformer.positions.iter().flatten().copied().collect();
+                volume(&x, &[1, 0, 2, 3])
+            })
+            .collect();
+        assert!(volumes[0] * volumes[1] < 0.0);
+    }
+
+    #[test]
+    fn double_bond_stereo() {
+        let (_, trans) = conformer("F/C=C/F");
+        let (_, cis) = conformer("F/C=C\\F");
+        assert!(distance(&trans, 0, 3) > 3.3);
+        assert!(distance(&cis, 0, 3) < 3.0);
+    }
+
+    #[test]
+    fn deterministic() {
+        let graph = molecule("CC(C)Cc1ccc(cc1)C(C)C(=O)O");
+        assert_eq!(embed(&graph, 7), embed(&graph, 7));
+    }
+
+    #[test]
+    fn disconnected() {
+        let (graph, conformer) = conformer("[Na+].[Cl-]");
+        assert_eq!(2, graph.node_count());
+        assert!(distance(&conformer, 0, 1) < 10.0);
+    }
+
+    #[test]
+    fn xyz() {
+        let graph = molecule("O");
+        let conformer = Conformer {
+            positions: vec![[0.0, 0.0, 0.0], [0.9572, 0.0, 0.0], [-0.24, 0.9266, 0.0]],
+        };
+        assert_eq!(
+            "3
+water
+O      0.000000     0.000000     0.000000
+H      0.957200     0.000000     0.000000
+H     -0.240000     0.926600     0.000000
+",
+            write_xyz(&graph, &conformer, "water")
+        );
+    }
+
+    #[test]
+    fn pdb() {
+        let graph = molecule("C[NH3+]");
+        let conformer = embed(&graph, 1).unwrap();
+        let pdb = write_pdb(&graph, &conformer, "methylammonium");
+        let lines: Vec<&str> = pdb.lines().collect();
+        assert_eq!("COMPND    methylammonium", lines[0]);
+        assert_eq!(8, pdb.matches("HETATM").count());
+        assert!(lines[1].starts_with("HETATM    1  C1  UNL     1    "));
+        assert!(lines[2].starts_with("HETATM    2  N1  UNL     1    "));
+        assert!(lines[2].ends_with("           N1+"));
+        assert!(lines[3].starts_with("HETATM    3  H1  UNL     1    "));
+        assert!(lines.iter().all(|line| line.len() <= 80));
+        assert!(pdb.contains("\nCONECT    1    2    3    4    5\n"));
+        assert!(pdb.ends_with("END\n"));
+    }
+}
@@ -0,0 +1,856 @@
+//! 2D coordinate generation for depictions and Molfile export.
+//!
+//! Ring systems are laid out first, each on its own: the largest ring as a regular polygon, then
+//! every ring that shares atoms with the rings placed so far, with its remaining atoms on a
+//! circular arc between the shared atoms. For fused and spiro rings this arc completes a regular
+//! polygon. Starting from the largest ring system (or from one end of the longest chain), the
+//! neighbors of every placed atom are then spread evenly around it, so that chains become
+//! zig-zags, and ring systems are attached as a whole. Finally, overlapping atoms are moved apart
+//! by reflecting substituents across the acyclic bonds they are attached with.
+
+use std::collections::{HashMap, VecDeque};
+use std::f64::consts::PI;
+
+use petgraph::graph::NodeIndex;
+use petgraph::visit::EdgeRef;
+
+use crate::graph::MoleculeGraph;
+use crate::Bond;
+
+/// Length of a bond in the computed coordinates.
+pub const BOND_LENGTH: f64 = 1.5;
+
+/// Non-bonded atoms closer than this are considered overlapping.
+const CLASH_DISTANCE: f64 = 0.75 * BOND_LENGTH;
+
+type Point = [f64; 2];
+
+fn add(a: Point, b: Point) -> Point {
+    [a[0] + b[0], a[1] + b[1]]
+}
+
+fn sub(a: Point, b: Point) -> Point {
+    [a[0] - b[0], a[1] - b[1]]
+}
+
+fn scale(a: Point, factor: f64) -> Point {
+    [a[0] * factor, a[1] * factor]
+}
+
+fn length(a: Point) -> f64 {
+    a[0].hypot(a[1])
+}
+
+fn distance(a: Point, b: Point) -> f64 {
+    length(sub(a, b))
+}
+
+fn angle(a: Point) -> f64 {
+    a[1].atan2(a[0])
+}
+
+fn polar(angle: f64, radius: f64) -> Point {
+    [radius * angle.cos(), radius * angle.sin()]
+}
+
+fn rotate(a: Point, angle: f64) -> Point {
+    let (sin, cos) = angle.sin_cos();
+    [a[0] * cos - a[1] * sin, a[0] * sin + a[1] * cos]
+}
+
+fn centroid<'a>(points: impl IntoIterator<Item = &'a Point>) -> Option<Point> {
+    let mut sum = [0.0, 0.0];
+    let mut count = 0;
+    for point in points {
+        sum = add(sum, *point);
+        count += 1;
+    }
+    if count == 0 {
+        None
+    } else {
+        Some(scale(sum, 1.0 / count as f64))
+    }
+}
+
+/// Smallest distance between any of the given points and the placed points, ignoring `except`.
+fn clearance(points: &[Point], placed: &HashMap<NodeIndex, Point>, except: &[NodeIndex]) -> f64 {
+    placed
+        .iter()
+        .filter(|(atom, _)| !except.contains(atom))
+        .flat_map(|(_, other)| points.iter().map(move |point| distance(*point, *other)))
+        .fold(f64::INFINITY, f64::min)
+}
+
+impl MoleculeGraph {
+    /// Compute 2D coordinates for all atoms and store them, replacing previous coordinates.
+    ///
+    /// Bonds are [`BOND_LENGTH`] long, apart from bonds in bridged ring systems. The connected
+    /// components of the graph are placed next to each other from left to right.
+    pub fn compute_2d_coords(&mut self) {
+        let coordinates = Layout::new(self).run();
+        self.set_coordinates_2d(coordinates);
+    }
+}
+
+struct Layout<'a> {
+    graph: &'a MoleculeGraph,
+    /// Neighbors of every atom that are not attached hydrogens.
+    neighbors: Vec<Vec<NodeIndex>>,
+    /// Ring systems as their rings, each in ring order.
+    systems: Vec<Vec<Vec<NodeIndex>>>,
+    system_of: Vec<Option<usize>>,
+    ring_bonds: Vec<bool>,
+    positions: Vec<Option<Point>>,
+}
+
+impl<'a> Layout<'a> {
+    fn new(graph: &'a MoleculeGraph) -> Self {
+        let neighbors = graph
+            .node_indices()
+            .map(|node| {
+                let mut neighbors: Vec<NodeIndex> = graph
+                    .neighbors(node)
+                    .filter(|&neighbor| !graph.is_attached_hydrogen(neighbor))
+                    .collect();
+                neighbors.sort_unstable();
+                neighbors
+            })
+            .collect();
+
+        // Rings that share an atom belong to the same ring system.
+        let rings = graph.rings();
+        let mut system_of: Vec<Option<usize>> = vec![None; graph.node_count()];
+        let mut systems: Vec<Vec<Vec<NodeIndex>>> = Vec::new();
+        for ring in rings {
+            let mut merged: Vec<usize> = ring
+                .iter()
+                .filter_map(|atom| system_of[atom.index()])
+                .collect();
+            merged.sort_unstable();
+            merged.dedup();
+            let target = match merged.first() {
+                Some(&target) => target,
+                None => {
+                    systems.push(Vec::new());
+                    systems.len() - 1
+                }
+            };
+            for &other in merged.iter().skip(1).rev() {
+                let rings = std::mem::take(&mut systems[other]);
+                systems[target].extend(rings);
+            }
+            systems[target].push(ring);
+            for ring in &systems[target] {
+                for atom in ring {
+                    system_of[atom.index()] = Some(target);
+                }
+            }
+        }
+
+        Layout {
+            graph,
+            neighbors,
+            systems,
+            system_of,
+            ring_bonds: graph.ring_bonds(),
+            positions: vec![None; graph.node_count()],
+        }
+    }
+
+    fn run(mut self) -> Vec<Point> {
+        let mut offset = 0.0;
+        for component in self.components() {
+            self.place_component(&component);
+            self.resolve_overlaps(&component);
+
+            let xs = component.iter().map(|atom| self.position(*atom)[0]);
+            let min_x = xs.clone().fold(f64::INFINITY, f64::min);
+            let max_x = xs.fold(f64::NEG_INFINITY, f64::max);
+            let center = centroid(
+                &component
+                    .iter()
+                    .map(|atom| self.position(*atom))
+                    .collect::<Vec<_>>(),
+            )
+            .unwrap();
+            let shift = [offset - min_x, -center[1]];
+            for &atom in &component {
+                self.positions[atom.index()] = Some(add(self.position(atom), shift));
+            }
+            offset += max_x - min_x + 1.5 * BOND_LENGTH;
+        }
+        self.place_hydrogens();
+
+        self.positions
+            .into_iter()
+            .map(|position| position.unwrap_or([0.0, 0.0]))
+            .collect()
+    }
+
+    fn position(&self, atom: NodeIndex) -> Point {
+        self.positions[atom.index()].unwrap()
+    }
+
+    /// Connected components of the atoms that are not attached hydrogens.
+    fn components(&self) -> Vec<Vec<NodeIndex>> {
+        let mut seen = vec![false; self.graph.node_count()];
+        let mut components = Vec::new();
+        for start in self.graph.node_indices() {
+            if seen[start.index()] || self.graph.is_attached_hydrogen(start) {
+                continue;
+            }
+            seen[start.index()] = true;
+            let mut component = vec![start];
+            let mut next = 0;
+            while next < component.len() {
+                for &neighbor in &self.neighbors[component[next].index()] {
+                    if !seen[neighbor.index()] {
+                        seen[neighbor.index()] = true;
+                        component.push(neighbor);
+                    }
+                }
+                next += 1;
+            }
+            component.sort_unstable();
+            components.push(component);
+        }
+        components
+    }
+
+    /// Coordinates of the atoms of a ring system, relative to each other.
+    fn layout_ring_system(&self, system: usize) -> HashMap<NodeIndex, Point> {
+        let rings = &self.systems[system];
+        let mut placed: HashMap<NodeIndex, Point> = HashMap::new();
+        let mut done = vec![false; rings.len()];
+
+        let first = (0..rings.len())
+            .max_by_key(|&index| (rings[index].len(), std::cmp::Reverse(index)))
+            .unwrap();
+        let size = rings[first].len();
+        let radius = BOND_LENGTH / (2.0 * (PI / size as f64).sin());
+        for (index, &atom) in rings[first].iter().enumerate() {
+            let angle = -PI / 2.0 - PI / size as f64 + 2.0 * PI * index as f64 / size as f64;
+            placed.insert(atom, polar(angle, radius));
+        }
+        done[first] = true;
+
+        while let Some(next) = (0..rings.len())
+            .filter(|&index| !done[index])
+            .max_by_key(|&index| {
+                let shared = rings[index]
+                    .iter()
+                    .filter(|atom| placed.contains_key(atom))
+                    .count();
+                (shared, std::cmp::Reverse(index))
+            })
+        {
+            done[next] = true;
+            let ring = &rings[next];
+            let size = ring.len();
+            let start = match (0..size).find(|&index| placed.contains_key(&ring[index])) {
+                Some(start) => start,
+                None => continue,
+            };
+            // Every run of unplaced atoms lies on an arc between the placed atoms around it.
+            let mut index = start;
+            for _ in 0..size {
+                let following = (index + 1) % size;
+                if placed.contains_key(&ring[index]) && !placed.contains_key(&ring[following]) {
+                    let mut run = Vec::new();
+                    let mut end = following;
+                    while !placed.contains_key(&ring[end]) {
+                        run.push(ring[end]);
+                        end = (end + 1) % size;
+                    }
+                    let points = self.arc(&placed, system, ring[index], ring[end], run.len());
+                    for (atom, point) in run.into_iter().zip(points) {
+                        placed.insert(atom, point);
+                    }
+                }
+                index = following;
+            }
+        }
+        placed
+    }
+
+    /// Points for `count` atoms on a circular arc from `first` to `last`, spaced one bond length
+    /// apart where possible, on the side with the most room.
+    fn arc(
+        &self,
+        placed: &HashMap<NodeIndex, Point>,
+        system: usize,
+        first: NodeIndex,
+        last: NodeIndex,
+        count: usize,
+    ) -> Vec<Point> {
+        let (a, b) = (placed[&first], placed[&last]);
+        let segments = (count + 1) as f64;
+        let chord = distance(a, b);
+
+        if chord < 1e-6 {
+            // A spiro ring: a regular polygon pointing away from the placed neighbors.
+            let neighbors: Vec<Point> = self.neighbors[first.index()]
+                .iter()
+                .filter(|neighbor| self.system_of[neighbor.index()] == Some(system))
+                .filter_map(|neighbor| placed.get(neighbor).copied())
+                .collect();
+            let outward = match centroid(&neighbors) {
+                Some(center) if distance(a, center) > 1e-6 => sub(a, center),
+                _ => [1.0, 0.0],
+            };
+            let step = 2.0 * PI / segments;
+            let radius = BOND_LENGTH / (2.0 * (step / 2.0).sin());
+            let base = angle(outward) + PI;
+            let center = add(a, polar(angle(outward), radius));
+            return (1..=count)
+                .map(|k| add(center, polar(base + k as f64 * step, radius)))
+                .collect();
+        }
+
+        let ratio = chord / BOND_LENGTH;
+        if ratio >= segments - 1e-9 {
+            return (1..=count)
+                .map(|k| add(a, scale(sub(b, a), k as f64 / segments)))
+                .collect();
+        }
+        // Angle per segment such that `segments` chords of one bond length span the chord
+        // between the end points.
+        let (mut low, mut high) = (0.0, 2.0 * PI / segments);
+        for _ in 0..60 {
+            let middle = (low + high) / 2.0;
+            let spanned = (segments * middle / 2.0).sin() / (middle / 2.0).sin();
+            if spanned > ratio {
+                low = middle;
+            } else {
+                high = middle;
+            }
+        }
+        let step = (low + high) / 2.0;
+        let radius = BOND_LENGTH / (2.0 * (step / 2.0).sin());
+        let total = segments * step;
+        let middle = scale(add(a, b), 0.5);
+        let normal = rotate(scale(sub(b, a), 1.0 / chord), PI / 2.0);
+
+        let mut best: Option<(f64, Vec<Point>)> = None;
+        for &side in &[1.0, -1.0] {
+            let center = sub(middle, scale(normal, side * radius * (total / 2.0).cos()));
+            let start = angle(sub(a, center));
+            for &direction in &[1.0, -1.0] {
+                let end = add(center, polar(start + direction * total, radius));
+                if distance(end, b) > 1e-3 * BOND_LENGTH {
+                    continue;
+                }
+                let points: Vec<Point> = (1..=count)
+                    .map(|k| add(center, polar(start + direction * k as f64 * step, radius)))
+                    .collect();
+                let room = clearance(&points, placed, &[first, last]);
+                if best
+                    .as_ref()
+                    .is_none_or(|(best_room, _)| room > *best_room + 1e-9)
+                {
+                    best = Some((room, points));
+                }
+            }
+        }
+        best.map(|(_, points)| points).unwrap_or_default()
+    }
+
+    fn is_linear(&self, atom: NodeIndex) -> bool {
+        let mut doubles = 0;
+        for edge in self.graph.edges(atom) {
+            match edge.weight() {
+                Bond::Triple | Bond::Quadruple => return true,
+                Bond::Double => doubles += 1,
+                _ => {}
+            }
+        }
+        doubles >= 2
+    }
+
+    /// Directions for `count` new neighbors of an atom with neighbors in the given directions.
+    ///
+    /// A single new neighbor after a single placed one continues a zig-zag, turning to the given
+    /// side. Otherwise the new neighbors are spread evenly over the largest free angle.
+    fn new_directions(placed: &[f64], count: usize, turn: f64, linear: bool) -> Vec<f64> {
+        match placed {
+            [] => (0..count)
+                .map(|index| PI / 6.0 + 2.0 * PI * index as f64 / count as f64)
+                .collect(),
+            [single] if count == 1 && linear => vec![single + PI],
+            [single] if count == 1 => vec![single + turn * 2.0 * PI / 3.0],
+            [single] => (1..=count)
+                .map(|index| single + 2.0 * PI * index as f64 / (count + 1) as f64)
+                .collect(),
+            _ => {
+                let mut sorted = placed.to_vec();
+                sorted.sort_by(|a, b| a.partial_cmp(b).unwrap());
+                let (start, gap) = (0..sorted.len())
+                    .map(|index| {
+                        let next = match sorted.get(index + 1) {
+                            Some(&next) => next,
+                            None => sorted[0] + 2.0 * PI,
+                        };
+                        (sorted[index], next - sorted[index])
+                    })
+                    .fold((0.0, f64::NEG_INFINITY), |best, candidate| {
+                        if candidate.1 > best.1 + 1e-9 {
+                            candidate
+                        } else {
+                            best
+                        }
+                    });
+                (1..=count)
+                    .map(|index| start + gap * index as f64 / (count + 1) as f64)
+                    .collect()
+            }
+        }
+    }
+
+    fn place_component(&mut self, component: &[NodeIndex]) {
+        let mut turns = vec![1.0; self.graph.node_count()];
+        let mut queue = VecDeque::new();
+
+        let largest_system = component
+            .iter()
+            .filter_map(|atom| self.system_of[atom.index()])
+            .max_by_key(|&system| {
+                let size: usize = self.systems[system].iter().map(Vec::len).sum();
+                (size, std::cmp::Reverse(system))
+            });
+        match largest_system {
+            Some(system) => {
+                let mut atoms: Vec<(NodeIndex, Point)> =
+                    self.layout_ring_system(system).into_iter().collect();
+                atoms.sort_by_key(|(atom, _)| *atom);
+                for (atom, point) in atoms {
+                    self.positions[atom.index()] = Some(point);
+                    queue.push_back(atom);
+                }
+            }
+            None => {
+                let start = self.chain_end(component);
+                self.positions[start.index()] = Some([0.0, 0.0]);
+                turns[start.index()] = -1.0;
+                queue.push_back(start);
+            }
+        }
+
+        while let Some(atom) = queue.pop_front() {
+            let center = self.position(atom);
+            let (placed, new): (Vec<NodeIndex>, Vec<NodeIndex>) = self.neighbors[atom.index()]
+                .iter()
+                .partition(|neighbor| self.positions[neighbor.index()].is_some());
+            if new.is_empty() {
+                continue;
+            }
+            let placed_directions: Vec<f64> = placed
+                .iter()
+                .map(|&neighbor| angle(sub(self.position(neighbor), center)))
+                .collect();
+            let directions = Self::new_directions(
+                &placed_directions,
+                new.len(),
+                turns[atom.index()],
+                self.is_linear(atom),
+            );
+
+            for (neighbor, direction) in new.into_iter().zip(directions) {
+                if self.positions[neighbor.index()].is_some() {
+                    // Placed in the meantime as part of a ring system.
+                    continue;
+                }
+                let turn = match placed_directions.first() {
+                    Some(&previous) if (direction - previous).sin() < 0.0 => 1.0,
+                    Some(_) => -1.0,
+                    None => 1.0,
+                };
+                let target = add(center, polar(direction, BOND_LENGTH));
+                match self.system_of[neighbor.index()] {
+                    Some(system) => {
+                        for placed in self.attach_ring_system(system, neighbor, target, direction) {
+                            queue.push_back(placed);
+                        }
+                    }
+                    None => {
+                        self.positions[neighbor.index()] = Some(target);
+                        turns[neighbor.index()] = turn;
+                        queue.push_back(neighbor);
+                    }
+                }
+            }
+        }
+    }
+
+    /// One end of a longest path through an acyclic component.
+    fn chain_end(&self, component: &[NodeIndex]) -> NodeIndex {
+        let mut distances = vec![usize::MAX; self.graph.node_count()];
+        distances[component[0].index()] = 0;
+        let mut queue = VecDeque::from(vec![component[0]]);
+        let mut farthest = component[0];
+        while let Some(atom) = queue.pop_front() {
+            if distances[atom.index()] > distances[farthest.index()] {
+                farthest = atom;
+            }
+            for &neighbor in &self.neighbors[atom.index()] {
+                if distances[neighbor.index()] == usize::MAX {
+                    distances[neighbor.index()] = distances[atom.index()] + 1;
+                    queue.push_back(neighbor);
+                }
+            }
+        }
+        farthest
+    }
+
+    /// Place a ring system such that `atom` is at `target` and the system extends away from the
+    /// atom it is attached to, in `direction`. Returns the atoms that were placed.
+    fn attach_ring_system(
+        &mut self,
+        system: usize,
+        atom: NodeIndex,
+        target: Point,
+        direction: f64,
+    ) -> Vec<NodeIndex> {
+        let local = self.layout_ring_system(system);
+        let ring_neighbors: Vec<Point> = self.neighbors[atom.index()]
+            .iter()
+            .filter_map(|neighbor| local.get(neighbor).copied())
+            .collect();
+        let outward = match centroid(&ring_neighbors) {
+            Some(center) if distance(local[&atom], center) > 1e-6 => sub(local[&atom], center),
+            _ => [1.0, 0.0],
+        };
+
+        let placed: HashMap<NodeIndex, Point> = self
+            .positions
+            .iter()
+            .enumerate()
+            .filter_map(|(index, position)| position.map(|point| (NodeIndex::new(index), point)))
+            .collect();
+        let mut atoms: Vec<NodeIndex> = local.keys().copied().collect();
+        atoms.sort_unstable();
+
+        // The outward direction of the attached atom points back to where it is attached, the
+        // mirror image is used if it has more room.
+        let mut best: Option<(f64, Vec<Point>)> = None;
+        for &mirror in &[1.0, -1.0] {
+            let outward = [outward[0], mirror * outward[1]];
+            let rotation = direction + PI - angle(outward);
+            let points: Vec<Point> = atoms
+                .iter()
+                .map(|other| {
+                    let relative = sub(local[other], local[&atom]);
+                    add(
+                        target,
+                        rotate([relative[0], mirror * relative[1]], rotation),
+                    )
+                })
+                .collect();
+            let room = clearance(&points, &placed, &[]);
+            if best
+                .as_ref()
+                .is_none_or(|(best_room, _)| room > *best_room + 1e-9)
+            {
+                best = Some((room, points));
+            }
+        }
+        for (other, point) in atoms.iter().zip(best.unwrap().1) {
+            self.positions[other.index()] = Some(point);
+        }
+        atoms
+    }
+
+    /// Penalty of the overlaps between two sets of atoms.
+    fn overlap(&self, first: &[NodeIndex], second: &[NodeIndex], positions: &[Point]) -> f64 {
+        let mut penalty = 0.0;
+        for (index, &a) in first.iter().enumerate() {
+            for &b in second {
+                if a == b || self.neighbors[a.index()].contains(&b) {
+                    continue;
+                }
+                let gap = CLASH_DISTANCE - distance(positions[index], self.position(b));
+                if gap > 0.0 {
+                    penalty += gap * gap;
+                }
+            }
+        }
+        penalty
+    }
+
+    /// Move substituents attached with acyclic bonds while that reduces overlaps.
+    ///
+    /// Reflections across the bond keep all bond angles and are tried first. Rotations around the
+    /// atom the substituent is attached to bend the bond angle, but also resolve overlaps between
+    /// symmetric substituents.
+    fn resolve_overlaps(&mut self, component: &[NodeIndex]) {
+        let bonds: Vec<(NodeIndex, NodeIndex)> = self
+            .graph
+            .edge_references()
+            .filter(|edge| !self.ring_bonds[edge.id().index()])
+            .map(|edge| (edge.source(), edge.target()))
+            .filter(|(a, b)| {
+                component.binary_search(a).is_ok() && component.binary_search(b).is_ok()
+            })
+            .collect();
+
+        self.improve(component, &bonds, |pivot, start, points| {
+            let axis = sub(start, pivot);
+            let axis = scale(axis, 1.0 / length(axis));
+            let reflected = points
+                .iter()
+                .map(|&point| {
+                    let relative = sub(point, pivot);
+                    let along = scale(axis, relative[0] * axis[0] + relative[1] * axis[1]);
+                    add(pivot, sub(scale(along, 2.0), relative))
+                })
+                .collect();
+            vec![reflected]
+        });
+        self.improve(component, &bonds, |pivot, _, points| {
+            [PI / 9.0, -PI / 9.0, 2.0 * PI / 9.0, -2.0 * PI / 9.0]
+                .iter()
+                .map(|&rotation| {
+                    points
+                        .iter()
+                        .map(|&point| add(pivot, rotate(sub(point, pivot), rotation)))
+                        .collect()
+                })
+                .collect()
+        });
+    }
+
+    /// Move the atoms on either side of the given bonds to the best of the candidate positions
+    /// returned by `moves` for the bond's atoms and the current positions, while that reduces
+    /// overlaps. Only the smaller side of a bond is moved.
+    fn improve<F>(&mut self, component: &[NodeIndex], bonds: &[(NodeIndex, NodeIndex)], moves: F)
+    where
+        F: Fn(Point, Point, &[Point]) -> Vec<Vec<Point>>,
+    {
+        for _ in 0..5 {
+            let mut improved = false;
+            for &(a, b) in bonds {
+                for &(pivot, start) in &[(a, b), (b, a)] {
+                    let side = self.side(start, pivot);
+                    if side.len() * 2 > component.len() {
+                        continue;
+                    }
+                    let rest: Vec<NodeIndex> = component
+                        .iter()
+                        .copied()
+                        .filter(|atom| side.binary_search(atom).is_err())
+                        .collect();
+                    let current: Vec<Point> =
+                        side.iter().map(|&atom| self.position(atom)).collect();
+                    let penalty = self.overlap(&side, &rest, &current);
+                    if penalty == 0.0 {
+                        continue;
+                    }
+                    let best = moves(self.position(pivot), self.position(start), &current)
+                        .into_iter()
+                        .map(|points| (self.overlap(&side, &rest, &points), points))
+                        .min_by(|x, y| x.0.partial_cmp(&y.0).unwrap());
+                    if let Some((candidate, points)) = best {
+                        if candidate < penalty - 1e-9 {
+                            for (&atom, point) in side.iter().zip(points) {
+                                self.positions[atom.index()] = Some(point);
+                            }
+                            improved = true;
+                        }
+                    }
+                }
+            }
+            if !improved {
+                break;
+            }
+        }
+    }
+
+    /// Atoms on the side of `start` of the bond between `start` and `other`, sorted.
+    fn side(&self, start: NodeIndex, other: NodeIndex) -> Vec<NodeIndex> {
+        let mut seen = vec![false; self.graph.node_count()];
+        seen[start.index()] = true;
+        seen[other.index()] = true;
+        let mut side = vec![start];
+        let mut next = 0;
+        while next < side.len() {
+            for &neighbor in &self.neighbors[side[next].index()] {
+                if !seen[neighbor.index()] {
+                    seen[neighbor.index()] = true;
+                    side.push(neighbor);
+                }
+            }
+            next += 1;
+        }
+        side.sort_unstable();
+        side
+    }
+
+    /// Place attached hydrogens in the largest free angle around their atom.
+    fn place_hydrogens(&mut self) {
+        for atom in self.graph.node_indices() {
+            if self.graph.is_attached_hydrogen(atom) {
+                continue;
+            }
+            let hydrogens: Vec<NodeIndex> = self
+                .graph
+                .neighbors(atom)
+                .filter(|&neighbor| self.graph.is_attached_hydrogen(neighbor))
+                .collect();
+            if hydrogens.is_empty() {
+                continue;
+            }
+            let center = self.position(atom);
+            let placed: Vec<f64> = self.neighbors[atom.index()]
+                .iter()
+                .map(|&neighbor| angle(sub(self.position(neighbor), center)))
+                .collect();
+            let directions = Self::new_directions(&placed, hydrogens.len(), 1.0, false);
+            for (hydrogen, direction) in hydrogens.into_iter().zip(directions) {
+                self.positions[hydrogen.index()] = Some(add(center, polar(direction, BOND_LENGTH)));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graph::{assert_close, molecule};
+
+    fn compute(smiles: &str) -> (MoleculeGraph, Vec<Point>) {
+        let mut graph = molecule(smiles);
+        graph.compute_2d_coords();
+        let coordinates = graph
+            .node_indices()
+            .map(|node| graph.coordinates_2d(node).unwrap())
+            .collect();
+        (graph, coordinates)
+    }
+
+    fn between(coordinates: &[Point], a: usize, b: usize) -> f64 {
+        distance(coordinates[a], coordinates[b])
+    }
+
+    /// Check that all bonds have the same length and no other atoms overlap.
+    fn assert_clean(smiles: &str) {
+        let (graph, coordinates) = compute(smiles);
+        for edge in graph.edge_references() {
+            let length = between(&coordinates, edge.source().index(), edge.target().index());
+            assert!(
+                (length - BOND_LENGTH).abs() < 1e-6,
+                "{}: bond {:?} of length {}",
+                smiles,
+                (edge.source(), edge.target()),
+                length
+            );
+        }
+        for a in graph.node_indices() {
+            for b in graph.node_indices().filter(|&b| b > a) {
+                if graph.is_attached_hydrogen(a) || graph.is_attached_hydrogen(b) {
+                    continue;
+                }
+                let gap = between(&coordinates, a.index(), b.index());
+                assert!(
+                    gap > 0.6 * BOND_LENGTH,
+                    "{}: atoms {:?} and {:?} overlap",
+                    smiles,
+                    a,
+                    b
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn zig_zag_chain() {
+        let (_, coordinates) = compute("CCCCCC");
+        for atom in 0..4 {
+            assert_close(
+                3f64.sqrt() * BOND_LENGTH,
+                between(&coordinates, atom, atom + 2),
+                1e-6,
+            );
+        }
+        for atom in 0..3 {
+            assert_close(
+                7f64.sqrt() * BOND_LENGTH,
+                between(&coordinates, atom, atom + 3),
+                1e-6,
+            );
+        }
+        assert_clean("CCCCCCCCCCCC");
+        assert_clean("CC#CC");
+        assert_close(3.0 * BOND_LENGTH, between(&compute("CC#CC").1, 0, 3), 1e-6);
+    }
+
+    #[test]
+    fn regular_polygons() {
+        for size in 3..=8 {
+            let smiles = format!("C1{}1", "C".repeat(size - 1));
+            let (_, coordinates) = compute(&smiles);
+            let radius = BOND_LENGTH / (2.0 * (PI / size as f64).sin());
+            let center = centroid(&coordinates[..size]).unwrap();
+            for point in &coordinates[..size] {
+                assert_close(radius, distance(*point, center), 1e-6);
+            }
+        }
+        let (_, benzene) = compute("c1ccccc1");
+        assert_close(2.0 * BOND_LENGTH, between(&benzene, 0, 3), 1e-6);
+    }
+
+    #[test]
+    fn ring_systems() {
+        for smiles in &[
+            "c1ccc2ccccc2c1",
+            "c1ccc2cc3ccccc3cc2c1",
+            "c1ccc2c(c1)ccc1ccccc12",
+            "C1CCC2(CC1)CCCC2",
+            "c1ccc2[nH]ccc2c1",
+            "CC(C)CCCC(C)C1CCC2C1(CCC3C2CC=C4C3(CCC(C4)O)C)C",
+        ] {
+            assert_clean(smiles);
+        }
+    }
+
+    #[test]
+    fn substituents() {
+        for smiles in &[
+            "CC(=O)Oc1ccccc1C(=O)O",
+            "CC(C)Cc1ccc(cc1)C(C)C(=O)O",
+            "Cn1cnc2c1c(=O)n(C)c(=O)n2C",
+            "CC(C)(C)C(C)(C)C",
+            "c1ccccc1-c1ccccc1",
+            "OC(=O)c1ccccc1-c1ccccc1C(=O)O",
+            "C1CC1C1CC1",
+        ] {
+            assert_clean(smiles);
+        }
+    }
+
+    #[test]
+    fn bridged_ring_systems_are_placed() {
+        let (graph, coordinates) = compute("C1CC2CCC1CC2");
+        assert_eq!(graph.node_count(), coordinates.len());
+        assert!(coordinates
+            .iter()
+            .all(|p| p[0].is_finite() && p[1].is_finite()));
+    }
+
+    #[test]
+    fn components_are_side_by_side() {
+        let (_, coordinates) = compute("CCO.[Na+]");
+        let max_x = (0..3).map(|i| coordinates[i][0]).fold(f64::MIN, f64::max);
+        assert!(coordinates[3][0] >= max_x + BOND_LENGTH);
+    }
+
+    #[test]
+    fn hydrogens() {
+        let (graph, coordinates) = compute("C");
+        for hydrogen in graph.neighbors(NodeIndex::new(0)) {
+            assert_close(
+                BOND_LENGTH,
+                between(&coordinates, 0, hydrogen.index()),
+                1e-6,
+            );
+        }
+    }
+}
@@ -0,0 +1,312 @@
+//! Wildman–Crippen estimation of logP and molar refractivity (J. Chem. Inf. Comput. Sci. 1999,
+//! 39, 868).
+//!
+//! Every atom, including hydrogens, is assigned an atom type by its element, neighbors and
+//! aromaticity, and logP and MR are the sums of the contributions of the types. Heavy atoms are
+//! typed with the first matching SMARTS pattern of the table below, which follows the RDKit
+//! implementation of the method. Hydrogens are typed by the atom they are attached to.
+
+use std::sync::OnceLock;
+
+use petgraph::graph::NodeIndex;
+use ptable::Element;
+
+use crate::graph::MoleculeGraph;
+use crate::smarts::{Pattern, Target};
+use crate::Bond;
+
+/// Atom types of heavy atoms, as type name, pattern, logP and MR contribution. The first
+/// matching pattern is used.
+const HEAVY_ATOM_TYPES: &[(&str, &str, f64, f64)] = &[
+    ("C1", "[CH4]", 0.1441, 2.503),
+    ("C1", "[CH3]C", 0.1441, 2.503),
+    ("C1", "[CH2](C)C", 0.1441, 2.503),
+    ("C2", "[CH](C)(C)C", 0.0, 2.433),
+    ("C2", "[C](C)(C)(C)C", 0.0, 2.433),
+    ("C3", "[CH3][N,O,P,S,F,Cl,Br,I]", -0.2035, 2.753),
+    ("C3", "[CH2X4]([N,O,P,S,F,Cl,Br,I])[A;!#1]", -0.2035, 2.753),
+    (
+        "C4",
+        "[CH1X4]([N,O,P,S,F,Cl,Br,I])[A;!#1][A;!#1]",
+        -0.2051,
+        2.731,
+    ),
+    (
+        "C4",
+        "[CH0X4]([N,O,P,S,F,Cl,Br,I])[A;!#1]([A;!#1])[A;!#1]",
+        -0.2051,
+        2.731,
+    ),
+    ("C5", "[C]=[!C;A;!#1]", -0.2783, 5.007),
+    ("C6", "[CH2]=C", 0.1551, 3.513),
+    ("C6", "[CH1](=C)[A;!#1]", 0.1551, 3.513),
+    ("C6", "[CH0](=C)([A;!#1])[A;!#1]", 0.1551, 3.513),
+    ("C6", "[C](=C)=C", 0.1551, 3.513),
+    ("C7", "[CX2]#[A]", 0.0017, 3.888),
+    ("C8", "[CH3]c", 0.08452, 2.464),
+    ("C9", "[CH3]a", -0.1444, 2.412),
+    ("C10", "[CH2X4]a", -0.0516, 2.488),
+    ("C11", "[CHX4]a", 0.1193, 2.582),
+    ("C12", "[CH0X4]a", -0.0967, 2.576),
+    (
+        "C13",
+        "[cH0]-[A;!C;!N;!O;!S;!F;!Cl;!Br;!I;!#1]",
+        -0.5443,
+        4.041,
+    ),
+    ("C14", "[c][#9]", 0.0, 3.257),
+    ("C15", "[c][#17]", 0.245, 3.564),
+    ("C16", "[c][#35]", 0.198, 3.18),
+    ("C17", "[c][#53]", 0.0, 3.104),
+    ("C18", "[cH]", 0.1581, 3.35),
+    ("C19", "[c](:a)(:a):a", 0.2955, 4.346),
+    ("C20", "[c](:a)(:a)-a", 0.2713, 3.904),
+    ("C21", "[c](:a)(:a)-C", 0.136, 3.509),
+    ("C22", "[c](:a)(:a)-N", 0.4619, 3.067),
+    ("C23", "[c](:a)(:a)-O", 0.5437, 3.853),
+    ("C24", "[c](:a)(:a)-S", 0.1893, 2.673),
+    ("C25", "[c](:a)(:a)=[C,N,O]", -0.8186, 3.135),
+    ("C26", "[C](=C)(a)[A;!#1]", 0.264, 4.305),
+    ("C26", "[C](=C)(c)a", 0.264, 4.305),
+    ("C26", "[CH1](=C)a", 0.264, 4.305),
+    ("C26", "[C]=c", 0.264, 4.305),
+    (
+        "C27",
+        "[CX4][A;!C;!N;!O;!P;!S;!F;!Cl;!Br;!I;!#1]",
+        0.2148,
+        2.693,
+    ),
+    ("CS", "[#6]", 0.08129, 3.243),
+    ("N1", "[NH2+0][A;!#1]", -1.019, 2.262),
+    ("N2", "[NH+0]([A;!#1])[A;!#1]", -0.7096, 2.173),
+    ("N3", "[NH2+0]a", -1.027, 2.827),
+    ("N4", "[NH1+0]([!#1;A,a])a", -0.5188, 3.0),
+    ("N5", "[NH+0]=[!#1;A,a]", 0.08387, 1.757),
+    ("N6", "[N+0](=[!#1;A,a])[!#1;A,a]", 0.1836, 2.428),
+    ("N7", "[N+0]([A;!#1])([A;!#1])[A;!#1]", -0.3187, 1.839),
+    ("N8", "[N+0](a)([!#1;A,a])[A;!#1]", -0.4458, 2.819),
+    ("N8", "[N+0](a)(a)a", -0.4458, 2.819),
+    ("N9", "[N+0]#[A;!#1]", 0.01508, 1.725),
+    ("N10", "[NH3,NH2,NH;+,+2,+3]", -1.95, 0.0),
+    ("N11", "[n+0]", -0.3239, 2.202),
+    ("N12", "[n;+,+2,+3]", -1.119, 0.0),
+    (
+        "N13",
+        "[NH0;+,+2,+3]([A;!#1])([A;!#1])([A;!#1])[A;!#1]",
+        -0.3396,
+        0.2604,
+    ),
+    (
+        "N13",
+        "[NH0;+,+2,+3](=[A;!#1])([A;!#1])[!#1;A,a]",
+        -0.3396,
+        0.2604,
+    ),
+    ("N13", "[NH0;+,+2,+3](=[#6])=[#7]", -0.3396, 0.2604),
+    ("N14", "[N;+,+2,+3]#[A;!#1]", 0.2887, 3.359),
+    ("N14", "[N;-,-2,-3]", 0.2887, 3.359),
+    ("N14", "[N;+,+2,+3](=[N;-,-2,-3])=N", 0.2887, 3.359),
+    ("NS", "[#7]", -0.4806, 2.134),
+    ("O1", "[o]", 0.1552, 1.08),
+    ("O2", "[OH,OH2]", -0.2893, 0.8238),
+    ("O3", "[O]([A;!#1])[A;!#1]", -0.0684, 1.085),
+    ("O4", "[O](a)[A;!#1]", -0.4195, 1.182),
+    ("O4", "[O](a)a", -0.4195, 1.182),
+    ("O5", "[O]=[#7,#8]", 0.0335, 3.367),
+    ("O5", "[OX1;-;$([OX1;-][#7])]", 0.0335, 3.367),
+    ("O6", "[OX1;-;$([OX1;-][#16])]", -0.3339, 0.7774),
+    ("O12", "[O-1]C(=O)", -1.326, 0.0),
+    ("O7", "[OX1;-;$([OX1;-]*)]", -1.189, 0.0),
+    ("O8", "[O]=c", 0.1788, 3.135),
+    ("O9", "[O]=[CH]C", -0.1526, 0.0),
+    ("O9", "[O]=C(C)C", -0.1526, 0.0),
+    ("O9", "[O]=C(C)[A;!#1]", -0.1526, 0.0),
+    ("O9", "[O]=[CH]N", -0.1526, 0.0),
+    ("O9", "[O]=[CH]O", -0.1526, 0.0),
+    ("O9", "[O]=[CH2]", -0.1526, 0.0),
+    ("O9", "[O]=[CX2]=O", -0.1526, 0.0),
+    ("O10", "[O]=[CH]c", 0.1129, 0.2215),
+    ("O10", "[O]=C([C,c])[a;!#1]", 0.1129, 0.2215),
+    ("O10", "[O]=C(c)[A;!#1]", 0.1129, 0.2215),
+    ("O11", "[O]=C([!#1;!#6])[!#1;!#6]", 0.4833, 0.389),
+    ("OS", "[#8]", -0.1188, 0.6865),
+    ("F", "[#9-0]", 0.4202, 1.108),
+    ("Cl", "[#17-0]", 0.6895, 5.853),
+    ("Br", "[#35-0]", 0.8456, 8.927),
+    ("I", "[#53-0]", 0.8857, 14.02),
+    ("Hal", "[#9,#17,#35,#53;-]", -2.996, 0.0),
+    ("Hal", "[#53;+,+2,+3]", -2.996, 0.0),
+    ("Hal", "[+;#3,#11,#19,#37,#55]", -2.996, 0.0),
+    ("P", "[#15]", 0.8612, 6.92),
+    ("S1", "[S-0]", 0.6482, 7.591),
+    ("S2", "[S-,S-2,S-3,S-4,S+,S+2,S+3]", -0.0024, 7.365),
+    ("S3", "[s]", 0.6237, 6.691),
+    ("Me1", "[#3,#11,#19,#37,#55]", -0.3808, 5.754),
+    ("Me2", "[#4,#12,#20,#38,#56]", -0.0025, 0.0),
+];
+
+/// Hydrogen types as type name, logP and MR contribution.
+const H1: (&str, f64, f64) = ("H1", 0.123, 1.057);
+const H2: (&str, f64, f64) = ("H2", -0.2677, 1.395);
+const H3: (&str, f64, f64) = ("H3", 0.2142, 0.9627);
+const H4: (&str, f64, f64) = ("H4", 0.298, 1.805);
+const HS: (&str, f64, f64) = ("HS", 0.1125, 1.112);
+
+fn heavy_atom_types() -> &'static [(&'static str, Pattern, f64, f64)] {
+    static TYPES: OnceLock<Vec<(&str, Pattern, f64, f64)>> = OnceLock::new();
+    TYPES.get_or_init(|| {
+        HEAVY_ATOM_TYPES
+            .iter()
+            .map(|&(name, smarts, logp, mr)| (name, Pattern::parse(smarts).unwrap(), logp, mr))
+            .collect()
+    })
+}
+
+fn heavy_neighbors(graph: &MoleculeGraph, atom: NodeIndex) -> impl Iterator<Item = NodeIndex> + '_ {
+    graph
+        .neighbors(atom)
+        .filter(move |&neighbor| !graph.is_attached_hydrogen(neighbor))
+}
+
+/// Type of a hydrogen bonded to `parent`, or of a lone hydrogen.
+fn hydrogen_type(graph: &MoleculeGraph, parent: Option<NodeIndex>) -> (&'static str, f64, f64) {
+    let parent = match parent {
+        Some(parent) => parent,
+        None => return HS,
+    };
+    match graph[parent].element() {
+        Some(Element::Carbon) | Some(Element::Hydrogen) => H1,
+        Some(Element::Nitrogen) => H3,
+        Some(Element::Oxygen) => {
+            let neighbors: Vec<NodeIndex> = heavy_neighbors(graph, parent).collect();
+            let is_sp3_carbon = |atom: NodeIndex| {
+                graph[atom].element() == Some(Element::Carbon)
+                    && !graph[atom].is_aromatic()
+                    && graph.neighbors(atom).count() == 4
+            };
+            let element = |atom: NodeIndex| graph[atom].element();
+            if neighbors.iter().any(|&atom| {
+                is_sp3_carbon(atom)
+                    || graph[atom].is_aromatic() && element(atom) == Some(Element::Carbon)
+                    || !matches!(
+                        element(atom),
+                        Some(Element::Carbon)
+                            | Some(Element::Nitrogen)
+                            | Some(Element::Oxygen)
+                            | Some(Element::Sulfur)
+                    )
+            }) {
+                H2
+            } else if neighbors
+                .iter()
+                .any(|&atom| element(atom) == Some(Element::Nitrogen))
+            {
+                H3
+            } else if neighbors.iter().any(|&atom| {
+                matches!(element(atom), Some(Element::Oxygen) | Some(Element::Sulfur))
+                    || graph.edges(atom).any(|edge| *edge.weight() == Bond::Double)
+            }) {
+                // Acids and enols: the oxygen is bonded to C=X, O or S
+                H4
+            } else {
+                HS
+            }
+        }
+        _ => H2,
+    }
+}
+
+/// Crippen atom type, logP and MR contribution of every atom, by node index. Atoms of elements
+/// without a type contribute nothing and have the type `""`.
+pub fn crippen_contributions(graph: &MoleculeGraph) -> Vec<(&'static str, f64, f64)> {
+    let target = Target::new(graph);
+    graph
+        .node_indices()
+        .map(|atom| {
+            if graph[atom].is_hydrogen() {
+                if graph[atom].charge() != 0 {
+                    return HS;
+                }
+                return hydrogen_type(graph, graph.neighbors(atom).next());
+            }
+            heavy_atom_types()
+                .iter()
+                .find(|(_, pattern, _, _)| pattern.matches_at(&target, atom))
+                .map_or(("", 0.0, 0.0), |&(name, _, logp, mr)| (name, logp, mr))
+        })
+        .collect()
+}
+
+/// Wildman–Crippen estimate of the octanol/water partition coefficient, log10 P.
+pub fn logp(graph: &MoleculeGraph) -> f64 {
+    crippen_contributions(graph)
+        .iter()
+        .map(|&(_, logp, _)| logp)
+        .sum()
+}
+
+/// Wildman–Crippen estimate of the molar refractivity in cm³/mol.
+pub fn molar_refractivity(graph: &MoleculeGraph) -> f64 {
+    crippen_contributions(graph)
+        .iter()
+        .map(|&(_, _, mr)| mr)
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graph::{assert_close, molecule};
+
+    #[test]
+    fn patterns_parse() {
+        assert_eq!(HEAVY_ATOM_TYPES.len(), heavy_atom_types().len());
+    }
+
+    #[test]
+    fn atom_types() {
+        let types = |smiles| -> Vec<&str> {
+            crippen_contributions(&molecule(smiles))
+                .into_iter()
+                .map(|(name, _, _)| name)
+                .collect()
+        };
+        assert_eq!(
+            vec!["C1", "C3", "O2", "H1", "H1", "H1", "H1", "H1", "H2"],
+            types("CCO")
+        );
+        assert_eq!(
+            vec!["C1", "C5", "O9", "O2", "H1", "H1", "H1", "H4"],
+            types("CC(=O)O")
+        );
+        assert_eq!(vec!["C18", "N11", "C18"], types("c1ncccc1")[..3]);
+        assert_eq!(vec!["HS", "HS"], types("[H+].[H+]"));
+    }
+
+    #[test]
+    fn fixtures() {
+        // Published values of the RDKit implementation of the method, with the molar
+        // refractivity where available
+        let fixtures = [
+            ("C", 0.6361, Some(6.731)),
+            ("CCO", -0.0014, Some(12.760)),
+            ("c1ccccc1", 1.6866, Some(26.442)),
+            ("c1ccncc1", 1.0816, None),
+            ("Clc1ccccc1", 2.3400, None),
+            ("CC(C)=O", 0.5953, None),
+            ("CCN(CC)CC", 1.3481, None),
+            ("OC(=O)c1ccccc1O", 1.0904, None),
+            ("CC(=O)Oc1ccccc1C(=O)O", 1.3101, Some(44.710)),
+            ("CC(=O)Nc1ccc(O)cc1", 1.3506, None),
+            ("CC(C)Cc1ccc(cc1)C(C)C(=O)O", 3.0732, Some(61.035)),
+            ("Cn1cnc2c1c(=O)n(C)c(=O)n2C", -1.0293, None),
+        ];
+        for &(smiles, expected_logp, expected_mr) in &fixtures {
+            let graph = molecule(smiles);
+            assert_close(expected_logp, logp(&graph), 0.0001);
+            if let Some(expected_mr) = expected_mr {
+                assert_close(expected_mr, molar_refractivity(&graph), 0.001);
+            }
+        }
+    }
+}
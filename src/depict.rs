@@ -0,0 +1,347 @@
+//! Depictions of a [`MoleculeGraph`] for debugging: Graphviz DOT output and SVG skeletal formulas.
+//!
+//! Attached hydrogens (see [`MoleculeGraph::is_attached_hydrogen`]) are not drawn as atoms of
+//! their own but as the hydrogen count in the label of the atom they are attached to.
+
+use std::fmt::Write as _;
+
+use petgraph::graph::NodeIndex;
+use petgraph::visit::EdgeRef;
+use ptable::Element;
+
+use crate::coords::BOND_LENGTH;
+use crate::graph::MoleculeGraph;
+use crate::Bond;
+
+/// Length of a bond in the SVG depiction, in pixels.
+const PIXELS_PER_BOND: f64 = 40.0;
+const SCALE: f64 = PIXELS_PER_BOND / BOND_LENGTH;
+const MARGIN: f64 = 30.0;
+const FONT_SIZE: f64 = 14.0;
+
+/// Label of an atom: isotope, symbol, hydrogen count and charge, e.g. `13CH3` or `NH4+`. With
+/// `aromatic_lowercase`, aromatic atoms have lowercase symbols like in SMILES, e.g. `nH`.
+fn atom_label(graph: &MoleculeGraph, node: NodeIndex, aromatic_lowercase: bool) -> String {
+    let atom = &graph[node];
+    let mut label = String::new();
+    if let Some(isotope) = atom.isotope() {
+        write!(label, "{}", isotope).unwrap();
+    }
+    let symbol = atom.element().map_or("*", |element| element.get_symbol());
+    if aromatic_lowercase && atom.is_aromatic() {
+        label.push_str(&symbol.to_lowercase());
+    } else {
+        label.push_str(symbol);
+    }
+    match graph.hydrogen_count(node) {
+        0 => {}
+        1 => label.push('H'),
+        count => write!(label, "H{}", count).unwrap(),
+    }
+    match atom.charge() {
+        0 => {}
+        1 => label.push('+'),
+        -1 => label.push('-'),
+        charge if charge > 0 => write!(label, "{}+", charge).unwrap(),
+        charge => write!(label, "{}-", -charge).unwrap(),
+    }
+    label
+}
+
+/// Atoms that are drawn, i.e. all atoms but attached hydrogens.
+fn skeleton(graph: &MoleculeGraph) -> Vec<NodeIndex> {
+    graph
+        .node_indices()
+        .filter(|&node| !graph.is_attached_hydrogen(node))
+        .collect()
+}
+
+fn skeleton_neighbors(graph: &MoleculeGraph, node: NodeIndex) -> Vec<NodeIndex> {
+    graph
+        .neighbors(node)
+        .filter(|&neighbor| !graph.is_attached_hydrogen(neighbor))
+        .collect()
+}
+
+/// Write a graph in the Graphviz DOT format.
+///
+/// Nodes are named by their node index and labeled with the element, hydrogen count and charge,
+/// with aromatic atoms in lowercase.
+/// Double, triple and quadruple bonds are drawn as parallel lines, aromatic bonds dashed and
+/// directional bonds labeled with `/` or `\` (relative to the direction from the lower to the
+/// higher node index).
+pub fn to_dot(graph: &MoleculeGraph) -> String {
+    let mut dot =
+        String::from("graph molecule {\n  node [shape=circle, fontname=\"Helvetica\"];\n");
+    for node in skeleton(graph) {
+        writeln!(
+            dot,
+            "  {} [label=\"{}\", xlabel=\"{}\"];",
+            node.index(),
+            atom_label(graph, node, true),
+            node.index()
+        )
+        .unwrap();
+    }
+    for edge in graph.edge_references() {
+        if graph.is_attached_hydrogen(edge.source()) || graph.is_attached_hydrogen(edge.target()) {
+            continue;
+        }
+        let style = match edge.weight() {
+            Bond::Single => "",
+            Bond::Double => " [color=\"black:invis:black\"]",
+            Bond::Triple => " [color=\"black:invis:black:invis:black\"]",
+            Bond::Quadruple => " [color=\"black:invis:black:invis:black:invis:black\"]",
+            Bond::Aromatic => " [style=dashed]",
+            Bond::Up => " [label=\"/\"]",
+            Bond::Down => " [label=\"\\\\\"]",
+        };
+        writeln!(
+            dot,
+            "  {} -- {}{};",
+            edge.source().index(),
+            edge.target().index(),
+            style
+        )
+        .unwrap();
+    }
+    dot.push_str("}\n");
+    dot
+}
+
+/// Coordinates of all atoms of a graph, indexed by node index.
+///
+/// These are the stored 2D coordinates if all atoms have them, otherwise they are computed with
+/// [`MoleculeGraph::compute_2d_coords`] on a copy of the graph.
+pub fn layout(graph: &MoleculeGraph) -> Vec<[f64; 2]> {
+    let stored: Option<Vec<[f64; 2]>> = graph
+        .node_indices()
+        .map(|node| graph.coordinates_2d(node))
+        .collect();
+    stored.unwrap_or_else(|| {
+        let mut graph = graph.clone();
+        graph.compute_2d_coords();
+        graph
+            .node_indices()
+            .map(|node| graph.coordinates_2d(node).unwrap_or_default())
+            .collect()
+    })
+}
+
+fn element_color(element: Option<Element>) -> &'static str {
+    match element {
+        Some(Element::Nitrogen) => "#3050f8",
+        Some(Element::Oxygen) => "#ff0d0d",
+        Some(Element::Sulfur) => "#b8a000",
+        Some(Element::Phosphorus) => "#ff8000",
+        Some(Element::Fluorine) | Some(Element::Chlorine) => "#1fb01f",
+        Some(Element::Bromine) => "#a62929",
+        Some(Element::Iodine) => "#940094",
+        _ => "#000000",
+    }
+}
+
+/// Whether an atom is labeled in a skeletal formula: all atoms but carbons with bonds and
+/// without charge or isotope.
+fn is_labeled(graph: &MoleculeGraph, node: NodeIndex) -> bool {
+    let atom = &graph[node];
+    atom.element() != Some(Element::Carbon)
+        || atom.charge() != 0
+        || atom.isotope().is_some()
+        || skeleton_neighbors(graph, node).is_empty()
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+/// Draw a graph as a skeletal formula in SVG, using the coordinates of [`layout`].
+///
+/// Carbon atoms are drawn as plain vertices, other atoms as labels with their hydrogen count and
+/// charge. Double and triple bonds are drawn as parallel lines and aromatic bonds as a solid and
+/// a dashed line. This is meant for debugging, not as a publication quality depiction.
+pub fn to_svg(graph: &MoleculeGraph) -> String {
+    let coordinates = layout(graph);
+    let nodes = skeleton(graph);
+    let min_x = nodes
+        .iter()
+        .map(|node| coordinates[node.index()][0])
+        .fold(f64::INFINITY, f64::min);
+    let max_x = nodes
+        .iter()
+        .map(|node| coordinates[node.index()][0])
+        .fold(f64::NEG_INFINITY, f64::max);
+    let min_y = nodes
+        .iter()
+        .map(|node| coordinates[node.index()][1])
+        .fold(f64::INFINITY, f64::min);
+    let max_y = nodes
+        .iter()
+        .map(|node| coordinates[node.index()][1])
+        .fold(f64::NEG_INFINITY, f64::max);
+    let (width, height) = if nodes.is_empty() {
+        (2.0 * MARGIN, 2.0 * MARGIN)
+    } else {
+        (
+            (max_x - min_x) * SCALE + 2.0 * MARGIN,
+            (max_y - min_y) * SCALE + 2.0 * MARGIN,
+        )
+    };
+    // SVG coordinates grow downwards.
+    let point = |node: NodeIndex| {
+        let [x, y] = coordinates[node.index()];
+        [(x - min_x) * SCALE + MARGIN, (max_y - y) * SCALE + MARGIN]
+    };
+
+    let mut svg = String::new();
+    writeln!(
+        svg,
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{:.0}\" height=\"{:.0}\" \
+         viewBox=\"0 0 {:.0} {:.0}\">",
+        width, height, width, height
+    )
+    .unwrap();
+    svg.push_str("<rect width=\"100%\" height=\"100%\" fill=\"white\"/>\n");
+
+    for edge in graph.edge_references() {
+        let (source, target) = (edge.source(), edge.target());
+        if graph.is_attached_hydrogen(source) || graph.is_attached_hydrogen(target) {
+            continue;
+        }
+        let (mut from, mut to) = (point(source), point(target));
+        let (dx, dy) = (to[0] - from[0], to[1] - from[1]);
+        let length = (dx * dx + dy * dy).sqrt().max(1e-9);
+        let (ux, uy) = (dx / length, dy / length);
+        // Bonds end short of atom labels.
+        let gap = 0.6 * FONT_SIZE;
+        if is_labeled(graph, source) {
+            from = [from[0] + ux * gap, from[1] + uy * gap];
+        }
+        if is_labeled(graph, target) {
+            to = [to[0] - ux * gap, to[1] - uy * gap];
+        }
+
+        let spacing = 0.1 * PIXELS_PER_BOND;
+        let lines: &[(f64, bool)] = match edge.weight() {
+            Bond::Single | Bond::Up | Bond::Down => &[(0.0, false)],
+            Bond::Double => &[(-0.5, false), (0.5, false)],
+            Bond::Triple => &[(-1.0, false), (0.0, false), (1.0, false)],
+            Bond::Quadruple => &[(-1.5, false), (-0.5, false), (0.5, false), (1.5, false)],
+            Bond::Aromatic => &[(-0.5, false), (0.5, true)],
+        };
+        for &(shift, dashed) in lines {
+            let (ox, oy) = (-uy * shift * spacing, ux * shift * spacing);
+            writeln!(
+                svg,
+                "<line x1=\"{:.1}\" y1=\"{:.1}\" x2=\"{:.1}\" y2=\"{:.1}\" stroke=\"black\" \
+                 stroke-width=\"1.5\"{}/>",
+                from[0] + ox,
+                from[1] + oy,
+                to[0] + ox,
+                to[1] + oy,
+                if dashed {
+                    " stroke-dasharray=\"3,3\""
+                } else {
+                    ""
+                }
+            )
+            .unwrap();
+        }
+    }
+
+    for &node in &nodes {
+        if !is_labeled(graph, node) {
+            continue;
+        }
+        let [x, y] = point(node);
+        writeln!(
+            svg,
+            "<text x=\"{:.1}\" y=\"{:.1}\" fill=\"{}\" font-family=\"sans-serif\" \
+             font-size=\"{}\" text-anchor=\"middle\" dominant-baseline=\"central\">{}</text>",
+            x,
+            y,
+            element_color(graph[node].element()),
+            FONT_SIZE,
+            escape(&atom_label(graph, node, false))
+        )
+        .unwrap();
+    }
+    svg.push_str("</svg>\n");
+    svg
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graph::molecule;
+
+    fn distance(coordinates: &[[f64; 2]], a: usize, b: usize) -> f64 {
+        let (dx, dy) = (
+            coordinates[a][0] - coordinates[b][0],
+            coordinates[a][1] - coordinates[b][1],
+        );
+        (dx * dx + dy * dy).sqrt()
+    }
+
+    #[test]
+    fn dot() {
+        let dot = to_dot(&molecule("C=C[NH3+].c1ccccc1"));
+        assert!(dot.starts_with("graph molecule {\n"));
+        assert!(dot.contains("  0 [label=\"CH2\", xlabel=\"0\"];\n"));
+        assert!(dot.contains("  2 [label=\"NH3+\", xlabel=\"2\"];\n"));
+        assert!(dot.contains("  3 [label=\"cH\", xlabel=\"3\"];\n"));
+        assert!(dot.contains("  0 -- 1 [color=\"black:invis:black\"];\n"));
+        assert!(dot.contains("  1 -- 2;\n"));
+        assert!(dot.contains("  3 -- 4 [style=dashed];\n"));
+        assert!(dot.contains("  3 -- 8 [style=dashed];\n"));
+        // Hydrogens are part of the labels.
+        assert_eq!(9, dot.matches("[label=").count());
+        assert_eq!(8, dot.matches(" -- ").count());
+    }
+
+    #[test]
+    fn bond_lengths() {
+        for smiles in &["CCCCCC", "c1ccccc1", "c1ccc2ccccc2c1", "CC(C)(C)O"] {
+            let graph = molecule(smiles);
+            let coordinates = layout(&graph);
+            for edge in graph.edge_references() {
+                let length = distance(&coordinates, edge.source().index(), edge.target().index());
+                assert!(
+                    (length - BOND_LENGTH).abs() < 1e-6,
+                    "{}: bond of length {}",
+                    smiles,
+                    length
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn components_do_not_overlap() {
+        let coordinates = layout(&molecule("CCO.[Na+]"));
+        let max_x = (0..3).map(|i| coordinates[i][0]).fold(f64::MIN, f64::max);
+        assert!(coordinates[3][0] > max_x + BOND_LENGTH);
+    }
+
+    #[test]
+    fn svg() {
+        let svg = to_svg(&molecule("CC(=O)N"));
+        assert!(svg.starts_with("<svg xmlns=\"http://www.w3.org/2000/svg\""));
+        assert!(svg.ends_with("</svg>\n"));
+        // One single bond, two lines for the double bond and one for the C-N bond.
+        assert_eq!(4, svg.matches("<line").count());
+        assert_eq!(2, svg.matches("<text").count());
+        assert!(svg.contains(">O</text>"));
+        assert!(svg.contains(">NH2</text>"));
+
+        let svg = to_svg(&molecule("[CH4]"));
+        assert!(svg.contains(">CH4</text>"));
+
+        // Aromatic atoms are labeled with their element symbol.
+        let svg = to_svg(&molecule("c1cc[nH]c1"));
+        assert!(svg.contains(">NH</text>"));
+        assert!(!svg.contains(">nH</text>"));
+    }
+}
@@ -0,0 +1,391 @@
+//! Molecular descriptors for filtering compound libraries, like Lipinski's rule of five.
+//!
+//! Aromatic rings and the polar surface area of aromatic atoms are only recognized in aromatic
+//! SMILES, see [aromaticity](crate::graph#aromaticity).
+
+use std::sync::OnceLock;
+
+use petgraph::graph::NodeIndex;
+use petgraph::visit::EdgeRef;
+use ptable::Element;
+
+use crate::crippen;
+use crate::graph::MoleculeGraph;
+use crate::smarts::Pattern;
+use crate::Bond;
+
+/// All descriptors of a molecule, see [`descriptors`].
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Descriptors {
+    pub molecular_weight: f64,
+    pub heavy_atoms: usize,
+    pub h_bond_donors: usize,
+    pub h_bond_acceptors: usize,
+    pub rotatable_bonds: usize,
+    pub rings: usize,
+    pub aromatic_rings: usize,
+    pub fraction_csp3: f64,
+    pub tpsa: f64,
+    /// Wildman–Crippen logP, see [`crate::crippen`]
+    pub logp: f64,
+    /// Wildman–Crippen molar refractivity
+    pub molar_refractivity: f64,
+}
+
+impl Descriptors {
+    /// Number of violated criteria of Lipinski's rule of five: at most 5 H-bond donors, at most
+    /// 10 H-bond acceptors, a molecular weight of at most 500 and a logP of at most 5.
+    pub fn rule_of_five_violations(&self) -> usize {
+        [
+            self.h_bond_donors > 5,
+            self.h_bond_acceptors > 10,
+            self.molecular_weight > 500.0,
+            self.logp > 5.0,
+        ]
+        .iter()
+        .filter(|&&violated| violated)
+        .count()
+    }
+}
+
+/// Compute all descriptors of a molecule.
+pub fn descriptors(graph: &MoleculeGraph) -> Descriptors {
+    let rings = graph.rings();
+    Descriptors {
+        molecular_weight: molecular_weight(graph),
+        heavy_atoms: heavy_atom_count(graph),
+        h_bond_donors: h_bond_donor_count(graph),
+        h_bond_acceptors: h_bond_acceptor_count(graph),
+        rotatable_bonds: rotatable_bond_count(graph),
+        rings: rings.len(),
+        aromatic_rings: count_aromatic_rings(graph, &rings),
+        fraction_csp3: fraction_csp3(graph),
+        tpsa: tpsa(graph),
+        logp: crippen::logp(graph),
+        molar_refractivity: crippen::molar_refractivity(graph),
+    }
+}
+
+/// Average molecular weight. Atoms with an isotope count with their mass number.
+pub fn molecular_weight(graph: &MoleculeGraph) -> f64 {
+    graph
+        .node_indices()
+        .map(
+            |atom| match (graph[atom].element(), graph[atom].isotope()) {
+                (Some(_), Some(isotope)) => isotope as f64,
+                (Some(element), None) => element.get_atomic_mass() as f64,
+                (None, _) => 0.0,
+            },
+        )
+        .sum()
+}
+
+/// Number of atoms other than hydrogen.
+pub fn heavy_atom_count(graph: &MoleculeGraph) -> usize {
+    graph
+        .node_indices()
+        .filter(|&atom| {
+            graph[atom]
+                .element()
+                .is_some_and(|element| element != Element::Hydrogen)
+        })
+        .count()
+}
+
+fn is_nitrogen_or_oxygen(graph: &MoleculeGraph, atom: NodeIndex) -> bool {
+    matches!(
+        graph[atom].element(),
+        Some(Element::Nitrogen) | Some(Element::Oxygen)
+    )
+}
+
+/// Number of hydrogen bond donors by Lipinski's definition: hydrogens bonded to nitrogen or
+/// oxygen.
+pub fn h_bond_donor_count(graph: &MoleculeGraph) -> usize {
+    graph
+        .node_indices()
+        .filter(|&atom| is_nitrogen_or_oxygen(graph, atom))
+        .map(|atom| graph.hydrogen_count(atom) as usize)
+        .sum()
+}
+
+/// Number of hydrogen bond acceptors by Lipinski's definition: nitrogen and oxygen atoms.
+pub fn h_bond_acceptor_count(graph: &MoleculeGraph) -> usize {
+    graph
+        .node_indices()
+        .filter(|&atom| is_nitrogen_or_oxygen(graph, atom))
+        .count()
+}
+
+/// Number of rotatable bonds: single bonds outside of rings between two non-terminal heavy
+/// atoms. Bonds next to triple bonds and amide C-N bonds are not rotatable.
+pub fn rotatable_bond_count(graph: &MoleculeGraph) -> usize {
+    static PATTERNS: OnceLock<(Pattern, Pattern)> = OnceLock::new();
+    let (rotatable, amide) = PATTERNS.get_or_init(|| {
+        (
+            Pattern::parse("[!D1&!$(*#*)]-&!@[!D1&!$(*#*)]").unwrap(),
+            Pattern::parse("[NX3]-&!@C=O").unwrap(),
+        )
+    });
+    let mut amide_bonds: Vec<Vec<NodeIndex>> = amide
+        .matches(graph)
+        .into_iter()
+        .map(|atoms| {
+            let mut bond = atoms[..2].to_vec();
+            bond.sort_unstable();
+            bond
+        })
+        .collect();
+    amide_bonds.sort_unstable();
+    rotatable
+        .matches(graph)
+        .into_iter()
+        .filter(|bond| {
+            let mut bond = bond.clone();
+            bond.sort_unstable();
+            amide_bonds.binary_search(&bond).is_err()
+        })
+        .count()
+}
+
+/// Number of rings in the smallest set of smallest rings.
+pub fn ring_count(graph: &MoleculeGraph) -> usize {
+    graph.rings().len()
+}
+
+fn count_aromatic_rings(graph: &MoleculeGraph, rings: &[Vec<NodeIndex>]) -> usize {
+    rings
+        .iter()
+        .filter(|ring| {
+            (0..ring.len()).all(|position| {
+                let next = ring[(position + 1) % ring.len()];
+                graph
+                    .find_edge(ring[position], next)
+                    .is_some_and(|edge| graph[edge] == Bond::Aromatic)
+            })
+        })
+        .count()
+}
+
+/// Number of rings in the smallest set of smallest rings with only aromatic bonds.
+pub fn aromatic_ring_count(graph: &MoleculeGraph) -> usize {
+    count_aromatic_rings(graph, &graph.rings())
+}
+
+/// Fraction of the carbon atoms that are sp3 hybridized, i.e. only have single bonds. Zero for
+/// molecules without carbon.
+pub fn fraction_csp3(graph: &MoleculeGraph) -> f64 {
+    let carbons: Vec<_> = graph
+        .node_indices()
+        .filter(|&atom| graph[atom].element() == Some(Element::Carbon))
+        .collect();
+    if carbons.is_empty() {
+        return 0.0;
+    }
+    let sp3 = carbons
+        .iter()
+        .filter(|&&atom| {
+            !graph[atom].is_aromatic()
+                && graph
+                    .edges(atom)
+                    .all(|edge| matches!(edge.weight(), Bond::Single | Bond::Up | Bond::Down))
+        })
+        .count();
+    sp3 as f64 / carbons.len() as f64
+}
+
+/// Topological polar surface area in Å², as the sum of the contributions of nitrogen and oxygen
+/// atoms by Ertl et al. (J. Med. Chem. 2000, 43, 3714). Atoms in environments that are not in the
+/// table get an estimate from their number of neighbors and hydrogens.
+pub fn tpsa(graph: &MoleculeGraph) -> f64 {
+    let ring_bonds = graph.ring_bonds();
+    let three_membered: Vec<bool> = {
+        let mut in_three_ring = vec![false; graph.node_count()];
+        for ring in graph.rings().iter().filter(|ring| ring.len() == 3) {
+            for atom in ring {
+                in_three_ring[atom.index()] = true;
+            }
+        }
+        in_three_ring
+    };
+
+    graph
+        .node_indices()
+        .filter(|&atom| is_nitrogen_or_oxygen(graph, atom))
+        .map(|atom| {
+            let (mut single, mut double, mut triple, mut aromatic) = (0, 0, 0, 0);
+            for edge in graph.edges(atom) {
+                let neighbor = graph.other_end(edge.id(), atom);
+                if graph.is_attached_hydrogen(neighbor) {
+                    continue;
+                }
+                match edge.weight() {
+                    Bond::Single | Bond::Up | Bond::Down => single += 1,
+                    Bond::Double => double += 1,
+                    Bond::Triple => triple += 1,
+                    Bond::Aromatic => aromatic += 1,
+                    Bond::Quadruple => {}
+                }
+            }
+            let hydrogens = graph.hydrogen_count(atom);
+            let charge = graph[atom].charge();
+            let in_three_ring = three_membered[atom.index()]
+                && graph.edges(atom).any(|edge| ring_bonds[edge.id().index()]);
+            let neighbors = single + double + triple + aromatic;
+
+            let contribution = if graph[atom].element() == Some(Element::Nitrogen) {
+                nitrogen_contribution(
+                    (single, double, triple, aromatic),
+                    hydrogens,
+                    charge,
+                    in_three_ring,
+                )
+                .unwrap_or(30.5 - neighbors as f64 * 8.2 + hydrogens as f64 * 1.5)
+            } else {
+                oxygen_contribution(
+                    (single, double, triple, aromatic),
+                    hydrogens,
+                    charge,
+                    in_three_ring,
+                )
+                .unwrap_or(28.5 - neighbors as f64 * 8.6 + hydrogens as f64 * 1.5)
+            };
+            contribution.max(0.0)
+        })
+        // Summing from `0.0`, because an empty `sum` of floats is `-0.0`
+        .fold(0.0, |area, contribution| area + contribution)
+}
+
+/// Ertl contribution of a nitrogen by its numbers of single, double, triple and aromatic bonds to
+/// heavy atoms.
+fn nitrogen_contribution(
+    bonds: (u8, u8, u8, u8),
+    hydrogens: u8,
+    charge: i8,
+    in_three_ring: bool,
+) -> Option<f64> {
+    let contribution = match (bonds, hydrogens, charge) {
+        ((3, 0, 0, 0), 0, 0) if in_three_ring => 3.01,
+        ((3, 0, 0, 0), 0, 0) => 3.24,
+        ((1, 1, 0, 0), 0, 0) => 12.36,
+        ((0, 0, 1, 0), 0, 0) => 23.79,
+        ((1, 2, 0, 0), 0, 0) => 11.68,
+        ((0, 1, 1, 0), 0, 0) => 13.60,
+        ((2, 0, 0, 0), 1, 0) if in_three_ring => 21.94,
+        ((2, 0, 0, 0), 1, 0) => 12.03,
+        ((0, 1, 0, 0), 1, 0) => 23.85,
+        ((1, 0, 0, 0), 2, 0) => 26.02,
+        ((4, 0, 0, 0), 0, 1) => 0.0,
+        ((2, 1, 0, 0), 0, 1) => 3.01,
+        ((1, 0, 1, 0), 0, 1) => 4.36,
+        ((3, 0, 0, 0), 1, 1) => 4.44,
+        ((1, 1, 0, 0), 1, 1) => 13.97,
+        ((2, 0, 0, 0), 2, 1) => 16.61,
+        ((0, 1, 0, 0), 2, 1) => 25.59,
+        ((1, 0, 0, 0), 3, 1) => 27.64,
+        ((0, 0, 0, 2), 0, 0) => 12.89,
+        ((0, 0, 0, 3), 0, 0) => 4.41,
+        ((1, 0, 0, 2), 0, 0) => 4.93,
+        ((0, 1, 0, 2), 0, 0) => 8.39,
+        ((0, 0, 0, 2), 1, 0) => 15.79,
+        ((0, 0, 0, 3), 0, 1) => 4.10,
+        ((1, 0, 0, 2), 0, 1) => 3.88,
+        ((0, 0, 0, 2), 1, 1) => 14.14,
+        _ => return None,
+    };
+    Some(contribution)
+}
+
+/// Ertl contribution of an oxygen, like [`nitrogen_contribution`].
+fn oxygen_contribution(
+    bonds: (u8, u8, u8, u8),
+    hydrogens: u8,
+    charge: i8,
+    in_three_ring: bool,
+) -> Option<f64> {
+    let contribution = match (bonds, hydrogens, charge) {
+        ((2, 0, 0, 0), 0, 0) if in_three_ring => 12.53,
+        ((2, 0, 0, 0), 0, 0) => 9.23,
+        ((0, 1, 0, 0), 0, 0) => 17.07,
+        ((1, 0, 0, 0), 1, 0) => 20.23,
+        ((1, 0, 0, 0), 0, -1) => 23.06,
+        ((0, 0, 0, 2), 0, 0) => 13.14,
+        _ => return None,
+    };
+    Some(contribution)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graph::{assert_close, molecule};
+
+    #[test]
+    fn drugs() {
+        let aspirin = descriptors(&molecule("CC(=O)Oc1ccccc1C(=O)O"));
+        assert_close(180.16, aspirin.molecular_weight, 0.01);
+        assert_eq!(13, aspirin.heavy_atoms);
+        assert_eq!(1, aspirin.h_bond_donors);
+        assert_eq!(4, aspirin.h_bond_acceptors);
+        assert_eq!(3, aspirin.rotatable_bonds);
+        assert_eq!(1, aspirin.rings);
+        assert_eq!(1, aspirin.aromatic_rings);
+        assert_close(1.0 / 9.0, aspirin.fraction_csp3, 0.01);
+        assert_close(63.60, aspirin.tpsa, 0.01);
+        assert_close(1.31, aspirin.logp, 0.01);
+        assert_eq!(0, aspirin.rule_of_five_violations());
+
+        let paracetamol = descriptors(&molecule("CC(=O)Nc1ccc(O)cc1"));
+        assert_eq!(2, paracetamol.h_bond_donors);
+        assert_eq!(1, paracetamol.rotatable_bonds);
+        assert_close(49.33, paracetamol.tpsa, 0.01);
+
+        let ibuprofen = descriptors(&molecule("CC(C)Cc1ccc(cc1)C(C)C(=O)O"));
+        assert_eq!(4, ibuprofen.rotatable_bonds);
+        assert_close(6.0 / 13.0, ibuprofen.fraction_csp3, 0.01);
+        assert_close(37.30, ibuprofen.tpsa, 0.01);
+
+        let caffeine = descriptors(&molecule("Cn1cnc2c1c(=O)n(C)c(=O)n2C"));
+        assert_eq!(0, caffeine.h_bond_donors);
+        assert_eq!(0, caffeine.rotatable_bonds);
+        assert_eq!(2, caffeine.aromatic_rings);
+        assert_close(61.82, caffeine.tpsa, 0.01);
+    }
+
+    #[test]
+    fn rule_of_five() {
+        // Cyclosporin-like peptide chain: too large, too many donors and acceptors
+        let peptide = descriptors(&molecule(
+            "CC(C)CC(NC(=O)C(CC(C)C)NC(=O)C(CC(C)C)NC(=O)C(CC(C)C)NC(=O)C(N)CC(C)C)C(=O)O",
+        ));
+        assert!(peptide.molecular_weight > 500.0);
+        assert_eq!(3, peptide.rule_of_five_violations());
+    }
+
+    #[test]
+    fn rings_and_saturation() {
+        assert_eq!(2, ring_count(&molecule("c1ccc2ccccc2c1")));
+        assert_eq!(2, aromatic_ring_count(&molecule("c1ccc2ccccc2c1")));
+        assert_eq!(0, aromatic_ring_count(&molecule("C1=CC=CC=C1")));
+        assert_eq!(1.0, fraction_csp3(&molecule("C1CCCCC1")));
+        assert_eq!(0.0, fraction_csp3(&molecule("O")));
+        assert_eq!(0, rotatable_bond_count(&molecule("CC#CC")));
+        assert_eq!(1, rotatable_bond_count(&molecule("CCCC")));
+        assert_eq!(1, rotatable_bond_count(&molecule("CC(=O)NCC")));
+        // The C-N bond of a tertiary amide isn't rotatable either
+        assert_eq!(3, rotatable_bond_count(&molecule("CCN(CC)C(=O)CC")));
+    }
+
+    #[test]
+    fn polar_surface_area() {
+        // Nitro group, charge separated
+        assert_close(43.14, tpsa(&molecule("c1ccccc1[N+](=O)[O-]")), 0.01);
+        // Epoxide oxygen and aziridine NH
+        assert_close(12.53, tpsa(&molecule("C1CO1")), 0.01);
+        assert_close(21.94, tpsa(&molecule("C1CN1")), 0.01);
+        assert_close(23.79, tpsa(&molecule("CC#N")), 0.01);
+        assert_close(0.0, tpsa(&molecule("CCCl")), 0.01);
+        assert_eq!(0.0f64.to_bits(), tpsa(&molecule("c1ccccc1")).to_bits());
+    }
+}
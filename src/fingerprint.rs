@@ -0,0 +1,332 @@
+//! Circular (Morgan/ECFP-style) fingerprints and fixed-length bit vector fingerprints.
+//!
+//! Every heavy atom starts with an identifier hashed from its element, number of heavy
+//! neighbors, charge, number of hydrogens, ring membership and aromaticity. In each iteration,
+//! the identifier of an atom is combined with the bond types and identifiers of its neighbors,
+//! describing an environment of growing radius around the atom. Environments that cover the
+//! same bonds as an environment that was already found are dropped.
+
+use std::collections::{BTreeMap, HashSet};
+
+use derive_more::Deref;
+use petgraph::graph::NodeIndex;
+use petgraph::visit::EdgeRef;
+
+use crate::graph::MoleculeGraph;
+use crate::Bond;
+
+/// Radius of ECFP4 fingerprints (environments of diameter 4).
+pub const ECFP4_RADIUS: u32 = 2;
+/// Radius of ECFP6 fingerprints (environments of diameter 6).
+pub const ECFP6_RADIUS: u32 = 3;
+
+/// A fingerprint of hashed features and how often each of them occurs.
+#[derive(Debug, Default, PartialEq, Eq, Clone, Deref)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SparseFingerprint(BTreeMap<u32, u32>);
+
+impl SparseFingerprint {
+    /// Fold the features into a bit vector of the given length, which must not be zero.
+    pub fn fold(&self, len: usize) -> BitFingerprint {
+        assert!(len > 0, "can't fold a fingerprint into zero bits");
+        let mut bits = BitFingerprint::new(len);
+        for &feature in self.keys() {
+            bits.set(feature as usize % len);
+        }
+        bits
+    }
+}
+
+/// A fingerprint of a fixed number of bits.
+#[derive(Debug, PartialEq, Eq, Clone, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BitFingerprint {
+    len: usize,
+    words: Vec<u64>,
+}
+
+impl BitFingerprint {
+    /// A fingerprint of `len` bits, all unset.
+    pub fn new(len: usize) -> Self {
+        BitFingerprint {
+            len,
+            words: vec![0; len.div_ceil(64)],
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn set(&mut self, bit: usize) {
+        assert!(bit < self.len, "bit {} out of range", bit);
+        self.words[bit / 64] |= 1 << (bit % 64);
+    }
+
+    pub fn get(&self, bit: usize) -> bool {
+        bit < self.len && self.words[bit / 64] & (1 << (bit % 64)) != 0
+    }
+
+    /// Number of set bits.
+    pub fn count_ones(&self) -> u32 {
+        self.words.iter().map(|word| word.count_ones()).sum()
+    }
+
+    /// Number of bits set in both fingerprints, which must have the same length.
+    pub fn intersection_count(&self, other: &BitFingerprint) -> u32 {
+        assert_eq!(
+            self.len, other.len,
+            "fingerprints of different lengths can't be compared"
+        );
+        self.words
+            .iter()
+            .zip(&other.words)
+            .map(|(a, b)| (a & b).count_ones())
+            .sum()
+    }
+
+    /// Indices of the set bits, in increasing order.
+    pub fn ones(&self) -> impl Iterator<Item = usize> + '_ {
+        (0..self.len).filter(move |&bit| self.get(bit))
+    }
+
+    pub fn as_words(&self) -> &[u64] {
+        &self.words
+    }
+}
+
+/// Combine a hash with another value, like `boost::hash_combine`.
+fn hash_combine(seed: u32, value: u32) -> u32 {
+    seed ^ value
+        .wrapping_add(0x9e37_79b9)
+        .wrapping_add(seed << 6)
+        .wrapping_add(seed >> 2)
+}
+
+fn hash_values(values: &[u32]) -> u32 {
+    values
+        .iter()
+        .fold(0, |seed, &value| hash_combine(seed, value))
+}
+
+fn bond_type(bond: Bond) -> u32 {
+    match bond {
+        Bond::Single | Bond::Up | Bond::Down => 1,
+        Bond::Double => 2,
+        Bond::Triple => 3,
+        Bond::Quadruple => 4,
+        Bond::Aromatic => 5,
+    }
+}
+
+/// Initial identifier of every heavy atom.
+fn atom_invariants(graph: &MoleculeGraph, atoms: &[NodeIndex], ring_atoms: &[bool]) -> Vec<u32> {
+    atoms
+        .iter()
+        .map(|&atom| {
+            let heavy_degree = graph
+                .neighbors(atom)
+                .filter(|&neighbor| !graph.is_attached_hydrogen(neighbor))
+                .count();
+            hash_values(&[
+                graph[atom]
+                    .element()
+                    .map_or(0, |element| element.get_atomic_number() as u32),
+                heavy_degree as u32,
+                graph[atom].charge() as i32 as u32,
+                graph.hydrogen_count(atom) as u32,
+                ring_atoms[atom.index()] as u32,
+                graph[atom].is_aromatic() as u32,
+            ])
+        })
+        .collect()
+}
+
+/// Morgan fingerprint with environments up to the given radius, as feature counts.
+///
+/// Use [`ECFP4_RADIUS`] or [`ECFP6_RADIUS`] for ECFP4 or ECFP6 fingerprints.
+pub fn morgan_counts(graph: &MoleculeGraph, radius: u32) -> SparseFingerprint {
+    let atoms: Vec<NodeIndex> = graph
+        .node_indices()
+        .filter(|&atom| !graph.is_attached_hydrogen(atom))
+        .collect();
+    let mut position = vec![usize::MAX; graph.node_count()];
+    for (index, atom) in atoms.iter().enumerate() {
+        position[atom.index()] = index;
+    }
+    // Neighbors as (bond type, neighbor, bond index)
+    let neighbors: Vec<Vec<(u32, usize, usize)>> = atoms
+        .iter()
+        .map(|&atom| {
+            graph
+                .edges(atom)
+                .filter_map(|edge| {
+                    let neighbor = graph.other_end(edge.id(), atom);
+                    let neighbor = position[neighbor.index()];
+                    if neighbor == usize::MAX {
+                        None
+                    } else {
+                        Some((bond_type(*edge.weight()), neighbor, edge.id().index()))
+                    }
+                })
+                .collect()
+        })
+        .collect();
+
+    let mut identifiers = atom_invariants(graph, &atoms, &graph.ring_atoms());
+    let mut counts = BTreeMap::new();
+    for &identifier in &identifiers {
+        *counts.entry(identifier).or_insert(0) += 1;
+    }
+
+    // Bonds covered by the environment of every atom, as sorted edge indices.
+    let mut environments: Vec<Vec<usize>> = vec![Vec::new(); atoms.len()];
+    let mut seen_environments = HashSet::new();
+    for iteration in 1..=radius {
+        let mut found: Vec<(Vec<usize>, u32)> = Vec::with_capacity(atoms.len());
+        let mut next_identifiers = Vec::with_capacity(atoms.len());
+        let mut next_environments = Vec::with_capacity(atoms.len());
+        for (atom, atom_neighbors) in neighbors.iter().enumerate() {
+            let mut neighbor_identifiers: Vec<(u32, u32)> = atom_neighbors
+                .iter()
+                .map(|&(bond, neighbor, _)| (bond, identifiers[neighbor]))
+                .collect();
+            neighbor_identifiers.sort_unstable();
+            let mut values = vec![iteration, identifiers[atom]];
+            for (bond, identifier) in neighbor_identifiers {
+                values.push(bond);
+                values.push(identifier);
+            }
+            let identifier = hash_values(&values);
+
+            let mut environment = environments[atom].clone();
+            for &(_, neighbor, bond) in atom_neighbors {
+                environment.push(bond);
+                environment.extend_from_slice(&environments[neighbor]);
+            }
+            environment.sort_unstable();
+            environment.dedup();
+
+            if !atom_neighbors.is_empty() {
+                found.push((environment.clone(), identifier));
+            }
+            next_identifiers.push(identifier);
+            next_environments.push(environment);
+        }
+
+        // Of environments covering the same bonds, only the one with the lowest identifier is
+        // kept, so that the result doesn't depend on the order of the atoms.
+        found.sort_unstable();
+        for (environment, identifier) in found {
+            if seen_environments.insert(environment) {
+                *counts.entry(identifier).or_insert(0) += 1;
+            }
+        }
+        identifiers = next_identifiers;
+        environments = next_environments;
+    }
+
+    SparseFingerprint(counts)
+}
+
+/// Morgan fingerprint with environments up to the given radius, folded into `len` bits.
+pub fn morgan_bits(graph: &MoleculeGraph, radius: u32, len: usize) -> BitFingerprint {
+    morgan_counts(graph, radius).fold(len)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graph::molecule;
+
+    fn feature_count(fingerprint: &SparseFingerprint) -> u32 {
+        fingerprint.values().sum()
+    }
+
+    #[test]
+    fn environments() {
+        // Three atoms at radius 0 and three distinct environments of one bond or two bonds at
+        // radius 1. At radius 2, all environments cover both bonds.
+        let ethanol = morgan_counts(&molecule("CCO"), ECFP4_RADIUS);
+        assert_eq!(6, feature_count(&ethanol));
+        assert_eq!(6, ethanol.len());
+
+        // All atoms are equal, so there is a single feature per radius. Every environment at a
+        // radius is distinct, as they cover different bonds.
+        let benzene = morgan_counts(&molecule("c1ccccc1"), ECFP4_RADIUS);
+        assert_eq!(3, benzene.len());
+        assert_eq!(18, feature_count(&benzene));
+
+        // No bonds, so no environments beyond radius 0.
+        let methane = morgan_counts(&molecule("C"), ECFP6_RADIUS);
+        assert_eq!(1, feature_count(&methane));
+    }
+
+    #[test]
+    fn independent_of_atom_order() {
+        let pairs = [
+            ("CCO", "OCC"),
+            ("CC(=O)Oc1ccccc1C(=O)O", "OC(=O)c1ccccc1OC(C)=O"),
+            ("C1CCC2(CC1)CO2", "O1CC12CCCCC2"),
+        ];
+        for (first, second) in pairs.iter() {
+            assert_eq!(
+                morgan_counts(&molecule(first), ECFP6_RADIUS),
+                morgan_counts(&molecule(second), ECFP6_RADIUS),
+                "{} vs {}",
+                first,
+                second
+            );
+        }
+    }
+
+    #[test]
+    fn invariants() {
+        let radius_zero = |smiles| morgan_counts(&molecule(smiles), 0);
+        assert_ne!(radius_zero("C"), radius_zero("[CH3-]"));
+        assert_ne!(radius_zero("C1CC1"), radius_zero("CCC"));
+        assert_ne!(radius_zero("c1ccccc1"), radius_zero("C1=CC=CC=C1"));
+        assert_ne!(radius_zero("CC"), radius_zero("C=C"));
+
+        let ecfp4 = morgan_counts(&molecule("CC(=O)Nc1ccc(O)cc1"), ECFP4_RADIUS);
+        let ecfp6 = morgan_counts(&molecule("CC(=O)Nc1ccc(O)cc1"), ECFP6_RADIUS);
+        assert!(ecfp4.keys().all(|feature| ecfp6.contains_key(feature)));
+        assert!(ecfp6.len() > ecfp4.len());
+    }
+
+    #[test]
+    fn folding() {
+        let counts = morgan_counts(&molecule("CC(=O)Nc1ccc(O)cc1"), ECFP4_RADIUS);
+        let bits = counts.fold(2048);
+        assert_eq!(2048, bits.len());
+        assert!(bits.count_ones() as usize <= counts.len());
+        assert!(bits.count_ones() > 10);
+        for &feature in counts.keys() {
+            assert!(bits.get(feature as usize % 2048));
+        }
+        assert_eq!(
+            bits,
+            morgan_bits(&molecule("Oc1ccc(NC(C)=O)cc1"), ECFP4_RADIUS, 2048)
+        );
+
+        let folded = counts.fold(8);
+        assert_eq!(folded.count_ones() as usize, folded.ones().count());
+        assert_eq!(folded.count_ones(), folded.intersection_count(&folded));
+    }
+
+    #[test]
+    #[should_panic(expected = "zero bits")]
+    fn folding_into_zero_bits() {
+        SparseFingerprint::default().fold(0);
+    }
+
+    #[test]
+    #[should_panic(expected = "different lengths")]
+    fn intersection_of_different_lengths() {
+        BitFingerprint::new(64).intersection_count(&BitFingerprint::new(128));
+    }
+}
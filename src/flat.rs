@@ -0,0 +1,290 @@
+use crate::{
+    atom, bond_or_dot, ring_bond, ring_bonds_conflict, Atom, Bond, BondOrDot, ParseError,
+    ParseErrorKind,
+};
+
+/// A bond between two atoms of a [`FlatChain`], referring to them by index.
+#[derive(Debug, PartialEq, Eq, Ord, PartialOrd, Copy, Clone, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FlatBond {
+    pub source: usize,
+    pub target: usize,
+    /// `None` if the bond was implicit in the SMILES string.
+    pub bond: Option<Bond>,
+    /// The ring number if this bond was created by a ring closure.
+    pub ring_number: Option<u8>,
+}
+
+/// Flat, arena-style alternative to [`Chain`](crate::Chain).
+///
+/// Atoms are stored in the order they appear in the SMILES string, and bonds refer to them by
+/// index. Ring closures are resolved into bonds while parsing. Parsing a molecule only allocates
+/// the two vectors, which can be reused between molecules with [`parse_flat_into`].
+#[derive(Debug, Default, PartialEq, Eq, Clone, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FlatChain {
+    pub atoms: Vec<Atom>,
+    pub bonds: Vec<FlatBond>,
+}
+
+impl FlatChain {
+    pub fn clear(&mut self) {
+        self.atoms.clear();
+        self.bonds.clear();
+    }
+}
+
+/// Parse a complete SMILES string into a [`FlatChain`].
+///
+/// This accepts the same input as [`parse`](crate::parse), except that it is stricter about
+/// dangling bonds, unclosed branches and unclosed rings, which are reported as errors.
+pub fn parse_flat(input: &[u8]) -> Result<FlatChain, ParseError> {
+    let mut flat = FlatChain::default();
+    parse_flat_into(input, &mut flat)?;
+    Ok(flat)
+}
+
+/// Like [`parse_flat`], but reuses the allocations of an existing [`FlatChain`].
+///
+/// On error the contents of `flat` are unspecified.
+pub fn parse_flat_into(input: &[u8], flat: &mut FlatChain) -> Result<(), ParseError> {
+    flat.clear();
+    if input.is_empty() {
+        return Err(error(ParseErrorKind::Empty, 0));
+    }
+
+    let position = |rest: &[u8]| input.len() - rest.len();
+
+    // Atom and position of the opening digit for every currently open ring number
+    let mut open_rings: [Option<(usize, Option<Bond>, usize)>; 100] = [None; 100];
+    // Atoms that the currently open branches are attached to
+    let mut branch_points: Vec<usize> = Vec::new();
+
+    let mut rest = input;
+    let mut previous: Option<usize> = None;
+    let mut previous_bond: Option<BondOrDot> = None;
+
+    loop {
+        let (after_atom, parsed_atom) =
+            atom(rest).map_err(|_| error(ParseErrorKind::InvalidAtom, position(rest)))?;
+        rest = after_atom;
+
+        let current = flat.atoms.len();
+        flat.atoms.push(parsed_atom);
+        if let Some(previous) = previous {
+            let bond = match previous_bond {
+                Some(BondOrDot::Dot(_)) => None,
+                Some(BondOrDot::Bond(bond)) => Some(Some(bond)),
+                None => Some(None),
+            };
+            if let Some(bond) = bond {
+                flat.bonds.push(FlatBond {
+                    source: previous,
+                    target: current,
+                    bond,
+                    ring_number: None,
+                });
+            }
+        }
+
+        while let Ok((after_ring_bond, parsed_ring_bond)) = ring_bond(rest) {
+            let ring_bond_position = position(rest);
+            rest = after_ring_bond;
+
+            let slot = &mut open_rings[parsed_ring_bond.ring_number as usize];
+            match slot.take() {
+                Some((opening, opening_bond, _)) => {
+                    let kind = if opening == current {
+                        Some(ParseErrorKind::RingBondToSelf)
+                    } else if flat
+                        .bonds
+                        .iter()
+                        .rev()
+                        .take_while(|bond| bond.target == current)
+                        .any(|bond| bond.source == opening)
+                    {
+                        Some(ParseErrorKind::DuplicateBond)
+                    } else if ring_bonds_conflict(opening_bond, parsed_ring_bond.bond) {
+                        Some(ParseErrorKind::ConflictingRingBond)
+                    } else {
+                        None
+                    };
+                    if let Some(kind) = kind {
+                        return Err(error(kind, ring_bond_position));
+                    }
+                    flat.bonds.push(FlatBond {
+                        source: opening,
+                        target: current,
+                        bond: opening_bond.or(parsed_ring_bond.bond),
+                        ring_number: Some(parsed_ring_bond.ring_number),
+                    });
+                }
+                None => *slot = Some((current, parsed_ring_bond.bond, ring_bond_position)),
+            }
+        }
+
+        // Branches and the continuation of the chain both attach to `attachment`, which is the
+        // current atom until a branch is closed.
+        let mut attachment = current;
+        loop {
+            match rest.first() {
+                Some(b'(') => {
+                    branch_points.push(attachment);
+                    rest = &rest[1..];
+                    break;
+                }
+                Some(b')') => match branch_points.pop() {
+                    Some(branch_point) => {
+                        attachment = branch_point;
+                        rest = &rest[1..];
+                    }
+                    None => return Err(error(ParseErrorKind::UnexpectedInput, position(rest))),
+                },
+                Some(_) => break,
+                None => {
+                    if !branch_points.is_empty() {
+                        return Err(error(ParseErrorKind::UnclosedBranch, input.len()));
+                    }
+                    if let Some((_, _, ring_position)) = open_rings.iter().flatten().next() {
+                        return Err(error(ParseErrorKind::UnclosedRing, *ring_position));
+                    }
+                    return Ok(());
+                }
+            }
+        }
+
+        previous = Some(attachment);
+        previous_bond = match bond_or_dot(rest) {
+            Ok((after_bond, parsed_bond)) => {
+                rest = after_bond;
+                Some(parsed_bond)
+            }
+            Err(_) => None,
+        };
+    }
+}
+
+fn error(kind: ParseErrorKind, position: usize) -> ParseError {
+    ParseError { kind, position }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::AliphaticOrganicAtom;
+    use ptable::Element;
+
+    fn bond(source: usize, target: usize, bond: Option<Bond>) -> FlatBond {
+        FlatBond {
+            source,
+            target,
+            bond,
+            ring_number: None,
+        }
+    }
+
+    #[test]
+    fn flat_branches() {
+        let flat = parse_flat(b"CC(C)(=C)CO").unwrap();
+        assert_eq!(6, flat.atoms.len());
+        assert_eq!(
+            Atom::AliphaticOrganic(AliphaticOrganicAtom {
+                element: Element::Oxygen
+            }),
+            flat.atoms[5]
+        );
+        assert_eq!(
+            vec![
+                bond(0, 1, None),
+                bond(1, 2, None),
+                bond(1, 3, Some(Bond::Double)),
+                bond(1, 4, None),
+                bond(4, 5, None),
+            ],
+            flat.bonds
+        );
+    }
+
+    #[test]
+    fn flat_rings_and_dots() {
+        let flat = parse_flat(b"C1CC=1.[Na+]").unwrap();
+        assert_eq!(4, flat.atoms.len());
+        assert_eq!(
+            vec![
+                bond(0, 1, None),
+                bond(1, 2, None),
+                FlatBond {
+                    source: 0,
+                    target: 2,
+                    bond: Some(Bond::Double),
+                    ring_number: Some(1),
+                },
+            ],
+            flat.bonds
+        );
+    }
+
+    #[test]
+    fn flat_matches_chain() {
+        for (smiles, atoms, bonds) in &[
+            ("C1CCC2(CC1)CO2", 8, 9),
+            ("F[As@TB15](Cl)(S)(Br)N", 6, 5),
+            ("CC(C)(C)C", 5, 4),
+            ("C(C(C))C", 4, 3),
+        ] {
+            let flat = parse_flat(smiles.as_bytes()).unwrap();
+            assert!(crate::parse(smiles.as_bytes()).is_ok());
+            assert_eq!(*atoms, flat.atoms.len());
+            assert_eq!(*bonds, flat.bonds.len());
+        }
+    }
+
+    #[test]
+    fn flat_errors() {
+        assert_eq!(Err(error(ParseErrorKind::Empty, 0)), parse_flat(b""));
+        assert_eq!(
+            Err(error(ParseErrorKind::InvalidAtom, 3)),
+            parse_flat(b"CC=")
+        );
+        assert_eq!(
+            Err(error(ParseErrorKind::UnexpectedInput, 2)),
+            parse_flat(b"CC)")
+        );
+        assert_eq!(
+            Err(error(ParseErrorKind::UnclosedBranch, 4)),
+            parse_flat(b"CC(C")
+        );
+        assert_eq!(
+            Err(error(ParseErrorKind::UnclosedRing, 2)),
+            parse_flat(b"CC1CC2CC2")
+        );
+        assert_eq!(
+            Err(error(ParseErrorKind::RingBondToSelf, 2)),
+            parse_flat(b"C11")
+        );
+        assert_eq!(
+            Err(error(ParseErrorKind::DuplicateBond, 3)),
+            parse_flat(b"C1C1")
+        );
+        assert_eq!(
+            Err(error(ParseErrorKind::DuplicateBond, 6)),
+            parse_flat(b"C12CC12")
+        );
+        assert_eq!(
+            Err(error(ParseErrorKind::ConflictingRingBond, 5)),
+            parse_flat(b"C=1CC-1")
+        );
+        assert!(parse_flat(b"C=1CC=1").is_ok());
+        assert!(parse_flat(b"C=1CC1").is_ok());
+        assert!(parse_flat(b"C/1CCC\\1").is_ok());
+    }
+
+    #[test]
+    fn flat_reuses_allocation() {
+        let mut flat = FlatChain::default();
+        parse_flat_into(b"CCCC", &mut flat).unwrap();
+        parse_flat_into(b"CC", &mut flat).unwrap();
+        assert_eq!(2, flat.atoms.len());
+        assert_eq!(1, flat.bonds.len());
+    }
+}
@@ -1,116 +1,553 @@
+//! Molecules as graphs of atoms and bonds, built from parsed SMILES strings.
+//!
+//! # Aromaticity
+//!
+//! Aromaticity is taken from the input as written and isn't perceived: atoms and bonds are
+//! aromatic if the SMILES string or Molfile says so. The Kekulé and the aromatic form of a
+//! molecule, like `C1=CC=CC=C1` and `c1ccccc1`, are different graphs, so everything that is
+//! computed from them, like canonical SMILES, fingerprints and descriptors, differs too.
+
+use std::collections::HashMap;
+
 use derive_more::{AsRef, Deref, DerefMut};
 use itertools::Itertools;
 use petgraph::algo::astar;
-use petgraph::graph::NodeIndex;
-use petgraph::visit::{IntoNodeIdentifiers, NodeFiltered};
+use petgraph::graph::{EdgeIndex, NodeIndex};
+use petgraph::visit::{EdgeRef, IntoNodeIdentifiers, NodeFiltered};
 use petgraph::{Graph, Undirected};
 use ptable::Element;
 
-use crate::{AliphaticOrganicAtom, Bond, BondOrDot, Chain};
+use crate::visit::{walk_chain, Visitor};
+use crate::{
+    AliphaticOrganicAtom, AromaticOrganicAtom, Bond, BondOrDot, BracketAtom, Branch, BranchedAtom,
+    Chain, Chirality, Dot, RingBond, Span, Symbol,
+};
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Atom {
     AliphaticOrganic(AliphaticOrganicAtom),
-    Element(Element),
+    AromaticOrganic(AromaticOrganicAtom),
+    Bracket(BracketAtom),
+    Element(#[cfg_attr(feature = "serde", serde(with = "crate::serialization::element"))] Element),
+    Unknown,
 }
 
-impl Into<Atom> for crate::Atom {
-    fn into(self) -> Atom {
-        match self {
+impl From<crate::Atom> for Atom {
+    fn from(atom: crate::Atom) -> Self {
+        match atom {
             crate::Atom::AliphaticOrganic(inner) => Atom::AliphaticOrganic(inner),
-            _ => panic!(),
+            crate::Atom::AromaticOrganic(inner) => Atom::AromaticOrganic(inner),
+            crate::Atom::Bracket(inner) => Atom::Bracket(inner),
+            crate::Atom::Unknown => Atom::Unknown,
         }
     }
 }
 
+impl Atom {
+    /// The element of the atom, or `None` for wildcard atoms.
+    pub fn element(&self) -> Option<Element> {
+        match self {
+            Atom::AliphaticOrganic(atom) => Some(atom.element),
+            Atom::AromaticOrganic(atom) => Some(atom.element),
+            Atom::Bracket(atom) => match atom.symbol {
+                Symbol::ElementSymbol(element) | Symbol::AromaticSymbol(element) => Some(element),
+                Symbol::Unknown => None,
+            },
+            Atom::Element(element) => Some(*element),
+            Atom::Unknown => None,
+        }
+    }
+
+    pub fn is_aromatic(&self) -> bool {
+        match self {
+            Atom::AromaticOrganic(_) => true,
+            Atom::Bracket(atom) => matches!(atom.symbol, Symbol::AromaticSymbol(_)),
+            _ => false,
+        }
+    }
+
+    pub fn is_hydrogen(&self) -> bool {
+        self.element() == Some(Element::Hydrogen)
+    }
+
+    pub fn charge(&self) -> i8 {
+        match self {
+            Atom::Bracket(atom) => atom.charge,
+            _ => 0,
+        }
+    }
+
+    pub fn isotope(&self) -> Option<u16> {
+        match self {
+            Atom::Bracket(atom) => atom.isotope,
+            _ => None,
+        }
+    }
+
+    pub fn chirality(&self) -> Option<Chirality> {
+        match self {
+            Atom::Bracket(atom) => atom.chiral,
+            _ => None,
+        }
+    }
+}
+
+/// Contribution of a bond to the valence of the atoms it connects.
+///
+/// Aromatic bonds count as single bonds; the extra electron of aromatic atoms is accounted for
+/// separately by [`implicit_hydrogen_count`].
+pub(crate) fn bond_order(bond: Bond) -> u8 {
+    match bond {
+        Bond::Single | Bond::Up | Bond::Down | Bond::Aromatic => 1,
+        Bond::Double => 2,
+        Bond::Triple => 3,
+        Bond::Quadruple => 4,
+    }
+}
+
+/// Normal valences of the elements in the organic subset, as defined by OpenSMILES.
+pub(crate) fn normal_valences(element: Element) -> &'static [u8] {
+    match element {
+        Element::Boron => &[3],
+        Element::Carbon => &[4],
+        Element::Nitrogen | Element::Phosphorus => &[3, 5],
+        Element::Oxygen => &[2],
+        Element::Sulfur => &[2, 4, 6],
+        Element::Fluorine | Element::Chlorine | Element::Bromine | Element::Iodine => &[1],
+        _ => &[],
+    }
+}
+
+/// Number of implicit hydrogens of an organic subset atom with the given sum of bond orders.
+///
+/// Aromatic atoms only use their lowest valence, so that e.g. the nitrogen of `Cn1cccc1` doesn't
+/// get a hydrogen.
+pub(crate) fn implicit_hydrogen_count(element: Element, bond_order_sum: u8, aromatic: bool) -> u8 {
+    let used = bond_order_sum + aromatic as u8;
+    let valences = normal_valences(element);
+    let valences = if aromatic {
+        &valences[..valences.len().min(1)]
+    } else {
+        valences
+    };
+    valences
+        .iter()
+        .find(|&&valence| valence >= used)
+        .map_or(0, |valence| valence - used)
+}
+
+/// Whether an element can be written as an aromatic atom of the organic subset.
+pub(crate) fn is_aromatic_organic(element: Element) -> bool {
+    matches!(
+        element,
+        Element::Boron
+            | Element::Carbon
+            | Element::Nitrogen
+            | Element::Oxygen
+            | Element::Sulfur
+            | Element::Phosphorus
+    )
+}
+
+fn flip_bond(bond: Bond) -> Bond {
+    match bond {
+        Bond::Up => Bond::Down,
+        Bond::Down => Bond::Up,
+        other => other,
+    }
+}
+
+/// A molecule as an undirected graph of atoms and bonds.
+///
+/// When built from a [`Chain`], the atoms are added in the order in which they appear in the
+/// SMILES string, followed by one explicit hydrogen atom for every implicit hydrogen.
+///
+/// Every edge is added from the atom that appears first in the SMILES string to the one that
+/// appears later, so the direction of [`Bond::Up`] and [`Bond::Down`] can be read from the edge
+/// endpoints. Tetrahedral chirality is normalized to be relative to the neighbors of an atom
+/// ordered by their node index, so that `@` (`Chirality::Anticlockwise`) means that, looking
+/// from the neighbor with the lowest index, the remaining neighbors are arranged anticlockwise.
+///
+/// Atoms can have 2D coordinates, see [`MoleculeGraph::compute_2d_coords`], and atoms built from
+/// a [`Chain`] know where they were written in the SMILES string, see [`MoleculeGraph::span`].
 #[derive(Default, Clone, AsRef, Deref, DerefMut)]
-pub struct MoleculeGraph(Graph<Atom, Bond, Undirected>);
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MoleculeGraph {
+    #[as_ref]
+    #[deref]
+    #[deref_mut]
+    graph: Graph<Atom, Bond, Undirected>,
+    // 2D coordinates by node index. Atoms added after the coordinates were computed have none.
+    #[cfg_attr(feature = "serde", serde(default))]
+    coordinates: Vec<Option<[f64; 2]>>,
+    // Spans of the atoms in the SMILES string by node index. Atoms that were not written in it
+    // have none.
+    #[cfg_attr(feature = "serde", serde(default))]
+    spans: Vec<Option<Span>>,
+}
+
+/// A molecule graph without coordinates or spans.
+impl From<Graph<Atom, Bond, Undirected>> for MoleculeGraph {
+    fn from(graph: Graph<Atom, Bond, Undirected>) -> Self {
+        MoleculeGraph {
+            graph,
+            ..MoleculeGraph::default()
+        }
+    }
+}
 
 impl MoleculeGraph {
+    /// Build the graph of a parsed SMILES string.
+    ///
+    /// [`parse`](crate::parse) rejects ring bonds that close on their own atom or bond two atoms
+    /// a second time. Such ring bonds in chains built by hand are left out of the graph.
     pub fn from_chain(chain: Chain) -> Self {
-        let mut graph = MoleculeGraph::default();
-
-        fn add_chain_to_graph(
-            graph: &mut MoleculeGraph,
-            chain: &Chain,
-            previous_node: Option<NodeIndex>,
-            branch_bond: Option<Bond>,
-        ) {
-            let branched_atom = chain.branched_atom.clone();
-            let current_node = graph.add_node(branched_atom.atom.into());
-            if let Some(previous_node) = previous_node {
-                let mut bond = branch_bond;
-                if bond.is_none() {
-                    bond = Some(
-                        chain
-                            .bond_or_dot
-                            .as_ref()
-                            .map(|n| match n {
-                                BondOrDot::Bond(bond) => Some(bond),
-                                _ => None,
-                            })
-                            .flatten()
-                            .unwrap_or(&Bond::Single)
-                            .to_owned(),
-                    );
+        let mut builder = GraphBuilder::default();
+        builder.add_chain(&chain);
+        builder.finish()
+    }
+
+    /// The 2D coordinates of an atom, if they have been computed.
+    pub fn coordinates_2d(&self, atom: NodeIndex) -> Option<[f64; 2]> {
+        self.coordinates.get(atom.index()).copied().flatten()
+    }
+
+    /// The span of an atom in the SMILES string it was parsed from, without its ring bonds and
+    /// branches. Hydrogens added for hydrogen counts and atoms of graphs that were not built
+    /// from a SMILES string have none.
+    pub fn span(&self, atom: NodeIndex) -> Option<Span> {
+        self.spans.get(atom.index()).copied().flatten()
+    }
+
+    /// Set the 2D coordinates of all atoms, by node index.
+    pub fn set_coordinates_2d(&mut self, coordinates: Vec<[f64; 2]>) {
+        self.coordinates = coordinates.into_iter().map(Some).collect();
+    }
+
+    /// Sum of the bond orders of all bonds of an atom.
+    pub fn bond_order_sum(&self, atom: NodeIndex) -> u8 {
+        self.edges(atom)
+            .map(|edge| bond_order(*edge.weight()))
+            .sum()
+    }
+
+    /// Whether an atom is a hydrogen atom that only serves as a hydrogen count of the single
+    /// atom it is attached to, like the explicit hydrogen atoms added for implicit hydrogens.
+    ///
+    /// Charged hydrogens and hydrogens bonded to other hydrogens are not attached hydrogens.
+    pub fn is_attached_hydrogen(&self, atom: NodeIndex) -> bool {
+        if !self[atom].is_hydrogen() || self[atom].charge() != 0 {
+            return false;
+        }
+        let mut neighbors = self.neighbors(atom);
+        match (neighbors.next(), neighbors.next()) {
+            (Some(neighbor), None) => !self[neighbor].is_hydrogen(),
+            _ => false,
+        }
+    }
+
+    /// The atom at the other end of a bond of `atom`.
+    pub(crate) fn other_end(&self, edge: EdgeIndex, atom: NodeIndex) -> NodeIndex {
+        let (source, target) = self.edge_endpoints(edge).unwrap();
+        if source == atom {
+            target
+        } else {
+            source
+        }
+    }
+
+    /// Number of attached hydrogens of an atom.
+    pub fn hydrogen_count(&self, atom: NodeIndex) -> u8 {
+        self.neighbors(atom)
+            .filter(|&neighbor| self.is_attached_hydrogen(neighbor))
+            .count() as u8
+    }
+
+    /// Whether each bond, by edge index, is part of a ring.
+    pub fn ring_bonds(&self) -> Vec<bool> {
+        // A bond is part of a ring if it is not a bridge. Bridges are found with an iterative
+        // depth-first search that keeps the lowest discovery time reachable from every atom.
+        let mut ring_bonds = vec![true; self.edge_count()];
+        let mut discovered = vec![usize::MAX; self.node_count()];
+        let mut low = vec![0; self.node_count()];
+        let mut time = 0;
+
+        for root in self.node_indices() {
+            if discovered[root.index()] != usize::MAX {
+                continue;
+            }
+            discovered[root.index()] = time;
+            low[root.index()] = time;
+            time += 1;
+            let mut stack = vec![(root, None, self.edges(root))];
+            while let Some((atom, parent_edge, edges)) = stack.last_mut() {
+                let (atom, parent_edge) = (*atom, *parent_edge);
+                match edges.next() {
+                    Some(edge) if Some(edge.id()) == parent_edge => {}
+                    Some(edge) => {
+                        let neighbor = self.other_end(edge.id(), atom);
+                        if discovered[neighbor.index()] == usize::MAX {
+                            discovered[neighbor.index()] = time;
+                            low[neighbor.index()] = time;
+                            time += 1;
+                            stack.push((neighbor, Some(edge.id()), self.edges(neighbor)));
+                        } else {
+                            low[atom.index()] = low[atom.index()].min(discovered[neighbor.index()]);
+                        }
+                    }
+                    None => {
+                        stack.pop();
+                        if let (Some(edge), Some((parent, _, _))) = (parent_edge, stack.last()) {
+                            let parent = parent.index();
+                            low[parent] = low[parent].min(low[atom.index()]);
+                            if low[atom.index()] > discovered[parent] {
+                                ring_bonds[edge.index()] = false;
+                            }
+                        }
+                    }
                 }
-                let bond = bond.unwrap();
-                graph.add_edge(current_node, previous_node, bond.clone());
             }
+        }
+        ring_bonds
+    }
+
+    /// Whether each atom, by node index, is part of a ring.
+    pub fn ring_atoms(&self) -> Vec<bool> {
+        let ring_bonds = self.ring_bonds();
+        let mut ring_atoms = vec![false; self.node_count()];
+        for edge in self.edge_indices().filter(|edge| ring_bonds[edge.index()]) {
+            let (first, second) = self.edge_endpoints(edge).unwrap();
+            ring_atoms[first.index()] = true;
+            ring_atoms[second.index()] = true;
+        }
+        ring_atoms
+    }
 
-            if let Some(chain) = &chain.chain {
-                add_chain_to_graph(graph, &*chain, Some(current_node), None);
+    /// The smallest set of smallest rings, each as its atoms in ring order.
+    ///
+    /// Candidate rings are the shortest cycles through every ring atom and ring bond (Horton's
+    /// candidates), of which the shortest linearly independent ones are kept. The number and
+    /// sizes of the rings are unique, the choice between rings of equal size may not be.
+    pub fn rings(&self) -> Vec<Vec<NodeIndex>> {
+        let ring_bonds = self.ring_bonds();
+        let ring_edges: Vec<EdgeIndex> = self
+            .edge_indices()
+            .filter(|edge| ring_bonds[edge.index()])
+            .collect();
+        if ring_edges.is_empty() {
+            return Vec::new();
+        }
+
+        let mut adjacency: Vec<Vec<(NodeIndex, EdgeIndex)>> = vec![Vec::new(); self.node_count()];
+        for &edge in &ring_edges {
+            let (first, second) = self.edge_endpoints(edge).unwrap();
+            adjacency[first.index()].push((second, edge));
+            adjacency[second.index()].push((first, edge));
+        }
+        let ring_atoms: Vec<NodeIndex> = self
+            .node_indices()
+            .filter(|atom| !adjacency[atom.index()].is_empty())
+            .collect();
+
+        // Number of independent rings: edges - atoms + components of the ring bond subgraph.
+        let mut component_count = 0;
+        let mut seen = vec![false; self.node_count()];
+        for &atom in &ring_atoms {
+            if seen[atom.index()] {
+                continue;
+            }
+            component_count += 1;
+            seen[atom.index()] = true;
+            let mut stack = vec![atom];
+            while let Some(atom) = stack.pop() {
+                for &(neighbor, _) in &adjacency[atom.index()] {
+                    if !seen[neighbor.index()] {
+                        seen[neighbor.index()] = true;
+                        stack.push(neighbor);
+                    }
+                }
             }
+        }
+        let ring_count = ring_edges.len() + component_count - ring_atoms.len();
 
-            for branch in branched_atom.branches {
-                let branch_bond = branch
-                    .bond_or_dot
-                    .clone()
-                    .map(|n| match n {
-                        BondOrDot::Bond(bond) => Some(bond),
-                        _ => None,
-                    })
-                    .flatten();
-                add_chain_to_graph(graph, &branch.chain, Some(current_node), branch_bond)
+        // Candidate rings as (atoms, bond set)
+        let words = self.edge_count().div_ceil(64);
+        let mut candidates: Vec<(Vec<NodeIndex>, Vec<u64>)> = Vec::new();
+        for &root in &ring_atoms {
+            let mut parent: Vec<Option<(NodeIndex, EdgeIndex)>> = vec![None; self.node_count()];
+            let mut distance = vec![usize::MAX; self.node_count()];
+            distance[root.index()] = 0;
+            let mut queue = std::collections::VecDeque::from(vec![root]);
+            while let Some(atom) = queue.pop_front() {
+                for &(neighbor, edge) in &adjacency[atom.index()] {
+                    if distance[neighbor.index()] == usize::MAX {
+                        distance[neighbor.index()] = distance[atom.index()] + 1;
+                        parent[neighbor.index()] = Some((atom, edge));
+                        queue.push_back(neighbor);
+                    }
+                }
+            }
+
+            let path = |mut atom: NodeIndex| {
+                let mut atoms = vec![atom];
+                let mut edges = vec![];
+                while let Some((previous, edge)) = parent[atom.index()] {
+                    atoms.push(previous);
+                    edges.push(edge);
+                    atom = previous;
+                }
+                (atoms, edges)
+            };
+            for &edge in &ring_edges {
+                let (first, second) = self.edge_endpoints(edge).unwrap();
+                let is_tree_edge =
+                    |atom: NodeIndex| matches!(parent[atom.index()], Some((_, e)) if e == edge);
+                let unreachable = distance[first.index()] == usize::MAX;
+                if unreachable || is_tree_edge(first) || is_tree_edge(second) {
+                    continue;
+                }
+                let (first_atoms, first_edges) = path(first);
+                let (second_atoms, second_edges) = path(second);
+                // Both paths end at the root; they must not share any other atom.
+                if first_atoms[..first_atoms.len() - 1]
+                    .iter()
+                    .any(|atom| second_atoms.contains(atom))
+                {
+                    continue;
+                }
+
+                let mut bonds = vec![0u64; words];
+                for edge in first_edges.iter().chain(&second_edges).chain(Some(&edge)) {
+                    bonds[edge.index() / 64] |= 1 << (edge.index() % 64);
+                }
+                let mut atoms: Vec<NodeIndex> = first_atoms.into_iter().rev().collect();
+                atoms.extend(&second_atoms[..second_atoms.len() - 1]);
+                candidates.push((atoms, bonds));
             }
         }
+        candidates.sort_by_key(|(atoms, _)| atoms.len());
 
-        fn fill_graph_with_hydrogen(graph: &mut MoleculeGraph) {
-            for atom_index in graph.node_indices() {
-                let atom = graph.node_weight(atom_index).unwrap();
+        // Keep the shortest candidates that are independent of the ones kept before, using
+        // Gaussian elimination over the bond sets.
+        let mut basis: Vec<(usize, Vec<u64>)> = Vec::new();
+        let mut rings = Vec::new();
+        for (atoms, mut bonds) in candidates {
+            if rings.len() == ring_count {
+                break;
+            }
+            for (pivot, vector) in &basis {
+                if bonds[pivot / 64] & (1 << (pivot % 64)) != 0 {
+                    for (word, other) in bonds.iter_mut().zip(vector) {
+                        *word ^= other;
+                    }
+                }
+            }
+            if let Some(word) = bonds.iter().position(|&word| word != 0) {
+                let pivot = word * 64 + bonds[word].trailing_zeros() as usize;
+                basis.push((pivot, bonds));
+                rings.push(atoms);
+            }
+        }
+        rings
+    }
 
-                let desired_bonds_num = match atom {
-                    Atom::AliphaticOrganic(atom) => match atom.element {
-                        Element::Carbon => Some(4),
-                        Element::Phosphorus => Some(5),
-                        Element::Oxygen => Some(2),
-                        _ => None,
-                    },
-                    _ => None,
+    /// Add explicit hydrogen atoms to all atoms of the organic subset according to their normal
+    /// valences, and to bracket atoms according to their hydrogen count.
+    pub(crate) fn add_hydrogens(&mut self) -> Vec<Vec<NodeIndex>> {
+        let mut added = vec![Vec::new(); self.node_count()];
+        for atom_index in self.node_indices().collect::<Vec<_>>() {
+            let needed_hydrogen = match &self[atom_index] {
+                Atom::AliphaticOrganic(atom) => {
+                    implicit_hydrogen_count(atom.element, self.bond_order_sum(atom_index), false)
                 }
-                .expect("Can't handle this atom yet");
-
-                let neighbor_edges = graph.edges(atom_index).collect::<Vec<_>>();
-                let current_bonds_num: usize = neighbor_edges
-                    .into_iter()
-                    .map(|bond| match bond.weight() {
-                        Bond::Single => 1,
-                        Bond::Double => 2,
-                        _ => panic!("Can't handle this bond type yet"),
-                    })
-                    .sum();
-                let needed_hydrogen = desired_bonds_num - current_bonds_num;
-                for _ in 0..needed_hydrogen {
-                    let new_atom_idx = graph.add_node(Atom::Element(Element::Hydrogen));
-                    graph.add_edge(atom_index, new_atom_idx, Bond::Single);
+                Atom::AromaticOrganic(atom) => {
+                    implicit_hydrogen_count(atom.element, self.bond_order_sum(atom_index), true)
                 }
+                Atom::Bracket(atom) => atom.hcount,
+                Atom::Element(_) | Atom::Unknown => 0,
+            };
+            for _ in 0..needed_hydrogen {
+                let new_atom_idx = self.add_node(Atom::Element(Element::Hydrogen));
+                self.add_edge(atom_index, new_atom_idx, Bond::Single);
+                added[atom_index.index()].push(new_atom_idx);
             }
         }
+        added
+    }
 
-        add_chain_to_graph(&mut graph, &chain, None, None);
-        fill_graph_with_hydrogen(&mut graph);
+    /// Replace an atom with one of the same element and the given properties, and the hydrogen
+    /// count of its attached hydrogens. The organic subset form is used if that doesn't change
+    /// the hydrogen count.
+    pub(crate) fn rebuild_atom(
+        &mut self,
+        atom: NodeIndex,
+        isotope: Option<u16>,
+        chiral: Option<Chirality>,
+        charge: i8,
+    ) {
+        let aromatic = self[atom].is_aromatic();
+        let hcount = self.hydrogen_count(atom);
+        let plain = isotope.is_none() && chiral.is_none() && charge == 0;
+        let new_atom = match self[atom].element() {
+            Some(Element::Hydrogen) if plain && hcount == 0 => Atom::Element(Element::Hydrogen),
+            Some(element)
+                if plain
+                    && !normal_valences(element).is_empty()
+                    && (!aromatic || is_aromatic_organic(element))
+                    && implicit_hydrogen_count(
+                        element,
+                        self.bond_order_sum(atom) - hcount,
+                        aromatic,
+                    ) == hcount =>
+            {
+                if aromatic {
+                    Atom::AromaticOrganic(AromaticOrganicAtom { element })
+                } else {
+                    Atom::AliphaticOrganic(AliphaticOrganicAtom { element })
+                }
+            }
+            element => Atom::Bracket(BracketAtom {
+                isotope,
+                symbol: match element {
+                    Some(element) if aromatic => Symbol::AromaticSymbol(element),
+                    Some(element) => Symbol::ElementSymbol(element),
+                    None => Symbol::Unknown,
+                },
+                chiral,
+                hcount,
+                charge,
+            }),
+        };
+        self[atom] = new_atom;
+    }
 
-        graph
+    /// A copy of the graph with only the atoms for which `keep` is true, by node index, and the
+    /// bonds between them. Atoms keep their relative order, so chirality stays valid as long as
+    /// no neighbor of a chiral atom is dropped.
+    pub(crate) fn subgraph(&self, keep: &[bool]) -> MoleculeGraph {
+        let mut subgraph = MoleculeGraph::default();
+        let mut new_indices = vec![None; self.node_count()];
+        for atom in self.node_indices().filter(|atom| keep[atom.index()]) {
+            new_indices[atom.index()] = Some(subgraph.add_node(self[atom].clone()));
+            subgraph.coordinates.push(self.coordinates_2d(atom));
+            subgraph.spans.push(self.span(atom));
+        }
+        for edge in self.edge_references() {
+            if let (Some(source), Some(target)) = (
+                new_indices[edge.source().index()],
+                new_indices[edge.target().index()],
+            ) {
+                subgraph.add_edge(source, target, *edge.weight());
+            }
+        }
+        if subgraph.coordinates.iter().all(Option::is_none) {
+            subgraph.coordinates.clear();
+        }
+        if subgraph.spans.iter().all(Option::is_none) {
+            subgraph.spans.clear();
+        }
+        subgraph
     }
 
     pub fn find_main_carbon_chain(&self) -> Vec<NodeIndex> {
@@ -128,15 +565,14 @@ impl MoleculeGraph {
         let all_paths: Vec<_> = node_pairs
             .into_iter()
             .map(|pair| {
-                let path = astar(
+                astar(
                     &carbon_atoms,
                     pair[0],
                     |finish| finish == pair[1],
                     |_| 1,
                     |_| 0,
                 )
-                .unwrap();
-                path
+                .unwrap()
             })
             .collect();
         let longest_path = all_paths.into_iter().max_by_key(|n| n.0).unwrap();
@@ -144,3 +580,389 @@ impl MoleculeGraph {
         longest_path.1
     }
 }
+
+/// A neighbor of an atom, in the order in which it appears in the SMILES string.
+#[derive(Debug, Clone, Copy)]
+enum Neighbor {
+    Atom(NodeIndex),
+    ImplicitHydrogen,
+    /// A ring bond that has not been closed (yet)
+    OpenRing,
+}
+
+#[derive(Default)]
+struct GraphBuilder {
+    graph: MoleculeGraph,
+    // Used to normalize the chirality of atoms once all neighbors are known
+    neighbors: Vec<Vec<Neighbor>>,
+    // Opening atom, bond and position in the neighbor list of every open ring bond
+    open_rings: HashMap<u8, (NodeIndex, Option<Bond>, usize)>,
+    // The last atom at every depth of branches up to the current one
+    atoms: Vec<NodeIndex>,
+    // The bond or dot before the next atom
+    bond_or_dot: Option<BondOrDot>,
+}
+
+impl GraphBuilder {
+    fn add_chain(&mut self, chain: &Chain) {
+        // Atoms are visited in SMILES order: all branches of an atom come before the
+        // continuation of the chain. This is required to resolve ring bonds correctly.
+        walk_chain(self, chain);
+    }
+
+    fn add_atom(
+        &mut self,
+        atom: crate::Atom,
+        previous: Option<NodeIndex>,
+        bond_or_dot: Option<BondOrDot>,
+    ) -> NodeIndex {
+        let current = self.graph.add_node(atom.into());
+        self.neighbors.push(Vec::new());
+
+        if let Some(previous) = previous {
+            match bond_or_dot {
+                Some(BondOrDot::Dot(_)) => {}
+                Some(BondOrDot::Bond(bond)) => self.add_bond(previous, current, bond),
+                None => {
+                    let bond = self.implicit_bond(previous, current);
+                    self.add_bond(previous, current, bond);
+                }
+            }
+        }
+
+        if let crate::Atom::Bracket(bracket_atom) = atom {
+            for _ in 0..bracket_atom.hcount {
+                self.neighbors[current.index()].push(Neighbor::ImplicitHydrogen);
+            }
+        }
+
+        current
+    }
+
+    fn add_ring_bond(&mut self, current: NodeIndex, ring_bond: &RingBond) {
+        match self.open_rings.remove(&ring_bond.ring_number) {
+            Some((opening, _, _))
+                if opening == current || self.graph.find_edge(opening, current).is_some() => {}
+            Some((opening, opening_bond, position)) => {
+                // A bond symbol at the closing digit is written from the perspective of the
+                // closing atom, so its direction has to be flipped.
+                let bond = opening_bond
+                    .or_else(|| ring_bond.bond.map(flip_bond))
+                    .unwrap_or_else(|| self.implicit_bond(opening, current));
+                self.graph.add_edge(opening, current, bond);
+                self.neighbors[opening.index()][position] = Neighbor::Atom(current);
+                self.neighbors[current.index()].push(Neighbor::Atom(opening));
+            }
+            None => {
+                let neighbors = &mut self.neighbors[current.index()];
+                neighbors.push(Neighbor::OpenRing);
+                self.open_rings.insert(
+                    ring_bond.ring_number,
+                    (current, ring_bond.bond, neighbors.len() - 1),
+                );
+            }
+        }
+    }
+
+    fn add_bond(&mut self, previous: NodeIndex, current: NodeIndex, bond: Bond) {
+        self.graph.add_edge(previous, current, bond);
+        self.neighbors[previous.index()].push(Neighbor::Atom(current));
+        self.neighbors[current.index()].push(Neighbor::Atom(previous));
+    }
+
+    fn implicit_bond(&self, first: NodeIndex, second: NodeIndex) -> Bond {
+        if self.graph[first].is_aromatic() && self.graph[second].is_aromatic() {
+            Bond::Aromatic
+        } else {
+            Bond::Single
+        }
+    }
+
+    fn finish(mut self) -> MoleculeGraph {
+        let hydrogens = self.graph.add_hydrogens();
+
+        for (atom_index, neighbors) in self.neighbors.iter().enumerate() {
+            let atom_index = NodeIndex::new(atom_index);
+            let mut implicit_hydrogens = hydrogens[atom_index.index()].iter();
+            let smiles_order: Vec<NodeIndex> = neighbors
+                .iter()
+                .filter_map(|neighbor| match neighbor {
+                    Neighbor::Atom(index) => Some(*index),
+                    Neighbor::ImplicitHydrogen => implicit_hydrogens.next().copied(),
+                    Neighbor::OpenRing => None,
+                })
+                .collect();
+            normalize_chirality(&mut self.graph[atom_index], &smiles_order);
+        }
+
+        self.graph
+    }
+}
+
+impl Visitor for GraphBuilder {
+    fn visit_atom(&mut self, atom: &BranchedAtom, depth: usize) {
+        // The first atom of a branch is bonded to the last atom of the enclosing chain.
+        let previous = self.atoms.get(depth).or_else(|| self.atoms.last()).copied();
+        let bond_or_dot = self.bond_or_dot.take();
+        let current = self.add_atom(atom.atom, previous, bond_or_dot);
+        self.graph.spans.push(Some(atom.atom_span()));
+        self.atoms.truncate(depth);
+        self.atoms.push(current);
+    }
+
+    fn visit_bond(&mut self, bond: &Bond, _depth: usize) {
+        self.bond_or_dot = Some(BondOrDot::Bond(*bond));
+    }
+
+    fn visit_dot(&mut self, dot: &Dot, _depth: usize) {
+        self.bond_or_dot = Some(BondOrDot::Dot(*dot));
+    }
+
+    fn visit_ring_bond(&mut self, ring_bond: &RingBond, _depth: usize) {
+        let current = *self.atoms.last().expect("ring bonds follow an atom");
+        self.add_ring_bond(current, ring_bond);
+    }
+
+    fn leave_branch(&mut self, _branch: &Branch, depth: usize) {
+        // A bond at the end of the branch connects nothing
+        self.bond_or_dot = None;
+        self.atoms.truncate(depth + 1);
+    }
+}
+
+/// Rewrite tetrahedral chirality that is relative to the given neighbor order to be relative to
+/// the neighbors ordered by index.
+pub(crate) fn normalize_chirality(atom: &mut Atom, neighbor_order: &[NodeIndex]) {
+    let bracket_atom = match atom {
+        Atom::Bracket(bracket_atom) => bracket_atom,
+        _ => return,
+    };
+    let anticlockwise = match bracket_atom.chiral {
+        Some(Chirality::Anticlockwise) | Some(Chirality::Tetrahedral(1)) => true,
+        Some(Chirality::Clockwise) | Some(Chirality::Tetrahedral(2)) => false,
+        _ => return,
+    };
+
+    let inversions = neighbor_order
+        .iter()
+        .enumerate()
+        .map(|(i, a)| neighbor_order[i + 1..].iter().filter(|b| a > b).count())
+        .sum::<usize>();
+    bracket_atom.chiral = Some(if anticlockwise == (inversions % 2 == 0) {
+        Chirality::Anticlockwise
+    } else {
+        Chirality::Clockwise
+    });
+}
+
+/// Builds the graph of a SMILES string that is known to parse.
+#[cfg(test)]
+pub(crate) fn molecule(smiles: &str) -> MoleculeGraph {
+    MoleculeGraph::from_chain(crate::parse(smiles.as_bytes()).unwrap())
+}
+
+/// Asserts that `actual` is within `tolerance` of `expected`.
+#[cfg(test)]
+pub(crate) fn assert_close(expected: f64, actual: f64, tolerance: f64) {
+    assert!(
+        (expected - actual).abs() < tolerance,
+        "{} != {}",
+        expected,
+        actual
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hydrogen_count(graph: &MoleculeGraph, atom: usize) -> usize {
+        graph
+            .neighbors(NodeIndex::new(atom))
+            .filter(|&neighbor| graph[neighbor].is_hydrogen())
+            .count()
+    }
+
+    #[test]
+    fn ethanol() {
+        let graph = molecule("CCO");
+        assert_eq!(9, graph.node_count());
+        assert_eq!(8, graph.edge_count());
+        assert_eq!(3, hydrogen_count(&graph, 0));
+        assert_eq!(2, hydrogen_count(&graph, 1));
+        assert_eq!(1, hydrogen_count(&graph, 2));
+    }
+
+    #[test]
+    fn bonds_and_dots() {
+        let graph = molecule("C=CC.[Na+]");
+        let bond = |a: usize, b: usize| {
+            let edge = graph
+                .find_edge(NodeIndex::new(a), NodeIndex::new(b))
+                .unwrap();
+            graph[edge]
+        };
+        assert_eq!(Bond::Double, bond(0, 1));
+        assert_eq!(Bond::Single, bond(1, 2));
+        assert_eq!(0, graph.edges(NodeIndex::new(3)).count());
+        assert_eq!(1, graph[NodeIndex::new(3)].charge());
+    }
+
+    #[test]
+    fn invalid_ring_bonds_are_left_out() {
+        // Unlike parse, chain doesn't check ring bonds
+        let graph = MoleculeGraph::from_chain(crate::chain(b"C11").unwrap().1);
+        assert_eq!(5, graph.node_count());
+        assert_eq!(4, graph.edge_count());
+        assert_eq!(4, hydrogen_count(&graph, 0));
+
+        let graph = MoleculeGraph::from_chain(crate::chain(b"C12CC12").unwrap().1);
+        assert_eq!(9, graph.node_count());
+        assert_eq!(9, graph.edge_count());
+        assert_eq!(2, hydrogen_count(&graph, 0));
+    }
+
+    #[test]
+    fn rings_and_branches() {
+        // Ring 1 is closed inside the branch and then reused
+        let graph = molecule("C1C(C1)C1CC1");
+        let heavy_edges = graph
+            .edge_references()
+            .filter(|edge| edge.target().index() < 6)
+            .map(|edge| (edge.source().index(), edge.target().index()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![(0, 1), (1, 2), (0, 2), (1, 3), (3, 4), (4, 5), (3, 5)],
+            heavy_edges
+        );
+    }
+
+    #[test]
+    fn aromatic_hydrogens() {
+        // Pyrrole
+        let graph = molecule("c1cc[nH]c1");
+        assert_eq!(
+            Bond::Aromatic,
+            graph[graph.find_edge(0.into(), 1.into()).unwrap()]
+        );
+        assert_eq!(1, hydrogen_count(&graph, 0));
+        assert_eq!(1, hydrogen_count(&graph, 3));
+        assert_eq!(10, graph.node_count());
+
+        // N-methylpyrrole
+        let graph = molecule("Cn1cccc1");
+        assert_eq!(0, hydrogen_count(&graph, 1));
+        assert_eq!(13, graph.node_count());
+    }
+
+    #[test]
+    fn spans() {
+        let graph = molecule("C1C(O)[NH2+]1.[Cl-]");
+        let spans: Vec<_> = graph
+            .node_indices()
+            .map(|atom| graph.span(atom).map(std::ops::Range::from))
+            .collect();
+        assert_eq!(
+            vec![
+                Some(0..1),
+                Some(2..3),
+                Some(4..5),
+                Some(6..12),
+                Some(14..19)
+            ],
+            spans[..5]
+        );
+        assert!(spans[5..].iter().all(Option::is_none));
+
+        let keep: Vec<bool> = (0..graph.node_count()).map(|atom| atom != 0).collect();
+        assert_eq!(
+            Some(Span { start: 2, end: 3 }),
+            graph.subgraph(&keep).span(NodeIndex::new(0))
+        );
+    }
+
+    #[test]
+    fn deeply_nested_branches() {
+        let smiles = format!("{}C{}", "C(".repeat(100_000), ")".repeat(100_000));
+        let graph = molecule(&smiles);
+        assert_eq!(100_001 + 200_004, graph.node_count());
+        assert_eq!(2, hydrogen_count(&graph, 1));
+        assert_eq!(3, hydrogen_count(&graph, 100_000));
+    }
+
+    #[test]
+    fn rings() {
+        // Spiro-fused three- and four-membered rings, connected by a chain to a cyclopropane
+        let graph = molecule("C1CC12CCC2CC1CC1");
+        let ring_atoms = graph.ring_atoms();
+        assert_eq!(
+            vec![true, true, true, true, true, true, false, true, true, true],
+            ring_atoms[..10]
+        );
+        assert!(!ring_atoms[10]);
+        assert_eq!(2, graph.hydrogen_count(NodeIndex::new(6)));
+
+        let ring_bonds = graph.ring_bonds();
+        let chain_bond = graph.find_edge(5.into(), 6.into()).unwrap();
+        assert!(!ring_bonds[chain_bond.index()]);
+        assert_eq!(
+            10,
+            graph
+                .edge_indices()
+                .filter(|edge| ring_bonds[edge.index()])
+                .count()
+        );
+
+        let ring_sizes = |smiles| {
+            let mut sizes: Vec<usize> = molecule(smiles)
+                .rings()
+                .iter()
+                .map(|ring| ring.len())
+                .collect();
+            sizes.sort_unstable();
+            sizes
+        };
+        assert_eq!(vec![3, 3, 4], ring_sizes("C1CC12CCC2CC1CC1"));
+        assert_eq!(vec![6, 6], ring_sizes("c1ccc2ccccc2c1"));
+        assert_eq!(vec![4, 4, 4, 4, 4], ring_sizes("C12C3C4C1C5C2C3C45"));
+        assert_eq!(vec![5, 5, 5], ring_sizes("C1CC2CCC1C2.C1CCCC1"));
+        assert!(ring_sizes("CCO").is_empty());
+
+        // Atoms are in ring order
+        let graph = molecule("C1CCCCC1");
+        for ring in graph.rings() {
+            for (index, &atom) in ring.iter().enumerate() {
+                let next = ring[(index + 1) % ring.len()];
+                assert!(graph.find_edge(atom, next).is_some());
+            }
+        }
+    }
+
+    #[test]
+    fn normalized_chirality() {
+        // Neighbors in SMILES order: N, H, C, O; ordered by index: N, C, O, H (even permutation)
+        let graph = molecule("N[C@H](C)O");
+        assert_eq!(
+            Some(Chirality::Anticlockwise),
+            graph[NodeIndex::new(1)].chirality()
+        );
+        // Neighbors in SMILES order: H, N, C, O; ordered by index: N, C, O, H (odd permutation)
+        let graph = molecule("[C@H](N)(C)O");
+        assert_eq!(
+            Some(Chirality::Clockwise),
+            graph[NodeIndex::new(0)].chirality()
+        );
+        // The ring closure comes before the branch: 0, 4, 2, 3 -> 0, 2, 3, 4 (even permutation)
+        let graph = molecule("C[C@@]1(N)CC1");
+        assert_eq!(
+            Some(Chirality::Clockwise),
+            graph[NodeIndex::new(1)].chirality()
+        );
+        // 0, 3, 2, 4 -> 0, 2, 3, 4 (odd permutation)
+        let graph = molecule("C[C@@]1(CC1)N");
+        assert_eq!(
+            Some(Chirality::Anticlockwise),
+            graph[NodeIndex::new(1)].chirality()
+        );
+    }
+}
@@ -0,0 +1,820 @@
+//! A layered identifier in the spirit of InChI, for deduplicating molecules.
+//!
+//! The identifier is made up of these layers, each (except the formula) introduced by a `/` and
+//! a letter and omitted when empty:
+//!
+//! - the molecular formula in Hill order, with a `.` between disconnected components
+//! - `/c`: the connectivity of the non-hydrogen atoms, without bond orders
+//! - `/h`: the hydrogen atoms of every atom, with mobile hydrogens as `(H<count>,<atoms>)`
+//! - `/q`: the net charge of every component
+//! - `/b` and `/t`: double bond and tetrahedral stereo
+//! - `/i`: isotopes
+//!
+//! Atoms are numbered by canonical rank, and of the ways to break ties between atoms that can't
+//! be told apart, the one that gives the smallest identifier is chosen, so different SMILES
+//! strings of the same compound give the same identifier. Hydrogens that can move between heteroatoms over a conjugated path (as
+//! in `CC(=O)O`, or between 2-pyridone and 2-hydroxypyridine) are only counted per group of
+//! heteroatoms, which makes the identifier independent of these tautomers. This is a simplified
+//! scheme and not compatible with actual InChIs.
+
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::fmt::Write as _;
+
+use petgraph::graph::NodeIndex;
+use petgraph::visit::EdgeRef;
+use ptable::Element;
+
+use crate::canon::{rank_atoms, Ranking};
+use crate::graph::MoleculeGraph;
+use crate::{Bond, Chirality};
+
+#[derive(Debug, Default, PartialEq, Eq, PartialOrd, Ord, Clone, Hash)]
+pub struct LayeredIdentifier {
+    pub formula: String,
+    pub connectivity: String,
+    pub hydrogens: String,
+    pub charge: String,
+    pub double_bond_stereo: String,
+    pub tetrahedral_stereo: String,
+    pub isotopes: String,
+}
+
+impl std::fmt::Display for LayeredIdentifier {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.formula)?;
+        let layers = [
+            ('c', &self.connectivity),
+            ('h', &self.hydrogens),
+            ('q', &self.charge),
+            ('b', &self.double_bond_stereo),
+            ('t', &self.tetrahedral_stereo),
+            ('i', &self.isotopes),
+        ];
+        for (prefix, layer) in layers.iter() {
+            if !layer.is_empty() {
+                write!(f, "/{}{}", prefix, layer)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Elements that can carry mobile hydrogens.
+fn is_heteroatom(element: Option<Element>) -> bool {
+    matches!(
+        element,
+        Some(Element::Nitrogen) | Some(Element::Oxygen) | Some(Element::Sulfur)
+    )
+}
+
+/// Rounded mass of the most common isotope of an element.
+fn nominal_mass(element: Element) -> i32 {
+    element.get_atomic_mass().round() as i32
+}
+
+/// Order of elements in a Hill formula with carbon: C, H, then alphabetically.
+fn hill_key(element: Option<Element>) -> (u8, &'static str) {
+    match element {
+        Some(Element::Carbon) => (0, "C"),
+        Some(Element::Hydrogen) => (1, "H"),
+        Some(element) => (2, element.get_symbol()),
+        None => (3, "*"),
+    }
+}
+
+/// Atoms of the skeleton (all atoms except attached hydrogens) and their properties.
+struct Skeleton {
+    nodes: Vec<NodeIndex>,
+    /// Position in `nodes` by node index.
+    position: HashMap<NodeIndex, usize>,
+    neighbors: Vec<Vec<usize>>,
+    hydrogens: Vec<u8>,
+    deuterium: Vec<u8>,
+    tritium: Vec<u8>,
+}
+
+impl Skeleton {
+    fn new(graph: &MoleculeGraph) -> Self {
+        let nodes: Vec<NodeIndex> = graph
+            .node_indices()
+            .filter(|&node| !graph.is_attached_hydrogen(node))
+            .collect();
+        let position: HashMap<NodeIndex, usize> = nodes
+            .iter()
+            .enumerate()
+            .map(|(position, &node)| (node, position))
+            .collect();
+
+        let mut skeleton = Skeleton {
+            neighbors: vec![Vec::new(); nodes.len()],
+            hydrogens: vec![0; nodes.len()],
+            deuterium: vec![0; nodes.len()],
+            tritium: vec![0; nodes.len()],
+            nodes,
+            position,
+        };
+        for (atom, &node) in skeleton.nodes.iter().enumerate() {
+            for neighbor in graph.neighbors(node) {
+                match skeleton.position.get(&neighbor) {
+                    Some(&position) => skeleton.neighbors[atom].push(position),
+                    None => {
+                        skeleton.hydrogens[atom] += 1;
+                        match graph[neighbor].isotope() {
+                            Some(2) => skeleton.deuterium[atom] += 1,
+                            Some(3) => skeleton.tritium[atom] += 1,
+                            _ => {}
+                        }
+                    }
+                }
+            }
+        }
+        skeleton
+    }
+
+    fn bond(&self, graph: &MoleculeGraph, a: usize, b: usize) -> Bond {
+        let edge = graph.find_edge(self.nodes[a], self.nodes[b]).unwrap();
+        graph[edge]
+    }
+
+    /// Groups of heteroatoms between which hydrogens can move.
+    ///
+    /// A hydrogen on a heteroatom can move to another heteroatom if there is a path of
+    /// alternating single and double bonds between them that ends in a double bond (aromatic
+    /// bonds count as either), and the other heteroatom has a free electron pair.
+    fn mobile_groups(&self, graph: &MoleculeGraph) -> Vec<Vec<usize>> {
+        let count = self.nodes.len();
+        let mut parent: Vec<usize> = (0..count).collect();
+        fn find(parent: &mut [usize], mut atom: usize) -> usize {
+            while parent[atom] != atom {
+                parent[atom] = parent[parent[atom]];
+                atom = parent[atom];
+            }
+            atom
+        }
+
+        for donor in 0..count {
+            if self.hydrogens[donor] == 0 || !is_heteroatom(graph[self.nodes[donor]].element()) {
+                continue;
+            }
+
+            // States are (atom, whether the next bond has to be a double bond).
+            let mut seen = vec![[false; 2]; count];
+            let mut queue = VecDeque::new();
+            seen[donor][0] = true;
+            queue.push_back((donor, false));
+            while let Some((atom, double)) = queue.pop_front() {
+                for &neighbor in &self.neighbors[atom] {
+                    if neighbor == donor || seen[neighbor][!double as usize] {
+                        continue;
+                    }
+                    let matches = match self.bond(graph, atom, neighbor) {
+                        Bond::Aromatic => true,
+                        Bond::Double => double,
+                        Bond::Single | Bond::Up | Bond::Down => !double,
+                        Bond::Triple | Bond::Quadruple => false,
+                    };
+                    if !matches {
+                        continue;
+                    }
+                    seen[neighbor][!double as usize] = true;
+
+                    let neighbor_atom = &graph[self.nodes[neighbor]];
+                    if is_heteroatom(neighbor_atom.element()) {
+                        let max_degree = match neighbor_atom.element() {
+                            Some(Element::Nitrogen) => 2,
+                            _ => 1,
+                        };
+                        if double && self.neighbors[neighbor].len() <= max_degree {
+                            let (a, b) = (find(&mut parent, donor), find(&mut parent, neighbor));
+                            parent[a] = b;
+                        }
+                        // Hydrogens don't move through heteroatoms.
+                        continue;
+                    }
+                    queue.push_back((neighbor, !double));
+                }
+            }
+        }
+
+        let mut groups: BTreeMap<usize, Vec<usize>> = BTreeMap::new();
+        for atom in 0..count {
+            let root = find(&mut parent, atom);
+            groups.entry(root).or_default().push(atom);
+        }
+        groups
+            .into_values()
+            .filter(|group| group.len() > 1)
+            .collect()
+    }
+}
+
+/// The skeleton of a molecule with its atoms ranked and split into components, which all layers
+/// of the identifier are computed from.
+struct Numbering {
+    skeleton: Skeleton,
+    groups: Vec<Vec<usize>>,
+    group_hydrogens: Vec<usize>,
+    fixed_hydrogens: Vec<u8>,
+    ranking: Ranking,
+    components: Vec<Vec<usize>>,
+}
+
+impl Numbering {
+    fn new(graph: &MoleculeGraph) -> Self {
+        let skeleton = Skeleton::new(graph);
+        let count = skeleton.nodes.len();
+
+        let groups = skeleton.mobile_groups(graph);
+        let mut group_of = vec![None; count];
+        let mut group_hydrogens = Vec::new();
+        for (index, group) in groups.iter().enumerate() {
+            for &atom in group {
+                group_of[atom] = Some(index);
+            }
+            group_hydrogens.push(
+                group
+                    .iter()
+                    .map(|&atom| skeleton.hydrogens[atom] as usize)
+                    .sum(),
+            );
+        }
+        let fixed_hydrogens: Vec<u8> = (0..count)
+            .map(|atom| match group_of[atom] {
+                Some(_) => 0,
+                None => skeleton.hydrogens[atom],
+            })
+            .collect();
+
+        let invariants: Vec<_> = (0..count)
+            .map(|atom| {
+                let graph_atom = &graph[skeleton.nodes[atom]];
+                (
+                    hill_key(graph_atom.element()),
+                    skeleton.neighbors[atom].len(),
+                    fixed_hydrogens[atom],
+                    group_of[atom].map(|group| group_hydrogens[group]),
+                    graph_atom.isotope(),
+                    skeleton.deuterium[atom],
+                    skeleton.tritium[atom],
+                )
+            })
+            .collect();
+        let neighbors: Vec<Vec<(usize, u8)>> = skeleton
+            .neighbors
+            .iter()
+            .map(|neighbors| neighbors.iter().map(|&neighbor| (neighbor, 0)).collect())
+            .collect();
+        let mut numbering = Numbering {
+            skeleton,
+            groups,
+            group_hydrogens,
+            fixed_hydrogens,
+            ranking: Ranking {
+                classes: Vec::new(),
+                ranks: Vec::new(),
+            },
+            components: Vec::new(),
+        };
+        let ranking = rank_atoms(&neighbors, &invariants, |ranking| {
+            numbering.set_ranking(ranking.clone());
+            numbering.identifier(graph)
+        });
+        numbering.set_ranking(ranking);
+        numbering
+    }
+
+    fn set_ranking(&mut self, ranking: Ranking) {
+        self.components = components(&self.skeleton, &ranking);
+        self.ranking = ranking;
+    }
+
+    /// The molecular formula, with a `.` between disconnected components.
+    fn formula(&self, graph: &MoleculeGraph) -> String {
+        self.components
+            .iter()
+            .map(|component| formula(graph, &self.skeleton, component))
+            .collect::<Vec<_>>()
+            .join(".")
+    }
+
+    /// All layers of the identifier, with the atoms numbered by the current ranking.
+    fn identifier(&self, graph: &MoleculeGraph) -> LayeredIdentifier {
+        let Numbering {
+            skeleton,
+            groups,
+            group_hydrogens,
+            fixed_hydrogens,
+            ranking,
+            components,
+        } = self;
+        let count = skeleton.nodes.len();
+        // Atom numbers in the identifier start at 1.
+        let number = |atom: usize| ranking.ranks[atom] + 1;
+
+        let mut identifier = LayeredIdentifier {
+            formula: self.formula(graph),
+            connectivity: components
+                .iter()
+                .filter(|component| component.len() > 1)
+                .map(|component| connectivity(skeleton, ranking, component[0]))
+                .collect::<Vec<_>>()
+                .join(";"),
+            ..Default::default()
+        };
+
+        // Hydrogen layer
+        let mut by_count: BTreeMap<u8, Vec<usize>> = BTreeMap::new();
+        for (atom, &hydrogens) in fixed_hydrogens.iter().enumerate() {
+            if hydrogens > 0 {
+                by_count.entry(hydrogens).or_default().push(number(atom));
+            }
+        }
+        let mut hydrogen_parts: Vec<(usize, String)> = by_count
+            .into_iter()
+            .map(|(hydrogens, mut atoms)| {
+                atoms.sort_unstable();
+                (
+                    atoms[0],
+                    format!("{}{}", ranges(&atoms), hydrogen_suffix(hydrogens as usize)),
+                )
+            })
+            .collect();
+        for (group, hydrogens) in groups.iter().zip(group_hydrogens) {
+            if *hydrogens == 0 {
+                continue;
+            }
+            let mut atoms: Vec<usize> = group.iter().map(|&atom| number(atom)).collect();
+            atoms.sort_unstable();
+            let list: Vec<String> = atoms.iter().map(usize::to_string).collect();
+            hydrogen_parts.push((
+                atoms[0],
+                format!("({},{})", hydrogen_suffix(*hydrogens), list.join(",")),
+            ));
+        }
+        hydrogen_parts.sort();
+        identifier.hydrogens = hydrogen_parts
+            .into_iter()
+            .map(|(_, part)| part)
+            .collect::<Vec<_>>()
+            .join(",");
+
+        // Charge layer
+        let charges: Vec<i32> = components
+            .iter()
+            .map(|component| {
+                component
+                    .iter()
+                    .map(|&atom| graph[skeleton.nodes[atom]].charge() as i32)
+                    .sum()
+            })
+            .collect();
+        if charges.iter().any(|&charge| charge != 0) {
+            identifier.charge = charges
+                .iter()
+                .map(|&charge| match charge {
+                    0 => String::new(),
+                    charge => format!("{:+}", charge),
+                })
+                .collect::<Vec<_>>()
+                .join(";");
+        }
+
+        identifier.double_bond_stereo = double_bond_stereo(graph, skeleton, ranking);
+        identifier.tetrahedral_stereo = tetrahedral_stereo(graph, skeleton, ranking);
+
+        // Isotope layer
+        let mut isotopes: Vec<(usize, String)> = Vec::new();
+        for atom in 0..count {
+            let graph_atom = &graph[skeleton.nodes[atom]];
+            let mut part = String::new();
+            if let (Some(isotope), Some(element)) = (graph_atom.isotope(), graph_atom.element()) {
+                write!(part, "{:+}", isotope as i32 - nominal_mass(element)).unwrap();
+            }
+            for (symbol, hydrogens) in [
+                ("D", skeleton.deuterium[atom]),
+                ("T", skeleton.tritium[atom]),
+            ]
+            .iter()
+            {
+                if *hydrogens > 0 {
+                    write!(part, "{}{}", symbol, count_suffix(*hydrogens as usize)).unwrap();
+                }
+            }
+            if !part.is_empty() {
+                isotopes.push((number(atom), part));
+            }
+        }
+        isotopes.sort();
+        identifier.isotopes = isotopes
+            .into_iter()
+            .map(|(number, part)| format!("{}{}", number, part))
+            .collect::<Vec<_>>()
+            .join(",");
+
+        identifier
+    }
+}
+
+/// The molecular formula in Hill order, with a `.` between disconnected components, as in the
+/// first layer of the [`layered_identifier`].
+pub fn molecular_formula(graph: &MoleculeGraph) -> String {
+    Numbering::new(graph).formula(graph)
+}
+
+/// Compute the layered identifier of a molecule.
+pub fn layered_identifier(graph: &MoleculeGraph) -> LayeredIdentifier {
+    Numbering::new(graph).identifier(graph)
+}
+
+fn count_suffix(count: usize) -> String {
+    if count == 1 {
+        String::new()
+    } else {
+        count.to_string()
+    }
+}
+
+fn hydrogen_suffix(count: usize) -> String {
+    format!("H{}", count_suffix(count))
+}
+
+/// Format sorted numbers, with runs of consecutive numbers as ranges (`1-3,5`).
+fn ranges(numbers: &[usize]) -> String {
+    let mut parts = Vec::new();
+    let mut start = 0;
+    for end in 0..numbers.len() {
+        if end + 1 == numbers.len() || numbers[end + 1] != numbers[end] + 1 {
+            if start == end {
+                parts.push(numbers[start].to_string());
+            } else {
+                parts.push(format!("{}-{}", numbers[start], numbers[end]));
+            }
+            start = end + 1;
+        }
+    }
+    parts.join(",")
+}
+
+/// Key by which the neighbors of a stereo center are ordered: attached hydrogens first, then the
+/// skeleton atoms by rank. Also returns the symmetry class, to tell if two neighbors are equal.
+fn neighbor_key(skeleton: &Skeleton, ranking: &Ranking, node: NodeIndex) -> Option<(usize, usize)> {
+    skeleton
+        .position
+        .get(&node)
+        .map(|&atom| (ranking.ranks[atom], ranking.classes[atom]))
+}
+
+fn has_equal_neighbors(keys: &[Option<(usize, usize)>]) -> bool {
+    let mut classes: Vec<Option<usize>> =
+        keys.iter().map(|key| key.map(|(_, class)| class)).collect();
+    classes.sort_unstable();
+    classes.windows(2).any(|pair| pair[0] == pair[1])
+}
+
+fn tetrahedral_stereo(graph: &MoleculeGraph, skeleton: &Skeleton, ranking: &Ranking) -> String {
+    let mut centers = Vec::new();
+    for (atom, &node) in skeleton.nodes.iter().enumerate() {
+        let chirality = match graph[node].chirality() {
+            Some(chirality @ Chirality::Clockwise) | Some(chirality @ Chirality::Anticlockwise) => {
+                chirality
+            }
+            _ => continue,
+        };
+        // The chirality of the graph is relative to the neighbors in node order.
+        let mut neighbors: Vec<NodeIndex> = graph.neighbors(node).collect();
+        neighbors.sort_unstable();
+        let keys: Vec<Option<(usize, usize)>> = neighbors
+            .iter()
+            .map(|&neighbor| neighbor_key(skeleton, ranking, neighbor))
+            .collect();
+        if has_equal_neighbors(&keys) {
+            continue;
+        }
+
+        let inversions = (0..keys.len())
+            .flat_map(|i| (i + 1..keys.len()).map(move |j| (i, j)))
+            .filter(|&(i, j)| keys[i] > keys[j])
+            .count();
+        let clockwise = (chirality == Chirality::Clockwise) != (inversions % 2 == 1);
+        centers.push((ranking.ranks[atom] + 1, if clockwise { '+' } else { '-' }));
+    }
+    centers.sort_unstable();
+    centers
+        .into_iter()
+        .map(|(number, parity)| format!("{}{}", number, parity))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// Side of the reference substituent of one atom of a double bond, as `true` for "above", or
+/// `None` if the double bond is not a stereo bond.
+fn double_bond_side(
+    graph: &MoleculeGraph,
+    skeleton: &Skeleton,
+    ranking: &Ranking,
+    atom: NodeIndex,
+    other: NodeIndex,
+) -> Option<bool> {
+    let substituents: Vec<NodeIndex> = graph
+        .neighbors(atom)
+        .filter(|&neighbor| neighbor != other)
+        .collect();
+    let keys: Vec<Option<(usize, usize)>> = substituents
+        .iter()
+        .map(|&substituent| neighbor_key(skeleton, ranking, substituent))
+        .collect();
+    if substituents.is_empty() || substituents.len() > 2 || has_equal_neighbors(&keys) {
+        return None;
+    }
+
+    let (substituent, above) = substituents.iter().find_map(|&substituent| {
+        let edge = graph.find_edge(atom, substituent).unwrap();
+        // `/` means that the later atom is above the earlier one.
+        let up = match graph[edge] {
+            Bond::Up => true,
+            Bond::Down => false,
+            _ => return None,
+        };
+        let (source, _) = graph.edge_endpoints(edge).unwrap();
+        Some((substituent, (source == atom) == up))
+    })?;
+
+    let reference = (0..substituents.len())
+        .max_by_key(|&index| keys[index])
+        .unwrap();
+    Some((substituents[reference] == substituent) == above)
+}
+
+fn double_bond_stereo(graph: &MoleculeGraph, skeleton: &Skeleton, ranking: &Ranking) -> String {
+    let mut bonds = Vec::new();
+    for edge in graph.edge_references() {
+        let (a, b) = (edge.source(), edge.target());
+        if *edge.weight() != Bond::Double {
+            continue;
+        }
+        let (first, second) = match (skeleton.position.get(&a), skeleton.position.get(&b)) {
+            (Some(&first), Some(&second)) => (first, second),
+            _ => continue,
+        };
+        let sides = (
+            double_bond_side(graph, skeleton, ranking, a, b),
+            double_bond_side(graph, skeleton, ranking, b, a),
+        );
+        if let (Some(first_side), Some(second_side)) = sides {
+            let numbers = (ranking.ranks[first] + 1, ranking.ranks[second] + 1);
+            let trans = first_side != second_side;
+            bonds.push((
+                numbers.0.max(numbers.1),
+                numbers.0.min(numbers.1),
+                if trans { '+' } else { '-' },
+            ));
+        }
+    }
+    bonds.sort_unstable();
+    bonds
+        .into_iter()
+        .map(|(first, second, parity)| format!("{}-{}{}", first, second, parity))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// Connected components of the skeleton, each sorted by rank and ordered by their lowest rank.
+fn components(skeleton: &Skeleton, ranking: &Ranking) -> Vec<Vec<usize>> {
+    let mut seen = vec![false; skeleton.nodes.len()];
+    let mut by_rank: Vec<usize> = (0..skeleton.nodes.len()).collect();
+    by_rank.sort_by_key(|&atom| ranking.ranks[atom]);
+
+    let mut components = Vec::new();
+    for start in by_rank {
+        if seen[start] {
+            continue;
+        }
+        seen[start] = true;
+        let mut component = vec![start];
+        let mut stack = vec![start];
+        while let Some(atom) = stack.pop() {
+            for &neighbor in &skeleton.neighbors[atom] {
+                if !seen[neighbor] {
+                    seen[neighbor] = true;
+                    component.push(neighbor);
+                    stack.push(neighbor);
+                }
+            }
+        }
+        component.sort_by_key(|&atom| ranking.ranks[atom]);
+        components.push(component);
+    }
+    components
+}
+
+fn formula(graph: &MoleculeGraph, skeleton: &Skeleton, component: &[usize]) -> String {
+    let mut counts: BTreeMap<(u8, &str), usize> = BTreeMap::new();
+    for &atom in component {
+        let element = graph[skeleton.nodes[atom]].element();
+        if element.is_some() {
+            *counts.entry(hill_key(element)).or_default() += 1;
+        }
+        if skeleton.hydrogens[atom] > 0 {
+            *counts.entry(hill_key(Some(Element::Hydrogen))).or_default() +=
+                skeleton.hydrogens[atom] as usize;
+        }
+    }
+
+    let mut counts: Vec<(&str, usize)> = counts
+        .into_iter()
+        .map(|((_, symbol), count)| (symbol, count))
+        .collect();
+    // Without carbon, all elements are in alphabetical order.
+    if counts.first().map(|(symbol, _)| *symbol) != Some("C") {
+        counts.sort();
+    }
+    counts
+        .into_iter()
+        .map(|(symbol, count)| format!("{}{}", symbol, count_suffix(count)))
+        .collect()
+}
+
+/// Connectivity of a component as a depth-first traversal in the order of atom numbers, with
+/// branches in parentheses and ring closures as a repeated atom number (`1-2-4-6-5-3-1`).
+fn connectivity(skeleton: &Skeleton, ranking: &Ranking, start: usize) -> String {
+    let count = skeleton.nodes.len();
+    let sorted_neighbors = |atom: usize| {
+        let mut neighbors = skeleton.neighbors[atom].clone();
+        neighbors.sort_by_key(|&neighbor| ranking.ranks[neighbor]);
+        neighbors
+    };
+
+    // Build the depth-first tree first, so that the entries of every atom are known when
+    // writing it. Ring closures are written at the atom that is visited last.
+    let mut visited = vec![false; count];
+    let mut depth = vec![0; count];
+    let mut entries: Vec<Vec<(usize, bool)>> = vec![Vec::new(); count];
+    let mut stack = vec![(start, None, sorted_neighbors(start).into_iter())];
+    visited[start] = true;
+    while let Some((atom, parent, neighbors)) = stack.last_mut() {
+        let atom = *atom;
+        let parent = *parent;
+        match neighbors.next() {
+            Some(neighbor) if Some(neighbor) == parent => {}
+            Some(neighbor) if visited[neighbor] => {
+                if depth[neighbor] < depth[atom] {
+                    entries[atom].push((neighbor, false));
+                }
+            }
+            Some(neighbor) => {
+                visited[neighbor] = true;
+                depth[neighbor] = depth[atom] + 1;
+                entries[atom].push((neighbor, true));
+                stack.push((neighbor, Some(atom), sorted_neighbors(neighbor).into_iter()));
+            }
+            None => {
+                stack.pop();
+            }
+        }
+    }
+    for entries in &mut entries {
+        entries.sort_by_key(|&(neighbor, _)| ranking.ranks[neighbor]);
+    }
+
+    enum Token {
+        Atom(usize),
+        RingClosure(usize),
+        Text(&'static str),
+    }
+    let mut out = String::new();
+    let mut tokens = vec![Token::Atom(start)];
+    while let Some(token) = tokens.pop() {
+        let atom = match token {
+            Token::Atom(atom) => atom,
+            Token::RingClosure(atom) => {
+                write!(out, "{}", ranking.ranks[atom] + 1).unwrap();
+                continue;
+            }
+            Token::Text(text) => {
+                out.push_str(text);
+                continue;
+            }
+        };
+        write!(out, "{}", ranking.ranks[atom] + 1).unwrap();
+
+        // All entries but the last are branches, pushed in reverse.
+        let entry = |&(neighbor, tree): &(usize, bool)| {
+            if tree {
+                Token::Atom(neighbor)
+            } else {
+                Token::RingClosure(neighbor)
+            }
+        };
+        if let Some((last, branches)) = entries[atom].split_last() {
+            tokens.push(entry(last));
+            if branches.is_empty() {
+                tokens.push(Token::Text("-"));
+            }
+            for branch in branches.iter().rev() {
+                tokens.push(Token::Text(")"));
+                tokens.push(entry(branch));
+                tokens.push(Token::Text("("));
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graph::molecule;
+    use crate::parse;
+
+    fn identifier(smiles: &str) -> String {
+        layered_identifier(&MoleculeGraph::from_chain(
+            parse(smiles.as_bytes()).unwrap(),
+        ))
+        .to_string()
+    }
+
+    fn assert_same(smiles: &[&str]) {
+        let expected = identifier(smiles[0]);
+        for other in &smiles[1..] {
+            assert_eq!(expected, identifier(other), "{} vs {}", smiles[0], other);
+        }
+    }
+
+    #[test]
+    fn layers() {
+        assert_eq!("C2H6O/c1-2-3/h1H3,2H2,3H", identifier("OCC"));
+        assert_eq!("C6H6/c1-2-4-6-5-3-1/h1-6H", identifier("c1ccccc1"));
+        assert_eq!("C4H10/c1-4(2)3/h1-3H3,4H", identifier("CC(C)C"));
+        assert_eq!("C2H4O2/c1-2(3)4/h1H3,(H,3,4)", identifier("CC(=O)O"));
+        assert_eq!(
+            "C2H3O2.Na/c1-2(4)5/h1H3/q-1;+1",
+            identifier("[Na+].CC(=O)[O-]")
+        );
+        assert_eq!("CH4/h1H4/i1+1D", identifier("[13CH3][2H]"));
+    }
+
+    #[test]
+    fn molecular_formulas() {
+        for smiles in ["OCC", "[Na+].CC(=O)[O-]", "Cl.O=S(=O)(O)O", "[13CH3][2H]"] {
+            let graph = molecule(smiles);
+            assert_eq!(
+                layered_identifier(&graph).formula,
+                molecular_formula(&graph)
+            );
+        }
+        assert_eq!(
+            "C2H3O2.Na",
+            molecular_formula(&molecule("[Na+].CC(=O)[O-]"))
+        );
+    }
+
+    #[test]
+    fn same_compound() {
+        assert_same(&["CCO", "OCC", "C(O)C", "[CH3][CH2][OH]"]);
+        assert_same(&["c1ccccc1", "C1=CC=CC=C1", "C=1C=CC=CC=1"]);
+        assert_same(&["c1ccc2ccccc2c1", "C1=CC2=CC=CC=C2C=C1"]);
+        assert_same(&[
+            "CN1C=NC2=C1C(=O)N(C(=O)N2C)C",
+            "Cn1cnc2c1c(=O)n(C)c(=O)n2C",
+            "O=C1N(C)C(=O)c2n(C)cnc2N1C",
+        ]);
+        assert_same(&["CC(=O)[O-].[Na+]", "[Na+].[O-]C(C)=O", "CC([O-])=O.[Na+]"]);
+        assert_same(&["C1CCCCC1.C1CCCC1", "C1CCCC1.C1CCCCC1"]);
+        assert_same(&[
+            "C1CCCCC1.C1CC1.C1CC1",
+            "C1CC1.C1CC1.C1CCCCC1",
+            "C1CC1.C1CCCCC1.C1CC1",
+        ]);
+    }
+
+    #[test]
+    fn tautomers() {
+        assert_same(&["CC(=O)O", "CC(O)=O"]);
+        assert_same(&["O=C1C=CC=CN1", "Oc1ccccn1", "OC1=NC=CC=C1"]);
+        assert_same(&["CC(=O)N", "CC(O)=N"]);
+        assert_same(&["c1cc[nH]n1", "c1ccn[nH]1"]);
+        assert_ne!(identifier("CCO"), identifier("COC"));
+    }
+
+    #[test]
+    fn stereo() {
+        assert_same(&["F/C=C/F", "F\\C=C\\F", "C(\\F)=C/F", "[H]/C(F)=C(F)/[H]"]);
+        assert_same(&["F/C=C\\F", "F\\C=C/F", "C(/F)=C/F", "[H]/C(F)=C(F)\\[H]"]);
+        assert_ne!(identifier("F/C=C/F"), identifier("F/C=C\\F"));
+        assert_eq!("C2H2F2/c1(2-4)3/h1-2H/b2-1+", identifier("F/C=C/F"));
+
+        assert_same(&[
+            "N[C@@H](C)C(=O)O",
+            "C[C@H](N)C(=O)O",
+            "OC(=O)[C@@H](N)C",
+            "[C@@H](C)(N)C(=O)O",
+        ]);
+        assert_ne!(
+            identifier("N[C@@H](C)C(=O)O"),
+            identifier("N[C@H](C)C(=O)O")
+        );
+
+        // Not a stereo center
+        assert_eq!(identifier("CCC(C)CC"), identifier("CC[C@H](C)CC"));
+    }
+}
@@ -0,0 +1,447 @@
+//! Parsing that recovers from errors and reports all of them.
+//!
+//! [`parse_lenient`] reads as much of a SMILES string as it can, instead of stopping at the first
+//! problem like [`parse`](crate::parse). It recovers like this:
+//!
+//! - Bytes that don't start an atom, bond, ring bond or branch are skipped.
+//! - A bracket atom with invalid contents is replaced by a wildcard atom, so that the atoms
+//!   around it stay connected.
+//! - A bracket atom that is never closed is closed after the longest part of it that is a valid
+//!   bracket atom.
+//! - Branches that are never closed are closed at the end of the input, and closing parentheses
+//!   without a branch are skipped.
+//! - Bonds that are not followed by an atom and branches without atoms are dropped.
+//! - Ring bonds after the branches of an atom are kept.
+//!
+//! Ring bonds that are never closed are reported, and kept. For input that [`parse_flat`]
+//! accepts, the result is the same as that of [`parse`](crate::parse), without diagnostics.
+//!
+//! [`parse_flat`]: crate::flat::parse_flat
+
+use std::collections::HashMap;
+
+use crate::{
+    atom, bond_or_dot, build_chain, ring_bond, Atom, BondOrDot, Branch, BranchedAtom, Chain,
+    ParseErrorKind, Span,
+};
+
+/// A problem found by [`parse_lenient`], with the bytes of the input it is about.
+#[derive(Debug, PartialEq, Eq, Copy, Clone, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Diagnostic {
+    pub kind: ParseErrorKind,
+    pub span: Span,
+}
+
+impl std::fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} at position {}", self.kind, self.span.start)
+    }
+}
+
+/// The result of [`parse_lenient`].
+#[derive(Debug, PartialEq, Eq, Clone, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LenientParse {
+    /// The chain that could be parsed, or `None` if the input has no atoms.
+    pub chain: Option<Chain>,
+    /// All problems, ordered by where they were found.
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+/// Parse a SMILES string, recovering from errors. See the [module documentation](self) for how.
+pub fn parse_lenient(input: &[u8]) -> LenientParse {
+    let mut parser = LenientParser {
+        input,
+        position: 0,
+        levels: vec![Level::default()],
+        pending: None,
+        open_rings: HashMap::new(),
+        diagnostics: Vec::new(),
+    };
+    if input.is_empty() {
+        parser.report(ParseErrorKind::Empty, Span::default());
+    }
+    parser.run();
+    let LenientParser {
+        mut levels,
+        diagnostics,
+        ..
+    } = parser;
+    LenientParse {
+        chain: build_chain(levels.pop().unwrap().links),
+        diagnostics,
+    }
+}
+
+/// The links of a chain that is being parsed: the top-level chain or that of a branch.
+#[derive(Default)]
+struct Level {
+    /// Every atom with the bond or dot to the next one.
+    links: Vec<(BranchedAtom, Option<BondOrDot>)>,
+    /// The position of the opening parenthesis of a branch and its bond or dot.
+    branch: Option<(usize, Option<BondOrDot>)>,
+}
+
+struct LenientParser<'a> {
+    input: &'a [u8],
+    position: usize,
+    levels: Vec<Level>,
+    /// A bond or dot that waits for the next atom.
+    pending: Option<(BondOrDot, Span)>,
+    open_rings: HashMap<u8, Span>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl LenientParser<'_> {
+    fn run(&mut self) {
+        while self.position < self.input.len() {
+            let rest = &self.input[self.position..];
+            match rest[0] {
+                b'(' => self.open_branch(),
+                b')' => self.close_branch(),
+                b'[' => self.bracket_atom(),
+                _ => {
+                    let has_atom = !self.level().links.is_empty();
+                    if let (true, None, Ok((after, mut parsed))) =
+                        (has_atom, self.pending, ring_bond(rest))
+                    {
+                        parsed.span = self.span_to(after);
+                        self.add_ring_bond(parsed);
+                        self.position = self.input.len() - after.len();
+                    } else if let Ok((after, parsed)) = atom(rest) {
+                        let span = self.span_to(after);
+                        self.add_atom(parsed, span);
+                        self.position = self.input.len() - after.len();
+                    } else if let Ok((after, parsed)) = bond_or_dot(rest) {
+                        let span = self.span_to(after);
+                        if self.pending.is_some() {
+                            self.report(ParseErrorKind::UnexpectedInput, span);
+                        } else {
+                            self.pending = Some((parsed, span));
+                        }
+                        self.position = self.input.len() - after.len();
+                    } else if rest[0].is_ascii_alphabetic() {
+                        self.skip(ParseErrorKind::InvalidAtom);
+                    } else {
+                        self.skip(ParseErrorKind::UnexpectedInput);
+                    }
+                }
+            }
+        }
+
+        self.drop_pending();
+        while self.levels.len() > 1 {
+            let start = self.level().branch.unwrap().0;
+            self.report(
+                ParseErrorKind::UnclosedBranch,
+                Span {
+                    start,
+                    end: start + 1,
+                },
+            );
+            self.finish_branch(self.input.len());
+        }
+        let mut unclosed: Vec<Span> = self.open_rings.values().copied().collect();
+        unclosed.sort_unstable();
+        for span in unclosed {
+            self.report(ParseErrorKind::UnclosedRing, span);
+        }
+    }
+
+    fn level(&mut self) -> &mut Level {
+        self.levels.last_mut().unwrap()
+    }
+
+    /// The span from the current position to the start of `rest`.
+    fn span_to(&self, rest: &[u8]) -> Span {
+        Span {
+            start: self.position,
+            end: self.input.len() - rest.len(),
+        }
+    }
+
+    fn report(&mut self, kind: ParseErrorKind, span: Span) {
+        self.diagnostics.push(Diagnostic { kind, span });
+    }
+
+    /// Skip a byte, extending the last diagnostic if it is about the bytes just before.
+    fn skip(&mut self, kind: ParseErrorKind) {
+        let position = self.position;
+        match self.diagnostics.last_mut() {
+            Some(last) if last.kind == kind && last.span.end == position => {
+                last.span.end += 1;
+            }
+            _ => self.report(
+                kind,
+                Span {
+                    start: position,
+                    end: position + 1,
+                },
+            ),
+        }
+        self.position += 1;
+    }
+
+    fn drop_pending(&mut self) {
+        if let Some((_, span)) = self.pending.take() {
+            self.report(ParseErrorKind::DanglingBond, span);
+        }
+    }
+
+    fn add_atom(&mut self, atom: Atom, span: Span) {
+        let pending = self.pending.take();
+        let level = self.levels.last_mut().unwrap();
+        let bond_or_dot = pending.map(|(bond_or_dot, _)| bond_or_dot);
+        match (level.links.last_mut(), &mut level.branch) {
+            (Some((_, bond)), _) => *bond = bond_or_dot,
+            (None, Some((_, bond))) => *bond = bond_or_dot,
+            (None, None) => {
+                if let Some((_, span)) = pending {
+                    self.report(ParseErrorKind::DanglingBond, span);
+                }
+            }
+        }
+        self.level().links.push((
+            BranchedAtom {
+                atom,
+                ring_bonds: Vec::new(),
+                branches: Vec::new(),
+                span,
+            },
+            None,
+        ));
+    }
+
+    fn add_ring_bond(&mut self, ring_bond: crate::RingBond) {
+        let span = ring_bond.span;
+        if self.open_rings.remove(&ring_bond.ring_number).is_none() {
+            self.open_rings.insert(ring_bond.ring_number, span);
+        }
+        let (branched_atom, _) = self.level().links.last_mut().unwrap();
+        let misplaced = !branched_atom.branches.is_empty();
+        branched_atom.ring_bonds.push(ring_bond);
+        branched_atom.span.end = span.end;
+        if misplaced {
+            self.report(ParseErrorKind::MisplacedRingBond, span);
+        }
+    }
+
+    fn bracket_atom(&mut self) {
+        let start = self.position;
+        let rest = &self.input[start..];
+        // The closing bracket, unless another atom starts before it.
+        let close = rest[1..]
+            .iter()
+            .position(|&byte| byte == b']' || byte == b'[')
+            .map(|index| index + 1)
+            .filter(|&index| rest[index] == b']');
+        if let Some(close) = close {
+            let span = Span {
+                start,
+                end: start + close + 1,
+            };
+            let parsed = match atom(&rest[..=close]) {
+                Ok(([], parsed)) => parsed,
+                _ => {
+                    self.report(ParseErrorKind::InvalidAtom, span);
+                    Atom::Unknown
+                }
+            };
+            self.add_atom(parsed, span);
+            self.position = span.end;
+            return;
+        }
+
+        // Close the bracket after the longest valid contents.
+        let contents = rest[1..]
+            .iter()
+            .take_while(|&&byte| byte.is_ascii_alphanumeric() || b"@+-:*".contains(&byte))
+            .count();
+        let closed = (1..=contents).rev().find_map(|length| {
+            let mut closed = rest[..=length].to_vec();
+            closed.push(b']');
+            match atom(&closed) {
+                Ok(([], parsed)) => Some((parsed, length)),
+                _ => None,
+            }
+        });
+        match closed {
+            Some((parsed, length)) => {
+                let span = Span {
+                    start,
+                    end: start + length + 1,
+                };
+                self.report(ParseErrorKind::UnclosedBracket, span);
+                self.add_atom(parsed, span);
+                self.position = span.end;
+            }
+            None => {
+                let span = Span {
+                    start,
+                    end: start + contents + 1,
+                };
+                self.report(ParseErrorKind::InvalidAtom, span);
+                self.add_atom(Atom::Unknown, span);
+                self.position = span.end;
+            }
+        }
+    }
+
+    fn open_branch(&mut self) {
+        if self.level().links.is_empty() {
+            self.skip(ParseErrorKind::UnexpectedInput);
+            return;
+        }
+        self.drop_pending();
+        self.levels.push(Level {
+            links: Vec::new(),
+            branch: Some((self.position, None)),
+        });
+        self.position += 1;
+    }
+
+    fn close_branch(&mut self) {
+        if self.levels.len() == 1 {
+            self.skip(ParseErrorKind::UnmatchedParenthesis);
+            return;
+        }
+        self.drop_pending();
+        self.position += 1;
+        self.finish_branch(self.position);
+    }
+
+    /// Add the innermost branch, ending at `end`, to its atom.
+    fn finish_branch(&mut self, end: usize) {
+        let level = self.levels.pop().unwrap();
+        let (start, bond_or_dot) = level.branch.unwrap();
+        let span = Span { start, end };
+        let chain = match build_chain(level.links) {
+            Some(chain) => chain,
+            None => {
+                self.report(ParseErrorKind::EmptyBranch, span);
+                return;
+            }
+        };
+        let (branched_atom, _) = self.level().links.last_mut().unwrap();
+        branched_atom.branches.push(Branch {
+            bond_or_dot,
+            chain,
+            span,
+        });
+        branched_atom.span.end = end;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::flat::parse_flat;
+    use crate::parse;
+
+    fn diagnostics(smiles: &str) -> Vec<(ParseErrorKind, std::ops::Range<usize>)> {
+        parse_lenient(smiles.as_bytes())
+            .diagnostics
+            .into_iter()
+            .map(|diagnostic| (diagnostic.kind, diagnostic.span.into()))
+            .collect()
+    }
+
+    /// The atoms of the recovered chain, without its branches.
+    fn atoms(smiles: &str) -> Vec<Atom> {
+        let chain = parse_lenient(smiles.as_bytes()).chain.unwrap();
+        let mut atoms = vec![chain.branched_atom.atom];
+        let mut link = &chain;
+        while let Some(next) = &link.chain {
+            atoms.push(next.branched_atom.atom);
+            link = next;
+        }
+        atoms
+    }
+
+    #[test]
+    fn valid_input() {
+        for smiles in [
+            "C",
+            "CC(=O)Oc1ccccc1C(=O)O",
+            "C1CCC2(CC1)CO2",
+            "F[As@TB15](Cl)(S)(Br)N",
+            "[Na+].[Cl-]",
+            "C(.C)C",
+            "C%12CC=%12",
+            "F/C=C/F",
+        ] {
+            assert!(parse_flat(smiles.as_bytes()).is_ok());
+            let lenient = parse_lenient(smiles.as_bytes());
+            assert_eq!(Vec::<Diagnostic>::new(), lenient.diagnostics, "{}", smiles);
+            assert_eq!(parse(smiles.as_bytes()).ok(), lenient.chain, "{}", smiles);
+        }
+    }
+
+    #[test]
+    fn empty() {
+        let lenient = parse_lenient(b"");
+        assert_eq!(None, lenient.chain);
+        assert_eq!(vec![(ParseErrorKind::Empty, 0..0)], diagnostics(""));
+        assert_eq!(None, parse_lenient(b"()").chain);
+    }
+
+    #[test]
+    fn skipped_input() {
+        assert_eq!(
+            vec![(ParseErrorKind::InvalidAtom, 2..4)],
+            diagnostics("CCXxO")
+        );
+        assert_eq!(atoms("CCO"), atoms("CCXxO"));
+        assert_eq!(
+            vec![
+                (ParseErrorKind::InvalidAtom, 1..7),
+                (ParseErrorKind::UnmatchedParenthesis, 8..9),
+            ],
+            diagnostics("C[Xy+2]C)O")
+        );
+        assert_eq!(Atom::Unknown, atoms("C[Xy+2]C)O")[1]);
+        assert_eq!(4, atoms("C[Xy+2]C)O").len());
+    }
+
+    #[test]
+    fn unclosed_brackets_and_branches() {
+        assert_eq!(
+            vec![
+                (ParseErrorKind::UnclosedBracket, 2..7),
+                (ParseErrorKind::UnclosedBranch, 1..2),
+            ],
+            diagnostics("C([NH4+CC")
+        );
+        let chain = parse_lenient(b"C([NH4+CC").chain.unwrap();
+        let branch = &chain.branched_atom.branches[0];
+        assert_eq!(Span { start: 1, end: 9 }, branch.span);
+        assert_eq!(
+            parse(b"[NH4+]").unwrap().branched_atom.atom,
+            branch.chain.branched_atom.atom
+        );
+        assert_eq!(Span { start: 0, end: 9 }, chain.branched_atom.span);
+    }
+
+    #[test]
+    fn bonds_and_rings() {
+        assert_eq!(
+            vec![
+                (ParseErrorKind::DanglingBond, 0..1),
+                (ParseErrorKind::DanglingBond, 3..4),
+                (ParseErrorKind::DanglingBond, 7..8),
+                (ParseErrorKind::EmptyBranch, 6..9),
+                (ParseErrorKind::DanglingBond, 10..11),
+                (ParseErrorKind::UnclosedBranch, 4..5),
+            ],
+            diagnostics("=CC=(C(=)C=")
+        );
+        assert_eq!(
+            vec![
+                (ParseErrorKind::MisplacedRingBond, 5..6),
+                (ParseErrorKind::UnclosedRing, 10..11),
+            ],
+            diagnostics("CC(C)1CC1C2")
+        );
+    }
+}
@@ -1,5 +1,53 @@
 #[cfg(feature = "graph")]
+pub mod canon;
+#[cfg(feature = "graph")]
+pub mod conformer;
+#[cfg(feature = "graph")]
+pub mod coords;
+#[cfg(feature = "graph")]
+pub mod crippen;
+#[cfg(feature = "graph")]
+pub mod depict;
+#[cfg(feature = "graph")]
+pub mod descriptors;
+#[cfg(feature = "graph")]
+pub mod fingerprint;
+pub mod flat;
+#[cfg(feature = "graph")]
 pub mod graph;
+#[cfg(feature = "graph")]
+pub mod identifier;
+pub mod lenient;
+#[cfg(feature = "graph")]
+pub mod maccs;
+#[cfg(feature = "graph")]
+mod minimize;
+#[cfg(feature = "graph")]
+pub mod molfile;
+#[cfg(feature = "rayon")]
+pub mod parallel;
+pub mod reader;
+#[cfg(feature = "graph")]
+pub mod sdf;
+#[cfg(feature = "serde")]
+pub mod serialization;
+#[cfg(feature = "graph")]
+pub mod similarity;
+#[cfg(feature = "graph")]
+pub(crate) mod smarts;
+#[cfg(feature = "graph")]
+pub mod standardize;
+#[cfg(feature = "graph")]
+pub mod stereoisomers;
+#[cfg(feature = "graph")]
+pub mod tautomer;
+#[cfg(feature = "graph")]
+pub mod uff;
+#[cfg(feature = "graph")]
+pub mod validate;
+pub mod visit;
+#[cfg(feature = "graph")]
+pub mod writer;
 
 use nom::branch::alt;
 use nom::bytes::complete::tag;
@@ -15,11 +63,17 @@ use nom::sequence::preceded;
 use nom::sequence::tuple;
 use nom::IResult;
 use ptable::Element;
+use std::collections::HashMap;
 
 #[derive(Debug, PartialEq, Eq, Ord, PartialOrd, Copy, Clone, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Symbol {
-    ElementSymbol(Element),
-    AromaticSymbol(Element),
+    ElementSymbol(
+        #[cfg_attr(feature = "serde", serde(with = "crate::serialization::element"))] Element,
+    ),
+    AromaticSymbol(
+        #[cfg_attr(feature = "serde", serde(with = "crate::serialization::element"))] Element,
+    ),
     Unknown,
 }
 
@@ -193,12 +247,13 @@ fn symbol(input: &[u8]) -> IResult<&[u8], Symbol> {
             let try_element = Element::from_symbol(other_str);
             try_element
                 .ok_or("Unknown element symbol")
-                .map(|element| Symbol::ElementSymbol(element))
+                .map(Symbol::ElementSymbol)
         }
     })(input)
 }
 
 #[derive(Debug, PartialEq, Eq, Ord, PartialOrd, Copy, Clone, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct BracketAtom {
     pub isotope: Option<u16>,
     pub symbol: Symbol,
@@ -229,7 +284,7 @@ fn charge(input: &[u8]) -> IResult<&[u8], i8> {
                 }
             },
         )),
-        |v| v.into_iter().fold(0, |acc, x| acc + x),
+        |v| v.into_iter().sum(),
     )(input)
 }
 
@@ -284,7 +339,9 @@ fn bracket_atom(input: &[u8]) -> IResult<&[u8], BracketAtom> {
 }
 
 #[derive(Debug, PartialEq, Eq, Ord, PartialOrd, Copy, Clone, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct AliphaticOrganicAtom {
+    #[cfg_attr(feature = "serde", serde(with = "crate::serialization::element"))]
     pub element: Element,
 }
 
@@ -315,42 +372,181 @@ fn aliphatic_organic_atom(input: &[u8]) -> IResult<&[u8], AliphaticOrganicAtom>
 }
 
 #[derive(Debug, PartialEq, Eq, Ord, PartialOrd, Copy, Clone, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct AromaticOrganicAtom {
+    #[cfg_attr(feature = "serde", serde(with = "crate::serialization::element"))]
+    pub element: Element,
+}
+
+fn aromatic_organic_atom(input: &[u8]) -> IResult<&[u8], AromaticOrganicAtom> {
+    map(
+        alt((
+            tag(b"b"),
+            tag(b"c"),
+            tag(b"n"),
+            tag(b"o"),
+            tag(b"s"),
+            tag(b"p"),
+        )),
+        |sym: &[u8]| {
+            let element = match sym {
+                b"b" => Element::Boron,
+                b"c" => Element::Carbon,
+                b"n" => Element::Nitrogen,
+                b"o" => Element::Oxygen,
+                b"s" => Element::Sulfur,
+                b"p" => Element::Phosphorus,
+                _ => unreachable!(),
+            };
+            AromaticOrganicAtom { element }
+        },
+    )(input)
+}
+
+#[derive(Debug, PartialEq, Eq, Ord, PartialOrd, Copy, Clone, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Atom {
     Bracket(BracketAtom),
     AliphaticOrganic(AliphaticOrganicAtom),
-    // AromaticOrganic not supported
+    AromaticOrganic(AromaticOrganicAtom),
     Unknown,
 }
 
 fn atom(input: &[u8]) -> IResult<&[u8], Atom> {
     alt((
         map(tag(b"*"), |_| Atom::Unknown),
-        map(bracket_atom, |inner| Atom::Bracket(inner)),
+        map(bracket_atom, Atom::Bracket),
         map(aliphatic_organic_atom, |inner| {
             Atom::AliphaticOrganic(inner)
         }),
+        map(aromatic_organic_atom, Atom::AromaticOrganic),
     ))(input)
 }
 
-#[derive(Debug, PartialEq, Eq, Ord, PartialOrd, Clone, Hash)]
+/// A range of bytes of the input that a part of a SMILES string was parsed from.
+#[derive(Debug, Default, PartialEq, Eq, Ord, PartialOrd, Copy, Clone, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn len(&self) -> usize {
+        self.end - self.start
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+
+    /// While parsing, spans hold the lengths of the remaining input at their start and end,
+    /// because the parsers don't know where their input starts. Once the length of the whole
+    /// input is known, [`chain`] turns them into offsets.
+    fn unresolved(input: &[u8], rest: &[u8]) -> Self {
+        Span {
+            start: input.len(),
+            end: rest.len(),
+        }
+    }
+
+    fn resolve(&mut self, length: usize) {
+        *self = Span {
+            start: length - self.start,
+            end: length - self.end,
+        };
+    }
+}
+
+impl From<Span> for std::ops::Range<usize> {
+    fn from(span: Span) -> Self {
+        span.start..span.end
+    }
+}
+
+/// Implement `PartialEq`, `Eq`, `PartialOrd`, `Ord` and `Hash` on the given fields of a struct,
+/// in order, leaving out its span. Structures parsed from different input compare equal.
+macro_rules! ignore_span {
+    ($type:ty { $($field:ident),+ }) => {
+        impl PartialEq for $type {
+            fn eq(&self, other: &Self) -> bool {
+                ($(&self.$field,)+) == ($(&other.$field,)+)
+            }
+        }
+
+        impl Eq for $type {}
+
+        impl PartialOrd for $type {
+            fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+                Some(self.cmp(other))
+            }
+        }
+
+        impl Ord for $type {
+            fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+                ($(&self.$field,)+).cmp(&($(&other.$field,)+))
+            }
+        }
+
+        impl std::hash::Hash for $type {
+            fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+                $(self.$field.hash(state);)+
+            }
+        }
+    };
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct BranchedAtom {
     pub atom: Atom,
     pub ring_bonds: Vec<RingBond>,
     pub branches: Vec<Branch>,
+    /// The atom with its ring bonds and branches.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub span: Span,
 }
 
-fn branched_atom(input: &[u8]) -> IResult<&[u8], BranchedAtom> {
-    map(
-        tuple((atom, many0(ring_bond), many0(branch))),
-        |(atom, ring_bonds, branches)| BranchedAtom {
+impl BranchedAtom {
+    /// The span of the atom alone, without its ring bonds and branches.
+    pub fn atom_span(&self) -> Span {
+        let end = self
+            .ring_bonds
+            .iter()
+            .map(|ring_bond| ring_bond.span.start)
+            .chain(self.branches.iter().map(|branch| branch.span.start))
+            .min()
+            .unwrap_or(self.span.end);
+        Span {
+            start: self.span.start,
+            end,
+        }
+    }
+}
+
+ignore_span!(BranchedAtom {
+    atom,
+    ring_bonds,
+    branches
+});
+
+/// An atom with its ring bonds. Its branches are added and the end of its span is set by
+/// [`unresolved_chain`].
+fn unbranched_atom(input: &[u8]) -> IResult<&[u8], BranchedAtom> {
+    let (rest, (atom, ring_bonds)) = tuple((atom, many0(ring_bond)))(input)?;
+    Ok((
+        rest,
+        BranchedAtom {
             atom,
             ring_bonds,
-            branches,
+            branches: Vec::new(),
+            span: Span::unresolved(input, rest),
         },
-    )(input)
+    ))
 }
 
 #[derive(Debug, PartialEq, Eq, Ord, PartialOrd, Copy, Clone, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Bond {
     Single,
     Double,
@@ -386,10 +582,29 @@ fn bond(input: &[u8]) -> IResult<&[u8], Bond> {
     })(input)
 }
 
-#[derive(Debug, PartialEq, Eq, Ord, PartialOrd, Copy, Clone, Hash)]
+#[derive(Debug, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct RingBond {
     pub bond: Option<Bond>,
     pub ring_number: u8,
+    /// The bond symbol, if any, and the ring number.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub span: Span,
+}
+
+ignore_span!(RingBond { bond, ring_number });
+
+/// Whether the bond symbols at the two ends of a ring bond contradict each other. Directional
+/// bonds count as single bonds, and a missing symbol agrees with any other.
+pub(crate) fn ring_bonds_conflict(opening: Option<Bond>, closing: Option<Bond>) -> bool {
+    let undirected = |bond| match bond {
+        Bond::Up | Bond::Down => Bond::Single,
+        bond => bond,
+    };
+    match (opening, closing) {
+        (Some(opening), Some(closing)) => undirected(opening) != undirected(closing),
+        _ => false,
+    }
 }
 
 fn bond_digits(input: &[u8]) -> IResult<&[u8], u8> {
@@ -406,31 +621,514 @@ fn bond_digits(input: &[u8]) -> IResult<&[u8], u8> {
 }
 
 fn ring_bond(input: &[u8]) -> IResult<&[u8], RingBond> {
-    map(tuple((opt(bond), bond_digits)), |(bond, ring_number)| {
-        RingBond { bond, ring_number }
-    })(input)
+    let (rest, (bond, ring_number)) = tuple((opt(bond), bond_digits))(input)?;
+    Ok((
+        rest,
+        RingBond {
+            bond,
+            ring_number,
+            span: Span::unresolved(input, rest),
+        },
+    ))
 }
 
-#[derive(Debug, PartialEq, Eq, Ord, PartialOrd, Clone, Hash)]
+/// A chain of atoms. Chains are compared, hashed, formatted, cloned and dropped without
+/// recursion, so neither long chains nor deeply nested branches overflow the stack.
 pub struct Chain {
     pub chain: Option<Box<Chain>>,
     pub bond_or_dot: Option<BondOrDot>,
     pub branched_atom: BranchedAtom,
 }
 
-pub fn chain(input: &[u8]) -> IResult<&[u8], Chain> {
-    map(
-        tuple((branched_atom, opt(bond_or_dot), opt(chain))),
-        |(branched_atom, bond_or_dot, chain)| Chain {
-            chain: chain.map(|n| Box::new(n)),
+impl Chain {
+    fn resolve_spans(&mut self, length: usize) {
+        let mut stack = vec![self];
+        while let Some(mut link) = stack.pop() {
+            loop {
+                let Chain {
+                    chain,
+                    branched_atom,
+                    ..
+                } = link;
+                branched_atom.span.resolve(length);
+                for ring_bond in &mut branched_atom.ring_bonds {
+                    ring_bond.span.resolve(length);
+                }
+                for branch in &mut branched_atom.branches {
+                    branch.span.resolve(length);
+                    stack.push(&mut branch.chain);
+                }
+                match chain {
+                    Some(next) => link = next,
+                    None => break,
+                }
+            }
+        }
+    }
+
+    /// Move the next link and the branches out of the chain, for [`Drop`].
+    fn detach(&mut self, stack: &mut Vec<Chain>) {
+        if let Some(next) = self.chain.take() {
+            stack.push(*next);
+        }
+        for branch in std::mem::take(&mut self.branched_atom.branches) {
+            stack.push(branch.chain);
+        }
+    }
+
+    fn tokens(&self) -> Tokens<'_> {
+        Tokens {
+            stack: vec![Step::Chain(self)],
+        }
+    }
+}
+
+/// A part of a chain, in SMILES order. A chain is determined by its sequence of tokens, which
+/// the trait implementations of [`Chain`] work on. Chains are compared and hashed by their
+/// tokens [`without_span`](Token::without_span).
+#[derive(Debug, PartialEq, Eq, Ord, PartialOrd, Hash)]
+enum Token<'a> {
+    Atom(&'a Atom, Span),
+    RingBond(&'a RingBond),
+    Branch(Option<BondOrDot>, Span),
+    BranchEnd,
+    /// The bond or dot after an atom, and whether another atom follows.
+    Link(Option<BondOrDot>, bool),
+}
+
+impl Token<'_> {
+    /// The token with its span, if any, replaced by the default span.
+    fn without_span(self) -> Self {
+        match self {
+            Token::Atom(atom, _) => Token::Atom(atom, Span::default()),
+            Token::Branch(bond_or_dot, _) => Token::Branch(bond_or_dot, Span::default()),
+            token => token,
+        }
+    }
+}
+
+enum Step<'a> {
+    Chain(&'a Chain),
+    Token(Token<'a>),
+}
+
+struct Tokens<'a> {
+    stack: Vec<Step<'a>>,
+}
+
+impl<'a> Iterator for Tokens<'a> {
+    type Item = Token<'a>;
+
+    fn next(&mut self) -> Option<Token<'a>> {
+        let chain = match self.stack.pop()? {
+            Step::Token(token) => return Some(token),
+            Step::Chain(chain) => chain,
+        };
+        let branched_atom = &chain.branched_atom;
+        if let Some(next) = &chain.chain {
+            self.stack.push(Step::Chain(next));
+        }
+        self.stack.push(Step::Token(Token::Link(
+            chain.bond_or_dot,
+            chain.chain.is_some(),
+        )));
+        for branch in branched_atom.branches.iter().rev() {
+            self.stack.push(Step::Token(Token::BranchEnd));
+            self.stack.push(Step::Chain(&branch.chain));
+            self.stack
+                .push(Step::Token(Token::Branch(branch.bond_or_dot, branch.span)));
+        }
+        for ring_bond in branched_atom.ring_bonds.iter().rev() {
+            self.stack.push(Step::Token(Token::RingBond(ring_bond)));
+        }
+        Some(Token::Atom(&branched_atom.atom, branched_atom.span))
+    }
+}
+
+/// The links of a chain that is being built: the top-level chain or that of a branch.
+struct Level {
+    /// Every atom with the bond or dot to the next one.
+    links: Vec<(BranchedAtom, Option<BondOrDot>)>,
+    /// The bond or dot and the span of the branch.
+    branch: Option<(Option<BondOrDot>, Span)>,
+}
+
+pub(crate) fn build_chain(links: Vec<(BranchedAtom, Option<BondOrDot>)>) -> Option<Chain> {
+    let mut chain = None;
+    for (branched_atom, bond_or_dot) in links.into_iter().rev() {
+        chain = Some(Box::new(Chain {
+            chain,
             bond_or_dot,
             branched_atom,
-        },
-    )(input)
+        }));
+    }
+    chain.map(|chain| *chain)
+}
+
+impl Clone for Chain {
+    fn clone(&self) -> Self {
+        let mut levels = vec![Level {
+            links: Vec::new(),
+            branch: None,
+        }];
+        for token in self.tokens() {
+            let level = levels.last_mut().unwrap();
+            match token {
+                Token::Atom(atom, span) => level.links.push((
+                    BranchedAtom {
+                        atom: *atom,
+                        ring_bonds: Vec::new(),
+                        branches: Vec::new(),
+                        span,
+                    },
+                    None,
+                )),
+                Token::RingBond(ring_bond) => level
+                    .links
+                    .last_mut()
+                    .unwrap()
+                    .0
+                    .ring_bonds
+                    .push(*ring_bond),
+                Token::Branch(bond_or_dot, span) => levels.push(Level {
+                    links: Vec::new(),
+                    branch: Some((bond_or_dot, span)),
+                }),
+                Token::BranchEnd => {
+                    let level = levels.pop().unwrap();
+                    let (bond_or_dot, span) = level.branch.unwrap();
+                    let (parent, _) = levels.last_mut().unwrap().links.last_mut().unwrap();
+                    parent.branches.push(Branch {
+                        bond_or_dot,
+                        chain: build_chain(level.links).unwrap(),
+                        span,
+                    });
+                }
+                Token::Link(bond_or_dot, _) => level.links.last_mut().unwrap().1 = bond_or_dot,
+            }
+        }
+        build_chain(levels.pop().unwrap().links).unwrap()
+    }
+}
+
+impl PartialEq for Chain {
+    fn eq(&self, other: &Self) -> bool {
+        self.tokens()
+            .map(Token::without_span)
+            .eq(other.tokens().map(Token::without_span))
+    }
+}
+
+impl Eq for Chain {}
+
+impl PartialOrd for Chain {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Chains are ordered by their parts in SMILES order.
+impl Ord for Chain {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.tokens()
+            .map(Token::without_span)
+            .cmp(other.tokens().map(Token::without_span))
+    }
+}
+
+impl std::hash::Hash for Chain {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        for token in self.tokens().map(Token::without_span) {
+            token.hash(state);
+        }
+    }
+}
+
+/// Formats the parts of the chain in SMILES order, as a flat list.
+impl std::fmt::Debug for Chain {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_list().entries(self.tokens()).finish()
+    }
+}
+
+// Dropping a long chain or deeply nested branches recursively would overflow the stack, so the
+// links and branches are detached one by one.
+impl Drop for Chain {
+    fn drop(&mut self) {
+        let mut stack = Vec::new();
+        self.detach(&mut stack);
+        while let Some(mut chain) = stack.pop() {
+            chain.detach(&mut stack);
+        }
+    }
+}
+
+/// Parse a chain, up to the first input that doesn't continue it. The spans in the chain are
+/// offsets into `input`.
+pub fn chain(input: &[u8]) -> IResult<&[u8], Chain> {
+    let (rest, mut chain) = unresolved_chain(input)?;
+    chain.resolve_spans(input.len());
+    Ok((rest, chain))
+}
+
+fn unresolved_chain(input: &[u8]) -> IResult<&[u8], Chain> {
+    // Chains and branches are parsed iteratively, so that the stack depth grows neither with the
+    // length of a chain nor with the nesting of branches.
+    let (mut rest, first) = unbranched_atom(input)?;
+    let mut levels = vec![Level {
+        links: vec![(first, None)],
+        branch: None,
+    }];
+    let mut in_branches = true;
+    loop {
+        if in_branches && rest.first() == Some(&b'(') {
+            let (after_bond, bond_or_dot) = opt(bond_or_dot)(&rest[1..])?;
+            match unbranched_atom(after_bond) {
+                Ok((after_atom, first)) => {
+                    levels.push(Level {
+                        links: vec![(first, None)],
+                        branch: Some((bond_or_dot, Span::unresolved(rest, rest))),
+                    });
+                    rest = after_atom;
+                    continue;
+                }
+                Err(nom::Err::Error(_)) => rest = abandon_branches(&mut levels, input, rest),
+                Err(err) => return Err(err),
+            }
+        }
+
+        // The atom is complete, continue the chain
+        let level = levels.last_mut().unwrap();
+        let (branched_atom, link) = level.links.last_mut().unwrap();
+        branched_atom.span.end = rest.len();
+        let (after_bond, bond_or_dot) = opt(bond_or_dot)(rest)?;
+        *link = bond_or_dot;
+        rest = after_bond;
+        match unbranched_atom(rest) {
+            Ok((after_atom, next)) => {
+                level.links.push((next, None));
+                rest = after_atom;
+                in_branches = true;
+                continue;
+            }
+            Err(nom::Err::Error(_)) => {}
+            Err(err) => return Err(err),
+        }
+
+        // The chain is complete, close its branch
+        let level = levels.pop().unwrap();
+        let chain = build_chain(level.links).unwrap();
+        let (bond_or_dot, mut span) = match level.branch {
+            Some(branch) => branch,
+            None => return Ok((rest, chain)),
+        };
+        match rest.first() {
+            Some(b')') => {
+                rest = &rest[1..];
+                span.end = rest.len();
+                let (parent, _) = levels.last_mut().unwrap().links.last_mut().unwrap();
+                parent.branches.push(Branch {
+                    bond_or_dot,
+                    chain,
+                    span,
+                });
+                in_branches = true;
+            }
+            _ => {
+                let start = &input[input.len() - span.start..];
+                rest = abandon_branches(&mut levels, input, start);
+                in_branches = false;
+            }
+        }
+    }
+}
+
+/// Drop all unfinished branches after the branch starting at `start` turned out to be invalid,
+/// returning the input at which the top-level chain ends. A branch that is not closed can't be
+/// closed by the branches around it either, so parsing continues before the outermost one.
+fn abandon_branches<'a>(levels: &mut Vec<Level>, input: &'a [u8], start: &'a [u8]) -> &'a [u8] {
+    match levels.get(1) {
+        Some(Level {
+            branch: Some((_, span)),
+            ..
+        }) => {
+            let start = input.len() - span.start;
+            levels.truncate(1);
+            &input[start..]
+        }
+        _ => start,
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Copy, Clone, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ParseErrorKind {
+    /// The input contained no SMILES at all.
+    Empty,
+    /// The input did not start with a valid atom.
+    InvalidAtom,
+    /// A valid chain was parsed, but it was followed by unparsable input.
+    UnexpectedInput,
+    /// The input ended inside of a branch.
+    UnclosedBranch,
+    /// A ring bond was opened but never closed.
+    UnclosedRing,
+    /// A bracket atom was opened but never closed.
+    UnclosedBracket,
+    /// A closing parenthesis without an open branch.
+    UnmatchedParenthesis,
+    /// A bond or dot that is not followed by an atom.
+    DanglingBond,
+    /// A branch without atoms.
+    EmptyBranch,
+    /// A ring bond after the branches of an atom, instead of before them.
+    MisplacedRingBond,
+    /// A ring bond that is closed on the atom that opened it, like in `C11`.
+    RingBondToSelf,
+    /// A ring bond between two atoms that are already bonded, like in `C1C1` or `C12CC12`.
+    DuplicateBond,
+    /// A ring bond with different bond symbols at its two ends, like in `C=1CC-1`.
+    ConflictingRingBond,
+}
+
+impl std::fmt::Display for ParseErrorKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            ParseErrorKind::Empty => "empty SMILES",
+            ParseErrorKind::InvalidAtom => "invalid atom",
+            ParseErrorKind::UnexpectedInput => "unexpected input",
+            ParseErrorKind::UnclosedBranch => "unclosed branch",
+            ParseErrorKind::UnclosedRing => "unclosed ring bond",
+            ParseErrorKind::UnclosedBracket => "unclosed bracket atom",
+            ParseErrorKind::UnmatchedParenthesis => "unmatched closing parenthesis",
+            ParseErrorKind::DanglingBond => "bond without a following atom",
+            ParseErrorKind::EmptyBranch => "empty branch",
+            ParseErrorKind::MisplacedRingBond => "ring bond after a branch",
+            ParseErrorKind::RingBondToSelf => "ring bond to the same atom",
+            ParseErrorKind::DuplicateBond => "second bond between the same atoms",
+            ParseErrorKind::ConflictingRingBond => "ring bond with conflicting bond symbols",
+        })
+    }
+}
+
+/// Error returned by [`parse`] if the input is not a complete SMILES string.
+#[derive(Debug, PartialEq, Eq, Copy, Clone, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ParseError {
+    pub kind: ParseErrorKind,
+    /// Byte offset into the input at which parsing stopped.
+    pub position: usize,
+}
+
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} at position {}", self.kind, self.position)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// Finds ring bonds that close on their own atom, that bond two atoms a second time or that
+/// have conflicting bond symbols.
+#[derive(Default)]
+struct RingBondCheck {
+    atom_count: usize,
+    // The last atom at every depth of branches up to the current one
+    atoms: Vec<usize>,
+    // Atoms that are already bonded to the current atom
+    bonded: Vec<usize>,
+    // Whether a dot separates the next atom from the previous one
+    dot: bool,
+    open_rings: HashMap<u8, (usize, Option<Bond>)>,
+    error: Option<ParseError>,
+}
+
+impl visit::Visitor for RingBondCheck {
+    fn visit_atom(&mut self, _atom: &BranchedAtom, depth: usize) {
+        let previous = self.atoms.get(depth).or_else(|| self.atoms.last()).copied();
+        let current = self.atom_count;
+        self.atom_count += 1;
+        self.bonded.clear();
+        if !std::mem::take(&mut self.dot) {
+            self.bonded.extend(previous);
+        }
+        self.atoms.truncate(depth);
+        self.atoms.push(current);
+    }
+
+    fn visit_dot(&mut self, _dot: &Dot, _depth: usize) {
+        self.dot = true;
+    }
+
+    fn visit_ring_bond(&mut self, ring_bond: &RingBond, _depth: usize) {
+        let current = *self.atoms.last().expect("ring bonds follow an atom");
+        match self.open_rings.remove(&ring_bond.ring_number) {
+            Some((opening, opening_bond)) => {
+                let kind = if opening == current {
+                    Some(ParseErrorKind::RingBondToSelf)
+                } else if self.bonded.contains(&opening) {
+                    Some(ParseErrorKind::DuplicateBond)
+                } else if ring_bonds_conflict(opening_bond, ring_bond.bond) {
+                    Some(ParseErrorKind::ConflictingRingBond)
+                } else {
+                    None
+                };
+                if let (None, Some(kind)) = (self.error, kind) {
+                    self.error = Some(ParseError {
+                        kind,
+                        position: ring_bond.span.start,
+                    });
+                }
+                self.bonded.push(opening);
+            }
+            None => {
+                self.open_rings
+                    .insert(ring_bond.ring_number, (current, ring_bond.bond));
+            }
+        }
+    }
+
+    fn leave_branch(&mut self, _branch: &Branch, depth: usize) {
+        self.dot = false;
+        self.atoms.truncate(depth + 1);
+    }
+}
+
+/// Parse a complete SMILES string.
+///
+/// Unlike [`chain`], this fails if any input is left over after the chain, and on ring bonds
+/// that close on their own atom, that bond two atoms a second time or that have conflicting
+/// bond symbols.
+pub fn parse(input: &[u8]) -> Result<Chain, ParseError> {
+    if input.is_empty() {
+        return Err(ParseError {
+            kind: ParseErrorKind::Empty,
+            position: 0,
+        });
+    }
+    match chain(input) {
+        Ok(([], chain)) => {
+            let mut check = RingBondCheck::default();
+            visit::walk_chain(&mut check, &chain);
+            match check.error {
+                Some(error) => Err(error),
+                None => Ok(chain),
+            }
+        }
+        Ok((rest, _)) => Err(ParseError {
+            kind: ParseErrorKind::UnexpectedInput,
+            position: input.len() - rest.len(),
+        }),
+        Err(_) => Err(ParseError {
+            kind: ParseErrorKind::InvalidAtom,
+            position: 0,
+        }),
+    }
 }
 
 // Symbol for non-connected parts of compound
 #[derive(Debug, PartialEq, Eq, Ord, PartialOrd, Copy, Clone, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Dot;
 
 fn dot(input: &[u8]) -> IResult<&[u8], Dot> {
@@ -438,35 +1136,30 @@ fn dot(input: &[u8]) -> IResult<&[u8], Dot> {
 }
 
 #[derive(Debug, PartialEq, Eq, Ord, PartialOrd, Copy, Clone, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum BondOrDot {
     Bond(Bond),
     Dot(Dot),
 }
 
 fn bond_or_dot(input: &[u8]) -> IResult<&[u8], BondOrDot> {
-    alt((
-        map(bond, |inner| BondOrDot::Bond(inner)),
-        map(dot, |inner| BondOrDot::Dot(inner)),
-    ))(input)
+    alt((map(bond, BondOrDot::Bond), map(dot, BondOrDot::Dot)))(input)
 }
 
-#[derive(Debug, PartialEq, Eq, Ord, PartialOrd, Clone, Hash)]
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Branch {
     pub bond_or_dot: Option<BondOrDot>,
     pub chain: Chain,
+    /// The branch with its parentheses.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub span: Span,
 }
 
-fn branch(input: &[u8]) -> IResult<&[u8], Branch> {
-    delimited(
-        char('('),
-        map(tuple((opt(bond_or_dot), chain)), |(bond_or_dot, chain)| {
-            Branch { bond_or_dot, chain }
-        }),
-        char(')'),
-    )(input)
-}
+ignore_span!(Branch { bond_or_dot, chain });
 
 #[derive(Debug, PartialEq, Eq, Ord, PartialOrd, Copy, Clone, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Chirality {
     /// `@`
     Anticlockwise,
@@ -690,6 +1383,15 @@ mod tests {
             atom(b"[16C]")
         );
         assert_eq!(Ok(("".as_bytes(), Atom::Unknown)), atom(b"*"));
+        assert_eq!(
+            Ok((
+                "1".as_bytes(),
+                Atom::AromaticOrganic(AromaticOrganicAtom {
+                    element: Element::Carbon
+                })
+            )),
+            atom(b"c1")
+        );
     }
 
     #[test]
@@ -706,7 +1408,8 @@ mod tests {
                                 element: Element::Carbon
                             }),
                             ring_bonds: vec![],
-                            branches: vec![]
+                            branches: vec![],
+                            span: Span::default()
                         }
                     })),
                     bond_or_dot: None,
@@ -715,7 +1418,8 @@ mod tests {
                             element: Element::Carbon
                         }),
                         ring_bonds: vec![],
-                        branches: vec![]
+                        branches: vec![],
+                        span: Span::default()
                     }
                 }
             )),
@@ -737,7 +1441,8 @@ mod tests {
                                 element: Element::Fluorine
                             }),
                             ring_bonds: vec![],
-                            branches: vec![]
+                            branches: vec![],
+                            span: Span::default()
                         }
                     })),
                     bond_or_dot: None,
@@ -746,7 +1451,8 @@ mod tests {
                             element: Element::Carbon
                         }),
                         ring_bonds: vec![],
-                        branches: vec![]
+                        branches: vec![],
+                        span: Span::default()
                     }
                 }
             )),
@@ -768,7 +1474,8 @@ mod tests {
                                 element: Element::Carbon
                             }),
                             ring_bonds: vec![],
-                            branches: vec![]
+                            branches: vec![],
+                            span: Span::default()
                         }
                     })),
                     bond_or_dot: Some(BondOrDot::Bond(Bond::Double)),
@@ -777,7 +1484,8 @@ mod tests {
                             element: Element::Carbon
                         }),
                         ring_bonds: vec![],
-                        branches: vec![]
+                        branches: vec![],
+                        span: Span::default()
                     }
                 }
             )),
@@ -785,6 +1493,169 @@ mod tests {
         );
     }
 
+    #[test]
+    fn parse_cases() {
+        assert!(parse(b"C1CCC2(CC1)CO2").is_ok());
+        assert_eq!(
+            Err(ParseError {
+                kind: ParseErrorKind::Empty,
+                position: 0,
+            }),
+            parse(b"")
+        );
+        assert_eq!(
+            Err(ParseError {
+                kind: ParseErrorKind::InvalidAtom,
+                position: 0,
+            }),
+            parse(b"(C)")
+        );
+        assert_eq!(
+            Err(ParseError {
+                kind: ParseErrorKind::UnexpectedInput,
+                position: 3,
+            }),
+            parse(b"CCC)")
+        );
+    }
+
+    #[test]
+    fn parse_invalid_ring_bonds() {
+        let error = |kind, position| Err(ParseError { kind, position });
+        assert_eq!(error(ParseErrorKind::RingBondToSelf, 2), parse(b"C11"));
+        assert_eq!(error(ParseErrorKind::DuplicateBond, 3), parse(b"C1C1"));
+        assert_eq!(error(ParseErrorKind::DuplicateBond, 6), parse(b"C12CC12"));
+        assert_eq!(error(ParseErrorKind::DuplicateBond, 4), parse(b"C1(C1)"));
+        assert_eq!(
+            error(ParseErrorKind::ConflictingRingBond, 5),
+            parse(b"C=1CC-1")
+        );
+
+        assert!(parse(b"C1.C1").is_ok());
+        assert!(parse(b"C1CC(C1)C").is_ok());
+        assert!(parse(b"C=1CC=1").is_ok());
+        assert!(parse(b"F/C=C/1.Cl1").is_ok());
+    }
+
+    #[test]
+    fn spans() {
+        let chain = parse(b"C%12CC(=O)N%12").unwrap();
+        let first = &chain.branched_atom;
+        assert_eq!(Span { start: 0, end: 4 }, first.span);
+        assert_eq!(Span { start: 0, end: 1 }, first.atom_span());
+        assert_eq!(Span { start: 1, end: 4 }, first.ring_bonds[0].span);
+
+        let third = &chain
+            .chain
+            .as_ref()
+            .unwrap()
+            .chain
+            .as_ref()
+            .unwrap()
+            .branched_atom;
+        assert_eq!(Span { start: 5, end: 10 }, third.span);
+        assert_eq!(Span { start: 5, end: 6 }, third.atom_span());
+        assert_eq!(Span { start: 6, end: 10 }, third.branches[0].span);
+        assert_eq!(
+            Span { start: 8, end: 9 },
+            third.branches[0].chain.branched_atom.span
+        );
+
+        let (rest, chain) = super::chain(b"[NH4+])").unwrap();
+        assert_eq!(b")", rest);
+        assert_eq!(0..6, std::ops::Range::from(chain.branched_atom.span));
+    }
+
+    #[test]
+    fn spans_are_not_compared() {
+        use std::collections::hash_map::DefaultHasher;
+        use std::hash::{Hash, Hasher};
+
+        let hash = |chain: &Chain| {
+            let mut hasher = DefaultHasher::new();
+            chain.hash(&mut hasher);
+            hasher.finish()
+        };
+        let ring = parse(b"C1CC1").unwrap();
+        let padded = parse(b"C%01CC%01").unwrap();
+        assert_eq!(ring, padded);
+        assert_eq!(std::cmp::Ordering::Equal, ring.cmp(&padded));
+        assert_eq!(hash(&ring), hash(&padded));
+
+        let ethane = parse(b"CC").unwrap();
+        let mixture = parse(b"O.CC").unwrap();
+        assert_eq!(ethane, **mixture.chain.as_ref().unwrap());
+        let branched = parse(b"CC(O)C").unwrap();
+        let other = parse(b"C(C(O)C)").unwrap();
+        assert_eq!(
+            branched.chain.as_ref().unwrap().branched_atom.branches,
+            other.branched_atom.branches[0].chain.branched_atom.branches
+        );
+    }
+
+    #[test]
+    fn long_linear_chain() {
+        let smiles = "C".repeat(100_000);
+        let chain = parse(smiles.as_bytes()).unwrap();
+
+        let mut length = 1;
+        let mut link = &chain;
+        while let Some(next) = &link.chain {
+            length += 1;
+            link = next;
+        }
+        assert_eq!(100_000, length);
+        assert_chain_traits(&chain, &parse(&smiles.as_bytes()[1..]).unwrap());
+    }
+
+    #[test]
+    fn deeply_nested_branches() {
+        let smiles = format!("{}C{}", "C(".repeat(100_000), ")".repeat(100_000));
+        let chain = parse(smiles.as_bytes()).unwrap();
+
+        let mut depth = 0;
+        let mut link = &chain;
+        while let Some(branch) = link.branched_atom.branches.first() {
+            depth += 1;
+            link = &branch.chain;
+        }
+        assert_eq!(100_000, depth);
+        assert_chain_traits(
+            &chain,
+            &parse(&smiles.as_bytes()[2..smiles.len() - 1]).unwrap(),
+        );
+
+        let unclosed = format!("C{}", "C(".repeat(100_000));
+        let (rest, chain) = super::chain(unclosed.as_bytes()).unwrap();
+        assert_eq!(&unclosed.as_bytes()[2..], rest);
+        assert!(chain
+            .chain
+            .as_ref()
+            .unwrap()
+            .branched_atom
+            .branches
+            .is_empty());
+    }
+
+    /// Clone, compare, hash and format a large chain, which must not overflow the stack.
+    fn assert_chain_traits(chain: &Chain, other: &Chain) {
+        use std::collections::hash_map::DefaultHasher;
+        use std::hash::{Hash, Hasher};
+
+        let hash = |chain: &Chain| {
+            let mut hasher = DefaultHasher::new();
+            chain.hash(&mut hasher);
+            hasher.finish()
+        };
+        let clone = chain.clone();
+        assert_eq!(chain, &clone);
+        assert_eq!(std::cmp::Ordering::Equal, chain.cmp(&clone));
+        assert_eq!(hash(chain), hash(&clone));
+        assert_eq!(format!("{:?}", chain), format!("{:?}", clone));
+        assert_ne!(chain, other);
+        assert_ne!(std::cmp::Ordering::Equal, chain.cmp(other));
+    }
+
     // 1-Oxaspiro[2.5]octane
     #[test]
     fn ring_and_branch_chain() {
@@ -793,6 +1664,14 @@ mod tests {
         assert!(chain.unwrap().0.is_empty());
     }
 
+    // Pyridine
+    #[test]
+    fn aromatic_chain() {
+        let chain = chain(b"c1ccncc1");
+        assert!(chain.is_ok());
+        assert!(chain.unwrap().0.is_empty());
+    }
+
     // Isobutane
     #[test]
     fn branch_isobutane() {
@@ -833,7 +1712,8 @@ mod tests {
                                     element: Element::Nitrogen
                                 }),
                                 ring_bonds: vec![],
-                                branches: vec![]
+                                branches: vec![],
+                                span: Span::default()
                             }
                         })),
                         bond_or_dot: None,
@@ -857,9 +1737,11 @@ mod tests {
                                                 element: Element::Chlorine
                                             }),
                                             ring_bonds: vec![],
-                                            branches: vec![]
+                                            branches: vec![],
+                                            span: Span::default()
                                         }
                                     },
+                                    span: Span::default(),
                                 },
                                 Branch {
                                     bond_or_dot: None,
@@ -871,9 +1753,11 @@ mod tests {
                                                 element: Element::Sulfur
                                             }),
                                             ring_bonds: vec![],
-                                            branches: vec![]
+                                            branches: vec![],
+                                            span: Span::default()
                                         }
                                     },
+                                    span: Span::default(),
                                 },
                                 Branch {
                                     bond_or_dot: None,
@@ -885,11 +1769,14 @@ mod tests {
                                                 element: Element::Bromine
                                             }),
                                             ring_bonds: vec![],
-                                            branches: vec![]
+                                            branches: vec![],
+                                            span: Span::default()
                                         }
                                     },
+                                    span: Span::default(),
                                 },
-                            ]
+                            ],
+                            span: Span::default()
                         }
                     })),
                     bond_or_dot: None,
@@ -898,7 +1785,8 @@ mod tests {
                             element: Element::Fluorine
                         }),
                         ring_bonds: vec![],
-                        branches: vec![]
+                        branches: vec![],
+                        span: Span::default()
                     }
                 }
             )),
@@ -924,7 +1812,8 @@ mod tests {
                                 charge: -1,
                             }),
                             ring_bonds: vec![],
-                            branches: vec![]
+                            branches: vec![],
+                            span: Span::default()
                         }
                     })),
                     bond_or_dot: Some(BondOrDot::Dot(Dot)),
@@ -937,7 +1826,8 @@ mod tests {
                             charge: 1,
                         }),
                         ring_bonds: vec![],
-                        branches: vec![]
+                        branches: vec![],
+                        span: Span::default()
                     }
                 }
             )),
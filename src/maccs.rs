@@ -0,0 +1,332 @@
+//! MACCS structural keys: a 166 bit fingerprint of predefined substructures.
+//!
+//! The keys are defined by the SMARTS patterns of the RDKit implementation, and the bits use the
+//! same layout: bit `i` is key `i`, bit 0 is never set. Like in RDKit, keys 1 and 44 are never
+//! set. A Kekulé SMILES of an aromatic compound sets different keys than its aromatic form, see
+//! [aromaticity](crate::graph#aromaticity).
+
+use std::sync::OnceLock;
+
+use petgraph::algo::connected_components;
+
+use crate::descriptors::aromatic_ring_count;
+use crate::fingerprint::BitFingerprint;
+use crate::graph::MoleculeGraph;
+use crate::smarts::{Pattern, Target};
+
+/// Number of bits of a MACCS fingerprint: the 166 keys and the unused bit 0.
+pub const MACCS_LEN: usize = 167;
+
+/// Pattern of every key, and the number of matches that has to be exceeded to set it. Keys
+/// without a pattern are computed separately or never set.
+const KEYS: [Option<(&str, usize)>; MACCS_LEN] = [
+    None,
+    None,
+    Some(("[#104]", 0)),
+    Some(("[#32,#33,#34,#50,#51,#52,#82,#83,#84]", 0)),
+    Some(("[Ac,Th,Pa,U,Np,Pu,Am,Cm,Bk,Cf,Es,Fm,Md,No,Lr]", 0)),
+    Some(("[Sc,Ti,Y,Zr,Hf]", 0)),
+    Some(("[La,Ce,Pr,Nd,Pm,Sm,Eu,Gd,Tb,Dy,Ho,Er,Tm,Yb,Lu]", 0)),
+    Some(("[V,Cr,Mn,Nb,Mo,Tc,Ta,W,Re]", 0)),
+    Some(("[!#6;!#1]1~*~*~*~1", 0)),
+    Some(("[Fe,Co,Ni,Ru,Rh,Pd,Os,Ir,Pt]", 0)),
+    Some(("[Be,Mg,Ca,Sr,Ba,Ra]", 0)),
+    Some(("*1~*~*~*~1", 0)),
+    Some(("[Cu,Zn,Ag,Cd,Au,Hg]", 0)),
+    Some(("[#8]~[#7](~[#6])~[#6]", 0)),
+    Some(("[#16]-[#16]", 0)),
+    Some(("[#8]~[#6](~[#8])~[#8]", 0)),
+    Some(("[!#6;!#1]1~*~*~1", 0)),
+    Some(("[#6]#[#6]", 0)),
+    Some(("[#5,#13,#31,#49,#81]", 0)),
+    Some(("*1~*~*~*~*~*~*~1", 0)),
+    // 20
+    Some(("[#14]", 0)),
+    Some(("[#6]=[#6](~[!#6;!#1])~[!#6;!#1]", 0)),
+    Some(("*1~*~*~1", 0)),
+    Some(("[#7]~[#6](~[#8])~[#8]", 0)),
+    Some(("[#7]-[#8]", 0)),
+    Some(("[#7]~[#6](~[#7])~[#7]", 0)),
+    Some(("[#6]=;@[#6](@*)@*", 0)),
+    Some(("[I]", 0)),
+    Some(("[!#6;!#1]~[CH2]~[!#6;!#1]", 0)),
+    Some(("[#15]", 0)),
+    // 30
+    Some(("[#6]~[!#6;!#1](~[#6])(~[#6])~*", 0)),
+    Some(("[!#6;!#1]~[F,Cl,Br,I]", 0)),
+    Some(("[#6]~[#16]~[#7]", 0)),
+    Some(("[#7]~[#16]", 0)),
+    Some(("[CH2]=*", 0)),
+    Some(("[Li,Na,K,Rb,Cs,Fr]", 0)),
+    Some(("[#16R]", 0)),
+    Some(("[#7]~[#6](~[#8])~[#7]", 0)),
+    Some(("[#7]~[#6](~[#6])~[#7]", 0)),
+    Some(("[#8]~[#16](~[#8])~[#8]", 0)),
+    // 40
+    Some(("[#16]-[#8]", 0)),
+    Some(("[#6]#[#7]", 0)),
+    Some(("F", 0)),
+    Some(("[!#6;!#1;!H0]~*~[!#6;!#1;!H0]", 0)),
+    None,
+    Some(("[#6]=[#6]~[#7]", 0)),
+    Some(("Br", 0)),
+    Some(("[#16]~*~[#7]", 0)),
+    Some(("[#8]~[!#6;!#1](~[#8])(~[#8])", 0)),
+    Some(("[!+0]", 0)),
+    // 50
+    Some(("[#6]=[#6](~[#6])~[#6]", 0)),
+    Some(("[#6]~[#16]~[#8]", 0)),
+    Some(("[#7]~[#7]", 0)),
+    Some(("[!#6;!#1;!H0]~*~*~*~[!#6;!#1;!H0]", 0)),
+    Some(("[!#6;!#1;!H0]~*~*~[!#6;!#1;!H0]", 0)),
+    Some(("[#8]~[#16]~[#8]", 0)),
+    Some(("[#8]~[#7](~[#8])~[#6]", 0)),
+    Some(("[#8R]", 0)),
+    Some(("[!#6;!#1]~[#16]~[!#6;!#1]", 0)),
+    Some(("[#16]!:*:*", 0)),
+    // 60
+    Some(("[#16]=[#8]", 0)),
+    Some(("*~[#16](~*)~*", 0)),
+    Some(("*@*!@*@*", 0)),
+    Some(("[#7]=[#8]", 0)),
+    Some(("*@*!@[#16]", 0)),
+    Some(("c:n", 0)),
+    Some(("[#6]~[#6](~[#6])(~[#6])~*", 0)),
+    Some(("[!#6;!#1]~[#16]", 0)),
+    Some(("[!#6;!#1;!H0]~[!#6;!#1;!H0]", 0)),
+    Some(("[!#6;!#1]~[!#6;!#1;!H0]", 0)),
+    // 70
+    Some(("[!#6;!#1]~[#7]~[!#6;!#1]", 0)),
+    Some(("[#7]~[#8]", 0)),
+    Some(("[#8]~*~*~[#8]", 0)),
+    Some(("[#16]=*", 0)),
+    Some(("[CH3]~*~[CH3]", 0)),
+    Some(("*!@[#7]@*", 0)),
+    Some(("[#6]=[#6](~*)~*", 0)),
+    Some(("[#7]~*~[#7]", 0)),
+    Some(("[#6]=[#7]", 0)),
+    Some(("[#7]~*~*~[#7]", 0)),
+    // 80
+    Some(("[#7]~*~*~*~[#7]", 0)),
+    Some(("[#16]~*(~*)~*", 0)),
+    Some(("*~[CH2]~[!#6;!#1;!H0]", 0)),
+    Some(("[!#6;!#1]1~*~*~*~*~1", 0)),
+    Some(("[NH2]", 0)),
+    Some(("[#6]~[#7](~[#6])~[#6]", 0)),
+    Some(("[C;H2,H3][!#6;!#1][C;H2,H3]", 0)),
+    Some(("[F,Cl,Br,I]!@*@*", 0)),
+    Some(("[#16]", 0)),
+    Some(("[#8]~*~*~*~[#8]", 0)),
+    // 90
+    Some((
+        "[$([!#6;!#1;!H0]~*~*~[CH2]~*),$([!#6;!#1;!H0;R]1@[R]@[R]@[CH2;R]1),\
+         $([!#6;!#1;!H0]~[R]1@[R]@[CH2;R]1)]",
+        0,
+    )),
+    Some((
+        "[$([!#6;!#1;!H0]~*~*~*~[CH2]~*),$([!#6;!#1;!H0;R]1@[R]@[R]@[R]@[CH2;R]1),\
+         $([!#6;!#1;!H0]~[R]1@[R]@[R]@[CH2;R]1),$([!#6;!#1;!H0]~*~[R]1@[R]@[CH2;R]1)]",
+        0,
+    )),
+    Some(("[#8]~[#6](~[#7])~[#6]", 0)),
+    Some(("[!#6;!#1]~[CH3]", 0)),
+    Some(("[!#6;!#1]~[#7]", 0)),
+    Some(("[#7]~*~*~[#8]", 0)),
+    Some(("*1~*~*~*~*~1", 0)),
+    Some(("[#7]~*~*~*~[#8]", 0)),
+    Some(("[!#6;!#1]1~*~*~*~*~*~1", 0)),
+    Some(("[#6]=[#6]", 0)),
+    // 100
+    Some(("*~[CH2]~[#7]", 0)),
+    Some((
+        "[$([R]@1@[R]@[R]@[R]@[R]@[R]@[R]@[R]1),\
+         $([R]@1@[R]@[R]@[R]@[R]@[R]@[R]@[R]@[R]1),\
+         $([R]@1@[R]@[R]@[R]@[R]@[R]@[R]@[R]@[R]@[R]1),\
+         $([R]@1@[R]@[R]@[R]@[R]@[R]@[R]@[R]@[R]@[R]@[R]1),\
+         $([R]@1@[R]@[R]@[R]@[R]@[R]@[R]@[R]@[R]@[R]@[R]@[R]1),\
+         $([R]@1@[R]@[R]@[R]@[R]@[R]@[R]@[R]@[R]@[R]@[R]@[R]@[R]1),\
+         $([R]@1@[R]@[R]@[R]@[R]@[R]@[R]@[R]@[R]@[R]@[R]@[R]@[R]@[R]1)]",
+        0,
+    )),
+    Some(("[!#6;!#1]~[#8]", 0)),
+    Some(("Cl", 0)),
+    Some(("[!#6;!#1;!H0]~*~[CH2]~*", 0)),
+    Some(("*@*(@*)@*", 0)),
+    Some(("[!#6;!#1]~*(~[!#6;!#1])~[!#6;!#1]", 0)),
+    Some(("[F,Cl,Br,I]~*(~*)~*", 0)),
+    Some(("[CH3]~*~*~*~[CH2]~*", 0)),
+    Some(("*~[CH2]~[#8]", 0)),
+    // 110
+    Some(("[#7]~[#6]~[#8]", 0)),
+    Some(("[#7]~*~[CH2]~*", 0)),
+    Some(("*~*(~*)(~*)~*", 0)),
+    Some(("[#8]!:*:*", 0)),
+    Some(("[CH3]~[CH2]~*", 0)),
+    Some(("[CH3]~*~[CH2]~*", 0)),
+    Some(("[$([CH3]~*~*~[CH2]~*),$([CH3]~*1~*~[CH2]1)]", 0)),
+    Some(("[#7]~*~[#8]", 0)),
+    Some(("[$(*~[CH2]~[CH2]~*),$(*1~[CH2]~[CH2]1)]", 1)),
+    Some(("[#7]=*", 0)),
+    // 120
+    Some(("[!#6;R]", 1)),
+    Some(("[#7;R]", 0)),
+    Some(("*~[#7](~*)~*", 0)),
+    Some(("[#8]~[#6]~[#8]", 0)),
+    Some(("[!#6;!#1]~[!#6;!#1]", 0)),
+    // More than one aromatic ring
+    None,
+    Some(("*!@[#8]!@*", 0)),
+    Some(("*@*!@[#8]", 1)),
+    Some((
+        "[$(*~[CH2]~*~*~*~[CH2]~*),$([R]1@[CH2;R]@[R]@[R]@[R]@[CH2;R]1),\
+         $(*~[CH2]~[R]1@[R]@[R]@[CH2;R]1),$(*~[CH2]~*~[R]1@[R]@[CH2;R]1)]",
+        0,
+    )),
+    Some((
+        "[$(*~[CH2]~*~*~[CH2]~*),$([R]1@[CH2]@[R]@[R]@[CH2;R]1),\
+         $(*~[CH2]~[R]1@[R]@[CH2;R]1)]",
+        0,
+    )),
+    // 130
+    Some(("[!#6;!#1]~[!#6;!#1]", 1)),
+    Some(("[!#6;!#1;!H0]", 1)),
+    Some(("[#8]~*~[CH2]~*", 0)),
+    Some(("*@*!@[#7]", 0)),
+    Some(("[F,Cl,Br,I]", 0)),
+    Some(("[#7]!:*:*", 0)),
+    Some(("[#8]=*", 1)),
+    Some(("[!C;!c;R]", 0)),
+    Some(("[!#6;!#1]~[CH2]~*", 1)),
+    Some(("[O;!H0]", 0)),
+    // 140
+    Some(("[#8]", 3)),
+    Some(("[CH3]", 2)),
+    Some(("[#7]", 1)),
+    Some(("*@*!@[#8]", 0)),
+    Some(("*!:*:*!:*", 0)),
+    Some(("*1~*~*~*~*~*~1", 1)),
+    Some(("[#8]", 2)),
+    Some(("[$(*~[CH2]~[CH2]~*),$([R]1@[CH2;R]@[CH2;R]1)]", 0)),
+    Some(("*~[!#6;!#1](~*)~*", 0)),
+    Some(("[C;H3,H4]", 1)),
+    // 150
+    Some(("*!@*@*!@*", 0)),
+    Some(("[#7;!H0]", 0)),
+    Some(("[#8]~[#6](~[#6])~[#6]", 0)),
+    Some(("[!#6;!#1]~[CH2]~*", 0)),
+    Some(("[#6]=[#8]", 0)),
+    Some(("*!@[CH2]!@*", 0)),
+    Some(("[#7]~*(~*)~*", 0)),
+    Some(("[#6]-[#8]", 0)),
+    Some(("[#6]-[#7]", 0)),
+    Some(("[#8]", 1)),
+    // 160
+    Some(("[C;H3,H4]", 0)),
+    Some(("[#7]", 0)),
+    Some(("a", 0)),
+    Some(("*1~*~*~*~*~*~1", 0)),
+    Some(("[#8]", 0)),
+    Some(("[R]", 0)),
+    // More than one fragment
+    None,
+];
+
+fn patterns() -> &'static [Option<(Pattern, usize)>] {
+    static PATTERNS: OnceLock<Vec<Option<(Pattern, usize)>>> = OnceLock::new();
+    PATTERNS.get_or_init(|| {
+        KEYS.iter()
+            .map(|key| key.map(|(smarts, count)| (Pattern::parse(smarts).unwrap(), count)))
+            .collect()
+    })
+}
+
+/// The MACCS keys of a molecule, as a fingerprint of [`MACCS_LEN`] bits.
+pub fn maccs_keys(graph: &MoleculeGraph) -> BitFingerprint {
+    let target = Target::new(graph);
+    let mut keys = BitFingerprint::new(MACCS_LEN);
+    for (key, pattern) in patterns().iter().enumerate() {
+        if let Some((pattern, count)) = pattern {
+            if pattern.count_matches(&target, count + 1) > *count {
+                keys.set(key);
+            }
+        }
+    }
+    if aromatic_ring_count(graph) > 1 {
+        keys.set(125);
+    }
+    if connected_components(&**graph) > 1 {
+        keys.set(166);
+    }
+    keys
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse;
+
+    fn keys(smiles: &str) -> Vec<usize> {
+        maccs_keys(&MoleculeGraph::from_chain(
+            parse(smiles.as_bytes()).unwrap(),
+        ))
+        .ones()
+        .collect()
+    }
+
+    #[test]
+    fn patterns_parse() {
+        assert_eq!(162, patterns().iter().flatten().count());
+    }
+
+    #[test]
+    fn simple_keys() {
+        assert_eq!(Vec::<usize>::new(), keys("[H][H]"));
+        // Methane: a CH4 and a carbon
+        assert_eq!(vec![160], keys("C"));
+        assert_eq!(vec![149, 160, 166], keys("C.C"));
+        // Two aromatic rings
+        assert!(keys("c1ccc2ccccc2c1").contains(&125));
+        assert!(!keys("c1ccccc1").contains(&125));
+    }
+
+    #[test]
+    fn drugs() {
+        let vectors: &[(&str, &[usize])] = &[
+            // Aspirin
+            (
+                "CC(=O)Oc1ccccc1C(=O)O",
+                &[
+                    89, 113, 123, 126, 127, 136, 139, 140, 143, 144, 146, 150, 152, 154, 157, 159,
+                    160, 162, 163, 164, 165,
+                ],
+            ),
+            // Paracetamol
+            (
+                "CC(=O)Nc1ccc(O)cc1",
+                &[
+                    92, 110, 113, 117, 127, 131, 133, 135, 139, 143, 151, 152, 154, 156, 157, 158,
+                    159, 160, 161, 162, 163, 164, 165,
+                ],
+            ),
+            // Ibuprofen
+            (
+                "CC(C)Cc1ccc(cc1)C(C)C(=O)O",
+                &[
+                    74, 115, 123, 139, 141, 149, 154, 155, 157, 159, 160, 162, 163, 164, 165,
+                ],
+            ),
+            // Caffeine, with two aromatic rings (key 125)
+            (
+                "Cn1cnc2c1c(=O)n(C)c(=O)n2C",
+                &[
+                    37, 38, 65, 75, 77, 79, 80, 83, 85, 89, 92, 93, 95, 96, 97, 98, 101, 105, 106,
+                    110, 113, 117, 120, 121, 122, 125, 127, 136, 137, 141, 142, 143, 144, 148, 149,
+                    150, 154, 156, 158, 159, 160, 161, 162, 163, 164, 165,
+                ],
+            ),
+        ];
+        for (smiles, expected) in vectors {
+            assert_eq!(expected.to_vec(), keys(smiles), "{}", smiles);
+        }
+    }
+}
@@ -0,0 +1,126 @@
+//! Unconstrained minimization with the limited-memory BFGS method, used to clean up coordinates.
+
+use std::collections::VecDeque;
+
+/// Number of previous steps that approximate the inverse Hessian.
+const MEMORY: usize = 8;
+
+fn dot(a: &[f64], b: &[f64]) -> f64 {
+    a.iter().zip(b).map(|(a, b)| a * b).sum()
+}
+
+/// Minimize a function, starting from and updating `x`, and return the value at the minimum.
+///
+/// The function returns its value at a point and writes the gradient into its second argument.
+/// Minimization stops after `max_iterations` steps, when no coordinate of the gradient is larger
+/// than `tolerance`, or when the line search can't decrease the value any further. No coordinate
+/// moves by more than `max_step` in a single step.
+pub(crate) fn minimize<F>(
+    x: &mut [f64],
+    max_iterations: usize,
+    tolerance: f64,
+    max_step: f64,
+    mut function: F,
+) -> f64
+where
+    F: FnMut(&[f64], &mut [f64]) -> f64,
+{
+    let count = x.len();
+    let mut gradient = vec![0.0; count];
+    let mut value = function(x, &mut gradient);
+    // Differences of positions and gradients of the previous steps, with their inverse product.
+    let mut history: VecDeque<(Vec<f64>, Vec<f64>, f64)> = VecDeque::with_capacity(MEMORY);
+    let mut trial = vec![0.0; count];
+    let mut trial_gradient = vec![0.0; count];
+
+    for _ in 0..max_iterations {
+        if gradient.iter().all(|g| g.abs() <= tolerance) {
+            break;
+        }
+
+        // Two-loop recursion for the product of the inverse Hessian and the gradient.
+        let mut direction: Vec<f64> = gradient.iter().map(|g| -g).collect();
+        let mut alphas = Vec::with_capacity(history.len());
+        for (s, y, rho) in history.iter().rev() {
+            let alpha = rho * dot(s, &direction);
+            for (d, y) in direction.iter_mut().zip(y) {
+                *d -= alpha * y;
+            }
+            alphas.push(alpha);
+        }
+        if let Some((s, y, _)) = history.back() {
+            let gamma = dot(s, y) / dot(y, y);
+            direction.iter_mut().for_each(|d| *d *= gamma);
+        }
+        for ((s, y, rho), alpha) in history.iter().zip(alphas.into_iter().rev()) {
+            let beta = rho * dot(y, &direction);
+            for (d, s) in direction.iter_mut().zip(s) {
+                *d += (alpha - beta) * s;
+            }
+        }
+        let mut slope = dot(&gradient, &direction);
+        if slope >= 0.0 {
+            // Not a descent direction, start over with steepest descent.
+            history.clear();
+            direction = gradient.iter().map(|g| -g).collect();
+            slope = dot(&gradient, &direction);
+        }
+        let largest = direction.iter().fold(0.0f64, |max, d| max.max(d.abs()));
+        if largest > max_step {
+            direction.iter_mut().for_each(|d| *d *= max_step / largest);
+            slope *= max_step / largest;
+        }
+
+        // Backtracking line search until the value decreases sufficiently.
+        let mut step = 1.0;
+        let trial_value = loop {
+            for ((trial, x), d) in trial.iter_mut().zip(x.iter()).zip(&direction) {
+                *trial = x + step * d;
+            }
+            let trial_value = function(&trial, &mut trial_gradient);
+            if trial_value <= value + 1e-4 * step * slope {
+                break trial_value;
+            }
+            step *= 0.5;
+            if step < 1e-10 {
+                return value;
+            }
+        };
+
+        let s: Vec<f64> = trial.iter().zip(x.iter()).map(|(t, x)| t - x).collect();
+        let y: Vec<f64> = trial_gradient
+            .iter()
+            .zip(&gradient)
+            .map(|(t, g)| t - g)
+            .collect();
+        let sy = dot(&s, &y);
+        if sy > 1e-12 {
+            if history.len() == MEMORY {
+                history.pop_front();
+            }
+            history.push_back((s, y, 1.0 / sy));
+        }
+        x.copy_from_slice(&trial);
+        gradient.copy_from_slice(&trial_gradient);
+        value = trial_value;
+    }
+    value
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rosenbrock() {
+        let mut x = [-1.2, 1.0];
+        let value = minimize(&mut x, 1000, 1e-8, 0.5, |x, gradient| {
+            let (a, b) = (1.0 - x[0], x[1] - x[0] * x[0]);
+            gradient[0] = -2.0 * a - 400.0 * x[0] * b;
+            gradient[1] = 200.0 * b;
+            a * a + 100.0 * b * b
+        });
+        assert!(value < 1e-10);
+        assert!((x[0] - 1.0).abs() < 1e-4 && (x[1] - 1.0).abs() < 1e-4);
+    }
+}
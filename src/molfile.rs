@@ -0,0 +1,977 @@
+//! Conversion between [`MoleculeGraph`] and MDL Molfile (V2000 and V3000) connection tables.
+//!
+//! Every atom of the graph is written to the connection table, including the explicit hydrogen
+//! atoms that [`MoleculeGraph::from_chain`] adds. When reading, hydrogens that are implicit in
+//! the Molfile are added as explicit hydrogen atoms again, after all atoms of the file.
+//!
+//! Tetrahedral stereo centers are written and read as atom parities, which are defined relative
+//! to the order of the atoms in the file, just like the normalized chirality of a
+//! [`MoleculeGraph`]. When all atoms have 2D coordinates, one bond of every stereo center is
+//! also written as a wedge or hash that draws its configuration. Double bonds that could have a
+//! configuration but have none are written as "either" double bonds. Bond stereo flags are
+//! ignored when reading.
+
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::fmt::Write as _;
+
+use petgraph::graph::{EdgeIndex, NodeIndex};
+use petgraph::visit::EdgeRef;
+use ptable::Element;
+
+use crate::graph::{
+    bond_order, implicit_hydrogen_count, is_aromatic_organic, normal_valences, Atom, MoleculeGraph,
+};
+use crate::stereoisomers::{stereo_elements, StereoElement};
+use crate::{AliphaticOrganicAtom, AromaticOrganicAtom, Bond, BracketAtom, Chirality, Symbol};
+
+/// Largest number of atoms or bonds a V2000 connection table can hold.
+const V2000_MAX_COUNT: usize = 999;
+
+/// V2000 bond stereo flags.
+const WEDGE: u8 = 1;
+const EITHER: u8 = 3;
+const HASH: u8 = 6;
+
+#[derive(Debug, PartialEq, Eq, Copy, Clone, Hash)]
+pub enum MolfileError {
+    /// The input ended before the connection table was complete.
+    UnexpectedEnd,
+    /// The line with the given (1-based) number could not be parsed.
+    InvalidLine(usize),
+    /// The atom on the line with the given (1-based) number has an unknown symbol.
+    UnknownElement(usize),
+    /// The molecule has more atoms or bonds than a V2000 connection table can hold.
+    TooLarge,
+    /// A bond can't be expressed as a Molfile bond type, or the bond type is a query type.
+    UnsupportedBond,
+}
+
+impl std::fmt::Display for MolfileError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MolfileError::UnexpectedEnd => write!(f, "unexpected end of Molfile"),
+            MolfileError::InvalidLine(line) => write!(f, "invalid Molfile line {}", line),
+            MolfileError::UnknownElement(line) => write!(f, "unknown element on line {}", line),
+            MolfileError::TooLarge => write!(f, "molecule is too large for a V2000 Molfile"),
+            MolfileError::UnsupportedBond => write!(f, "unsupported bond type"),
+        }
+    }
+}
+
+impl std::error::Error for MolfileError {}
+
+/// A molecule read from a Molfile, together with the title from its header block.
+#[derive(Clone)]
+pub struct Molfile {
+    pub title: String,
+    pub graph: MoleculeGraph,
+}
+
+struct AtomRecord {
+    element: Option<Element>,
+    charge: i8,
+    isotope: Option<u16>,
+    parity: u8,
+}
+
+struct BondRecord {
+    first: usize,
+    second: usize,
+    bond: Bond,
+}
+
+type ParsedConnectionTable = (Vec<AtomRecord>, Vec<BondRecord>);
+
+fn atom_record(atom: &Atom) -> AtomRecord {
+    AtomRecord {
+        element: atom.element(),
+        charge: atom.charge(),
+        isotope: atom.isotope(),
+        parity: match atom.chirality() {
+            Some(Chirality::Clockwise) => 1,
+            Some(Chirality::Anticlockwise) => 2,
+            _ => 0,
+        },
+    }
+}
+
+fn atom_symbol(element: Option<Element>) -> &'static str {
+    element.map_or("A", |element| element.get_symbol())
+}
+
+fn bond_type(bond: Bond) -> Result<u8, MolfileError> {
+    match bond {
+        Bond::Single | Bond::Up | Bond::Down => Ok(1),
+        Bond::Double => Ok(2),
+        Bond::Triple => Ok(3),
+        Bond::Aromatic => Ok(4),
+        Bond::Quadruple => Err(MolfileError::UnsupportedBond),
+    }
+}
+
+fn header(title: &str) -> String {
+    let title: String = title
+        .chars()
+        .filter(|c| *c != '\n' && *c != '\r')
+        .take(80)
+        .collect();
+    format!("{}\n  smiles\n\n", title)
+}
+
+/// Atoms, their 2D coordinates and bonds of a connection table to be written. Bonds are given as
+/// 1-based atom indices, Molfile bond type and V2000 bond stereo flag.
+type ConnectionTable = (Vec<AtomRecord>, Vec<[f64; 2]>, Vec<(usize, usize, u8, u8)>);
+
+fn connection_table(graph: &MoleculeGraph) -> Result<ConnectionTable, MolfileError> {
+    let atoms: Vec<AtomRecord> = graph
+        .node_indices()
+        .map(|node| atom_record(&graph[node]))
+        .collect();
+    // Atoms without coordinates are written at the origin.
+    let coordinates: Vec<[f64; 2]> = graph
+        .node_indices()
+        .map(|node| graph.coordinates_2d(node).unwrap_or_default())
+        .collect();
+    let mut bonds: Vec<(usize, usize, u8, u8)> = graph
+        .edge_indices()
+        .map(|edge| {
+            let (first, second) = graph.edge_endpoints(edge).unwrap();
+            Ok((
+                first.index() + 1,
+                second.index() + 1,
+                bond_type(graph[edge])?,
+                0,
+            ))
+        })
+        .collect::<Result<_, _>>()?;
+
+    for element in stereo_elements(graph) {
+        if let StereoElement::DoubleBond(a, b) = element {
+            bonds[graph.find_edge(a, b).unwrap().index()].3 = EITHER;
+        }
+    }
+    // Wedges drawn over atoms at the origin would mean nothing.
+    if graph
+        .node_indices()
+        .all(|node| graph.coordinates_2d(node).is_some())
+    {
+        let ring_bonds = graph.ring_bonds();
+        for center in graph.node_indices() {
+            let picked = wedge(graph, &atoms, &coordinates, &bonds, &ring_bonds, center);
+            if let Some((edge, flag)) = picked {
+                // The wedge starts at the stereo center.
+                let other = graph.other_end(edge, center);
+                bonds[edge.index()] = (
+                    center.index() + 1,
+                    other.index() + 1,
+                    bonds[edge.index()].2,
+                    flag,
+                );
+            }
+        }
+    }
+    Ok((atoms, coordinates, bonds))
+}
+
+/// A bond from a stereo center to mark as wedge or hash, so that the drawing has the
+/// configuration of the atom parity. Single bonds out of rings to atoms that are no stereo
+/// centers themselves, preferably hydrogens, are picked first.
+fn wedge(
+    graph: &MoleculeGraph,
+    atoms: &[AtomRecord],
+    coordinates: &[[f64; 2]],
+    bonds: &[(usize, usize, u8, u8)],
+    ring_bonds: &[bool],
+    center: NodeIndex,
+) -> Option<(EdgeIndex, u8)> {
+    let parity = atoms[center.index()].parity;
+    if parity == 0 {
+        return None;
+    }
+    let mut neighbors: Vec<NodeIndex> = graph.neighbors(center).collect();
+    neighbors.sort_unstable();
+    let mut candidates: Vec<EdgeIndex> = graph
+        .edges(center)
+        .filter(|edge| *edge.weight() == Bond::Single && bonds[edge.id().index()].3 == 0)
+        .map(|edge| edge.id())
+        .collect();
+    candidates.sort_by_key(|&edge| {
+        let other = graph.other_end(edge, center);
+        (
+            atoms[other.index()].parity != 0,
+            ring_bonds[edge.index()],
+            !graph[other].is_hydrogen(),
+            edge,
+        )
+    });
+
+    let [x, y] = coordinates[center.index()];
+    candidates.into_iter().find_map(|edge| {
+        let raised = graph.other_end(edge, center);
+        let mut vectors: Vec<[f64; 3]> = neighbors
+            .iter()
+            .map(|&neighbor| {
+                let [nx, ny] = coordinates[neighbor.index()];
+                [nx - x, ny - y, if neighbor == raised { 1.0 } else { 0.0 }]
+            })
+            .collect();
+        // The implicit lone pair of a center with three neighbors is the last one.
+        if vectors.len() == 3 {
+            vectors.push([0.0; 3]);
+        }
+        if vectors.len() != 4 {
+            return None;
+        }
+        // Looking from the side opposite to the last neighbor, the others are clockwise for
+        // parity 1, and their signed volume relative to the last one is negative.
+        let [a, b, c] = [0, 1, 2].map(|index| {
+            let (v, last) = (vectors[index], vectors[3]);
+            [v[0] - last[0], v[1] - last[1], v[2] - last[2]]
+        });
+        let volume = a[0] * (b[1] * c[2] - b[2] * c[1]) - a[1] * (b[0] * c[2] - b[2] * c[0])
+            + a[2] * (b[0] * c[1] - b[1] * c[0]);
+        if volume.abs() < 1e-6 {
+            // The other neighbors are in line, so the bond doesn't tell the configuration.
+            None
+        } else if (volume < 0.0) == (parity == 1) {
+            Some((edge, WEDGE))
+        } else {
+            Some((edge, HASH))
+        }
+    })
+}
+
+/// Write a graph as a V2000 Molfile.
+pub fn write_v2000(graph: &MoleculeGraph, title: &str) -> Result<String, MolfileError> {
+    let (atoms, coordinates, bonds) = connection_table(graph)?;
+    if atoms.len() > V2000_MAX_COUNT || bonds.len() > V2000_MAX_COUNT {
+        return Err(MolfileError::TooLarge);
+    }
+    let chiral = atoms.iter().any(|atom| atom.parity != 0) as u8;
+
+    let mut out = header(title);
+    writeln!(
+        out,
+        "{:>3}{:>3}  0  0{:>3}  0  0  0  0  0999 V2000",
+        atoms.len(),
+        bonds.len(),
+        chiral
+    )
+    .unwrap();
+    for (atom, [x, y]) in atoms.iter().zip(coordinates) {
+        let charge_code = match atom.charge {
+            3 => 1,
+            2 => 2,
+            1 => 3,
+            -1 => 5,
+            -2 => 6,
+            -3 => 7,
+            _ => 0,
+        };
+        writeln!(
+            out,
+            "{:>10.4}{:>10.4}{:>10.4} {:<3} 0{:>3}{:>3}  0  0  0  0  0  0  0  0  0",
+            x,
+            y,
+            0.0,
+            atom_symbol(atom.element),
+            charge_code,
+            atom.parity
+        )
+        .unwrap();
+    }
+    for (first, second, bond_type, stereo) in &bonds {
+        writeln!(
+            out,
+            "{:>3}{:>3}{:>3}{:>3}  0  0  0",
+            first, second, bond_type, stereo
+        )
+        .unwrap();
+    }
+
+    let charges: Vec<(usize, i16)> = atoms
+        .iter()
+        .enumerate()
+        .filter(|(_, atom)| atom.charge != 0)
+        .map(|(index, atom)| (index + 1, atom.charge as i16))
+        .collect();
+    let isotopes: Vec<(usize, i16)> = atoms
+        .iter()
+        .enumerate()
+        .filter_map(|(index, atom)| atom.isotope.map(|isotope| (index + 1, isotope as i16)))
+        .collect();
+    write_property(&mut out, "CHG", &charges);
+    write_property(&mut out, "ISO", &isotopes);
+    out.push_str("M  END\n");
+
+    Ok(out)
+}
+
+fn write_property(out: &mut String, name: &str, values: &[(usize, i16)]) {
+    for chunk in values.chunks(8) {
+        write!(out, "M  {}{:>3}", name, chunk.len()).unwrap();
+        for (index, value) in chunk {
+            write!(out, " {:>3} {:>3}", index, value).unwrap();
+        }
+        out.push('\n');
+    }
+}
+
+/// Write a graph as a V3000 Molfile.
+pub fn write_v3000(graph: &MoleculeGraph, title: &str) -> Result<String, MolfileError> {
+    let (atoms, coordinates, bonds) = connection_table(graph)?;
+    let chiral = atoms.iter().any(|atom| atom.parity != 0) as u8;
+
+    let mut out = header(title);
+    out.push_str("  0  0  0     0  0            999 V3000\n");
+    out.push_str("M  V30 BEGIN CTAB\n");
+    writeln!(
+        out,
+        "M  V30 COUNTS {} {} 0 0 {}",
+        atoms.len(),
+        bonds.len(),
+        chiral
+    )
+    .unwrap();
+
+    out.push_str("M  V30 BEGIN ATOM\n");
+    for (index, (atom, [x, y])) in atoms.iter().zip(coordinates).enumerate() {
+        write!(
+            out,
+            "M  V30 {} {} {:.4} {:.4} {:.4} 0",
+            index + 1,
+            atom_symbol(atom.element),
+            x,
+            y,
+            0.0
+        )
+        .unwrap();
+        if atom.charge != 0 {
+            write!(out, " CHG={}", atom.charge).unwrap();
+        }
+        if let Some(isotope) = atom.isotope {
+            write!(out, " MASS={}", isotope).unwrap();
+        }
+        if atom.parity != 0 {
+            write!(out, " CFG={}", atom.parity).unwrap();
+        }
+        out.push('\n');
+    }
+    out.push_str("M  V30 END ATOM\n");
+
+    if !bonds.is_empty() {
+        out.push_str("M  V30 BEGIN BOND\n");
+        for (index, (first, second, bond_type, stereo)) in bonds.iter().enumerate() {
+            write!(
+                out,
+                "M  V30 {} {} {} {}",
+                index + 1,
+                bond_type,
+                first,
+                second
+            )
+            .unwrap();
+            match *stereo {
+                WEDGE => out.push_str(" CFG=1"),
+                EITHER => out.push_str(" CFG=2"),
+                HASH => out.push_str(" CFG=3"),
+                _ => {}
+            }
+            out.push('\n');
+        }
+        out.push_str("M  V30 END BOND\n");
+    }
+
+    out.push_str("M  V30 END CTAB\n");
+    out.push_str("M  END\n");
+
+    Ok(out)
+}
+
+/// Read a V2000 or V3000 Molfile.
+///
+/// Reading stops at the `M  END` line, so anything following it (like the data items of an SD
+/// file record) is ignored.
+pub fn read_molfile(input: &str) -> Result<Molfile, MolfileError> {
+    let lines: Vec<&str> = input.lines().collect();
+    if lines.len() < 4 {
+        return Err(MolfileError::UnexpectedEnd);
+    }
+
+    let title = lines[0].trim_end().to_owned();
+    let (atoms, bonds) = if lines[3].contains("V3000") {
+        read_v3000_ctab(&lines)?
+    } else {
+        read_v2000_ctab(&lines)?
+    };
+
+    Ok(Molfile {
+        title,
+        graph: build_graph(atoms, bonds),
+    })
+}
+
+/// Fixed-width field of a V2000 line, or `None` if the line is too short.
+fn field(line: &str, start: usize, end: usize) -> Option<&str> {
+    let end = end.min(line.len());
+    if start >= end {
+        return None;
+    }
+    line.get(start..end)
+        .map(str::trim)
+        .filter(|value| !value.is_empty())
+}
+
+fn parse_field<T: std::str::FromStr + Default>(
+    line: &str,
+    start: usize,
+    end: usize,
+    line_number: usize,
+) -> Result<T, MolfileError> {
+    match field(line, start, end) {
+        Some(value) => value
+            .parse()
+            .map_err(|_| MolfileError::InvalidLine(line_number)),
+        None => Ok(T::default()),
+    }
+}
+
+fn parse_element(symbol: &str, line_number: usize) -> Result<Option<Element>, MolfileError> {
+    match symbol {
+        "A" | "*" | "Q" | "R" | "R#" | "L" => Ok(None),
+        symbol => Element::from_symbol(symbol)
+            .map(Some)
+            .ok_or(MolfileError::UnknownElement(line_number)),
+    }
+}
+
+fn parse_bond_type(bond_type: u8) -> Result<Bond, MolfileError> {
+    match bond_type {
+        1 => Ok(Bond::Single),
+        2 => Ok(Bond::Double),
+        3 => Ok(Bond::Triple),
+        4 => Ok(Bond::Aromatic),
+        _ => Err(MolfileError::UnsupportedBond),
+    }
+}
+
+fn read_v2000_ctab(lines: &[&str]) -> Result<ParsedConnectionTable, MolfileError> {
+    let line = |index: usize| lines.get(index).copied().ok_or(MolfileError::UnexpectedEnd);
+
+    let atom_count: usize = parse_field(lines[3], 0, 3, 4)?;
+    let bond_count: usize = parse_field(lines[3], 3, 6, 4)?;
+
+    let mut atoms = Vec::with_capacity(atom_count);
+    for index in 4..4 + atom_count {
+        let atom_line = line(index)?;
+        let line_number = index + 1;
+        let symbol = field(atom_line, 31, 34).ok_or(MolfileError::InvalidLine(line_number))?;
+        let element = parse_element(symbol, line_number)?;
+        let mass_difference: i16 = parse_field(atom_line, 34, 36, line_number)?;
+        let charge_code: u8 = parse_field(atom_line, 36, 39, line_number)?;
+        let parity: u8 = parse_field(atom_line, 39, 42, line_number)?;
+
+        let isotope = match (element, mass_difference) {
+            (Some(element), difference) if difference != 0 => Some(
+                u16::try_from(element.get_atomic_mass().round() as i32 + i32::from(difference))
+                    .map_err(|_| MolfileError::InvalidLine(line_number))?,
+            ),
+            _ => None,
+        };
+        let charge = match charge_code {
+            1 => 3,
+            2 => 2,
+            3 => 1,
+            5 => -1,
+            6 => -2,
+            7 => -3,
+            _ => 0,
+        };
+        atoms.push(AtomRecord {
+            element,
+            charge,
+            isotope,
+            parity: if parity <= 2 { parity } else { 0 },
+        });
+    }
+
+    let mut bonds = Vec::with_capacity(bond_count);
+    let bonds_start = 4 + atom_count;
+    for index in bonds_start..bonds_start + bond_count {
+        let bond_line = line(index)?;
+        let line_number = index + 1;
+        let first: usize = parse_field(bond_line, 0, 3, line_number)?;
+        let second: usize = parse_field(bond_line, 3, 6, line_number)?;
+        let bond_type: u8 = parse_field(bond_line, 6, 9, line_number)?;
+        bonds.push(bond_record(
+            first,
+            second,
+            bond_type,
+            atom_count,
+            line_number,
+        )?);
+    }
+
+    let mut charges_reset = false;
+    for index in bonds_start + bond_count.. {
+        let property_line = line(index)?;
+        let line_number = index + 1;
+        if property_line.starts_with("M  END") {
+            break;
+        }
+
+        let property = property_line.get(..6);
+        if property != Some("M  CHG") && property != Some("M  ISO") {
+            continue;
+        }
+        let values: Vec<i32> = property_line[6..]
+            .split_whitespace()
+            .map(|value| value.parse())
+            .collect::<Result<_, _>>()
+            .map_err(|_| MolfileError::InvalidLine(line_number))?;
+        let invalid = |_| MolfileError::InvalidLine(line_number);
+        let count = values
+            .first()
+            .ok_or(MolfileError::InvalidLine(line_number))
+            .and_then(|&count| usize::try_from(count).map_err(invalid))?;
+        if (values.len() - 1) / 2 < count {
+            return Err(MolfileError::InvalidLine(line_number));
+        }
+
+        if property == Some("M  CHG") && !charges_reset {
+            // Charges in the atom block are superseded by the property block
+            for atom in &mut atoms {
+                atom.charge = 0;
+            }
+            charges_reset = true;
+        }
+        for pair in values[1..1 + 2 * count].chunks(2) {
+            let atom = usize::try_from(pair[0])
+                .ok()
+                .and_then(|number| number.checked_sub(1))
+                .and_then(|index| atoms.get_mut(index))
+                .ok_or(MolfileError::InvalidLine(line_number))?;
+            if property == Some("M  CHG") {
+                atom.charge = i8::try_from(pair[1]).map_err(invalid)?;
+            } else {
+                atom.isotope = Some(u16::try_from(pair[1]).map_err(invalid)?);
+            }
+        }
+    }
+
+    Ok((atoms, bonds))
+}
+
+fn bond_record(
+    first: usize,
+    second: usize,
+    bond_type: u8,
+    atom_count: usize,
+    line_number: usize,
+) -> Result<BondRecord, MolfileError> {
+    if first == 0 || second == 0 || first > atom_count || second > atom_count {
+        return Err(MolfileError::InvalidLine(line_number));
+    }
+    Ok(BondRecord {
+        first: first - 1,
+        second: second - 1,
+        bond: parse_bond_type(bond_type)?,
+    })
+}
+
+fn read_v3000_ctab(lines: &[&str]) -> Result<ParsedConnectionTable, MolfileError> {
+    // Join continuation lines (ending in `-`) and strip the `M  V30 ` prefix
+    let mut v30_lines: Vec<(usize, String)> = Vec::new();
+    let mut continued = false;
+    for (index, line) in lines.iter().enumerate().skip(4) {
+        if line.starts_with("M  END") {
+            break;
+        }
+        let content = match line.strip_prefix("M  V30 ") {
+            Some(content) => content.trim_end(),
+            None => continue,
+        };
+        let (content, continues) = match content.strip_suffix('-') {
+            Some(content) => (content, true),
+            None => (content, false),
+        };
+        if continued {
+            v30_lines.last_mut().unwrap().1.push_str(content);
+        } else {
+            v30_lines.push((index + 1, content.to_owned()));
+        }
+        continued = continues;
+    }
+
+    let mut atoms = Vec::new();
+    let mut atom_indices = HashMap::new();
+    let mut bonds = Vec::new();
+    let mut block = "";
+    for (line_number, line) in &v30_lines {
+        let line_number = *line_number;
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        match tokens.as_slice() {
+            ["BEGIN", name, ..] => block = name,
+            ["END", ..] => block = "",
+            [index, symbol, _x, _y, _z, _map, properties @ ..] if block == "ATOM" => {
+                let element = parse_element(symbol, line_number)?;
+                let mut atom = AtomRecord {
+                    element,
+                    charge: 0,
+                    isotope: None,
+                    parity: 0,
+                };
+                for property in properties {
+                    let (key, value) = match property.split_once('=') {
+                        Some(pair) => pair,
+                        None => continue,
+                    };
+                    let invalid = |_| MolfileError::InvalidLine(line_number);
+                    match key {
+                        "CHG" => atom.charge = value.parse().map_err(invalid)?,
+                        "MASS" => atom.isotope = Some(value.parse().map_err(invalid)?),
+                        "CFG" => atom.parity = value.parse().map_err(invalid)?,
+                        _ => {}
+                    }
+                }
+                if atom.parity > 2 {
+                    atom.parity = 0;
+                }
+                atom_indices.insert(index.to_string(), atoms.len() + 1);
+                atoms.push(atom);
+            }
+            [_index, bond_type, first, second, ..] if block == "BOND" => {
+                let invalid = MolfileError::InvalidLine(line_number);
+                let atom_index = |index: &str| atom_indices.get(index).copied().unwrap_or(0);
+                let bond_type = bond_type.parse().map_err(|_| invalid)?;
+                bonds.push(bond_record(
+                    atom_index(first),
+                    atom_index(second),
+                    bond_type,
+                    atoms.len(),
+                    line_number,
+                )?);
+            }
+            _ if block == "ATOM" || block == "BOND" => {
+                return Err(MolfileError::InvalidLine(line_number))
+            }
+            _ => {}
+        }
+    }
+
+    Ok((atoms, bonds))
+}
+
+/// Implicit hydrogen count of an atom of the organic subset, taking its charge into account.
+///
+/// Aromatic atoms always use their lowest valence, as atoms like the nitrogen of pyrrole would
+/// otherwise be assigned a hydrogen even if they already have an explicit one.
+fn hydrogen_count(element: Element, charge: i8, bond_order_sum: u8, aromatic: bool) -> u8 {
+    if charge == 0 && !aromatic {
+        return implicit_hydrogen_count(element, bond_order_sum, aromatic);
+    }
+
+    let neutral_valence = normal_valences(element)[0] as i16;
+    let charge = charge as i16;
+    let valence = match element {
+        Element::Carbon => neutral_valence - charge.abs(),
+        Element::Boron => neutral_valence - charge,
+        _ => neutral_valence + charge,
+    };
+    (valence - bond_order_sum as i16 - aromatic as i16).max(0) as u8
+}
+
+fn build_graph(atoms: Vec<AtomRecord>, bonds: Vec<BondRecord>) -> MoleculeGraph {
+    let mut aromatic = vec![false; atoms.len()];
+    let mut bond_order_sums = vec![0u8; atoms.len()];
+    for bond in &bonds {
+        if bond.bond == Bond::Aromatic {
+            aromatic[bond.first] = true;
+            aromatic[bond.second] = true;
+        }
+        for atom in &[bond.first, bond.second] {
+            bond_order_sums[*atom] = bond_order_sums[*atom].saturating_add(bond_order(bond.bond));
+        }
+    }
+
+    let mut graph = MoleculeGraph::default();
+    for (index, record) in atoms.iter().enumerate() {
+        let element = match record.element {
+            Some(element) => element,
+            None => {
+                graph.add_node(Atom::Unknown);
+                continue;
+            }
+        };
+        let is_aromatic = aromatic[index];
+        let is_plain = record.charge == 0 && record.isotope.is_none() && record.parity == 0;
+
+        // Hydrogens are implicit in Molfiles, so atoms of the organic subset get the hydrogen
+        // count that their valence requires. Atoms are only kept in the organic subset if that
+        // yields the same hydrogen count as it would in SMILES.
+        let (hcount, organic) = if normal_valences(element).is_empty() {
+            (0, false)
+        } else {
+            let sum = bond_order_sums[index];
+            let hcount = hydrogen_count(element, record.charge, sum, is_aromatic);
+            let organic = is_plain
+                && (!is_aromatic || is_aromatic_organic(element))
+                && hcount == implicit_hydrogen_count(element, sum, is_aromatic);
+            (hcount, organic)
+        };
+
+        let atom = if is_plain && element == Element::Hydrogen {
+            Atom::Element(element)
+        } else if organic && !is_aromatic {
+            Atom::AliphaticOrganic(AliphaticOrganicAtom { element })
+        } else if organic {
+            Atom::AromaticOrganic(AromaticOrganicAtom { element })
+        } else {
+            Atom::Bracket(BracketAtom {
+                isotope: record.isotope,
+                symbol: if is_aromatic {
+                    Symbol::AromaticSymbol(element)
+                } else {
+                    Symbol::ElementSymbol(element)
+                },
+                chiral: match record.parity {
+                    1 => Some(Chirality::Clockwise),
+                    2 => Some(Chirality::Anticlockwise),
+                    _ => None,
+                },
+                hcount,
+                charge: record.charge,
+            })
+        };
+        graph.add_node(atom);
+    }
+
+    for bond in &bonds {
+        graph.add_edge(
+            NodeIndex::new(bond.first),
+            NodeIndex::new(bond.second),
+            bond.bond,
+        );
+    }
+    graph.add_hydrogens();
+
+    graph
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graph::molecule;
+
+    /// Element, charge, isotope, chirality and degree of every atom.
+    fn summary(graph: &MoleculeGraph) -> Vec<String> {
+        graph
+            .node_indices()
+            .map(|node| {
+                let atom = &graph[node];
+                format!(
+                    "{:?} {} {:?} {:?} {}",
+                    atom.element(),
+                    atom.charge(),
+                    atom.isotope(),
+                    atom.chirality(),
+                    graph.edges(node).count(),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn write_formaldehyde_v2000() {
+        let molfile = write_v2000(&molecule("C=O"), "formaldehyde").unwrap();
+        assert_eq!(
+            "formaldehyde
+  smiles
+
+  4  3  0  0  0  0  0  0  0  0999 V2000
+    0.0000    0.0000    0.0000 C   0  0  0  0  0  0  0  0  0  0  0  0
+    0.0000    0.0000    0.0000 O   0  0  0  0  0  0  0  0  0  0  0  0
+    0.0000    0.0000    0.0000 H   0  0  0  0  0  0  0  0  0  0  0  0
+    0.0000    0.0000    0.0000 H   0  0  0  0  0  0  0  0  0  0  0  0
+  1  2  2  0  0  0  0
+  1  3  1  0  0  0  0
+  1  4  1  0  0  0  0
+M  END
+",
+            molfile
+        );
+    }
+
+    #[test]
+    fn write_coordinates() {
+        let mut graph = molecule("C=O");
+        graph.compute_2d_coords();
+        let molfile = write_v2000(&graph, "").unwrap();
+        assert!(molfile.contains("\n    1.2990    0.3750    0.0000 C   0"));
+        assert!(molfile.contains("\n    0.0000   -0.3750    0.0000 O   0"));
+        let molfile = write_v3000(&graph, "").unwrap();
+        assert!(molfile.contains("M  V30 1 C 1.2990 0.3750 0.0000 0\n"));
+    }
+
+    #[test]
+    fn write_v3000_properties() {
+        let molfile = write_v3000(&molecule("[13CH3][N+](C)(C)[C@H](O)F"), "").unwrap();
+        assert!(molfile.contains("M  V30 COUNTS 18 17 0 0 1\n"));
+        assert!(molfile.contains("M  V30 1 C 0.0000 0.0000 0.0000 0 MASS=13\n"));
+        assert!(molfile.contains("M  V30 2 N 0.0000 0.0000 0.0000 0 CHG=1\n"));
+        assert!(molfile.contains("M  V30 5 C 0.0000 0.0000 0.0000 0 CFG=2\n"));
+        assert!(molfile.contains("M  V30 1 1 1 2\n"));
+    }
+
+    #[test]
+    fn write_bond_stereo() {
+        // The hydrogen of the stereo center is drawn below it, toward or away from the viewer.
+        let mut graph = molecule("[C@@H](F)(Cl)Br");
+        graph.compute_2d_coords();
+        assert!(write_v2000(&graph, "")
+            .unwrap()
+            .contains("\n  1  5  1  6  0  0  0\n"));
+        assert!(write_v3000(&graph, "")
+            .unwrap()
+            .contains("M  V30 4 1 1 5 CFG=3\n"));
+        let mut graph = molecule("[C@H](F)(Cl)Br");
+        graph.compute_2d_coords();
+        assert!(write_v2000(&graph, "")
+            .unwrap()
+            .contains("\n  1  5  1  1  0  0  0\n"));
+        assert!(write_v3000(&graph, "")
+            .unwrap()
+            .contains("M  V30 4 1 1 5 CFG=1\n"));
+
+        // Wedges are only written with coordinates.
+        let molfile = write_v2000(&molecule("[C@@H](F)(Cl)Br"), "").unwrap();
+        assert!(molfile.contains("\n  1  5  1  0  0  0  0\n"));
+
+        let molfile = write_v2000(&molecule("CC=CC"), "").unwrap();
+        assert!(molfile.contains("\n  2  3  2  3  0  0  0\n"));
+        assert!(write_v3000(&molecule("CC=CC"), "")
+            .unwrap()
+            .contains("M  V30 2 2 2 3 CFG=2\n"));
+        let molfile = write_v2000(&molecule("C/C=C/C"), "").unwrap();
+        assert!(molfile.contains("\n  2  3  2  0  0  0  0\n"));
+        let molfile = write_v2000(&molecule("CC=C(C)C"), "").unwrap();
+        assert!(molfile.contains("\n  2  3  2  0  0  0  0\n"));
+    }
+
+    #[test]
+    fn round_trip() {
+        for smiles in &[
+            "[13CH3][N+](C)(C)[C@H](O)F",
+            "c1ccc2c(c1)[nH]cc2",
+            "CC(=O)[O-].[Na+]",
+            "N[C@@H](C)C(=O)O",
+            "C#N",
+        ] {
+            let graph = molecule(smiles);
+            for molfile in &[
+                write_v2000(&graph, smiles).unwrap(),
+                write_v3000(&graph, smiles).unwrap(),
+            ] {
+                let read = read_molfile(molfile).unwrap();
+                assert_eq!(*smiles, read.title);
+                assert_eq!(summary(&graph), summary(&read.graph));
+            }
+        }
+    }
+
+    #[test]
+    fn read_implicit_hydrogens() {
+        // Acetate with charge in the property block, V2000 without hydrogens
+        let molfile = "acetate
+
+
+  4  3  0  0  0  0  0  0  0  0999 V2000
+    0.0000    0.0000    0.0000 C   0  0  0  0  0  0  0  0  0  0  0  0
+    0.0000    0.0000    0.0000 C   0  0  0  0  0  0  0  0  0  0  0  0
+    0.0000    0.0000    0.0000 O   0  0  0  0  0  0  0  0  0  0  0  0
+    0.0000    0.0000    0.0000 O   0  0  0  0  0  0  0  0  0  0  0  0
+  1  2  1  0
+  2  3  2  0
+  2  4  1  0
+M  CHG  1   4  -1
+M  END
+";
+        let graph = read_molfile(molfile).unwrap().graph;
+        assert_eq!(7, graph.node_count());
+        assert_eq!(-1, graph[NodeIndex::new(3)].charge());
+        assert_eq!(1, graph.edges(NodeIndex::new(3)).count());
+    }
+
+    #[test]
+    fn read_v3000_continuation() {
+        let molfile = "
+  smiles
+
+  0  0  0     0  0            999 V3000
+M  V30 BEGIN CTAB
+M  V30 COUNTS 2 1 0 0 0
+M  V30 BEGIN ATOM
+M  V30 1 N 0 0 0 0 -
+M  V30 CHG=1
+M  V30 2 C 0 0 0 0
+M  V30 END ATOM
+M  V30 BEGIN BOND
+M  V30 1 1 1 2
+M  V30 END BOND
+M  V30 END CTAB
+M  END
+";
+        let graph = read_molfile(molfile).unwrap().graph;
+        assert_eq!(1, graph[NodeIndex::new(0)].charge());
+        // NH3+ and CH3
+        assert_eq!(8, graph.node_count());
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(
+            Err(MolfileError::UnexpectedEnd),
+            read_molfile("").map(|_| ())
+        );
+        let truncated = "\n\n\n  2  1  0  0  0  0  0  0  0  0999 V2000\n    0.0000    0.0000    0.0000 C   0  0\n";
+        assert_eq!(
+            Err(MolfileError::UnexpectedEnd),
+            read_molfile(truncated).map(|_| ())
+        );
+        let unknown = "\n\n\n  1  0  0  0  0  0  0  0  0  0999 V2000\n    0.0000    0.0000    0.0000 Xx  0  0\nM  END\n";
+        assert_eq!(
+            Err(MolfileError::UnknownElement(5)),
+            read_molfile(unknown).map(|_| ())
+        );
+        assert_eq!(
+            Err(MolfileError::UnsupportedBond),
+            write_v2000(&molecule("[Re]$[Re]"), "")
+        );
+
+        let with_atom = |atom: &str, property: &str| {
+            format!(
+                "\n\n\n  1  0  0  0  0  0  0  0  0  0999 V2000\n    0.0000    0.0000    0.0000 {}\n{}M  END\n",
+                atom, property
+            )
+        };
+        for property in [
+            "M  ISO -1\n",
+            "M  ISO  1   1  -5\n",
+            "M  CHG  1   1 300\n",
+            "M  CHG  1  -1   1\n",
+            "M  CHG  2   1   1\n",
+        ] {
+            assert_eq!(
+                Err(MolfileError::InvalidLine(6)),
+                read_molfile(&with_atom("C   0  0", property)).map(|_| ()),
+                "{}",
+                property
+            );
+        }
+        assert_eq!(
+            Err(MolfileError::InvalidLine(5)),
+            read_molfile(&with_atom("H  -5  0", "")).map(|_| ())
+        );
+    }
+}
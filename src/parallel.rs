@@ -0,0 +1,165 @@
+use std::io::BufRead;
+
+use rayon::prelude::*;
+
+use crate::reader::{SmilesReader, SmilesRecord};
+use crate::{parse, Chain, ParseError};
+
+/// Parse many SMILES strings in parallel.
+///
+/// The results are returned in the same order as the inputs.
+pub fn parse_many(inputs: &[&str]) -> Vec<Result<Chain, ParseError>> {
+    inputs
+        .par_iter()
+        .map(|input| parse(input.as_bytes()))
+        .collect()
+}
+
+const DEFAULT_BATCH_SIZE: usize = 4096;
+
+/// Parallel version of [`SmilesReader`].
+///
+/// Lines are read sequentially in batches, and each batch is parsed on the rayon thread pool.
+/// Records are yielded in input order, so the output is identical to that of a [`SmilesReader`]
+/// with the same parser.
+pub struct ParallelSmilesReader<R> {
+    reader: SmilesReader<R>,
+    parser: fn(&[u8]) -> Result<Chain, ParseError>,
+    batch_size: usize,
+    batch: std::vec::IntoIter<SmilesRecord>,
+    pending_error: Option<std::io::Error>,
+}
+
+impl<R: BufRead> ParallelSmilesReader<R> {
+    pub fn new(inner: R) -> Self {
+        Self::with_parser(inner, parse)
+    }
+
+    /// A reader that parses every SMILES with `parser` instead of [`parse`], like
+    /// [`SmilesReader::with_parser`].
+    pub fn with_parser(inner: R, parser: fn(&[u8]) -> Result<Chain, ParseError>) -> Self {
+        ParallelSmilesReader {
+            reader: SmilesReader::new(inner),
+            parser,
+            batch_size: DEFAULT_BATCH_SIZE,
+            batch: Vec::new().into_iter(),
+            pending_error: None,
+        }
+    }
+
+    /// Set the number of lines that are read before they are parsed in parallel.
+    pub fn with_batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size.max(1);
+        self
+    }
+
+    fn fill_batch(&mut self) {
+        let mut lines = Vec::with_capacity(self.batch_size);
+        while lines.len() < self.batch_size {
+            match self.reader.next_line() {
+                Ok(Some((line_number, smiles, title))) => {
+                    lines.push((line_number, smiles.to_vec(), title))
+                }
+                Ok(None) => break,
+                Err(err) => {
+                    self.pending_error = Some(err);
+                    break;
+                }
+            }
+        }
+
+        let parser = self.parser;
+        let records: Vec<SmilesRecord> = lines
+            .into_par_iter()
+            .map(|(line_number, smiles, title)| (line_number, title, parser(&smiles)))
+            .collect();
+        self.batch = records.into_iter();
+    }
+}
+
+impl<R: BufRead> Iterator for ParallelSmilesReader<R> {
+    type Item = std::io::Result<SmilesRecord>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(record) = self.batch.next() {
+            return Some(Ok(record));
+        }
+        if let Some(err) = self.pending_error.take() {
+            return Some(Err(err));
+        }
+
+        self.fill_batch();
+        match self.batch.next() {
+            Some(record) => Some(Ok(record)),
+            None => self.pending_error.take().map(Err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_many_preserves_order() {
+        let results = parse_many(&["CC", "C(", "CCO"]);
+        assert_eq!(3, results.len());
+        assert_eq!(parse(b"CC"), results[0]);
+        assert!(results[1].is_err());
+        assert_eq!(parse(b"CCO"), results[2]);
+    }
+
+    #[test]
+    fn parallel_reader_matches_sequential_reader() {
+        let input = (0..100)
+            .map(|i| {
+                if i % 7 == 0 {
+                    format!("C{} broken{}\n", "(".repeat(i % 3 + 1), i)
+                } else {
+                    format!("{} chain{}\n", "C".repeat(i + 1), i)
+                }
+            })
+            .collect::<String>();
+
+        let sequential = SmilesReader::new(input.as_bytes())
+            .collect::<std::io::Result<Vec<_>>>()
+            .unwrap();
+        let parallel = ParallelSmilesReader::new(input.as_bytes())
+            .with_batch_size(8)
+            .collect::<std::io::Result<Vec<_>>>()
+            .unwrap();
+
+        assert_eq!(sequential, parallel);
+    }
+
+    #[test]
+    fn parallel_reader_with_parser() {
+        let input = "C1CC ring\nCC\n";
+        let strict = |smiles: &[u8]| {
+            crate::flat::parse_flat(smiles)?;
+            parse(smiles)
+        };
+        let sequential = SmilesReader::with_parser(input.as_bytes(), strict)
+            .collect::<std::io::Result<Vec<_>>>()
+            .unwrap();
+        let parallel = ParallelSmilesReader::with_parser(input.as_bytes(), strict)
+            .collect::<std::io::Result<Vec<_>>>()
+            .unwrap();
+
+        assert!(parallel[0].2.is_err());
+        assert_eq!(sequential, parallel);
+    }
+
+    #[test]
+    fn parallel_reader_stops_after_io_error() {
+        use std::io::{BufReader, Read};
+
+        let inner = BufReader::new(b"CC\nCCO\n".chain(crate::reader::BrokenReader));
+        let mut reader = ParallelSmilesReader::new(inner).with_batch_size(8);
+        assert!(matches!(reader.next(), Some(Ok((1, None, Ok(_))))));
+        assert!(matches!(reader.next(), Some(Ok((2, None, Ok(_))))));
+        assert!(matches!(reader.next(), Some(Err(_))));
+        assert!(reader.next().is_none());
+        assert!(reader.next().is_none());
+    }
+}
@@ -0,0 +1,209 @@
+use std::io::BufRead;
+
+use crate::{parse, Chain, ParseError};
+
+/// A single parsed line of a `.smi` file: 1-based line number, optional title and the parse result.
+pub type SmilesRecord = (usize, Option<String>, Result<Chain, ParseError>);
+
+/// A raw line of a `.smi` file, split into line number, SMILES and title.
+pub(crate) type Line<'a> = (usize, &'a [u8], Option<String>);
+
+/// Streaming reader for `.smi` files.
+///
+/// Each non-empty line contains a SMILES string, optionally followed by a space or tab and a
+/// title. Blank lines and lines starting with `#` are skipped. Lines are read one at a time, so
+/// arbitrarily large files can be processed with constant memory.
+///
+/// A line that fails to parse does not stop the reader; its error is reported in the record and
+/// reading continues with the next line. An I/O error is yielded once and ends the reader.
+pub struct SmilesReader<R> {
+    inner: R,
+    buffer: Vec<u8>,
+    line_number: usize,
+    parser: fn(&[u8]) -> Result<Chain, ParseError>,
+    failed: bool,
+}
+
+impl<R: BufRead> SmilesReader<R> {
+    pub fn new(inner: R) -> Self {
+        Self::with_parser(inner, parse)
+    }
+
+    /// A reader that parses every SMILES with `parser` instead of [`parse`], e.g. to be stricter.
+    pub fn with_parser(inner: R, parser: fn(&[u8]) -> Result<Chain, ParseError>) -> Self {
+        SmilesReader {
+            inner,
+            buffer: Vec::new(),
+            line_number: 0,
+            parser,
+            failed: false,
+        }
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    /// Read the next non-skipped line, returning its line number, SMILES and title.
+    ///
+    /// Returns `Ok(None)` at the end of the input and after an I/O error.
+    pub(crate) fn next_line(&mut self) -> std::io::Result<Option<Line<'_>>> {
+        if self.failed {
+            return Ok(None);
+        }
+        loop {
+            self.buffer.clear();
+            match self.inner.read_until(b'\n', &mut self.buffer) {
+                Ok(0) => return Ok(None),
+                Ok(_) => {}
+                Err(err) => {
+                    self.failed = true;
+                    return Err(err);
+                }
+            }
+            self.line_number += 1;
+
+            let line = trim(&self.buffer);
+            if !line.is_empty() && line[0] != b'#' {
+                break;
+            }
+        }
+
+        let (smiles, title) = split_title(trim(&self.buffer));
+        Ok(Some((self.line_number, smiles, title)))
+    }
+}
+
+impl<R: BufRead> Iterator for SmilesReader<R> {
+    type Item = std::io::Result<SmilesRecord>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let parser = self.parser;
+        match self.next_line() {
+            Ok(Some((line_number, smiles, title))) => {
+                let chain = parser(smiles);
+                Some(Ok((line_number, title, chain)))
+            }
+            Ok(None) => None,
+            Err(err) => Some(Err(err)),
+        }
+    }
+}
+
+fn is_whitespace(byte: u8) -> bool {
+    byte == b' ' || byte == b'\t' || byte == b'\r' || byte == b'\n'
+}
+
+fn trim(line: &[u8]) -> &[u8] {
+    let start = line
+        .iter()
+        .position(|&b| !is_whitespace(b))
+        .unwrap_or(line.len());
+    let end = line
+        .iter()
+        .rposition(|&b| !is_whitespace(b))
+        .map_or(start, |pos| pos + 1);
+    &line[start..end]
+}
+
+/// Split an already trimmed line into the SMILES and the (optional) title following it.
+fn split_title(line: &[u8]) -> (&[u8], Option<String>) {
+    match line.iter().position(|&b| b == b' ' || b == b'\t') {
+        Some(pos) => {
+            let title = trim(&line[pos..]);
+            (
+                &line[..pos],
+                Some(String::from_utf8_lossy(title).into_owned()),
+            )
+        }
+        None => (line, None),
+    }
+}
+
+/// A reader that fails on every read.
+#[cfg(test)]
+pub(crate) struct BrokenReader;
+
+#[cfg(test)]
+impl std::io::Read for BrokenReader {
+    fn read(&mut self, _: &mut [u8]) -> std::io::Result<usize> {
+        Err(std::io::Error::other("broken"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ParseErrorKind;
+
+    #[test]
+    fn reads_titles_comments_and_blank_lines() {
+        let input = b"# header\r\nCCO ethanol\r\n\r\nC1CC1\tcyclopropane ring\n  \nCC\n";
+        let records = SmilesReader::new(&input[..])
+            .collect::<std::io::Result<Vec<_>>>()
+            .unwrap();
+
+        assert_eq!(3, records.len());
+        assert_eq!(2, records[0].0);
+        assert_eq!(Some("ethanol".to_owned()), records[0].1);
+        assert!(records[0].2.is_ok());
+        assert_eq!(4, records[1].0);
+        assert_eq!(Some("cyclopropane ring".to_owned()), records[1].1);
+        assert!(records[1].2.is_ok());
+        assert_eq!(6, records[2].0);
+        assert_eq!(None, records[2].1);
+        assert!(records[2].2.is_ok());
+    }
+
+    #[test]
+    fn reports_errors_per_line() {
+        let input = b"CC(C good\nCCC fine";
+        let records = SmilesReader::new(&input[..])
+            .collect::<std::io::Result<Vec<_>>>()
+            .unwrap();
+
+        assert_eq!(2, records.len());
+        assert_eq!(
+            Err(ParseError {
+                kind: ParseErrorKind::UnexpectedInput,
+                position: 2,
+            }),
+            records[0].2
+        );
+        assert!(records[1].2.is_ok());
+    }
+
+    #[test]
+    fn custom_parser() {
+        let input = b"C1CC ring\nCC";
+        let strict = |smiles: &[u8]| {
+            crate::flat::parse_flat(smiles)?;
+            parse(smiles)
+        };
+        let records = SmilesReader::with_parser(&input[..], strict)
+            .collect::<std::io::Result<Vec<_>>>()
+            .unwrap();
+
+        assert_eq!(2, records.len());
+        assert_eq!(Some("ring".to_owned()), records[0].1);
+        assert_eq!(
+            Err(ParseError {
+                kind: ParseErrorKind::UnclosedRing,
+                position: 1,
+            }),
+            records[0].2
+        );
+        assert!(records[1].2.is_ok());
+    }
+
+    #[test]
+    fn stops_after_io_error() {
+        use std::io::{BufReader, Read};
+
+        let mut reader = SmilesReader::new(BufReader::new(b"CC\n".chain(BrokenReader)));
+        assert!(matches!(reader.next(), Some(Ok((1, None, Ok(_))))));
+        assert!(matches!(reader.next(), Some(Err(_))));
+        assert!(reader.next().is_none());
+        assert!(reader.next().is_none());
+    }
+}
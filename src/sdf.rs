@@ -0,0 +1,377 @@
+//! Reading and writing SD files: Molfiles separated by `$$$$` lines, each optionally followed by
+//! data items of the form
+//!
+//! ```text
+//! > <NAME>
+//! value
+//!
+//! ```
+//!
+//! Values can span several lines, they are terminated by a blank line.
+
+use std::io::{BufRead, Write};
+
+use crate::graph::MoleculeGraph;
+use crate::molfile::{read_molfile, write_v2000, write_v3000, MolfileError};
+use crate::Chain;
+
+/// Data items of a record as pairs of name and value, in the order of the file. A name can
+/// occur more than once.
+pub type Properties = Vec<(String, String)>;
+
+/// A single record of an SD file.
+#[derive(Clone)]
+pub struct SdfRecord {
+    pub title: String,
+    pub graph: MoleculeGraph,
+    pub properties: Properties,
+}
+
+impl SdfRecord {
+    /// The value of the first data item with the given name.
+    pub fn property(&self, name: &str) -> Option<&str> {
+        self.properties
+            .iter()
+            .find(|(item, _)| item == name)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// The result of reading a single record: the (1-based) line number of the first line of the
+/// record and either the record or the reason its connection table couldn't be read.
+pub type SdfRecordResult = (usize, Result<SdfRecord, MolfileError>);
+
+#[derive(Debug)]
+pub enum SdfError {
+    Io(std::io::Error),
+    Molfile(MolfileError),
+    /// The value of the named data item has a blank line, which would end it early.
+    BlankLineInValue(String),
+}
+
+impl std::fmt::Display for SdfError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SdfError::Io(err) => write!(f, "{}", err),
+            SdfError::Molfile(err) => write!(f, "{}", err),
+            SdfError::BlankLineInValue(name) => {
+                write!(f, "value of data item <{}> has a blank line", name)
+            }
+        }
+    }
+}
+
+impl std::error::Error for SdfError {}
+
+impl From<std::io::Error> for SdfError {
+    fn from(err: std::io::Error) -> Self {
+        SdfError::Io(err)
+    }
+}
+
+impl From<MolfileError> for SdfError {
+    fn from(err: MolfileError) -> Self {
+        SdfError::Molfile(err)
+    }
+}
+
+/// Streaming reader for SD files.
+///
+/// Records are read one at a time. Like [`SmilesReader`](crate::reader::SmilesReader), a record
+/// that can't be read does not stop the reader; its error is reported and reading continues with
+/// the next record.
+pub struct SdfReader<R> {
+    inner: R,
+    line_number: usize,
+}
+
+impl<R: BufRead> SdfReader<R> {
+    pub fn new(inner: R) -> Self {
+        SdfReader {
+            inner,
+            line_number: 0,
+        }
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    /// Read the lines of the next record, up to but excluding its `$$$$` line.
+    ///
+    /// Returns `Ok(None)` at the end of the input.
+    fn next_record(&mut self) -> std::io::Result<Option<(usize, Vec<String>)>> {
+        let start = self.line_number + 1;
+        let mut lines = Vec::new();
+        let mut line = String::new();
+        loop {
+            line.clear();
+            if self.inner.read_line(&mut line)? == 0 {
+                break;
+            }
+            self.line_number += 1;
+
+            let line = line.trim_end_matches(['\n', '\r']);
+            if line.starts_with("$$$$") {
+                return Ok(Some((start, lines)));
+            }
+            lines.push(line.to_owned());
+        }
+
+        // A final record without a `$$$$` line is still a record, trailing blank lines are not.
+        if lines.iter().all(|line| line.trim().is_empty()) {
+            Ok(None)
+        } else {
+            Ok(Some((start, lines)))
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for SdfReader<R> {
+    type Item = std::io::Result<SdfRecordResult>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.next_record() {
+            Ok(Some((line_number, lines))) => Some(Ok((line_number, parse_record(&lines)))),
+            Ok(None) => None,
+            Err(err) => Some(Err(err)),
+        }
+    }
+}
+
+fn parse_record(lines: &[String]) -> Result<SdfRecord, MolfileError> {
+    let end = lines
+        .iter()
+        .position(|line| line.starts_with("M  END"))
+        .ok_or(MolfileError::UnexpectedEnd)?;
+    let molfile = read_molfile(&lines[..=end].join("\n"))?;
+
+    Ok(SdfRecord {
+        title: molfile.title,
+        graph: molfile.graph,
+        properties: parse_properties(&lines[end + 1..]),
+    })
+}
+
+fn parse_properties(lines: &[String]) -> Properties {
+    let mut properties = Properties::new();
+    let mut lines = lines.iter();
+    while let Some(line) = lines.next() {
+        let name = match property_name(line) {
+            Some(name) => name,
+            None => continue,
+        };
+        let value: Vec<&str> = lines
+            .by_ref()
+            .map(|line| line.trim_end())
+            .take_while(|line| !line.is_empty())
+            .collect();
+        properties.push((name.to_owned(), value.join("\n")));
+    }
+    properties
+}
+
+/// Name of a data header line like `> <NAME>` or `>  25  <NAME>  (ID-1)`.
+fn property_name(line: &str) -> Option<&str> {
+    if !line.starts_with('>') {
+        return None;
+    }
+    let start = line.find('<')? + 1;
+    let end = start + line[start..].find('>')?;
+    Some(&line[start..end])
+}
+
+/// Writer for SD files.
+///
+/// Connection tables are written in the V2000 format, or in the V3000 format for molecules that
+/// are too large for V2000.
+pub struct SdfWriter<W> {
+    inner: W,
+}
+
+impl<W: Write> SdfWriter<W> {
+    pub fn new(inner: W) -> Self {
+        SdfWriter { inner }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+
+    /// Write a record for a parsed SMILES chain.
+    pub fn write_chain<'a, P>(
+        &mut self,
+        chain: Chain,
+        title: &str,
+        properties: P,
+    ) -> Result<(), SdfError>
+    where
+        P: IntoIterator<Item = (&'a str, &'a str)>,
+    {
+        self.write_graph(&MoleculeGraph::from_chain(chain), title, properties)
+    }
+
+    /// Write a record for a graph.
+    ///
+    /// Values of data items can't have blank lines, since a blank line ends a value. Nothing is
+    /// written for a record with such a value, it returns [`SdfError::BlankLineInValue`].
+    pub fn write_graph<'a, P>(
+        &mut self,
+        graph: &MoleculeGraph,
+        title: &str,
+        properties: P,
+    ) -> Result<(), SdfError>
+    where
+        P: IntoIterator<Item = (&'a str, &'a str)>,
+    {
+        let properties: Vec<_> = properties.into_iter().collect();
+        if let Some((name, _)) = properties
+            .iter()
+            .find(|(_, value)| value.lines().any(|line| line.trim().is_empty()))
+        {
+            return Err(SdfError::BlankLineInValue((*name).to_owned()));
+        }
+        let molfile = match write_v2000(graph, title) {
+            Err(MolfileError::TooLarge) => write_v3000(graph, title)?,
+            molfile => molfile?,
+        };
+        self.inner.write_all(molfile.as_bytes())?;
+
+        for (name, value) in properties {
+            writeln!(self.inner, "> <{}>", name)?;
+            for line in value.lines() {
+                writeln!(self.inner, "{}", line)?;
+            }
+            writeln!(self.inner)?;
+        }
+        writeln!(self.inner, "$$$$")?;
+        Ok(())
+    }
+
+    /// Write a previously read record.
+    pub fn write_record(&mut self, record: &SdfRecord) -> Result<(), SdfError> {
+        self.write_graph(
+            &record.graph,
+            &record.title,
+            record
+                .properties
+                .iter()
+                .map(|(name, value)| (name.as_str(), value.as_str())),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graph::molecule;
+    use crate::parse;
+
+    fn read_all(input: &[u8]) -> Vec<SdfRecordResult> {
+        SdfReader::new(input)
+            .collect::<std::io::Result<Vec<_>>>()
+            .unwrap()
+    }
+
+    #[test]
+    fn write_and_read() {
+        let mut writer = SdfWriter::new(Vec::new());
+        writer
+            .write_chain(
+                parse(b"CCO").unwrap(),
+                "ethanol",
+                vec![("ID", "MOL-1"), ("NOTES", "first line\nsecond line")],
+            )
+            .unwrap();
+        writer
+            .write_chain(parse(b"c1ccccc1").unwrap(), "benzene", vec![])
+            .unwrap();
+        let output = writer.into_inner();
+
+        let records = read_all(&output);
+        assert_eq!(2, records.len());
+
+        let (line_number, ethanol) = &records[0];
+        let ethanol = ethanol.as_ref().unwrap();
+        assert_eq!(1, *line_number);
+        assert_eq!("ethanol", ethanol.title);
+        assert_eq!(9, ethanol.graph.node_count());
+        assert_eq!(Some("MOL-1"), ethanol.property("ID"));
+        assert_eq!(Some("first line\nsecond line"), ethanol.property("NOTES"));
+
+        let benzene = records[1].1.as_ref().unwrap();
+        assert_eq!("benzene", benzene.title);
+        assert_eq!(12, benzene.graph.node_count());
+        assert!(benzene.properties.is_empty());
+    }
+
+    #[test]
+    fn blank_lines_in_values() {
+        let mut writer = SdfWriter::new(Vec::new());
+        let result = writer.write_graph(
+            &molecule("CCO"),
+            "ethanol",
+            vec![("ID", "MOL-1"), ("NOTES", "first line\n\nsecond line")],
+        );
+        assert!(matches!(result, Err(SdfError::BlankLineInValue(name)) if name == "NOTES"));
+        assert!(writer.into_inner().is_empty());
+    }
+
+    #[test]
+    fn data_header_variants_and_errors() {
+        let mut molfile = write_v2000(&molecule("[Na+]"), "sodium").unwrap();
+        molfile.push_str(">  25  <MELTING.POINT>  (MD-08974)\r\n97.8\r\n\r\n");
+        let input = format!("{}$$$$\nbroken\n\n\nM  END\n$$$$\n{}\n\n", molfile, molfile);
+
+        let records = read_all(input.as_bytes());
+        assert_eq!(3, records.len());
+
+        let sodium = records[0].1.as_ref().unwrap();
+        assert_eq!(1, sodium.graph.node_count());
+        assert_eq!(Some("97.8"), sodium.property("MELTING.POINT"));
+        assert!(records[1].1.is_err());
+        // The last record has no `$$$$` line.
+        assert!(records[2].1.is_ok());
+        assert_eq!(records[1].0 + 5, records[2].0);
+    }
+
+    #[test]
+    fn large_molecules_use_v3000() {
+        let smiles = "C".repeat(400);
+        let mut writer = SdfWriter::new(Vec::new());
+        writer
+            .write_chain(parse(smiles.as_bytes()).unwrap(), "", vec![("N", "400")])
+            .unwrap();
+        let output = String::from_utf8(writer.into_inner()).unwrap();
+        assert!(output.contains("V3000"));
+
+        let records = read_all(output.as_bytes());
+        let record = records[0].1.as_ref().unwrap();
+        assert_eq!(1202, record.graph.node_count());
+        assert_eq!(Some("400"), record.property("N"));
+    }
+
+    #[test]
+    fn data_items_round_trip_in_order() {
+        let items = vec![("ZETA", "1"), ("ALPHA", "2"), ("ZETA", "3")];
+        let mut writer = SdfWriter::new(Vec::new());
+        writer
+            .write_graph(&molecule("CCO"), "ethanol", items.clone())
+            .unwrap();
+        let output = writer.into_inner();
+
+        let records = read_all(&output);
+        let record = records[0].1.as_ref().unwrap();
+        let read: Vec<(&str, &str)> = record
+            .properties
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.as_str()))
+            .collect();
+        assert_eq!(items, read);
+        assert_eq!(Some("1"), record.property("ZETA"));
+
+        let mut writer = SdfWriter::new(Vec::new());
+        writer.write_record(record).unwrap();
+        assert_eq!(output, writer.into_inner());
+    }
+}
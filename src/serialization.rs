@@ -0,0 +1,158 @@
+//! Serde support, enabled by the `serde` feature.
+//!
+//! All public types use serde's default representation, with two exceptions:
+//!
+//! - [`Element`]s are serialized as their symbol string (e.g. `"Cl"`).
+//! - A [`Chain`] is serialized as a flat sequence of its links, each with a `branched_atom` and
+//!   a `bond_or_dot` field, instead of as a nested structure. This keeps the nesting depth of the
+//!   output independent of the chain length.
+//!
+//! Spans are optional when deserializing and default to an empty span at the start.
+
+use ptable::Element;
+use serde::de::Error as _;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::{BondOrDot, BranchedAtom, Chain};
+
+pub(crate) mod element {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(element: &Element, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(element.get_symbol())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Element, D::Error> {
+        let symbol = String::deserialize(deserializer)?;
+        Element::from_symbol(&symbol)
+            .ok_or_else(|| D::Error::custom(format!("unknown element symbol `{}`", symbol)))
+    }
+}
+
+#[derive(Serialize)]
+struct ChainLinkRef<'a> {
+    branched_atom: &'a BranchedAtom,
+    bond_or_dot: &'a Option<BondOrDot>,
+}
+
+#[derive(Deserialize)]
+struct ChainLink {
+    branched_atom: BranchedAtom,
+    bond_or_dot: Option<BondOrDot>,
+}
+
+impl Serialize for Chain {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut links = Vec::new();
+        let mut link = Some(self);
+        while let Some(chain) = link {
+            links.push(ChainLinkRef {
+                branched_atom: &chain.branched_atom,
+                bond_or_dot: &chain.bond_or_dot,
+            });
+            link = chain.chain.as_deref();
+        }
+        links.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Chain {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let links = Vec::<ChainLink>::deserialize(deserializer)?;
+
+        let mut chain = None;
+        for link in links.into_iter().rev() {
+            chain = Some(Box::new(Chain {
+                chain,
+                bond_or_dot: link.bond_or_dot,
+                branched_atom: link.branched_atom,
+            }));
+        }
+        chain
+            .map(|chain| *chain)
+            .ok_or_else(|| D::Error::custom("a chain needs at least one atom"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse;
+
+    #[test]
+    fn chain_round_trip() {
+        let chain = parse(b"F[As@TB15](Cl)(S)(Br)N.[Na+]").unwrap();
+        let json = serde_json::to_string(&chain).unwrap();
+        assert_eq!(chain, serde_json::from_str::<Chain>(&json).unwrap());
+    }
+
+    #[test]
+    fn chain_format() {
+        let chain = parse(b"C=[13CH2-]").unwrap();
+        assert_eq!(
+            serde_json::json!([
+                {
+                    "branched_atom": {
+                        "atom": {"AliphaticOrganic": {"element": "C"}},
+                        "ring_bonds": [],
+                        "branches": [],
+                        "span": {"start": 0, "end": 1}
+                    },
+                    "bond_or_dot": {"Bond": "Double"}
+                },
+                {
+                    "branched_atom": {
+                        "atom": {"Bracket": {
+                            "isotope": 13,
+                            "symbol": {"ElementSymbol": "C"},
+                            "chiral": null,
+                            "hcount": 2,
+                            "charge": -1
+                        }},
+                        "ring_bonds": [],
+                        "branches": [],
+                        "span": {"start": 2, "end": 10}
+                    },
+                    "bond_or_dot": null
+                }
+            ]),
+            serde_json::to_value(&chain).unwrap()
+        );
+    }
+
+    #[test]
+    fn missing_spans() {
+        let json = r#"[{
+            "branched_atom": {
+                "atom": {"AliphaticOrganic": {"element": "C"}},
+                "ring_bonds": [{"bond": null, "ring_number": 1}],
+                "branches": []
+            },
+            "bond_or_dot": null
+        }]"#;
+        let chain: Chain = serde_json::from_str(json).unwrap();
+        assert_eq!(crate::Span::default(), chain.branched_atom.span);
+        assert_eq!(
+            crate::Span::default(),
+            chain.branched_atom.ring_bonds[0].span
+        );
+    }
+
+    #[cfg(feature = "graph")]
+    #[test]
+    fn molecule_graph_round_trip() {
+        use crate::graph::MoleculeGraph;
+
+        let graph = MoleculeGraph::from_chain(parse(b"CCO").unwrap());
+        let json = serde_json::to_string(&graph).unwrap();
+        let deserialized: MoleculeGraph = serde_json::from_str(&json).unwrap();
+        assert_eq!(graph.node_count(), deserialized.node_count());
+        assert_eq!(graph.edge_count(), deserialized.edge_count());
+    }
+
+    #[test]
+    fn unknown_element() {
+        assert!(serde_json::from_str::<crate::Symbol>(r#"{"ElementSymbol": "Xx"}"#).is_err());
+        assert!(serde_json::from_str::<Chain>("[]").is_err());
+    }
+}
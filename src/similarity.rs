@@ -0,0 +1,295 @@
+//! Similarity metrics for bit fingerprints and an in-memory nearest neighbor index.
+
+use crate::fingerprint::{morgan_bits, BitFingerprint, ECFP4_RADIUS};
+use crate::graph::MoleculeGraph;
+use crate::{parse, ParseError};
+
+/// Similarity metric between two bit fingerprints.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum Metric {
+    Tanimoto,
+    Dice,
+    /// Tversky similarity, weighting the bits only set in the query by `alpha` and the bits only
+    /// set in the target by `beta`. `alpha = beta = 1` is Tanimoto, `alpha = beta = 0.5` is Dice.
+    Tversky {
+        alpha: f64,
+        beta: f64,
+    },
+}
+
+impl Metric {
+    /// Similarity from the number of set bits in the query, in the target and in both.
+    fn similarity_from_counts(self, query: u32, target: u32, common: u32) -> f64 {
+        let (query, target, common) = (query as f64, target as f64, common as f64);
+        let denominator = match self {
+            Metric::Tanimoto => query + target - common,
+            Metric::Dice => (query + target) / 2.0,
+            Metric::Tversky { alpha, beta } => {
+                alpha * (query - common) + beta * (target - common) + common
+            }
+        };
+        if denominator > 0.0 {
+            common / denominator
+        } else {
+            0.0
+        }
+    }
+
+    /// Similarity of two fingerprints of the same length.
+    pub fn similarity(self, query: &BitFingerprint, target: &BitFingerprint) -> f64 {
+        self.similarity_from_counts(
+            query.count_ones(),
+            target.count_ones(),
+            query.intersection_count(target),
+        )
+    }
+
+    /// Upper bound of the similarity of fingerprints with the given numbers of set bits, reached
+    /// when all bits of the sparser one are also set in the other.
+    fn bound(self, query: u32, target: u32) -> f64 {
+        self.similarity_from_counts(query, target, query.min(target))
+    }
+}
+
+/// Tanimoto (Jaccard) similarity: the number of common bits divided by the number of bits set
+/// in either fingerprint. Two empty fingerprints have a similarity of 0.
+pub fn tanimoto(a: &BitFingerprint, b: &BitFingerprint) -> f64 {
+    Metric::Tanimoto.similarity(a, b)
+}
+
+/// Dice similarity: twice the number of common bits divided by the sum of the set bits.
+pub fn dice(a: &BitFingerprint, b: &BitFingerprint) -> f64 {
+    Metric::Dice.similarity(a, b)
+}
+
+/// Tversky similarity, see [`Metric::Tversky`].
+pub fn tversky(query: &BitFingerprint, target: &BitFingerprint, alpha: f64, beta: f64) -> f64 {
+    Metric::Tversky { alpha, beta }.similarity(query, target)
+}
+
+/// In-memory index of Morgan fingerprints for top-k similarity searches.
+///
+/// Fingerprints are grouped by their number of set bits. As that number alone bounds the
+/// possible similarity to the query, a search visits the groups in order of decreasing bound and
+/// stops as soon as no remaining group can improve the result.
+pub struct SimilarityIndex<T> {
+    radius: u32,
+    len: usize,
+    entries: Vec<(T, BitFingerprint)>,
+    /// Entry indices by number of set bits.
+    by_count: Vec<Vec<usize>>,
+}
+
+impl<T> Default for SimilarityIndex<T> {
+    /// An index of 2048 bit ECFP4 fingerprints.
+    fn default() -> Self {
+        SimilarityIndex::new(ECFP4_RADIUS, 2048)
+    }
+}
+
+impl<T> SimilarityIndex<T> {
+    /// An index of Morgan fingerprints of the given radius, folded to `len` bits.
+    pub fn new(radius: u32, len: usize) -> Self {
+        SimilarityIndex {
+            radius,
+            len,
+            entries: Vec::new(),
+            by_count: vec![Vec::new(); len + 1],
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn fingerprint(&self, graph: &MoleculeGraph) -> BitFingerprint {
+        morgan_bits(graph, self.radius, self.len)
+    }
+
+    pub fn insert(&mut self, key: T, graph: &MoleculeGraph) {
+        let fingerprint = self.fingerprint(graph);
+        self.by_count[fingerprint.count_ones() as usize].push(self.entries.len());
+        self.entries.push((key, fingerprint));
+    }
+
+    pub fn insert_smiles(&mut self, key: T, smiles: &str) -> Result<(), ParseError> {
+        let graph = MoleculeGraph::from_chain(parse(smiles.as_bytes())?);
+        self.insert(key, &graph);
+        Ok(())
+    }
+
+    /// The `k` entries most similar to a query fingerprint, most similar first. Entries with
+    /// equal similarity are returned in insertion order.
+    ///
+    /// The query must have the length of the fingerprints of the index.
+    pub fn nearest(&self, query: &BitFingerprint, k: usize, metric: Metric) -> Vec<(&T, f64)> {
+        assert_eq!(
+            self.len,
+            query.len(),
+            "query fingerprint has a different length than the index"
+        );
+        let query_count = query.count_ones();
+        let mut counts: Vec<(f64, usize)> = (0..self.by_count.len())
+            .filter(|&count| !self.by_count[count].is_empty())
+            .map(|count| (metric.bound(query_count, count as u32), count))
+            .collect();
+        counts.sort_by(|a, b| b.0.total_cmp(&a.0));
+
+        // Sorted by decreasing similarity, then by entry index.
+        let mut best: Vec<(f64, usize)> = Vec::with_capacity(k + 1);
+        for (bound, count) in counts {
+            if best.len() == k {
+                let worst = best.last().map_or(f64::INFINITY, |&(worst, _)| worst);
+                if bound < worst {
+                    break;
+                }
+            }
+            for &index in &self.by_count[count] {
+                let similarity = metric.similarity(query, &self.entries[index].1);
+                let position = best.partition_point(|&(other, other_index)| {
+                    other > similarity || (other == similarity && other_index < index)
+                });
+                if position < k {
+                    best.insert(position, (similarity, index));
+                    best.truncate(k);
+                }
+            }
+        }
+
+        best.into_iter()
+            .map(|(similarity, index)| (&self.entries[index].0, similarity))
+            .collect()
+    }
+
+    /// The `k` entries most similar to a query SMILES, by Tanimoto similarity.
+    pub fn nearest_smiles(&self, smiles: &str, k: usize) -> Result<Vec<(&T, f64)>, ParseError> {
+        let graph = MoleculeGraph::from_chain(parse(smiles.as_bytes())?);
+        Ok(self.nearest(&self.fingerprint(&graph), k, Metric::Tanimoto))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graph::molecule;
+
+    const MOLECULES: &[&str] = &[
+        "CC(=O)Oc1ccccc1C(=O)O",
+        "CC(=O)Nc1ccc(O)cc1",
+        "CC(C)Cc1ccc(cc1)C(C)C(=O)O",
+        "CN1C=NC2=C1C(=O)N(C(=O)N2C)C",
+        "OC(=O)c1ccccc1O",
+        "c1ccccc1",
+        "Cc1ccccc1",
+        "CCO",
+        "CCCO",
+        "CCCCO",
+        "OCC(O)CO",
+        "C1CCCCC1",
+        "c1ccc2ccccc2c1",
+        "Oc1ccccc1",
+        "Nc1ccccc1",
+        "CC(=O)O",
+        "CCN(CC)CC",
+        "C=CC=C",
+        "ClC(Cl)Cl",
+        "O=C=O",
+    ];
+
+    fn bits(a: &[usize]) -> BitFingerprint {
+        let mut fingerprint = BitFingerprint::new(16);
+        for &bit in a {
+            fingerprint.set(bit);
+        }
+        fingerprint
+    }
+
+    #[test]
+    fn metrics() {
+        let a = bits(&[0, 1, 2, 3]);
+        let b = bits(&[2, 3, 4]);
+        assert_eq!(2.0 / 5.0, tanimoto(&a, &b));
+        assert_eq!(4.0 / 7.0, dice(&a, &b));
+        assert_eq!(tanimoto(&a, &b), tversky(&a, &b, 1.0, 1.0));
+        assert_eq!(dice(&a, &b), tversky(&a, &b, 0.5, 0.5));
+        // With beta = 0, bits only set in the target are ignored
+        assert_eq!(1.0, tversky(&bits(&[2, 3]), &a, 1.0, 0.0));
+        assert_eq!(1.0, tanimoto(&a, &a));
+        assert_eq!(0.0, tanimoto(&bits(&[]), &bits(&[])));
+    }
+
+    #[test]
+    fn nearest_matches_exhaustive_search() {
+        let mut index = SimilarityIndex::default();
+        for (key, smiles) in MOLECULES.iter().enumerate() {
+            index.insert_smiles(key, smiles).unwrap();
+        }
+        assert_eq!(MOLECULES.len(), index.len());
+
+        let metrics = [
+            Metric::Tanimoto,
+            Metric::Dice,
+            Metric::Tversky {
+                alpha: 0.9,
+                beta: 0.1,
+            },
+            Metric::Tversky {
+                alpha: 0.0,
+                beta: 0.0,
+            },
+            Metric::Tversky {
+                alpha: f64::NAN,
+                beta: f64::INFINITY,
+            },
+        ];
+        for query in MOLECULES {
+            let query = index.fingerprint(&molecule(query));
+            for &metric in &metrics {
+                let mut expected: Vec<(usize, f64)> = index
+                    .entries
+                    .iter()
+                    .map(|(key, fingerprint)| (*key, metric.similarity(&query, fingerprint)))
+                    .collect();
+                expected.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+                expected.truncate(5);
+
+                let found: Vec<(usize, f64)> = index
+                    .nearest(&query, 5, metric)
+                    .into_iter()
+                    .map(|(key, similarity)| (*key, similarity))
+                    .collect();
+                assert_eq!(expected, found);
+            }
+        }
+    }
+
+    #[test]
+    fn nearest_smiles() {
+        let mut index = SimilarityIndex::new(ECFP4_RADIUS, 1024);
+        for smiles in MOLECULES {
+            index.insert_smiles(*smiles, smiles).unwrap();
+        }
+
+        let hits = index.nearest_smiles("OC(=O)c1ccccc1O", 3).unwrap();
+        assert_eq!(3, hits.len());
+        assert_eq!((&"OC(=O)c1ccccc1O", 1.0), hits[0]);
+        assert!(hits[1].1 >= hits[2].1);
+
+        assert!(index.nearest_smiles("C(", 3).is_err());
+        assert!(SimilarityIndex::<()>::default()
+            .nearest_smiles("C", 3)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    #[should_panic(expected = "different length")]
+    fn nearest_with_query_of_different_length() {
+        let index = SimilarityIndex::<()>::new(ECFP4_RADIUS, 1024);
+        index.nearest(&BitFingerprint::new(2048), 3, Metric::Tanimoto);
+    }
+}
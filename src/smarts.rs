@@ -0,0 +1,742 @@
+//! Substructure search with patterns written in a subset of SMARTS.
+//!
+//! Supported are atoms in and outside of brackets, bonds, branches and ring closures, but not
+//! disconnected patterns (`.`), chirality or atom maps. Bracket atoms can combine these
+//! primitives with `!`, `&`, `,` and `;`:
+//!
+//! - `*`, element symbols (`C`, `c`, `Cl`, ...), `a` (aromatic) and `A` (aliphatic)
+//! - `#<n>`: atomic number
+//! - `H<n>`: total number of hydrogens
+//! - `D<n>`: number of heavy atom neighbors, `X<n>`: `D<n>` plus the number of hydrogens
+//! - `R`: in a ring, `R<n>`: in `n` rings of the smallest set of smallest rings
+//! - `+<n>`, `-<n>`: charge
+//! - `$(<pattern>)`: the atom is the first atom of a match of another pattern
+//!
+//! Bond primitives are `-`, `=`, `#`, `:`, `~` (any bond) and `@` (ring bond). Without a bond
+//! symbol, atoms are connected by a single or an aromatic bond.
+//!
+//! Hydrogens attached to other atoms (see [`MoleculeGraph::is_attached_hydrogen`]) are only
+//! counted by `H` and `X`, they are never matched by pattern atoms.
+
+use std::collections::{HashMap, HashSet};
+use std::convert::TryFrom;
+
+use petgraph::graph::{EdgeIndex, NodeIndex};
+use ptable::Element;
+
+use crate::graph::MoleculeGraph;
+use crate::{ParseError, ParseErrorKind};
+
+#[derive(Debug, Clone)]
+enum AtomPrimitive {
+    /// An element, optionally restricted to aromatic or aliphatic atoms
+    Element(Element, Option<bool>),
+    AtomicNumber(u32),
+    Aromatic(bool),
+    Hydrogens(u8),
+    Degree(u8),
+    Connectivity(u8),
+    /// Number of rings, or any number but zero
+    Rings(Option<u8>),
+    Charge(i8),
+    Recursive(Box<Pattern>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BondPrimitive {
+    Single,
+    Double,
+    Triple,
+    Aromatic,
+    Ring,
+}
+
+#[derive(Debug, Clone)]
+enum Query<P> {
+    Any,
+    Primitive(P),
+    Not(Box<Query<P>>),
+    And(Box<Query<P>>, Box<Query<P>>),
+    Or(Box<Query<P>>, Box<Query<P>>),
+}
+
+impl<P> Query<P> {
+    fn evaluate(&self, primitive: &mut impl FnMut(&P) -> bool) -> bool {
+        match self {
+            Query::Any => true,
+            Query::Primitive(inner) => primitive(inner),
+            Query::Not(inner) => !inner.evaluate(primitive),
+            Query::And(first, second) => first.evaluate(primitive) && second.evaluate(primitive),
+            Query::Or(first, second) => first.evaluate(primitive) || second.evaluate(primitive),
+        }
+    }
+}
+
+type AtomQuery = Query<AtomPrimitive>;
+
+/// A bond query, or `None` for an implicit single or aromatic bond.
+type BondQuery = Option<Query<BondPrimitive>>;
+
+/// Properties of the atoms and bonds of a graph that are needed for matching.
+pub(crate) struct Target<'a> {
+    graph: &'a MoleculeGraph,
+    heavy: Vec<bool>,
+    hydrogens: Vec<u8>,
+    degrees: Vec<u8>,
+    ring_counts: Vec<u8>,
+    ring_bonds: Vec<bool>,
+}
+
+impl<'a> Target<'a> {
+    pub(crate) fn new(graph: &'a MoleculeGraph) -> Self {
+        let heavy: Vec<bool> = graph
+            .node_indices()
+            .map(|atom| !graph.is_attached_hydrogen(atom))
+            .collect();
+        let mut ring_counts = vec![0; graph.node_count()];
+        for ring in graph.rings() {
+            for atom in ring {
+                ring_counts[atom.index()] += 1;
+            }
+        }
+        Target {
+            graph,
+            hydrogens: graph
+                .node_indices()
+                .map(|atom| graph.hydrogen_count(atom))
+                .collect(),
+            degrees: graph
+                .node_indices()
+                .map(|atom| {
+                    graph
+                        .neighbors(atom)
+                        .filter(|neighbor| heavy[neighbor.index()])
+                        .count() as u8
+                })
+                .collect(),
+            heavy,
+            ring_counts,
+            ring_bonds: graph.ring_bonds(),
+        }
+    }
+
+    fn matches_atom(&self, query: &AtomQuery, atom: NodeIndex) -> bool {
+        let graph_atom = &self.graph[atom];
+        let index = atom.index();
+        query.evaluate(&mut |primitive| match primitive {
+            AtomPrimitive::Element(element, aromatic) => {
+                graph_atom.element() == Some(*element)
+                    && aromatic.is_none_or(|aromatic| aromatic == graph_atom.is_aromatic())
+            }
+            AtomPrimitive::AtomicNumber(number) => graph_atom
+                .element()
+                .is_some_and(|element| element.get_atomic_number() as u32 == *number),
+            AtomPrimitive::Aromatic(aromatic) => graph_atom.is_aromatic() == *aromatic,
+            AtomPrimitive::Hydrogens(count) => self.hydrogens[index] == *count,
+            AtomPrimitive::Degree(degree) => self.degrees[index] == *degree,
+            AtomPrimitive::Connectivity(count) => {
+                self.degrees[index] + self.hydrogens[index] == *count
+            }
+            AtomPrimitive::Rings(Some(count)) => self.ring_counts[index] == *count,
+            AtomPrimitive::Rings(None) => self.ring_counts[index] > 0,
+            AtomPrimitive::Charge(charge) => graph_atom.charge() == *charge,
+            AtomPrimitive::Recursive(pattern) => pattern.matches_at(self, atom),
+        })
+    }
+
+    fn matches_bond(&self, query: &BondQuery, edge: EdgeIndex) -> bool {
+        use crate::Bond;
+
+        let bond = self.graph[edge];
+        let is = |primitive: &BondPrimitive| match primitive {
+            BondPrimitive::Single => matches!(bond, Bond::Single | Bond::Up | Bond::Down),
+            BondPrimitive::Double => bond == Bond::Double,
+            BondPrimitive::Triple => bond == Bond::Triple,
+            BondPrimitive::Aromatic => bond == Bond::Aromatic,
+            BondPrimitive::Ring => self.ring_bonds[edge.index()],
+        };
+        match query {
+            None => is(&BondPrimitive::Single) || is(&BondPrimitive::Aromatic),
+            Some(query) => query.evaluate(&mut |primitive| is(primitive)),
+        }
+    }
+}
+
+/// A substructure pattern.
+#[derive(Debug, Clone)]
+pub struct Pattern {
+    atoms: Vec<AtomQuery>,
+    /// Bonds of every atom to atoms that appear before it in the pattern.
+    bonds: Vec<Vec<(usize, BondQuery)>>,
+}
+
+impl Pattern {
+    /// Parse a pattern from a SMARTS string.
+    pub fn parse(smarts: &str) -> Result<Self, ParseError> {
+        PatternParser {
+            input: smarts.as_bytes(),
+            position: 0,
+        }
+        .parse()
+    }
+
+    /// All matches of the pattern, as the matched atoms in the order of the pattern atoms.
+    /// Matches of the same set of atoms are only reported once.
+    pub fn matches(&self, graph: &MoleculeGraph) -> Vec<Vec<NodeIndex>> {
+        let mut seen = HashSet::new();
+        let mut matches = Vec::new();
+        self.search(&Target::new(graph), &mut Vec::new(), &mut |mapping| {
+            let mut atoms = mapping.to_vec();
+            atoms.sort_unstable();
+            if seen.insert(atoms) {
+                matches.push(mapping.to_vec());
+            }
+            true
+        });
+        matches
+    }
+
+    /// Number of matches with distinct sets of atoms, counting at most `limit`.
+    pub(crate) fn count_matches(&self, target: &Target, limit: usize) -> usize {
+        let mut seen = HashSet::new();
+        self.search(target, &mut Vec::new(), &mut |mapping| {
+            let mut atoms = mapping.to_vec();
+            atoms.sort_unstable();
+            seen.insert(atoms);
+            seen.len() < limit
+        });
+        seen.len()
+    }
+
+    /// Whether there is a match with the first pattern atom on the given atom.
+    pub(crate) fn matches_at(&self, target: &Target, atom: NodeIndex) -> bool {
+        target.matches_atom(&self.atoms[0], atom)
+            && !self.search(target, &mut vec![atom], &mut |_| false)
+    }
+
+    /// Extend a partial match in all possible ways, calling `visit` for every complete match
+    /// until it returns `false`. Returns `false` if the search was stopped.
+    fn search(
+        &self,
+        target: &Target,
+        mapping: &mut Vec<NodeIndex>,
+        visit: &mut dyn FnMut(&[NodeIndex]) -> bool,
+    ) -> bool {
+        let next = mapping.len();
+        if next == self.atoms.len() {
+            return visit(mapping);
+        }
+
+        let candidates: Vec<NodeIndex> = match self.bonds[next].first() {
+            Some(&(previous, _)) => target.graph.neighbors(mapping[previous]).collect(),
+            None => target.graph.node_indices().collect(),
+        };
+        for candidate in candidates {
+            if !target.heavy[candidate.index()]
+                || mapping.contains(&candidate)
+                || !target.matches_atom(&self.atoms[next], candidate)
+            {
+                continue;
+            }
+            let bonds_match = self.bonds[next].iter().all(|(previous, query)| {
+                target
+                    .graph
+                    .find_edge(mapping[*previous], candidate)
+                    .is_some_and(|edge| target.matches_bond(query, edge))
+            });
+            if !bonds_match {
+                continue;
+            }
+
+            mapping.push(candidate);
+            let proceed = self.search(target, mapping, visit);
+            mapping.pop();
+            if !proceed {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+/// A parsed primitive, or `None` if there is none at the current position.
+type PrimitiveResult<P> = Result<Option<Query<P>>, ParseError>;
+
+struct PatternParser<'a> {
+    input: &'a [u8],
+    position: usize,
+}
+
+impl<'a> PatternParser<'a> {
+    fn error(&self, kind: ParseErrorKind) -> ParseError {
+        ParseError {
+            kind,
+            position: self.position,
+        }
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.input.get(self.position).copied()
+    }
+
+    fn eat(&mut self, byte: u8) -> bool {
+        if self.peek() == Some(byte) {
+            self.position += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn number(&mut self) -> Option<u32> {
+        let start = self.position;
+        while self.peek().is_some_and(|byte| byte.is_ascii_digit()) {
+            self.position += 1;
+        }
+        std::str::from_utf8(&self.input[start..self.position])
+            .unwrap()
+            .parse()
+            .ok()
+    }
+
+    /// A number that has to fit into `T`, or `None` if there are no digits at the current
+    /// position.
+    fn bounded_number<T: TryFrom<u32>>(&mut self) -> Result<Option<T>, ParseError> {
+        let start = self.position;
+        match self.number().map(T::try_from) {
+            None if self.position == start => Ok(None),
+            Some(Ok(number)) => Ok(Some(number)),
+            _ => Err(ParseError {
+                kind: ParseErrorKind::InvalidAtom,
+                position: start,
+            }),
+        }
+    }
+
+    fn parse(mut self) -> Result<Pattern, ParseError> {
+        if self.input.is_empty() {
+            return Err(self.error(ParseErrorKind::Empty));
+        }
+
+        let mut pattern = Pattern {
+            atoms: Vec::new(),
+            bonds: Vec::new(),
+        };
+        let mut previous: Option<usize> = None;
+        let mut branches = Vec::new();
+        let mut bond: Option<BondQuery> = None;
+        let mut open_rings: HashMap<u32, (usize, BondQuery)> = HashMap::new();
+
+        while let Some(byte) = self.peek() {
+            match byte {
+                b'(' if previous.is_some() && bond.is_none() => {
+                    self.position += 1;
+                    branches.push(previous);
+                }
+                b')' if bond.is_none() && !branches.is_empty() => {
+                    self.position += 1;
+                    previous = branches.pop().unwrap();
+                }
+                b'-' | b'=' | b'#' | b':' | b'~' | b'@' | b'!' | b'/' | b'\\'
+                    if previous.is_some() && bond.is_none() =>
+                {
+                    bond = Some(Some(self.bond_query()?));
+                }
+                b'0'..=b'9' | b'%' if previous.is_some() => {
+                    let ring_bond_position = self.position;
+                    self.position += 1;
+                    let number = if byte == b'%' {
+                        let start = self.position;
+                        let number = self.number();
+                        if self.position - start != 2 {
+                            return Err(self.error(ParseErrorKind::UnexpectedInput));
+                        }
+                        number.unwrap()
+                    } else {
+                        (byte - b'0') as u32
+                    };
+                    let atom = previous.unwrap();
+                    match open_rings.remove(&number) {
+                        Some((other, opening_bond)) => {
+                            let kind = if other == atom {
+                                Some(ParseErrorKind::RingBondToSelf)
+                            } else if pattern.bonds[atom]
+                                .iter()
+                                .any(|&(bonded, _)| bonded == other)
+                            {
+                                Some(ParseErrorKind::DuplicateBond)
+                            } else {
+                                None
+                            };
+                            if let Some(kind) = kind {
+                                return Err(ParseError {
+                                    kind,
+                                    position: ring_bond_position,
+                                });
+                            }
+                            let query = bond.take().unwrap_or(opening_bond);
+                            pattern.bonds[atom].push((other, query));
+                        }
+                        None => {
+                            open_rings.insert(number, (atom, bond.take().unwrap_or(None)));
+                        }
+                    }
+                }
+                _ => {
+                    let atom = self.atom_query()?;
+                    let index = pattern.atoms.len();
+                    pattern.atoms.push(atom);
+                    pattern.bonds.push(Vec::new());
+                    if let Some(previous) = previous {
+                        pattern.bonds[index].push((previous, bond.take().unwrap_or(None)));
+                    } else if index > 0 {
+                        return Err(self.error(ParseErrorKind::UnexpectedInput));
+                    }
+                    previous = Some(index);
+                }
+            }
+        }
+
+        if bond.is_some() {
+            Err(self.error(ParseErrorKind::UnexpectedInput))
+        } else if !branches.is_empty() {
+            Err(self.error(ParseErrorKind::UnclosedBranch))
+        } else if !open_rings.is_empty() {
+            Err(self.error(ParseErrorKind::UnclosedRing))
+        } else {
+            Ok(pattern)
+        }
+    }
+
+    /// Parse a query expression with the SMARTS operator precedence: `!` binds tightest, then
+    /// `&` (or no operator), then `,` and then `;`.
+    fn expression<P>(
+        &mut self,
+        primitive: &mut dyn FnMut(&mut Self) -> PrimitiveResult<P>,
+    ) -> Result<Query<P>, ParseError> {
+        let mut low = self.or_expression(primitive)?;
+        while self.eat(b';') {
+            low = Query::And(Box::new(low), Box::new(self.or_expression(primitive)?));
+        }
+        Ok(low)
+    }
+
+    fn or_expression<P>(
+        &mut self,
+        primitive: &mut dyn FnMut(&mut Self) -> PrimitiveResult<P>,
+    ) -> Result<Query<P>, ParseError> {
+        let mut or = self.and_expression(primitive)?;
+        while self.eat(b',') {
+            or = Query::Or(Box::new(or), Box::new(self.and_expression(primitive)?));
+        }
+        Ok(or)
+    }
+
+    fn and_expression<P>(
+        &mut self,
+        primitive: &mut dyn FnMut(&mut Self) -> PrimitiveResult<P>,
+    ) -> Result<Query<P>, ParseError> {
+        let mut and = self
+            .not_expression(primitive)?
+            .ok_or_else(|| self.error(ParseErrorKind::InvalidAtom))?;
+        loop {
+            let explicit = self.eat(b'&');
+            match self.not_expression(primitive)? {
+                Some(next) => and = Query::And(Box::new(and), Box::new(next)),
+                None if explicit => return Err(self.error(ParseErrorKind::InvalidAtom)),
+                None => return Ok(and),
+            }
+        }
+    }
+
+    fn not_expression<P>(
+        &mut self,
+        primitive: &mut dyn FnMut(&mut Self) -> PrimitiveResult<P>,
+    ) -> Result<Option<Query<P>>, ParseError> {
+        if self.eat(b'!') {
+            let inner = self
+                .not_expression(primitive)?
+                .ok_or_else(|| self.error(ParseErrorKind::InvalidAtom))?;
+            Ok(Some(Query::Not(Box::new(inner))))
+        } else {
+            primitive(self)
+        }
+    }
+
+    fn bond_query(&mut self) -> Result<Query<BondPrimitive>, ParseError> {
+        self.expression(&mut |parser| {
+            let primitive = match parser.peek() {
+                Some(b'-') | Some(b'/') | Some(b'\\') => BondPrimitive::Single,
+                Some(b'=') => BondPrimitive::Double,
+                Some(b'#') => BondPrimitive::Triple,
+                Some(b':') => BondPrimitive::Aromatic,
+                Some(b'@') => BondPrimitive::Ring,
+                Some(b'~') => {
+                    parser.position += 1;
+                    return Ok(Some(Query::Any));
+                }
+                _ => return Ok(None),
+            };
+            parser.position += 1;
+            Ok(Some(Query::Primitive(primitive)))
+        })
+    }
+
+    /// An element symbol at the current position: two letters if that is a valid symbol,
+    /// otherwise one.
+    fn element_symbol(&mut self) -> Option<(Element, bool)> {
+        let rest = &self.input[self.position..];
+        let first = *rest.first()?;
+        if first.is_ascii_uppercase() {
+            if let Some(&second) = rest.get(1).filter(|second| second.is_ascii_lowercase()) {
+                let symbol = [first, second];
+                if let Some(element) = Element::from_symbol(std::str::from_utf8(&symbol).unwrap()) {
+                    self.position += 2;
+                    return Some((element, false));
+                }
+            }
+            let element = Element::from_symbol(std::str::from_utf8(&[first]).unwrap())?;
+            self.position += 1;
+            Some((element, false))
+        } else {
+            let element = match first {
+                b'b' => Element::Boron,
+                b'c' => Element::Carbon,
+                b'n' => Element::Nitrogen,
+                b'o' => Element::Oxygen,
+                b'p' => Element::Phosphorus,
+                b's' => Element::Sulfur,
+                _ => return None,
+            };
+            self.position += 1;
+            Some((element, true))
+        }
+    }
+
+    fn atom_query(&mut self) -> Result<AtomQuery, ParseError> {
+        let primitive = match self.peek() {
+            Some(b'[') => {
+                self.position += 1;
+                let query = self.expression(&mut |parser| parser.bracket_primitive())?;
+                if !self.eat(b']') {
+                    return Err(self.error(ParseErrorKind::InvalidAtom));
+                }
+                return Ok(query);
+            }
+            Some(b'*') => {
+                self.position += 1;
+                return Ok(Query::Any);
+            }
+            Some(b'a') => AtomPrimitive::Aromatic(true),
+            Some(b'A') => AtomPrimitive::Aromatic(false),
+            _ => {
+                // Outside of brackets, only the organic subset is allowed
+                let rest = &self.input[self.position..];
+                let len = if rest.starts_with(b"Cl") || rest.starts_with(b"Br") {
+                    2
+                } else {
+                    1
+                };
+                let organic = matches!(
+                    rest.first(),
+                    Some(b'B' | b'C' | b'N' | b'O' | b'P' | b'S' | b'F' | b'I')
+                        | Some(b'b' | b'c' | b'n' | b'o' | b'p' | b's')
+                );
+                if !organic {
+                    return Err(self.error(ParseErrorKind::InvalidAtom));
+                }
+                let symbol = std::str::from_utf8(&rest[..len]).unwrap();
+                let aromatic = symbol.starts_with(|first: char| first.is_ascii_lowercase());
+                let mut symbol = symbol.to_string();
+                symbol[..1].make_ascii_uppercase();
+                self.position += len;
+                return Ok(Query::Primitive(AtomPrimitive::Element(
+                    Element::from_symbol(&symbol).unwrap(),
+                    Some(aromatic),
+                )));
+            }
+        };
+        self.position += 1;
+        Ok(Query::Primitive(primitive))
+    }
+
+    fn bracket_primitive(&mut self) -> Result<Option<AtomQuery>, ParseError> {
+        let byte = match self.peek() {
+            Some(byte) => byte,
+            None => return Ok(None),
+        };
+        // Two letter element symbols like `Al` or `Hg` start with a letter of another primitive
+        let followed_by_lowercase = self
+            .input
+            .get(self.position + 1)
+            .is_some_and(|next| next.is_ascii_lowercase());
+        let primitive = match byte {
+            b'*' => {
+                self.position += 1;
+                return Ok(Some(Query::Any));
+            }
+            b'#' => {
+                self.position += 1;
+                let number = self
+                    .bounded_number()?
+                    .ok_or_else(|| self.error(ParseErrorKind::InvalidAtom))?;
+                AtomPrimitive::AtomicNumber(number)
+            }
+            b'$' => {
+                self.position += 1;
+                if !self.eat(b'(') {
+                    return Err(self.error(ParseErrorKind::InvalidAtom));
+                }
+                let start = self.position;
+                let mut depth = 1;
+                while depth > 0 {
+                    match self.peek() {
+                        Some(b'(') => depth += 1,
+                        Some(b')') => depth -= 1,
+                        Some(_) => {}
+                        None => return Err(self.error(ParseErrorKind::UnclosedBranch)),
+                    }
+                    self.position += 1;
+                }
+                let inner = PatternParser {
+                    input: &self.input[start..self.position - 1],
+                    position: 0,
+                }
+                .parse()
+                .map_err(|err| ParseError {
+                    kind: err.kind,
+                    position: start + err.position,
+                })?;
+                AtomPrimitive::Recursive(Box::new(inner))
+            }
+            b'+' | b'-' => {
+                self.position += 1;
+                let sign = if byte == b'+' { 1 } else { -1 };
+                let magnitude = match self.bounded_number::<i8>()? {
+                    Some(number) => number,
+                    None => {
+                        let mut count: i8 = 1;
+                        while self.peek() == Some(byte) {
+                            count = count
+                                .checked_add(1)
+                                .ok_or_else(|| self.error(ParseErrorKind::InvalidAtom))?;
+                            self.position += 1;
+                        }
+                        count
+                    }
+                };
+                AtomPrimitive::Charge(sign * magnitude)
+            }
+            b'a' => {
+                self.position += 1;
+                AtomPrimitive::Aromatic(true)
+            }
+            b'A' if !followed_by_lowercase => {
+                self.position += 1;
+                AtomPrimitive::Aromatic(false)
+            }
+            b'H' | b'D' | b'X' | b'R' if !followed_by_lowercase => {
+                self.position += 1;
+                let number = self.bounded_number()?;
+                match byte {
+                    b'H' => AtomPrimitive::Hydrogens(number.unwrap_or(1)),
+                    b'D' => AtomPrimitive::Degree(number.unwrap_or(1)),
+                    b'X' => AtomPrimitive::Connectivity(number.unwrap_or(1)),
+                    _ => AtomPrimitive::Rings(number),
+                }
+            }
+            _ => match self.element_symbol() {
+                Some((element, aromatic)) => AtomPrimitive::Element(element, Some(aromatic)),
+                None => return Ok(None),
+            },
+        };
+        Ok(Some(Query::Primitive(primitive)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graph::molecule;
+
+    fn count(smarts: &str, smiles: &str) -> usize {
+        Pattern::parse(smarts)
+            .unwrap()
+            .matches(&molecule(smiles))
+            .len()
+    }
+
+    #[test]
+    fn atoms() {
+        assert_eq!(2, count("C", "CCO"));
+        assert_eq!(0, count("c", "CCO"));
+        assert_eq!(6, count("c", "c1ccccc1"));
+        assert_eq!(6, count("[#6]", "c1ccccc1"));
+        assert_eq!(1, count("[CH3]", "CCO"));
+        assert_eq!(1, count("[O;!H0]", "CC(=O)O"));
+        assert_eq!(2, count("[!#6;!#1]", "CC(=O)O"));
+        assert_eq!(1, count("[N+]", "C[N+](C)(C)C.[Cl-]"));
+        assert_eq!(1, count("[!+0]", "[Na+].CC"));
+        assert_eq!(1, count("[D3]", "CC(C)O"));
+        assert_eq!(2, count("[X4]", "CC(C)=O"));
+        assert_eq!(2, count("[Cl,Br]", "ClCCBr"));
+        assert_eq!(1, count("[Sc,Co,Cs]", "[Co]"));
+        assert_eq!(1, count("[Al,Hg]", "[Hg]"));
+        assert_eq!(1, count("Sc", "CSc1ccccc1"));
+        assert_eq!(6, count("[R]", "C1CCCCC1CC"));
+        assert_eq!(2, count("[R2]", "c1ccc2ccccc2c1"));
+        assert_eq!(1, count("[$(C=O)]", "CC(=O)O"));
+        assert_eq!(1, count("[$([OH]C=O),$(N)]", "OC(=O)CCO"));
+    }
+
+    #[test]
+    fn bonds_and_rings() {
+        assert_eq!(1, count("C=O", "CC(=O)O"));
+        assert_eq!(2, count("C~O", "CC(=O)O"));
+        assert_eq!(1, count("CO", "CC(=O)O"));
+        assert_eq!(6, count("cc", "c1ccccc1"));
+        assert_eq!(6, count("c:c", "c1ccccc1"));
+        assert_eq!(0, count("c-c", "c1ccccc1"));
+        assert_eq!(1, count("*1~*~*~*~*~*~1", "C1CCCCC1"));
+        assert_eq!(2, count("*@*!@*", "C1CC1C"));
+        assert_eq!(1, count("O=C(O)C", "CC(=O)O"));
+        assert_eq!(1, count("*~*(~*)(~*)~*", "CC(C)(C)C"));
+    }
+
+    #[test]
+    fn matches_and_errors() {
+        let pattern = Pattern::parse("C=O").unwrap();
+        let graph = molecule("OC(=O)C");
+        assert_eq!(
+            vec![vec![NodeIndex::new(1), NodeIndex::new(2)]],
+            pattern.matches(&graph)
+        );
+        assert!(pattern.matches(&molecule("CCO")).is_empty());
+
+        for (smarts, kind, position) in [
+            ("", ParseErrorKind::Empty, 0),
+            ("C(", ParseErrorKind::UnclosedBranch, 2),
+            ("C1CC", ParseErrorKind::UnclosedRing, 4),
+            ("C=", ParseErrorKind::UnexpectedInput, 2),
+            ("[C", ParseErrorKind::InvalidAtom, 2),
+            ("C.C", ParseErrorKind::InvalidAtom, 1),
+            ("[$(C(]", ParseErrorKind::UnclosedBranch, 6),
+            ("C11", ParseErrorKind::RingBondToSelf, 2),
+            ("C1C1", ParseErrorKind::DuplicateBond, 3),
+            ("C12CC12", ParseErrorKind::DuplicateBond, 6),
+            ("[-128]", ParseErrorKind::InvalidAtom, 2),
+            ("[+300]", ParseErrorKind::InvalidAtom, 2),
+            ("[H300]", ParseErrorKind::InvalidAtom, 2),
+            ("[#99999999999]", ParseErrorKind::InvalidAtom, 2),
+        ]
+        .iter()
+        {
+            let err = Pattern::parse(smarts).unwrap_err();
+            assert_eq!((*kind, *position), (err.kind, err.position), "{}", smarts);
+        }
+
+        let charge = format!("[{}]", "+".repeat(128));
+        let err = Pattern::parse(&charge).unwrap_err();
+        assert_eq!((ParseErrorKind::InvalidAtom, 128), (err.kind, err.position));
+    }
+}
@@ -0,0 +1,627 @@
+//! Standardization of molecules for registration.
+//!
+//! [`standardize`] runs these steps, each of which can be turned off in [`StandardizeOptions`]:
+//!
+//! 1. Keep only the largest organic fragment of a molecule with several components, such as a
+//!    salt or a solvate.
+//! 2. Normalize functional groups: nitro groups are written charge-separated, as
+//!    `[N+](=O)[O-]`, sulfoxides and sulfones with double bonds, as `S=O`, and azides as
+//!    `N=[N+]=[N-]`.
+//! 3. Neutralize charges by adding or removing hydrogens. Charges that are balanced by an
+//!    adjacent opposite charge, like those of nitro groups and N-oxides, are kept, and so are
+//!    as many negative charges as are needed to balance positive charges that can't be
+//!    neutralized, like those of quaternary ammonium ions.
+//! 4. Remove isotopes (off by default).
+//! 5. Remove tetrahedral and double bond stereochemistry (off by default).
+//!
+//! Every step reports what it changed, referring to atoms by their node index in the input graph.
+
+use std::collections::VecDeque;
+
+use petgraph::graph::NodeIndex;
+use petgraph::visit::EdgeRef;
+use ptable::Element;
+
+use crate::graph::{normal_valences, Atom, MoleculeGraph};
+use crate::Bond;
+
+/// Which steps [`standardize`] runs.
+#[derive(Debug, PartialEq, Eq, Copy, Clone, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct StandardizeOptions {
+    pub largest_fragment: bool,
+    pub normalize: bool,
+    pub neutralize: bool,
+    pub remove_isotopes: bool,
+    pub remove_stereo: bool,
+}
+
+impl Default for StandardizeOptions {
+    fn default() -> Self {
+        StandardizeOptions {
+            largest_fragment: true,
+            normalize: true,
+            neutralize: true,
+            remove_isotopes: false,
+            remove_stereo: false,
+        }
+    }
+}
+
+/// A functional group that is normalized.
+#[derive(Debug, PartialEq, Eq, Copy, Clone, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Group {
+    Nitro,
+    /// Sulfoxides and sulfones.
+    Sulfoxide,
+    Azide,
+}
+
+/// A change made by [`standardize`]. Atoms are node indices in the input graph.
+#[derive(Debug, PartialEq, Eq, Clone, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Change {
+    /// A fragment was removed, with its atoms other than attached hydrogens.
+    RemovedFragment(Vec<usize>),
+    /// A functional group was rewritten, with the atoms whose bonds or charges changed.
+    Normalized {
+        group: Group,
+        atoms: Vec<usize>,
+    },
+    /// The charge of an atom was changed by adding or removing hydrogens.
+    Neutralized {
+        atom: usize,
+        old_charge: i8,
+        new_charge: i8,
+    },
+    RemovedIsotope {
+        atom: usize,
+        isotope: u16,
+    },
+    RemovedChirality {
+        atom: usize,
+    },
+    /// A directional bond, given by its atoms, was replaced by a single bond.
+    RemovedBondDirection {
+        atoms: [usize; 2],
+    },
+}
+
+/// Standardize a molecule, returning the standardized graph and the changes made.
+///
+/// Atoms keep their order, except that removed atoms are dropped and hydrogens that are added to
+/// neutralize an atom come last.
+pub fn standardize(
+    graph: &MoleculeGraph,
+    options: &StandardizeOptions,
+) -> (MoleculeGraph, Vec<Change>) {
+    let mut standardizer = Standardizer {
+        graph: graph.clone(),
+        removed: vec![false; graph.node_count()],
+        changes: Vec::new(),
+    };
+    if options.largest_fragment {
+        standardizer.keep_largest_fragment();
+    }
+    if options.normalize {
+        standardizer.normalize();
+    }
+    if options.neutralize {
+        standardizer.neutralize();
+    }
+    if options.remove_isotopes {
+        standardizer.remove_isotopes();
+    }
+    if options.remove_stereo {
+        standardizer.remove_stereo();
+    }
+    let keep: Vec<bool> = standardizer
+        .removed
+        .iter()
+        .map(|removed| !removed)
+        .collect();
+    (standardizer.graph.subgraph(&keep), standardizer.changes)
+}
+
+struct Standardizer {
+    graph: MoleculeGraph,
+    removed: Vec<bool>,
+    changes: Vec<Change>,
+}
+
+impl Standardizer {
+    fn atoms(&self) -> Vec<NodeIndex> {
+        self.graph
+            .node_indices()
+            .filter(|atom| !self.removed[atom.index()])
+            .collect()
+    }
+
+    fn set_charge(&mut self, atom: NodeIndex, charge: i8) {
+        let (isotope, chirality) = (self.graph[atom].isotope(), self.graph[atom].chirality());
+        self.graph.rebuild_atom(atom, isotope, chirality, charge);
+    }
+
+    fn set_bond(&mut self, a: NodeIndex, b: NodeIndex, bond: Bond) {
+        let edge = self.graph.find_edge(a, b).unwrap();
+        self.graph[edge] = bond;
+    }
+
+    fn bond(&self, a: NodeIndex, b: NodeIndex) -> Option<Bond> {
+        self.graph.find_edge(a, b).map(|edge| self.graph[edge])
+    }
+
+    fn element(&self, atom: NodeIndex) -> Option<Element> {
+        self.graph[atom].element()
+    }
+
+    /// Neighbors of an atom that are not attached hydrogens, in node order.
+    fn heavy_neighbors(&self, atom: NodeIndex) -> Vec<NodeIndex> {
+        let mut neighbors: Vec<NodeIndex> = self
+            .graph
+            .neighbors(atom)
+            .filter(|&neighbor| !self.graph.is_attached_hydrogen(neighbor))
+            .collect();
+        neighbors.sort_unstable();
+        neighbors
+    }
+
+    fn keep_largest_fragment(&mut self) {
+        let mut component = vec![None; self.graph.node_count()];
+        let mut fragments: Vec<Vec<NodeIndex>> = Vec::new();
+        for start in self.graph.node_indices() {
+            if component[start.index()].is_some() {
+                continue;
+            }
+            let mut atoms = Vec::new();
+            let mut queue = VecDeque::from(vec![start]);
+            component[start.index()] = Some(fragments.len());
+            while let Some(atom) = queue.pop_front() {
+                atoms.push(atom);
+                for neighbor in self.graph.neighbors(atom) {
+                    if component[neighbor.index()].is_none() {
+                        component[neighbor.index()] = Some(fragments.len());
+                        queue.push_back(neighbor);
+                    }
+                }
+            }
+            fragments.push(atoms);
+        }
+        if fragments.len() < 2 {
+            return;
+        }
+
+        // Organic fragments first, then by the number of atoms other than attached hydrogens.
+        // Ties go to the fragment that comes first.
+        let key = |fragment: &[NodeIndex]| {
+            let organic = fragment
+                .iter()
+                .any(|&atom| self.element(atom) == Some(Element::Carbon));
+            let size = fragment
+                .iter()
+                .filter(|&&atom| !self.graph.is_attached_hydrogen(atom))
+                .count();
+            (organic, size)
+        };
+        let mut largest = 0;
+        for (index, fragment) in fragments.iter().enumerate() {
+            if key(fragment) > key(&fragments[largest]) {
+                largest = index;
+            }
+        }
+        for (index, fragment) in fragments.iter().enumerate() {
+            if index == largest {
+                continue;
+            }
+            let mut atoms = Vec::new();
+            for &atom in fragment {
+                self.removed[atom.index()] = true;
+                if !self.graph.is_attached_hydrogen(atom) {
+                    atoms.push(atom.index());
+                }
+            }
+            self.changes.push(Change::RemovedFragment(atoms));
+        }
+    }
+
+    fn normalize(&mut self) {
+        for atom in self.atoms() {
+            let group = match self.element(atom) {
+                Some(Element::Nitrogen) => self
+                    .normalize_nitro(atom)
+                    .map(|atoms| (Group::Nitro, atoms))
+                    .or_else(|| {
+                        self.normalize_azide(atom)
+                            .map(|atoms| (Group::Azide, atoms))
+                    }),
+                Some(Element::Sulfur) => self
+                    .normalize_sulfoxide(atom)
+                    .map(|atoms| (Group::Sulfoxide, atoms)),
+                _ => None,
+            };
+            if let Some((group, atoms)) = group {
+                let atoms = atoms.iter().map(|atom| atom.index()).collect();
+                self.changes.push(Change::Normalized { group, atoms });
+            }
+        }
+    }
+
+    /// Terminal neighbors of an atom of the given element and charge, bonded by the given bond.
+    fn terminal_neighbors(
+        &self,
+        atom: NodeIndex,
+        element: Element,
+        charge: i8,
+        bond: Bond,
+    ) -> Vec<NodeIndex> {
+        self.heavy_neighbors(atom)
+            .into_iter()
+            .filter(|&neighbor| {
+                self.element(neighbor) == Some(element)
+                    && self.graph[neighbor].charge() == charge
+                    && self.heavy_neighbors(neighbor).len() == 1
+                    && self.graph.hydrogen_count(neighbor) == 0
+                    && self.bond(atom, neighbor) == Some(bond)
+            })
+            .collect()
+    }
+
+    /// `N(=O)=O` to `[N+](=O)[O-]`.
+    fn normalize_nitro(&mut self, atom: NodeIndex) -> Option<Vec<NodeIndex>> {
+        let oxygens = self.terminal_neighbors(atom, Element::Oxygen, 0, Bond::Double);
+        if self.graph[atom].charge() != 0 || oxygens.len() != 2 {
+            return None;
+        }
+        let oxygen = oxygens[1];
+        self.set_bond(atom, oxygen, Bond::Single);
+        self.set_charge(atom, 1);
+        self.set_charge(oxygen, -1);
+        Some(vec![atom, oxygen])
+    }
+
+    /// `N=N#N` and `[N-]-[N+]#N` to `N=[N+]=[N-]`.
+    fn normalize_azide(&mut self, middle: NodeIndex) -> Option<Vec<NodeIndex>> {
+        let neighbors = self.heavy_neighbors(middle);
+        if neighbors.len() != 2
+            || neighbors
+                .iter()
+                .any(|&neighbor| self.element(neighbor) != Some(Element::Nitrogen))
+        {
+            return None;
+        }
+        let (first, last) =
+            match self.terminal_neighbors(middle, Element::Nitrogen, 0, Bond::Triple) {
+                terminal if terminal.len() == 1 && neighbors.contains(&terminal[0]) => {
+                    let last = terminal[0];
+                    let first = if neighbors[0] == last {
+                        neighbors[1]
+                    } else {
+                        neighbors[0]
+                    };
+                    (first, last)
+                }
+                _ => return None,
+            };
+        let charges = (
+            self.graph[first].charge(),
+            self.graph[middle].charge(),
+            self.bond(first, middle),
+        );
+        let mut changed = vec![middle, last];
+        match charges {
+            (0, 0, Some(Bond::Double)) => {}
+            (-1, 1, Some(Bond::Single)) => {
+                self.set_bond(first, middle, Bond::Double);
+                self.set_charge(first, 0);
+                changed.insert(0, first);
+            }
+            _ => return None,
+        }
+        self.set_bond(middle, last, Bond::Double);
+        self.set_charge(middle, 1);
+        self.set_charge(last, -1);
+        Some(changed)
+    }
+
+    /// `[S+]-[O-]` to `S=O`, for every charge-separated oxygen.
+    fn normalize_sulfoxide(&mut self, atom: NodeIndex) -> Option<Vec<NodeIndex>> {
+        let mut changed = vec![atom];
+        for oxygen in self.terminal_neighbors(atom, Element::Oxygen, -1, Bond::Single) {
+            let charge = self.graph[atom].charge();
+            if charge <= 0 {
+                break;
+            }
+            self.set_bond(atom, oxygen, Bond::Double);
+            self.set_charge(oxygen, 0);
+            self.set_charge(atom, charge - 1);
+            changed.push(oxygen);
+        }
+        if changed.len() > 1 {
+            Some(changed)
+        } else {
+            None
+        }
+    }
+
+    /// Whether a charged atom is next to an atom of opposite charge.
+    fn is_charge_separated(&self, atom: NodeIndex) -> bool {
+        let charge = self.graph[atom].charge();
+        self.graph
+            .neighbors(atom)
+            .any(|neighbor| self.graph[neighbor].charge().signum() == -charge.signum())
+    }
+
+    fn neutralize(&mut self) {
+        let mut charges: Vec<(NodeIndex, i8)> = self
+            .atoms()
+            .into_iter()
+            .filter(|&atom| self.graph[atom].charge() != 0 && !self.is_charge_separated(atom))
+            .map(|atom| (atom, self.graph[atom].charge()))
+            .collect();
+
+        // Positive atoms lose hydrogens.
+        for (atom, charge) in charges.iter_mut().filter(|(_, charge)| *charge > 0) {
+            let old_charge = *charge;
+            while *charge > 0 {
+                let hydrogen = self
+                    .graph
+                    .neighbors(*atom)
+                    .filter(|&neighbor| self.graph.is_attached_hydrogen(neighbor))
+                    .max();
+                let hydrogen = match hydrogen {
+                    Some(hydrogen) => hydrogen,
+                    None => break,
+                };
+                let edge = self.graph.find_edge(*atom, hydrogen).unwrap();
+                self.graph.remove_edge(edge);
+                self.removed[hydrogen.index()] = true;
+                *charge -= 1;
+            }
+            if *charge != old_charge {
+                self.set_charge(*atom, *charge);
+                self.changes.push(Change::Neutralized {
+                    atom: atom.index(),
+                    old_charge,
+                    new_charge: *charge,
+                });
+            }
+        }
+
+        // Negative atoms gain hydrogens, except for the charges that balance the remaining
+        // positive charges.
+        let positive: i32 = charges
+            .iter()
+            .map(|&(_, charge)| i32::from(charge.max(0)))
+            .sum();
+        let negative: i32 = charges
+            .iter()
+            .map(|&(_, charge)| i32::from(-charge.min(0)))
+            .sum();
+        let mut excess = negative - positive;
+        for &(atom, old_charge) in &charges {
+            if excess <= 0 {
+                break;
+            }
+            let can_gain_hydrogen = self
+                .element(atom)
+                .is_some_and(|element| !normal_valences(element).is_empty());
+            if old_charge >= 0 || !can_gain_hydrogen {
+                continue;
+            }
+            let mut charge = old_charge;
+            while charge < 0 && excess > 0 {
+                let hydrogen = self.graph.add_node(Atom::Element(Element::Hydrogen));
+                self.graph.add_edge(atom, hydrogen, Bond::Single);
+                self.removed.push(false);
+                charge += 1;
+                excess -= 1;
+            }
+            self.set_charge(atom, charge);
+            self.changes.push(Change::Neutralized {
+                atom: atom.index(),
+                old_charge,
+                new_charge: charge,
+            });
+        }
+    }
+
+    fn remove_isotopes(&mut self) {
+        for atom in self.atoms() {
+            if let Some(isotope) = self.graph[atom].isotope() {
+                let (chirality, charge) = (self.graph[atom].chirality(), self.graph[atom].charge());
+                self.graph.rebuild_atom(atom, None, chirality, charge);
+                self.changes.push(Change::RemovedIsotope {
+                    atom: atom.index(),
+                    isotope,
+                });
+            }
+        }
+    }
+
+    fn remove_stereo(&mut self) {
+        for atom in self.atoms() {
+            if self.graph[atom].chirality().is_some() {
+                let (isotope, charge) = (self.graph[atom].isotope(), self.graph[atom].charge());
+                self.graph.rebuild_atom(atom, isotope, None, charge);
+                self.changes
+                    .push(Change::RemovedChirality { atom: atom.index() });
+            }
+        }
+        let directional: Vec<_> = self
+            .graph
+            .edge_references()
+            .filter(|edge| matches!(edge.weight(), Bond::Up | Bond::Down))
+            .filter(|edge| !self.removed[edge.source().index()])
+            .map(|edge| (edge.id(), [edge.source().index(), edge.target().index()]))
+            .collect();
+        for (edge, atoms) in directional {
+            self.graph[edge] = Bond::Single;
+            self.changes.push(Change::RemovedBondDirection { atoms });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::canon::canonical_smiles;
+    use crate::graph::molecule;
+    use crate::writer::write_smiles;
+
+    fn run(smiles: &str, options: &StandardizeOptions) -> (String, Vec<Change>) {
+        let (graph, changes) = standardize(&molecule(smiles), options);
+        (write_smiles(&graph), changes)
+    }
+
+    fn standardized(smiles: &str) -> String {
+        run(smiles, &StandardizeOptions::default()).0
+    }
+
+    fn only(step: fn(&mut StandardizeOptions)) -> StandardizeOptions {
+        let mut options = StandardizeOptions {
+            largest_fragment: false,
+            normalize: false,
+            neutralize: false,
+            remove_isotopes: false,
+            remove_stereo: false,
+        };
+        step(&mut options);
+        options
+    }
+
+    #[test]
+    fn largest_fragment() {
+        let options = only(|options| options.largest_fragment = true);
+        assert_eq!(
+            (
+                "CC(=O)[O-]".to_string(),
+                vec![Change::RemovedFragment(vec![4])]
+            ),
+            run("CC(=O)[O-].[Na+]", &options)
+        );
+        // Organic fragments win over larger inorganic ones.
+        assert_eq!("CN", run("OS(=O)(=O)O.CN", &options).0);
+        assert_eq!("CCO", run("O.CCO.O", &options).0);
+        assert_eq!("O", run("O.[Na+].[Cl-]", &options).0);
+        assert_eq!(("CCO".to_string(), Vec::new()), run("CCO", &options));
+    }
+
+    #[test]
+    fn neutralization() {
+        let options = only(|options| options.neutralize = true);
+        assert_eq!(
+            (
+                "CC(=O)O".to_string(),
+                vec![Change::Neutralized {
+                    atom: 3,
+                    old_charge: -1,
+                    new_charge: 0,
+                }]
+            ),
+            run("CC(=O)[O-]", &options)
+        );
+        assert_eq!("CN", run("C[NH3+]", &options).0);
+        assert_eq!("NCC(=O)O", run("[NH3+]CC(=O)[O-]", &options).0);
+        assert_eq!("c1ccncc1", run("c1cc[nH+]cc1", &options).0);
+        assert_eq!("CC[S-].[Na+]", run("CC[S-].[Na+]", &options).0);
+        // Quaternary ammonium ions keep a counter charge.
+        assert_eq!(
+            "C[N+](C)(C)CC(=O)[O-]",
+            run("C[N+](C)(C)CC(=O)[O-]", &options).0
+        );
+        assert_eq!(
+            "C[N+](C)(C)CC(=O)O.[O-]C(=O)C",
+            run("C[N+](C)(C)CC(=O)[O-].[O-]C(=O)C", &options).0
+        );
+        // Charge-separated groups are left alone.
+        for smiles in &["C[N+](=O)[O-]", "[O-][n+]1ccccc1"] {
+            assert_eq!((smiles.to_string(), Vec::new()), run(smiles, &options));
+        }
+    }
+
+    #[test]
+    fn normalization() {
+        let options = only(|options| options.normalize = true);
+        assert_eq!(
+            (
+                "C[N+](=O)[O-]".to_string(),
+                vec![Change::Normalized {
+                    group: Group::Nitro,
+                    atoms: vec![1, 3],
+                }]
+            ),
+            run("CN(=O)=O", &options)
+        );
+        assert_eq!("CS(=O)C", run("C[S+]([O-])C", &options).0);
+        assert_eq!("CS(=O)(=O)C", run("C[S+2]([O-])([O-])C", &options).0);
+        assert_eq!(
+            (
+                "CN=[N+]=[N-]".to_string(),
+                vec![Change::Normalized {
+                    group: Group::Azide,
+                    atoms: vec![2, 3],
+                }]
+            ),
+            run("CN=N#N", &options)
+        );
+        assert_eq!(
+            canonical_smiles(&molecule("CN=[N+]=[N-]")),
+            canonical_smiles(&standardize(&molecule("C[N-][N+]#N"), &options).0)
+        );
+        for smiles in &["CN=[N+]=[N-]", "C[N+](=O)[O-]", "CS(=O)C", "CN=O", "N#N"] {
+            assert_eq!((smiles.to_string(), Vec::new()), run(smiles, &options));
+        }
+    }
+
+    #[test]
+    fn isotopes_and_stereo() {
+        let smiles = "[13CH3][C@H](N)/C=C/[2H]";
+        assert_eq!(smiles, standardized(smiles));
+        let options = only(|options| options.remove_isotopes = true);
+        let (written, changes) = run(smiles, &options);
+        assert_eq!("C[C@H](N)/C=C/[H]", written);
+        assert_eq!(
+            vec![
+                Change::RemovedIsotope {
+                    atom: 0,
+                    isotope: 13
+                },
+                Change::RemovedIsotope {
+                    atom: 5,
+                    isotope: 2
+                },
+            ],
+            changes
+        );
+        let options = only(|options| options.remove_stereo = true);
+        let (written, changes) = run(smiles, &options);
+        assert_eq!("[13CH3]C(N)C=C[2H]", written);
+        assert_eq!(
+            vec![
+                Change::RemovedChirality { atom: 1 },
+                Change::RemovedBondDirection { atoms: [1, 3] },
+                Change::RemovedBondDirection { atoms: [4, 5] },
+            ],
+            changes
+        );
+    }
+
+    #[test]
+    fn pipeline() {
+        assert_eq!(
+            "CC(=O)Nc1ccc(cc1)[N+](=O)[O-]",
+            standardized("CC(=O)Nc1ccc(cc1)N(=O)=O.Cl")
+        );
+        assert_eq!("CCCN", standardized("[Cl-].CCC[NH3+]"));
+        let options = StandardizeOptions {
+            remove_isotopes: true,
+            remove_stereo: true,
+            ..StandardizeOptions::default()
+        };
+        assert_eq!(
+            "NC(C)C(=O)O",
+            run("[15NH3+][C@@H](C)C(=O)[O-].O", &options).0
+        );
+    }
+}
@@ -0,0 +1,389 @@
+//! Stereoisomer enumeration.
+//!
+//! Stereo elements are found from the constitution of a molecule:
+//!
+//! - Tetrahedral centers: C, Si, Ge, P, As and N⁺ with four neighbors, and S, Se and P with
+//!   three, whose neighbors are all different, or which have two equal neighbors that lead to
+//!   another center, like the ends of a 1,4-disubstituted ring.
+//! - Double bonds between C or N, outside of rings with fewer than eight atoms, with two
+//!   different substituents at each end, or a single one at a nitrogen. Cumulated double bonds
+//!   are left out.
+//!
+//! Every combination of configurations is written, and stereoisomers that turn out to be the
+//! same molecule, like the two ways to write a meso compound, are only kept once.
+
+use std::collections::HashSet;
+
+use petgraph::graph::NodeIndex;
+use petgraph::visit::EdgeRef;
+use ptable::Element;
+
+use crate::canon::{canonical_smiles, para_partners, symmetry_classes};
+use crate::graph::MoleculeGraph;
+use crate::{Bond, Chirality};
+
+/// Options for [`enumerate_stereoisomers`].
+#[derive(Debug, PartialEq, Eq, Copy, Clone, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct StereoisomerOptions {
+    /// Keep the configurations given in the input and only enumerate the unassigned stereo
+    /// elements. Otherwise all stereo elements are enumerated.
+    pub only_unassigned: bool,
+    /// The most stereoisomers to return.
+    pub max_isomers: usize,
+}
+
+impl Default for StereoisomerOptions {
+    fn default() -> Self {
+        StereoisomerOptions {
+            only_unassigned: true,
+            max_isomers: 1024,
+        }
+    }
+}
+
+/// A stereo element that can be assigned.
+#[derive(Debug, Copy, Clone)]
+pub(crate) enum StereoElement {
+    Tetrahedral(NodeIndex),
+    DoubleBond(NodeIndex, NodeIndex),
+}
+
+/// Enumerate the stereoisomers of a molecule, each with a different canonical SMILES.
+///
+/// A molecule without stereo elements to enumerate gives itself.
+pub fn enumerate_stereoisomers(
+    graph: &MoleculeGraph,
+    options: &StereoisomerOptions,
+) -> Vec<MoleculeGraph> {
+    let mut graph = graph.clone();
+    if !options.only_unassigned {
+        remove_stereo(&mut graph);
+    }
+    let elements = stereo_elements(&graph);
+    let mut seen = HashSet::new();
+    let mut isomers = Vec::new();
+    // Combinations beyond the limit on isomers are never reached.
+    let combinations = 1u64.checked_shl(elements.len() as u32).unwrap_or(u64::MAX);
+    for mask in 0..combinations {
+        if isomers.len() >= options.max_isomers {
+            break;
+        }
+        if let Some(isomer) = assign(&graph, &elements, mask) {
+            if seen.insert(canonical_smiles(&isomer)) {
+                isomers.push(isomer);
+            }
+        }
+    }
+    isomers
+}
+
+/// The canonical SMILES of the stereoisomers of a molecule, as returned by
+/// [`enumerate_stereoisomers`].
+pub fn stereoisomer_smiles(graph: &MoleculeGraph, options: &StereoisomerOptions) -> Vec<String> {
+    enumerate_stereoisomers(graph, options)
+        .iter()
+        .map(canonical_smiles)
+        .collect()
+}
+
+fn remove_stereo(graph: &mut MoleculeGraph) {
+    for atom in graph.node_indices() {
+        if graph[atom].chirality().is_some() {
+            let (isotope, charge) = (graph[atom].isotope(), graph[atom].charge());
+            graph.rebuild_atom(atom, isotope, None, charge);
+        }
+    }
+    for edge in graph.edge_indices() {
+        if matches!(graph[edge], Bond::Up | Bond::Down) {
+            graph[edge] = Bond::Single;
+        }
+    }
+}
+
+/// The unassigned stereo elements of a molecule.
+pub(crate) fn stereo_elements(graph: &MoleculeGraph) -> Vec<StereoElement> {
+    let classes = symmetry_classes(graph);
+    let mut elements = Vec::new();
+
+    // Centers with at most one pair of equal neighbors, of which those with a pair need another
+    // center to be relative to.
+    let possible: Vec<bool> = graph
+        .node_indices()
+        .map(|atom| {
+            is_tetrahedral_candidate(graph, atom)
+                && equal_neighbor_pairs(graph, atom, &classes) <= 1
+        })
+        .collect();
+    for atom in graph.node_indices().filter(|atom| possible[atom.index()]) {
+        let stereogenic = equal_neighbor_pairs(graph, atom, &classes) == 0
+            || !para_partners(graph, atom, &classes, &possible).is_empty();
+        if stereogenic && graph[atom].chirality().is_none() {
+            elements.push(StereoElement::Tetrahedral(atom));
+        }
+    }
+
+    let rings = graph.rings();
+    let in_small_ring = |a: NodeIndex, b: NodeIndex| {
+        rings.iter().any(|ring| {
+            ring.len() < 8
+                && (0..ring.len()).any(|i| {
+                    let (first, second) = (ring[i], ring[(i + 1) % ring.len()]);
+                    first == a && second == b || first == b && second == a
+                })
+        })
+    };
+    for edge in graph.edge_references() {
+        let (a, b) = (edge.source(), edge.target());
+        if *edge.weight() == Bond::Double
+            && is_double_bond_end(graph, a, b, &classes)
+            && is_double_bond_end(graph, b, a, &classes)
+            && !in_small_ring(a, b)
+            && !(has_direction(graph, a, b) && has_direction(graph, b, a))
+        {
+            elements.push(StereoElement::DoubleBond(a, b));
+        }
+    }
+    elements
+}
+
+fn is_tetrahedral_candidate(graph: &MoleculeGraph, atom: NodeIndex) -> bool {
+    if graph[atom].is_aromatic() {
+        return false;
+    }
+    let degree = graph.neighbors(atom).count();
+    matches!(
+        (graph[atom].element(), graph[atom].charge(), degree),
+        (Some(Element::Carbon), 0, 4)
+            | (Some(Element::Silicon), 0, 4)
+            | (Some(Element::Germanium), 0, 4)
+            | (Some(Element::Nitrogen), 1, 4)
+            | (Some(Element::Phosphorus), 0 | 1, 4)
+            | (Some(Element::Arsenic), 0 | 1, 4)
+            | (Some(Element::Phosphorus), 0, 3)
+            | (Some(Element::Sulfur), 0 | 1, 3)
+            | (Some(Element::Selenium), 0 | 1, 3)
+    )
+}
+
+/// The number of pairs of neighbors of an atom that are in the same class.
+fn equal_neighbor_pairs(graph: &MoleculeGraph, atom: NodeIndex, classes: &[usize]) -> usize {
+    let neighbors: Vec<usize> = graph
+        .neighbors(atom)
+        .map(|neighbor| classes[neighbor.index()])
+        .collect();
+    let mut pairs = 0;
+    for (i, a) in neighbors.iter().enumerate() {
+        pairs += neighbors[i + 1..].iter().filter(|b| a == *b).count();
+    }
+    pairs
+}
+
+/// Whether `atom` can be the end of a stereo double bond to `other`.
+fn is_double_bond_end(
+    graph: &MoleculeGraph,
+    atom: NodeIndex,
+    other: NodeIndex,
+    classes: &[usize],
+) -> bool {
+    if graph[atom].is_aromatic()
+        || graph
+            .edges(atom)
+            .filter(|edge| matches!(edge.weight(), Bond::Double | Bond::Triple | Bond::Quadruple))
+            .count()
+            > 1
+    {
+        return false;
+    }
+    let substituents: Vec<NodeIndex> = graph
+        .neighbors(atom)
+        .filter(|&neighbor| neighbor != other)
+        .collect();
+    match (graph[atom].element(), substituents.as_slice()) {
+        (Some(Element::Carbon), [a, b]) | (Some(Element::Nitrogen), [a, b]) => {
+            classes[a.index()] != classes[b.index()]
+        }
+        (Some(Element::Nitrogen), [_]) => true,
+        _ => false,
+    }
+}
+
+/// The substituents of `atom`, other than `other`, ordered with heavy atoms first.
+fn substituents(graph: &MoleculeGraph, atom: NodeIndex, other: NodeIndex) -> Vec<NodeIndex> {
+    let mut substituents: Vec<NodeIndex> = graph
+        .neighbors(atom)
+        .filter(|&neighbor| neighbor != other)
+        .collect();
+    substituents.sort_unstable_by_key(|&substituent| {
+        (graph.is_attached_hydrogen(substituent), substituent)
+    });
+    substituents
+}
+
+/// The side of the first substituent of `atom` with a directional bond, as the substituent and
+/// whether it is above the double bond to `other`.
+fn direction(
+    graph: &MoleculeGraph,
+    atom: NodeIndex,
+    other: NodeIndex,
+) -> Option<(NodeIndex, bool)> {
+    substituents(graph, atom, other)
+        .into_iter()
+        .find_map(|substituent| {
+            let edge = graph.find_edge(atom, substituent)?;
+            let up = match graph[edge] {
+                Bond::Up => true,
+                Bond::Down => false,
+                _ => return None,
+            };
+            let (source, _) = graph.edge_endpoints(edge)?;
+            Some((substituent, (source == atom) == up))
+        })
+}
+
+fn has_direction(graph: &MoleculeGraph, atom: NodeIndex, other: NodeIndex) -> bool {
+    direction(graph, atom, other).is_some()
+}
+
+/// Mark the bond from `atom` to `substituent` so that the substituent is above `atom`.
+fn mark(graph: &mut MoleculeGraph, atom: NodeIndex, substituent: NodeIndex, above: bool) {
+    let edge = graph.find_edge(atom, substituent).unwrap();
+    let (source, _) = graph.edge_endpoints(edge).unwrap();
+    // `/` means that the later atom is above the earlier one.
+    graph[edge] = if (source == atom) == above {
+        Bond::Up
+    } else {
+        Bond::Down
+    };
+}
+
+/// The molecule with the stereo elements configured by the bits of `mask`, or `None` if double
+/// bonds that share substituents can't be marked consistently.
+fn assign(graph: &MoleculeGraph, elements: &[StereoElement], mask: u64) -> Option<MoleculeGraph> {
+    let mut graph = graph.clone();
+    for (index, element) in elements.iter().enumerate() {
+        let bit = mask.checked_shr(index as u32).unwrap_or(0) & 1 == 1;
+        match *element {
+            StereoElement::Tetrahedral(atom) => {
+                let chirality = if bit {
+                    Chirality::Clockwise
+                } else {
+                    Chirality::Anticlockwise
+                };
+                let (isotope, charge) = (graph[atom].isotope(), graph[atom].charge());
+                graph.rebuild_atom(atom, isotope, Some(chirality), charge);
+            }
+            StereoElement::DoubleBond(a, b) => {
+                // With the bit set, the marked substituents are trans.
+                let first_above = match direction(&graph, a, b) {
+                    Some((_, above)) => above,
+                    None => {
+                        let substituent = substituents(&graph, a, b)[0];
+                        mark(&mut graph, a, substituent, true);
+                        true
+                    }
+                };
+                let second_above = first_above != bit;
+                match direction(&graph, b, a) {
+                    Some((_, above)) if above != second_above => return None,
+                    Some(_) => {}
+                    None => {
+                        let substituent = substituents(&graph, b, a)[0];
+                        mark(&mut graph, b, substituent, second_above);
+                    }
+                }
+            }
+        }
+    }
+    Some(graph)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graph::molecule;
+
+    fn count(smiles: &str, only_unassigned: bool) -> usize {
+        let options = StereoisomerOptions {
+            only_unassigned,
+            ..StereoisomerOptions::default()
+        };
+        stereoisomer_smiles(&molecule(smiles), &options).len()
+    }
+
+    fn canonical(smiles: &str) -> String {
+        canonical_smiles(&molecule(smiles))
+    }
+
+    #[test]
+    fn tetrahedral_centers() {
+        assert_eq!(2, count("CC(N)C(=O)O", true));
+        assert_eq!(4, count("CC(O)C(N)CC", true));
+        assert_eq!(1, count("CC(C)CC", true));
+        assert_eq!(1, count("CCO", true));
+        // Sulfoxides
+        assert_eq!(2, count("CS(=O)CC", true));
+        assert_eq!(1, count("CS(=O)(=O)CC", true));
+    }
+
+    #[test]
+    fn meso_compounds() {
+        let isomers = stereoisomer_smiles(&molecule("CC(O)C(O)C"), &StereoisomerOptions::default());
+        assert_eq!(3, isomers.len());
+        assert!(isomers.contains(&canonical("C[C@H](O)[C@H](O)C")));
+        assert!(isomers.contains(&canonical("C[C@H](O)[C@@H](O)C")));
+        assert!(isomers.contains(&canonical("C[C@@H](O)[C@H](O)C")));
+
+        assert_eq!(3, count("OC(C(=O)O)C(O)C(=O)O", true));
+        // Two meso forms and a pair of enantiomers, with a pseudoasymmetric center in the middle.
+        assert_eq!(4, count("CC(O)C(O)C(O)C", true));
+    }
+
+    #[test]
+    fn rings() {
+        // cis and trans
+        assert_eq!(2, count("OC1CCC(N)CC1", true));
+        assert_eq!(4, count("OC1CCCC(N)C1", true));
+        assert_eq!(1, count("CC1(C)CCC(O)CC1", true));
+    }
+
+    #[test]
+    fn double_bonds() {
+        let isomers = stereoisomer_smiles(&molecule("CC=CC"), &StereoisomerOptions::default());
+        assert_eq!(2, isomers.len());
+        assert!(isomers.contains(&canonical("C/C=C/C")));
+        assert!(isomers.contains(&canonical("C/C=C\\C")));
+
+        assert_eq!(1, count("C=CC", true));
+        assert_eq!(1, count("CC(C)=CC", true));
+        assert_eq!(1, count("C1=CCCCC1", true));
+        assert_eq!(2, count("CC=NO", true));
+        assert_eq!(3, count("CC=CC=CC", true));
+        assert_eq!(6, count("CC=CC=CC=CC", true));
+    }
+
+    #[test]
+    fn only_unassigned() {
+        assert_eq!(2, count("C/C=C/C(Cl)F", true));
+        assert_eq!(4, count("C/C=C/C(Cl)F", false));
+        assert_eq!(1, count("N[C@@H](C)C(=O)O", true));
+        assert_eq!(2, count("N[C@@H](C)C(=O)O", false));
+
+        let options = StereoisomerOptions::default();
+        let isomers = stereoisomer_smiles(&molecule("C/C=C/C(Cl)F"), &options);
+        assert!(isomers.contains(&canonical("C/C=C/[C@H](Cl)F")));
+        assert!(isomers.contains(&canonical("C/C=C/[C@@H](Cl)F")));
+    }
+
+    #[test]
+    fn max_isomers() {
+        let options = StereoisomerOptions {
+            max_isomers: 5,
+            ..StereoisomerOptions::default()
+        };
+        assert_eq!(
+            5,
+            enumerate_stereoisomers(&molecule("CC(O)C(N)C(F)C(Cl)C"), &options).len()
+        );
+    }
+}
@@ -0,0 +1,636 @@
+//! Tautomer enumeration and a canonical tautomer.
+//!
+//! Tautomers are generated by moving a hydrogen between two atoms, with these rules:
+//!
+//! - 1,3 shifts along non-aromatic bonds, `H-X-Y=Z` to `X=Y-Z-H`, where `X` and `Z` are C, N, O
+//!   or S, at least one of them a heteroatom, and `Y` is C or N. This covers keto/enol,
+//!   amide/imidic acid, imine/enamine, amidine and nitroso/oxime tautomers.
+//! - Proton shifts in aromatic systems: between aromatic nitrogens, like in imidazoles and
+//!   purines, and between an aromatic nitrogen and an exocyclic O, S or N, like in pyridones and
+//!   aminopyridines. Only shifts that leave the aromatic system with a Kekulé structure are used.
+//!
+//! The Kekulé and the aromatic form of a molecule give different tautomers, see
+//! [aromaticity](crate::graph#aromaticity). Charged atoms don't take part. Tetrahedral and double bond stereo is
+//! removed from the atoms that change in any of the tautomers, so it is the same in all of them.
+
+use std::collections::HashSet;
+
+use petgraph::graph::NodeIndex;
+use petgraph::visit::EdgeRef;
+use ptable::Element;
+
+use crate::canon::canonical_smiles;
+use crate::graph::{bond_order, MoleculeGraph};
+use crate::Bond;
+
+/// A limit on the number of tautomers that is large enough for most drug-like molecules.
+pub const DEFAULT_MAX_TAUTOMERS: usize = 1000;
+
+/// Enumerate the tautomers of a molecule, up to `max_tautomers` of them.
+///
+/// The input comes first, followed by the other tautomers in the order in which they are found,
+/// each with a different canonical SMILES. The input is always returned, even if
+/// `max_tautomers` is 0.
+pub fn enumerate_tautomers(graph: &MoleculeGraph, max_tautomers: usize) -> Vec<MoleculeGraph> {
+    let mut seen = HashSet::new();
+    seen.insert(canonical_smiles(graph));
+    let mut tautomers = vec![graph.clone()];
+    let mut changed = vec![false; graph.node_count()];
+    let mut next = 0;
+    while next < tautomers.len() && tautomers.len() < max_tautomers {
+        for (tautomer, atoms) in proton_shifts(&tautomers[next]) {
+            if tautomers.len() >= max_tautomers {
+                break;
+            }
+            for atom in atoms {
+                changed[atom.index()] = true;
+            }
+            if seen.insert(canonical_smiles(&tautomer)) {
+                tautomers.push(tautomer);
+            }
+        }
+        next += 1;
+    }
+
+    // Stereo of the changing atoms may have been lost in some of the tautomers and not in
+    // others, which leaves duplicates once it is removed everywhere.
+    let changed: Vec<NodeIndex> = graph
+        .node_indices()
+        .filter(|atom| changed[atom.index()])
+        .collect();
+    seen.clear();
+    tautomers
+        .into_iter()
+        .filter_map(|mut tautomer| {
+            remove_stereo(&mut tautomer, &changed);
+            if seen.insert(canonical_smiles(&tautomer)) {
+                Some(tautomer)
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+/// The tautomer with the highest [`tautomer_score`] among the first `max_tautomers` tautomers of
+/// a molecule, with ties broken by the smallest canonical SMILES.
+///
+/// All tautomers of a molecule have the same canonical tautomer, as long as all of them are
+/// enumerated.
+pub fn canonical_tautomer(graph: &MoleculeGraph, max_tautomers: usize) -> MoleculeGraph {
+    enumerate_tautomers(graph, max_tautomers)
+        .into_iter()
+        .map(|tautomer| {
+            (
+                tautomer_score(&tautomer),
+                canonical_smiles(&tautomer),
+                tautomer,
+            )
+        })
+        .max_by(|a, b| a.0.cmp(&b.0).then_with(|| b.1.cmp(&a.1)))
+        .unwrap()
+        .2
+}
+
+/// A score for how favourable a tautomer is: 250 for every aromatic carbocycle and 100 for
+/// every other aromatic ring, 2 for every C=O, N=O and P=O and another 2 for every C=O of a
+/// carboxylic acid, 1 for every other double bond between carbon and a heteroatom except for
+/// exocyclic imines of aromatic carbons, which count -1, and -4 for every hydrogen on P, S, Se
+/// or Te.
+///
+/// Rings in Kekulé form count as aromatic if they have 4n + 2 π electrons.
+pub fn tautomer_score(graph: &MoleculeGraph) -> i32 {
+    let ring_atoms = graph.ring_atoms();
+    let mut score = 0;
+    for ring in graph.rings() {
+        if is_aromatic_ring(graph, &ring, &ring_atoms) {
+            let carbocycle = ring
+                .iter()
+                .all(|&atom| graph[atom].element() == Some(Element::Carbon));
+            score += if carbocycle { 250 } else { 100 };
+        }
+    }
+    for edge in graph.edge_references() {
+        if *edge.weight() != Bond::Double {
+            continue;
+        }
+        let (source, target) = (&graph[edge.source()], &graph[edge.target()]);
+        if source.is_aromatic() != target.is_aromatic()
+            && source.element() == Some(Element::Carbon)
+            && target.element() == Some(Element::Nitrogen)
+            || source.is_aromatic() != target.is_aromatic()
+                && source.element() == Some(Element::Nitrogen)
+                && target.element() == Some(Element::Carbon)
+        {
+            score -= 1;
+            continue;
+        }
+        let mut elements = [
+            graph[edge.source()].element(),
+            graph[edge.target()].element(),
+        ];
+        elements.sort_by_key(|element| element.map(|element| element.get_symbol()));
+        score += match elements {
+            [Some(Element::Carbon), Some(Element::Oxygen)] => {
+                let carbon = if source.element() == Some(Element::Carbon) {
+                    edge.source()
+                } else {
+                    edge.target()
+                };
+                if is_carboxylic_acid(graph, carbon) {
+                    4
+                } else {
+                    2
+                }
+            }
+            [Some(Element::Nitrogen), Some(Element::Oxygen)]
+            | [Some(Element::Oxygen), Some(Element::Phosphorus)] => 2,
+            [Some(Element::Carbon), Some(Element::Nitrogen)]
+            | [Some(Element::Carbon), Some(Element::Sulfur)] => 1,
+            _ => 0,
+        };
+    }
+    for atom in graph.node_indices() {
+        if matches!(
+            graph[atom].element(),
+            Some(Element::Phosphorus)
+                | Some(Element::Sulfur)
+                | Some(Element::Selenium)
+                | Some(Element::Tellurium)
+        ) {
+            score -= 4 * i32::from(graph.hydrogen_count(atom));
+        }
+    }
+    score
+}
+
+/// Whether a carbon has a single bond to an oxygen with a hydrogen, which makes it the carbon of
+/// a carboxylic acid if it also has a C=O.
+fn is_carboxylic_acid(graph: &MoleculeGraph, carbon: NodeIndex) -> bool {
+    graph.edges(carbon).any(|edge| {
+        let oxygen = graph.other_end(edge.id(), carbon);
+        *edge.weight() == Bond::Single
+            && graph[oxygen].element() == Some(Element::Oxygen)
+            && graph.hydrogen_count(oxygen) > 0
+    })
+}
+
+/// Whether a ring is aromatic: either all its bonds are aromatic, or it has 4n + 2 π electrons,
+/// counting one for every atom with a double bond within the ring system, none for carbons
+/// with an exocyclic double bond to a heteroatom, and two for heteroatoms without a double bond.
+fn is_aromatic_ring(graph: &MoleculeGraph, ring: &[NodeIndex], ring_atoms: &[bool]) -> bool {
+    let all_aromatic = ring.iter().enumerate().all(|(index, &atom)| {
+        let next = ring[(index + 1) % ring.len()];
+        graph
+            .find_edge(atom, next)
+            .is_some_and(|edge| graph[edge] == Bond::Aromatic)
+    });
+    if all_aromatic {
+        return true;
+    }
+    let mut electrons = 0;
+    for &atom in ring {
+        let mut double_bonds = graph
+            .edges(atom)
+            .filter(|edge| *edge.weight() == Bond::Double);
+        let partner = match (double_bonds.next(), double_bonds.next()) {
+            (Some(edge), None) => Some(graph.other_end(edge.id(), atom)),
+            (None, _) => None,
+            (Some(_), Some(_)) => return false,
+        };
+        electrons += match (graph[atom].element(), partner) {
+            (_, Some(partner)) if ring_atoms[partner.index()] => 1,
+            (Some(Element::Carbon), Some(partner)) if is_heteroatom(graph[partner].element()) => 0,
+            (Some(Element::Carbon), None) if graph[atom].charge() == -1 => 2,
+            (element, None) if is_heteroatom(element) && graph[atom].charge() == 0 => 2,
+            _ => return false,
+        };
+    }
+    electrons % 4 == 2
+}
+
+fn is_heteroatom(element: Option<Element>) -> bool {
+    matches!(
+        element,
+        Some(Element::Nitrogen) | Some(Element::Oxygen) | Some(Element::Sulfur)
+    )
+}
+
+/// Elements between which hydrogens move.
+fn is_tautomeric(element: Option<Element>) -> bool {
+    element == Some(Element::Carbon) || is_heteroatom(element)
+}
+
+fn has_multiple_bond(graph: &MoleculeGraph, atom: NodeIndex) -> bool {
+    graph
+        .edges(atom)
+        .any(|edge| *edge.weight() == Bond::Aromatic || bond_order(*edge.weight()) > 1)
+}
+
+fn can_take_part(graph: &MoleculeGraph, atom: NodeIndex) -> bool {
+    graph[atom].charge() == 0 && is_tautomeric(graph[atom].element())
+}
+
+fn set_bond(graph: &mut MoleculeGraph, a: NodeIndex, b: NodeIndex, bond: Bond) {
+    let edge = graph.find_edge(a, b).unwrap();
+    graph[edge] = bond;
+}
+
+/// Move an attached hydrogen from one atom to another.
+fn move_hydrogen(graph: &mut MoleculeGraph, from: NodeIndex, to: NodeIndex) {
+    let hydrogen = graph
+        .neighbors(from)
+        .filter(|&neighbor| graph.is_attached_hydrogen(neighbor))
+        .max()
+        .unwrap();
+    let edge = graph.find_edge(from, hydrogen).unwrap();
+    graph.remove_edge(edge);
+    graph.add_edge(to, hydrogen, Bond::Single);
+}
+
+/// Remove the chirality of atoms and the direction of their bonds.
+fn remove_stereo(graph: &mut MoleculeGraph, atoms: &[NodeIndex]) {
+    for &atom in atoms {
+        let (isotope, charge) = (graph[atom].isotope(), graph[atom].charge());
+        graph.rebuild_atom(atom, isotope, None, charge);
+        let directional: Vec<_> = graph
+            .edges(atom)
+            .filter(|edge| matches!(edge.weight(), Bond::Up | Bond::Down))
+            .map(|edge| edge.id())
+            .collect();
+        for edge in directional {
+            graph[edge] = Bond::Single;
+        }
+    }
+}
+
+/// All tautomers one proton shift away, with the atoms whose bonds or hydrogens change.
+fn proton_shifts(graph: &MoleculeGraph) -> Vec<(MoleculeGraph, Vec<NodeIndex>)> {
+    let mut shifts = Vec::new();
+
+    // H-X-Y=Z to X=Y-Z-H
+    for x in graph.node_indices() {
+        if !can_take_part(graph, x)
+            || graph[x].is_aromatic()
+            || graph.hydrogen_count(x) == 0
+            || has_multiple_bond(graph, x)
+        {
+            continue;
+        }
+        for xy in graph.edges(x) {
+            let y = graph.other_end(xy.id(), x);
+            let y_element = graph[y].element();
+            if !matches!(y_element, Some(Element::Carbon) | Some(Element::Nitrogen))
+                || graph[y].charge() != 0
+                || graph[y].is_aromatic()
+            {
+                continue;
+            }
+            for yz in graph.edges(y) {
+                let z = graph.other_end(yz.id(), y);
+                if z == x
+                    || *yz.weight() != Bond::Double
+                    || !can_take_part(graph, z)
+                    || graph[z].is_aromatic()
+                    || graph[x].element() == Some(Element::Carbon)
+                        && graph[z].element() == Some(Element::Carbon)
+                {
+                    continue;
+                }
+                let mut tautomer = graph.clone();
+                move_hydrogen(&mut tautomer, x, z);
+                set_bond(&mut tautomer, x, y, Bond::Double);
+                set_bond(&mut tautomer, y, z, Bond::Single);
+                let atoms = vec![x, y, z];
+                remove_stereo(&mut tautomer, &atoms);
+                shifts.push((tautomer, atoms));
+            }
+        }
+    }
+
+    shifts.extend(aromatic_shifts(graph));
+    shifts
+}
+
+/// Proton shifts between aromatic nitrogens and exocyclic heteroatoms of the same aromatic
+/// system.
+fn aromatic_shifts(graph: &MoleculeGraph) -> Vec<(MoleculeGraph, Vec<NodeIndex>)> {
+    // Aromatic systems, connected by aromatic bonds.
+    let mut systems = vec![None; graph.node_count()];
+    let mut count = 0;
+    for start in graph.node_indices() {
+        if !graph[start].is_aromatic() || systems[start.index()].is_some() {
+            continue;
+        }
+        systems[start.index()] = Some(count);
+        let mut stack = vec![start];
+        while let Some(atom) = stack.pop() {
+            for edge in graph.edges(atom) {
+                let neighbor = graph.other_end(edge.id(), atom);
+                if *edge.weight() == Bond::Aromatic && systems[neighbor.index()].is_none() {
+                    systems[neighbor.index()] = Some(count);
+                    stack.push(neighbor);
+                }
+            }
+        }
+        count += 1;
+    }
+    if count == 0 {
+        return Vec::new();
+    }
+
+    // Donors and acceptors of a proton with their system and, for exocyclic atoms, the aromatic
+    // carbon they are attached to.
+    let mut donors = Vec::new();
+    let mut acceptors = Vec::new();
+    for atom in graph.node_indices() {
+        if graph[atom].charge() != 0 {
+            continue;
+        }
+        let element = graph[atom].element();
+        let hydrogens = graph.hydrogen_count(atom);
+        if graph[atom].is_aromatic() {
+            if element == Some(Element::Nitrogen) {
+                if hydrogens > 0 {
+                    donors.push((atom, systems[atom.index()], None));
+                } else if free_valence(graph, atom) == Some(1) {
+                    acceptors.push((atom, systems[atom.index()], None));
+                }
+            }
+            continue;
+        }
+        if !is_heteroatom(element) {
+            continue;
+        }
+        for edge in graph.edges(atom) {
+            let anchor = graph.other_end(edge.id(), atom);
+            if !graph[anchor].is_aromatic() || graph[anchor].element() != Some(Element::Carbon) {
+                continue;
+            }
+            let system = systems[anchor.index()];
+            match edge.weight() {
+                Bond::Single if hydrogens > 0 && !has_multiple_bond(graph, atom) => {
+                    donors.push((atom, system, Some(anchor)))
+                }
+                Bond::Double => acceptors.push((atom, system, Some(anchor))),
+                _ => {}
+            }
+        }
+    }
+
+    let mut shifts = Vec::new();
+    for &(donor, donor_system, donor_anchor) in &donors {
+        for &(acceptor, acceptor_system, acceptor_anchor) in &acceptors {
+            if donor == acceptor
+                || donor_system != acceptor_system
+                || donor_anchor.is_some() && acceptor_anchor.is_some()
+            {
+                continue;
+            }
+            let mut tautomer = graph.clone();
+            move_hydrogen(&mut tautomer, donor, acceptor);
+            let mut atoms = vec![donor, acceptor];
+            if let Some(anchor) = donor_anchor {
+                set_bond(&mut tautomer, donor, anchor, Bond::Double);
+                atoms.push(anchor);
+            }
+            if let Some(anchor) = acceptor_anchor {
+                set_bond(&mut tautomer, acceptor, anchor, Bond::Single);
+                atoms.push(anchor);
+            }
+            remove_stereo(&mut tautomer, &atoms);
+            if has_kekule_structure(&tautomer) {
+                shifts.push((tautomer, atoms));
+            }
+        }
+    }
+    shifts
+}
+
+/// Valence of an aromatic atom that is left for a double bond within its aromatic system.
+fn free_valence(graph: &MoleculeGraph, atom: NodeIndex) -> Option<i16> {
+    let element = graph[atom].element()?;
+    let charge = i16::from(graph[atom].charge());
+    let valence = match element {
+        Element::Boron => 3 - charge.abs(),
+        Element::Carbon => 4 - charge.abs(),
+        Element::Nitrogen | Element::Phosphorus | Element::Arsenic => 3 + charge,
+        Element::Oxygen | Element::Sulfur | Element::Selenium | Element::Tellurium => 2 + charge,
+        _ => return None,
+    };
+    Some(valence - i16::from(graph.bond_order_sum(atom)))
+}
+
+/// Whether every aromatic atom that needs a double bond can get exactly one from its aromatic
+/// bonds.
+fn has_kekule_structure(graph: &MoleculeGraph) -> bool {
+    let mut needing = vec![None; graph.node_count()];
+    let mut count = 0;
+    for atom in graph
+        .node_indices()
+        .filter(|&atom| graph[atom].is_aromatic())
+    {
+        match free_valence(graph, atom) {
+            Some(0) => {}
+            Some(1) => {
+                needing[atom.index()] = Some(count);
+                count += 1;
+            }
+            _ => return false,
+        }
+    }
+    let mut adjacency = vec![Vec::new(); count];
+    for edge in graph.edge_references() {
+        if *edge.weight() != Bond::Aromatic {
+            continue;
+        }
+        if let (Some(a), Some(b)) = (
+            needing[edge.source().index()],
+            needing[edge.target().index()],
+        ) {
+            adjacency[a].push(b);
+            adjacency[b].push(a);
+        }
+    }
+    perfect_matching(&adjacency, &mut vec![false; count])
+}
+
+/// Whether the unmatched vertices of a graph have a perfect matching, trying the vertex with the
+/// fewest unmatched neighbors first.
+fn perfect_matching(adjacency: &[Vec<usize>], matched: &mut [bool]) -> bool {
+    let mut best: Option<(usize, usize)> = None;
+    for vertex in (0..adjacency.len()).filter(|&vertex| !matched[vertex]) {
+        let free = adjacency[vertex]
+            .iter()
+            .filter(|&&neighbor| !matched[neighbor])
+            .count();
+        if free == 0 {
+            return false;
+        }
+        if best.is_none_or(|(_, fewest)| free < fewest) {
+            best = Some((vertex, free));
+        }
+    }
+    let vertex = match best {
+        Some((vertex, _)) => vertex,
+        None => return true,
+    };
+    matched[vertex] = true;
+    for &neighbor in &adjacency[vertex] {
+        if !matched[neighbor] {
+            matched[neighbor] = true;
+            if perfect_matching(adjacency, matched) {
+                return true;
+            }
+            matched[neighbor] = false;
+        }
+    }
+    matched[vertex] = false;
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graph::molecule;
+
+    fn canonical(smiles: &str) -> String {
+        canonical_smiles(&molecule(smiles))
+    }
+
+    fn tautomers(smiles: &str) -> Vec<String> {
+        let mut tautomers: Vec<String> =
+            enumerate_tautomers(&molecule(smiles), DEFAULT_MAX_TAUTOMERS)
+                .iter()
+                .map(canonical_smiles)
+                .collect();
+        tautomers.sort();
+        tautomers
+    }
+
+    fn expected(smiles: &[&str]) -> Vec<String> {
+        let mut expected: Vec<String> = smiles.iter().map(|smiles| canonical(smiles)).collect();
+        expected.sort();
+        expected
+    }
+
+    #[test]
+    fn enumeration() {
+        assert_eq!(expected(&["CC(C)=O", "C=C(C)O"]), tautomers("CC(=O)C"));
+        assert_eq!(
+            expected(&["CC(N)=O", "CC(O)=N", "C=C(N)O"]),
+            tautomers("CC(N)=O")
+        );
+        assert_eq!(expected(&["CC=NC", "C=CNC"]), tautomers("CC=NC"));
+        assert_eq!(expected(&["CC=NO", "CCN=O", "C=CNO"]), tautomers("CCN=O"));
+        // No heteroatom, no tautomers.
+        assert_eq!(expected(&["CC=CC"]), tautomers("CC=CC"));
+        assert_eq!(expected(&["C[N+](C)=CC"]), tautomers("C[N+](C)=CC"));
+    }
+
+    #[test]
+    fn aromatic() {
+        assert_eq!(
+            expected(&["Oc1ccccn1", "O=c1cccc[nH]1"]),
+            tautomers("Oc1ccccn1")
+        );
+        assert_eq!(
+            expected(&["Oc1ccncc1", "O=c1cc[nH]cc1"]),
+            tautomers("O=c1cc[nH]cc1")
+        );
+        assert_eq!(
+            expected(&["Nc1ccccn1", "N=c1cccc[nH]1"]),
+            tautomers("Nc1ccccn1")
+        );
+        assert_eq!(
+            expected(&["Cc1c[nH]cn1", "Cc1cnc[nH]1"]),
+            tautomers("Cc1c[nH]cn1")
+        );
+        assert_eq!(expected(&["c1cc[nH]n1"]), tautomers("c1cc[nH]n1"));
+        // 3-hydroxypyridine has no pyridone without charges, and phenol keeps its ring.
+        assert_eq!(expected(&["Oc1cccnc1"]), tautomers("Oc1cccnc1"));
+        assert_eq!(expected(&["Oc1ccccc1"]), tautomers("Oc1ccccc1"));
+        // Purine: 1H, 3H, 7H and 9H.
+        assert_eq!(4, tautomers("c1ncc2[nH]cnc2n1").len());
+    }
+
+    #[test]
+    fn limit() {
+        let graph = molecule("CC(=O)CC(=O)CC(=O)C");
+        assert!(enumerate_tautomers(&graph, DEFAULT_MAX_TAUTOMERS).len() > 10);
+        assert_eq!(5, enumerate_tautomers(&graph, 5).len());
+        assert_eq!(1, enumerate_tautomers(&graph, 0).len());
+    }
+
+    #[test]
+    fn stereo_of_changing_atoms_is_removed() {
+        let all = tautomers("C[C@H](CC)C(C)=O");
+        assert!(all.iter().all(|smiles| !smiles.contains('@')));
+        assert!(all.contains(&canonical("CC(CC)C(C)=O")));
+        let all = tautomers("C[C@@H](O)CC(N)=O");
+        assert_eq!(3, all.len());
+        assert!(all.iter().all(|smiles| smiles.contains('@')));
+    }
+
+    #[test]
+    fn scores() {
+        assert_eq!(2, tautomer_score(&molecule("CC(C)=O")));
+        assert_eq!(0, tautomer_score(&molecule("C=C(C)O")));
+        assert_eq!(250, tautomer_score(&molecule("Oc1ccccc1")));
+        assert_eq!(250, tautomer_score(&molecule("OC1=CC=CC=C1")));
+        assert_eq!(2, tautomer_score(&molecule("O=C1C=CC=CC1")));
+        assert_eq!(102, tautomer_score(&molecule("O=C1C=CC=CN1")));
+        assert_eq!(102, tautomer_score(&molecule("O=c1cccc[nH]1")));
+        assert_eq!(500, tautomer_score(&molecule("c1ccc2ccccc2c1")));
+        assert_eq!(-4, tautomer_score(&molecule("CS")));
+        assert_eq!(99, tautomer_score(&molecule("N=c1cccc[nH]1")));
+    }
+
+    #[test]
+    fn canonical_tautomers() {
+        let groups: &[&[&str]] = &[
+            &["CC(C)=O", "C=C(C)O"],
+            &["CC(N)=O", "CC(O)=N"],
+            &["Oc1ccccn1", "O=c1cccc[nH]1"],
+            &["OC1=NC=CC=C1", "O=C1C=CC=CN1"],
+            &["Cc1c[nH]cn1", "Cc1cnc[nH]1"],
+            &["OC1=CC=CC=C1", "O=C1CC=CC=C1"],
+            &["O=c1[nH]cnc2[nH]cnc12", "Oc1ncnc2nc[nH]c12"],
+            &["Nc1nc2[nH]cnc2c(=O)[nH]1", "N=c1[nH]c(=O)c2[nH]cnc2[nH]1"],
+            &["OCC(=O)O", "OC(O)C=O"],
+            &["OC(C(O)C(=O)O)C(=O)O", "C(C(=O)O)(C(C(O)O)O)=O"],
+            &["OC(CC(=O)O)C(=O)O", "OC(O)C(=O)CC(=O)O"],
+        ];
+        for group in groups {
+            let expected = canonical_smiles(&canonical_tautomer(
+                &molecule(group[0]),
+                DEFAULT_MAX_TAUTOMERS,
+            ));
+            for smiles in group.iter() {
+                let tautomer = canonical_tautomer(&molecule(smiles), DEFAULT_MAX_TAUTOMERS);
+                assert_eq!(expected, canonical_smiles(&tautomer), "{}", smiles);
+            }
+        }
+        let tautomer = |smiles: &str| {
+            canonical_smiles(&canonical_tautomer(
+                &molecule(smiles),
+                DEFAULT_MAX_TAUTOMERS,
+            ))
+        };
+        assert_eq!(canonical("CC(C)=O"), tautomer("C=C(C)O"));
+        assert_eq!(canonical("CC(N)=O"), tautomer("CC(O)=N"));
+        assert_eq!(canonical("O=c1cccc[nH]1"), tautomer("Oc1ccccn1"));
+        assert_eq!(canonical("Nc1ccccn1"), tautomer("N=c1cccc[nH]1"));
+        assert!(!tautomer("N=c1[nH]c(=O)c2[nH]cnc2[nH]1").contains("=N"));
+        assert_eq!(canonical("OC1=CC=CC=C1"), tautomer("O=C1CC=CC=C1"));
+        assert_eq!(canonical("OCC(=O)O"), tautomer("OCC(=O)O"));
+        assert_eq!(
+            canonical("OC(C(O)C(=O)O)C(=O)O"),
+            tautomer("OC(C(O)C(=O)O)C(=O)O")
+        );
+        assert_eq!(
+            canonical("OC(CC(=O)O)C(=O)O"),
+            tautomer("OC(CC(=O)O)C(=O)O")
+        );
+    }
+}
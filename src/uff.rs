@@ -0,0 +1,878 @@
+//! The Universal Force Field (UFF) of Rappé et al., J. Am. Chem. Soc. 114, 10024 (1992).
+//!
+//! Atoms are typed from their element, bonds and aromaticity, and the energy is the sum of bond
+//! stretching, angle bending, torsion, inversion and van der Waals terms in kcal/mol, for
+//! coordinates in ångström. Electrostatics are left out, as in most UFF implementations.
+//!
+//! Only the atom types of common organic elements are supported: H, B, C, N, O, F, Si, P, S,
+//! Cl, Se, Br and I.
+
+use std::f64::consts::PI;
+
+use petgraph::graph::NodeIndex;
+use petgraph::visit::EdgeRef;
+use ptable::Element;
+
+use crate::conformer::Conformer;
+use crate::graph::{bond_order, MoleculeGraph};
+use crate::minimize::minimize;
+use crate::Bond;
+
+/// Parameters of an atom type.
+#[derive(Debug)]
+struct Parameters {
+    label: &'static str,
+    /// Bond radius, in ångström.
+    radius: f64,
+    /// Natural bond angle, in degrees.
+    angle: f64,
+    /// Van der Waals distance, in ångström.
+    vdw_distance: f64,
+    /// Van der Waals well depth, in kcal/mol.
+    vdw_depth: f64,
+    /// Effective charge.
+    charge: f64,
+    /// Torsional barrier for sp3 atoms, in kcal/mol.
+    sp3_torsion: f64,
+    /// Torsional constant for sp2 atoms, in kcal/mol.
+    sp2_torsion: f64,
+    /// GMP electronegativity.
+    electronegativity: f64,
+}
+
+macro_rules! parameters {
+    ($($label:literal $radius:literal $angle:literal $x:literal $d:literal $z:literal $v:literal
+       $u:literal $chi:literal;)*) => {
+        &[$(Parameters {
+            label: $label,
+            radius: $radius,
+            angle: $angle,
+            vdw_distance: $x,
+            vdw_depth: $d,
+            charge: $z,
+            sp3_torsion: $v,
+            sp2_torsion: $u,
+            electronegativity: $chi,
+        }),*]
+    };
+}
+
+#[rustfmt::skip]
+const PARAMETERS: &[Parameters] = parameters! {
+    "H_"    0.354 180.0  2.886 0.044 0.712 0.0   0.0  4.528;
+    "B_3"   0.838 109.47 4.083 0.180 1.755 0.0   1.25 5.110;
+    "B_2"   0.828 120.0  4.083 0.180 1.755 0.0   1.25 5.110;
+    "C_3"   0.757 109.47 3.851 0.105 1.912 2.119 2.0  5.343;
+    "C_R"   0.729 120.0  3.851 0.105 1.912 0.0   2.0  5.343;
+    "C_2"   0.732 120.0  3.851 0.105 1.912 0.0   2.0  5.343;
+    "C_1"   0.706 180.0  3.851 0.105 1.912 0.0   2.0  5.343;
+    "N_3"   0.700 106.7  3.660 0.069 2.544 0.450 2.0  6.899;
+    "N_R"   0.699 120.0  3.660 0.069 2.544 0.0   2.0  6.899;
+    "N_2"   0.685 111.2  3.660 0.069 2.544 0.0   2.0  6.899;
+    "N_1"   0.656 180.0  3.660 0.069 2.544 0.0   2.0  6.899;
+    "O_3"   0.658 104.51 3.500 0.060 2.300 0.018 2.0  8.741;
+    "O_R"   0.680 110.0  3.500 0.060 2.300 0.0   2.0  8.741;
+    "O_2"   0.634 120.0  3.500 0.060 2.300 0.0   2.0  8.741;
+    "O_1"   0.639 180.0  3.500 0.060 2.300 0.0   2.0  8.741;
+    "F_"    0.668 180.0  3.364 0.050 1.735 0.0   2.0  10.874;
+    "Si3"   1.117 109.47 4.295 0.402 2.323 1.225 1.25 4.168;
+    "P_3+3" 1.101 93.8   4.147 0.305 2.863 2.400 1.25 5.463;
+    "P_3+5" 1.056 109.47 4.147 0.305 2.863 2.400 1.25 5.463;
+    "S_3+2" 1.064 92.1   4.035 0.274 2.703 0.484 1.25 6.928;
+    "S_3+4" 1.049 103.2  4.035 0.274 2.703 0.484 1.25 6.928;
+    "S_3+6" 1.027 109.47 4.035 0.274 2.703 0.484 1.25 6.928;
+    "S_R"   1.077 92.2   4.035 0.274 2.703 0.0   1.25 6.928;
+    "S_2"   0.854 120.0  4.035 0.274 2.703 0.0   1.25 6.928;
+    "Cl"    1.044 180.0  3.947 0.227 2.348 0.0   1.25 8.564;
+    "Se3+2" 1.190 90.6   4.205 0.291 2.764 0.335 0.7  6.874;
+    "Br"    1.192 180.0  4.189 0.251 2.519 0.0   0.7  7.790;
+    "I_"    1.382 180.0  4.500 0.339 2.650 0.0   0.2  6.822;
+};
+
+#[derive(Debug, PartialEq, Eq, Copy, Clone, Hash)]
+pub enum UffError {
+    /// There is no UFF atom type for the atom with the given node index.
+    UnsupportedAtom(usize),
+}
+
+impl std::fmt::Display for UffError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            UffError::UnsupportedAtom(atom) => write!(f, "no UFF atom type for atom {}", atom),
+        }
+    }
+}
+
+impl std::error::Error for UffError {}
+
+/// UFF energy of a conformer, by term, in kcal/mol.
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub struct UffEnergy {
+    pub bond: f64,
+    pub angle: f64,
+    pub torsion: f64,
+    pub inversion: f64,
+    pub van_der_waals: f64,
+}
+
+impl UffEnergy {
+    pub fn total(&self) -> f64 {
+        self.bond + self.angle + self.torsion + self.inversion + self.van_der_waals
+    }
+}
+
+fn is_carbonyl_carbon(graph: &MoleculeGraph, atom: NodeIndex) -> bool {
+    graph[atom].element() == Some(Element::Carbon)
+        && graph.edges(atom).any(|edge| {
+            let other = graph.other_end(edge.id(), atom);
+            *edge.weight() == Bond::Double && graph[other].element() == Some(Element::Oxygen)
+        })
+}
+
+/// Whether an atom is the nitrogen of an amide, which is planar.
+fn is_amide_nitrogen(graph: &MoleculeGraph, atom: NodeIndex) -> bool {
+    graph[atom].element() == Some(Element::Nitrogen)
+        && graph
+            .edges(atom)
+            .all(|edge| matches!(edge.weight(), Bond::Single | Bond::Up | Bond::Down))
+        && graph
+            .neighbors(atom)
+            .any(|neighbor| is_carbonyl_carbon(graph, neighbor))
+}
+
+fn type_label(graph: &MoleculeGraph, atom: NodeIndex) -> Option<&'static str> {
+    let (mut doubles, mut triples) = (0, 0);
+    for edge in graph.edges(atom) {
+        match edge.weight() {
+            Bond::Double => doubles += 1,
+            Bond::Triple => triples += 1,
+            _ => {}
+        }
+    }
+    let aromatic = graph[atom].is_aromatic();
+    let degree = graph.neighbors(atom).count();
+    let valence = graph.bond_order_sum(atom);
+
+    let label = match graph[atom].element()? {
+        Element::Hydrogen => "H_",
+        Element::Boron if degree >= 4 => "B_3",
+        Element::Boron => "B_2",
+        Element::Carbon if aromatic => "C_R",
+        Element::Carbon if triples > 0 || doubles > 1 => "C_1",
+        Element::Carbon if doubles > 0 => "C_2",
+        Element::Carbon => "C_3",
+        Element::Nitrogen if aromatic || is_amide_nitrogen(graph, atom) => "N_R",
+        Element::Nitrogen if triples > 0 => "N_1",
+        Element::Nitrogen if doubles > 0 && degree <= 2 => "N_2",
+        Element::Nitrogen if doubles > 0 => "N_R",
+        Element::Nitrogen => "N_3",
+        Element::Oxygen if aromatic => "O_R",
+        Element::Oxygen if triples > 0 => "O_1",
+        Element::Oxygen if doubles > 0 => "O_2",
+        Element::Oxygen => "O_3",
+        Element::Fluorine => "F_",
+        Element::Silicon => "Si3",
+        Element::Phosphorus if valence > 3 => "P_3+5",
+        Element::Phosphorus => "P_3+3",
+        Element::Sulfur if aromatic => "S_R",
+        Element::Sulfur if degree == 1 && doubles == 1 => "S_2",
+        Element::Sulfur if valence > 4 => "S_3+6",
+        Element::Sulfur if valence > 2 => "S_3+4",
+        Element::Sulfur => "S_3+2",
+        Element::Chlorine => "Cl",
+        Element::Selenium => "Se3+2",
+        Element::Bromine => "Br",
+        Element::Iodine => "I_",
+        _ => return None,
+    };
+    Some(label)
+}
+
+fn parameters(label: &str) -> &'static Parameters {
+    PARAMETERS
+        .iter()
+        .find(|parameters| parameters.label == label)
+        .unwrap()
+}
+
+/// Hybridization from the third character of the label: `1`, `2`, `3` or `R` (resonant).
+fn hybridization(parameters: &Parameters) -> Option<u8> {
+    match parameters.label.as_bytes().get(2) {
+        Some(b'1') => Some(1),
+        Some(b'2') | Some(b'R') => Some(2),
+        Some(b'3') => Some(3),
+        _ if parameters.label == "Si3" => Some(3),
+        _ => None,
+    }
+}
+
+fn is_group_16(element: Option<Element>) -> bool {
+    matches!(
+        element,
+        Some(Element::Oxygen) | Some(Element::Sulfur) | Some(Element::Selenium)
+    )
+}
+
+/// Natural bond length of a bond between two atom types with the given bond order.
+fn rest_length(first: &Parameters, second: &Parameters, order: f64) -> f64 {
+    let (ri, rj) = (first.radius, second.radius);
+    let bond_order_correction = -0.1332 * (ri + rj) * order.ln();
+    let (chi_i, chi_j) = (first.electronegativity, second.electronegativity);
+    let electronegativity_correction =
+        ri * rj * (chi_i.sqrt() - chi_j.sqrt()).powi(2) / (chi_i * ri + chi_j * rj);
+    ri + rj + bond_order_correction - electronegativity_correction
+}
+
+#[derive(Debug, Copy, Clone)]
+enum AngleShape {
+    /// Fourier expansion around the natural angle.
+    General {
+        c0: f64,
+        c1: f64,
+        c2: f64,
+    },
+    Linear,
+    Trigonal,
+    Square,
+}
+
+struct BondTerm {
+    atoms: [usize; 2],
+    force_constant: f64,
+    rest_length: f64,
+}
+
+struct AngleTerm {
+    /// The central atom is in the middle.
+    atoms: [usize; 3],
+    force_constant: f64,
+    shape: AngleShape,
+}
+
+struct TorsionTerm {
+    atoms: [usize; 4],
+    barrier: f64,
+    periodicity: u8,
+    /// Cosine of the periodicity times the natural torsion, either 1 or -1.
+    phase: f64,
+}
+
+struct InversionTerm {
+    /// The central atom, two neighbors spanning the plane and the neighbor out of the plane.
+    atoms: [usize; 4],
+    force_constant: f64,
+}
+
+struct VdwTerm {
+    atoms: [usize; 2],
+    distance: f64,
+    depth: f64,
+}
+
+/// The UFF terms of a molecule.
+pub struct Uff {
+    labels: Vec<&'static str>,
+    bonds: Vec<BondTerm>,
+    angles: Vec<AngleTerm>,
+    torsions: Vec<TorsionTerm>,
+    inversions: Vec<InversionTerm>,
+    vdw: Vec<VdwTerm>,
+}
+
+type Vector = [f64; 3];
+
+fn sub(a: Vector, b: Vector) -> Vector {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+fn scale(a: Vector, factor: f64) -> Vector {
+    [a[0] * factor, a[1] * factor, a[2] * factor]
+}
+
+fn dot(a: Vector, b: Vector) -> f64 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+fn cross(a: Vector, b: Vector) -> Vector {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
+
+fn norm(a: Vector) -> f64 {
+    dot(a, a).sqrt()
+}
+
+fn point(x: &[f64], atom: usize) -> Vector {
+    [x[3 * atom], x[3 * atom + 1], x[3 * atom + 2]]
+}
+
+fn add_gradient(gradient: &mut [f64], atom: usize, partial: Vector) {
+    for k in 0..3 {
+        gradient[3 * atom + k] += partial[k];
+    }
+}
+
+/// Gradient of the cosine of the angle between `u` and `v` with respect to `u`.
+fn cosine_gradient(u: Vector, v: Vector, cosine: f64) -> Vector {
+    let (lu, lv) = (norm(u), norm(v));
+    sub(scale(v, 1.0 / (lu * lv)), scale(u, cosine / (lu * lu)))
+}
+
+impl Uff {
+    /// Type the atoms of a graph and set up the force field terms.
+    ///
+    /// All atoms need explicit hydrogens, like the graphs built by [`MoleculeGraph::from_chain`].
+    pub fn new(graph: &MoleculeGraph) -> Result<Self, UffError> {
+        let labels = graph
+            .node_indices()
+            .map(|atom| type_label(graph, atom).ok_or(UffError::UnsupportedAtom(atom.index())))
+            .collect::<Result<Vec<_>, _>>()?;
+        let types: Vec<&Parameters> = labels.iter().map(|label| parameters(label)).collect();
+        let neighbors: Vec<Vec<NodeIndex>> = graph
+            .node_indices()
+            .map(|atom| {
+                let mut neighbors: Vec<NodeIndex> = graph.neighbors(atom).collect();
+                neighbors.sort_unstable();
+                neighbors
+            })
+            .collect();
+        let order = |a: NodeIndex, b: NodeIndex| -> f64 {
+            let bond = graph[graph.find_edge(a, b).unwrap()];
+            if bond == Bond::Aromatic {
+                1.5
+            } else if bond_order(bond) == 1
+                && ((is_amide_nitrogen(graph, a) && is_carbonyl_carbon(graph, b))
+                    || (is_amide_nitrogen(graph, b) && is_carbonyl_carbon(graph, a)))
+            {
+                1.41
+            } else {
+                f64::from(bond_order(bond))
+            }
+        };
+        let length = |a: NodeIndex, b: NodeIndex| {
+            rest_length(types[a.index()], types[b.index()], order(a, b))
+        };
+
+        let bonds = graph
+            .edge_references()
+            .map(|edge| {
+                let (a, b) = (edge.source(), edge.target());
+                let rest_length = length(a, b);
+                BondTerm {
+                    atoms: [a.index(), b.index()],
+                    force_constant: 664.12 * types[a.index()].charge * types[b.index()].charge
+                        / rest_length.powi(3),
+                    rest_length,
+                }
+            })
+            .collect();
+
+        let mut angles = Vec::new();
+        for center in graph.node_indices() {
+            let around = &neighbors[center.index()];
+            let natural = types[center.index()].angle.to_radians();
+            let shape = if (natural - PI).abs() < 1e-6 {
+                AngleShape::Linear
+            } else if (natural - 2.0 * PI / 3.0).abs() < 1e-6 {
+                AngleShape::Trigonal
+            } else if (natural - PI / 2.0).abs() < 1e-6 {
+                AngleShape::Square
+            } else {
+                let c2 = 1.0 / (4.0 * natural.sin().powi(2));
+                AngleShape::General {
+                    c0: c2 * (2.0 * natural.cos().powi(2) + 1.0),
+                    c1: -4.0 * c2 * natural.cos(),
+                    c2,
+                }
+            };
+            for (index, &a) in around.iter().enumerate() {
+                for &c in &around[index + 1..] {
+                    let (rab, rcb) = (length(a, center), length(c, center));
+                    let cosine = natural.cos();
+                    let rac2 = rab * rab + rcb * rcb - 2.0 * rab * rcb * cosine;
+                    let force_constant =
+                        664.12 / (rab * rcb) * types[a.index()].charge * types[c.index()].charge
+                            / rac2.powf(2.5)
+                            * (3.0 * rab * rcb * (1.0 - cosine * cosine) - rac2 * cosine);
+                    angles.push(AngleTerm {
+                        atoms: [a.index(), center.index(), c.index()],
+                        force_constant,
+                        shape,
+                    });
+                }
+            }
+        }
+
+        let mut torsions = Vec::new();
+        for edge in graph.edge_references() {
+            let (j, k) = (edge.source(), edge.target());
+            let (type_j, type_k) = (types[j.index()], types[k.index()]);
+            let (hybrid_j, hybrid_k) = match (hybridization(type_j), hybridization(type_k)) {
+                (Some(j), Some(k)) if j > 1 && k > 1 => (j, k),
+                _ => continue,
+            };
+            let (degree_j, degree_k) = (neighbors[j.index()].len(), neighbors[k.index()].len());
+            if degree_j < 2 || degree_k < 2 {
+                continue;
+            }
+            let count = ((degree_j - 1) * (degree_k - 1)) as f64;
+            let (element_j, element_k) = (graph[j].element(), graph[k].element());
+            let sp2_barrier = 5.0
+                * (type_j.sp2_torsion * type_k.sp2_torsion).sqrt()
+                * (1.0 + 4.18 * order(j, k).ln());
+
+            for &i in neighbors[j.index()].iter().filter(|&&i| i != k) {
+                for &l in neighbors[k.index()].iter().filter(|&&l| l != j && l != i) {
+                    let (barrier, periodicity, phase) = match (hybrid_j, hybrid_k) {
+                        (3, 3) if is_group_16(element_j) && is_group_16(element_k) => {
+                            let v = |element| -> f64 {
+                                if element == Some(Element::Oxygen) {
+                                    2.0
+                                } else {
+                                    6.8
+                                }
+                            };
+                            ((v(element_j) * v(element_k)).sqrt(), 2, -1.0)
+                        }
+                        (3, 3) => ((type_j.sp3_torsion * type_k.sp3_torsion).sqrt(), 3, -1.0),
+                        (2, 2) => (sp2_barrier, 2, 1.0),
+                        _ => {
+                            // One sp3 and one sp2 atom.
+                            let (sp3, sp2, sp2_neighbor) = if hybrid_j == 3 {
+                                (element_j, element_k, l)
+                            } else {
+                                (element_k, element_j, i)
+                            };
+                            if is_group_16(sp3) && !is_group_16(sp2) {
+                                (sp2_barrier, 2, -1.0)
+                            } else if hybridization(types[sp2_neighbor.index()]) == Some(2) {
+                                (2.0, 3, -1.0)
+                            } else {
+                                (1.0, 6, 1.0)
+                            }
+                        }
+                    };
+                    torsions.push(TorsionTerm {
+                        atoms: [i.index(), j.index(), k.index(), l.index()],
+                        barrier: barrier / count,
+                        periodicity,
+                        phase,
+                    });
+                }
+            }
+        }
+
+        let mut inversions = Vec::new();
+        for center in graph.node_indices() {
+            let around = &neighbors[center.index()];
+            let planar_type = matches!(labels[center.index()], "C_2" | "C_R" | "N_2" | "N_R");
+            if !planar_type || around.len() != 3 {
+                continue;
+            }
+            let force_constant = if labels[center.index()].starts_with('C')
+                && around
+                    .iter()
+                    .any(|&neighbor| labels[neighbor.index()] == "O_2")
+            {
+                50.0
+            } else {
+                6.0
+            };
+            for out in 0..3 {
+                let [a, b] = match out {
+                    0 => [around[1], around[2]],
+                    1 => [around[0], around[2]],
+                    _ => [around[0], around[1]],
+                };
+                inversions.push(InversionTerm {
+                    atoms: [center.index(), a.index(), b.index(), around[out].index()],
+                    force_constant: force_constant / 3.0,
+                });
+            }
+        }
+
+        // Van der Waals interactions between atoms that are neither bonded nor share a neighbor.
+        let mut vdw = Vec::new();
+        for a in graph.node_indices() {
+            for b in graph.node_indices().filter(|&b| b > a) {
+                let close = neighbors[a.index()].contains(&b)
+                    || neighbors[a.index()]
+                        .iter()
+                        .any(|neighbor| neighbors[neighbor.index()].contains(&b));
+                if close {
+                    continue;
+                }
+                let (type_a, type_b) = (types[a.index()], types[b.index()]);
+                vdw.push(VdwTerm {
+                    atoms: [a.index(), b.index()],
+                    distance: (type_a.vdw_distance * type_b.vdw_distance).sqrt(),
+                    depth: (type_a.vdw_depth * type_b.vdw_depth).sqrt(),
+                });
+            }
+        }
+
+        Ok(Uff {
+            labels,
+            bonds,
+            angles,
+            torsions,
+            inversions,
+            vdw,
+        })
+    }
+
+    /// UFF atom type label of an atom, like `C_3` or `N_R`.
+    pub fn atom_type(&self, atom: NodeIndex) -> &'static str {
+        self.labels[atom.index()]
+    }
+
+    /// Energy of the terms for flat coordinates, adding the gradient to `gradient`.
+    fn evaluate(&self, x: &[f64], gradient: &mut [f64]) -> UffEnergy {
+        let mut energy = UffEnergy::default();
+
+        for term in &self.bonds {
+            let [a, b] = term.atoms;
+            let delta = sub(point(x, a), point(x, b));
+            let distance = norm(delta);
+            let stretch = distance - term.rest_length;
+            energy.bond += 0.5 * term.force_constant * stretch * stretch;
+            if distance > 1e-12 {
+                let partial = scale(delta, term.force_constant * stretch / distance);
+                add_gradient(gradient, a, partial);
+                add_gradient(gradient, b, scale(partial, -1.0));
+            }
+        }
+
+        for term in &self.angles {
+            let [a, center, c] = term.atoms;
+            let (u, v) = (
+                sub(point(x, a), point(x, center)),
+                sub(point(x, c), point(x, center)),
+            );
+            if norm(u) < 1e-12 || norm(v) < 1e-12 {
+                continue;
+            }
+            let cosine = (dot(u, v) / (norm(u) * norm(v))).clamp(-1.0, 1.0);
+            let k = term.force_constant;
+            // Energy and its derivative by the cosine of the angle.
+            let (value, derivative) = match term.shape {
+                AngleShape::General { c0, c1, c2 } => (
+                    k * (c0 + c1 * cosine + c2 * (2.0 * cosine * cosine - 1.0)),
+                    k * (c1 + 4.0 * c2 * cosine),
+                ),
+                AngleShape::Linear => (k * (1.0 + cosine), k),
+                // (1 - cos 3θ) / 9
+                AngleShape::Trigonal => (
+                    k / 9.0 * (1.0 - 4.0 * cosine.powi(3) + 3.0 * cosine),
+                    k / 9.0 * (3.0 - 12.0 * cosine * cosine),
+                ),
+                // (1 - cos 4θ) / 16
+                AngleShape::Square => (
+                    k / 16.0 * (8.0 * cosine * cosine - 8.0 * cosine.powi(4)),
+                    k / 16.0 * (16.0 * cosine - 32.0 * cosine.powi(3)),
+                ),
+            };
+            energy.angle += value;
+            let partial_a = scale(cosine_gradient(u, v, cosine), derivative);
+            let partial_c = scale(cosine_gradient(v, u, cosine), derivative);
+            add_gradient(gradient, a, partial_a);
+            add_gradient(gradient, c, partial_c);
+            add_gradient(gradient, center, scale(partial_a, -1.0));
+            add_gradient(gradient, center, scale(partial_c, -1.0));
+        }
+
+        for term in &self.torsions {
+            let [i, j, k, l] = term.atoms;
+            let (b1, b2, b3) = (
+                sub(point(x, j), point(x, i)),
+                sub(point(x, k), point(x, j)),
+                sub(point(x, l), point(x, k)),
+            );
+            let (n1, n2) = (cross(b1, b2), cross(b2, b3));
+            if norm(n1) < 1e-12 || norm(n2) < 1e-12 {
+                continue;
+            }
+            let cosine = (dot(n1, n2) / (norm(n1) * norm(n2))).clamp(-1.0, 1.0);
+            // Chebyshev polynomials for cos(nφ) and their derivatives.
+            let (value, derivative) = match term.periodicity {
+                2 => (2.0 * cosine * cosine - 1.0, 4.0 * cosine),
+                3 => (
+                    4.0 * cosine.powi(3) - 3.0 * cosine,
+                    12.0 * cosine * cosine - 3.0,
+                ),
+                _ => (
+                    32.0 * cosine.powi(6) - 48.0 * cosine.powi(4) + 18.0 * cosine * cosine - 1.0,
+                    192.0 * cosine.powi(5) - 192.0 * cosine.powi(3) + 36.0 * cosine,
+                ),
+            };
+            energy.torsion += 0.5 * term.barrier * (1.0 - term.phase * value);
+            let factor = -0.5 * term.barrier * term.phase * derivative;
+            let (g1, g2) = (
+                scale(cosine_gradient(n1, n2, cosine), factor),
+                scale(cosine_gradient(n2, n1, cosine), factor),
+            );
+            let (db1, db3) = (cross(b2, g1), cross(g2, b2));
+            let db2 = [
+                cross(g1, b1)[0] + cross(b3, g2)[0],
+                cross(g1, b1)[1] + cross(b3, g2)[1],
+                cross(g1, b1)[2] + cross(b3, g2)[2],
+            ];
+            add_gradient(gradient, i, scale(db1, -1.0));
+            add_gradient(gradient, j, sub(db1, db2));
+            add_gradient(gradient, k, sub(db2, db3));
+            add_gradient(gradient, l, db3);
+        }
+
+        for term in &self.inversions {
+            let [center, a, b, out] = term.atoms;
+            let origin = point(x, center);
+            let (u, v, w) = (
+                sub(point(x, a), origin),
+                sub(point(x, b), origin),
+                sub(point(x, out), origin),
+            );
+            let normal = cross(u, v);
+            let (normal_length, w_length) = (norm(normal), norm(w));
+            if normal_length < 1e-12 || w_length < 1e-12 {
+                continue;
+            }
+            // The angle between the normal of the plane and the bond to the atom out of it.
+            let cosine = (dot(normal, w) / (normal_length * w_length)).clamp(-1.0, 1.0);
+            let sine = (1.0 - cosine * cosine).sqrt().max(1e-8);
+            // With C0 = 1, C1 = -1 and C2 = 0 for carbon and nitrogen.
+            energy.inversion += term.force_constant * (1.0 - sine);
+            let derivative = term.force_constant * cosine / sine;
+            let g_normal = scale(cosine_gradient(normal, w, cosine), derivative);
+            let g_w = scale(cosine_gradient(w, normal, cosine), derivative);
+            let (g_u, g_v) = (cross(v, g_normal), cross(g_normal, u));
+            add_gradient(gradient, a, g_u);
+            add_gradient(gradient, b, g_v);
+            add_gradient(gradient, out, g_w);
+            let total = [
+                g_u[0] + g_v[0] + g_w[0],
+                g_u[1] + g_v[1] + g_w[1],
+                g_u[2] + g_v[2] + g_w[2],
+            ];
+            add_gradient(gradient, center, scale(total, -1.0));
+        }
+
+        for term in &self.vdw {
+            let [a, b] = term.atoms;
+            let delta = sub(point(x, a), point(x, b));
+            let distance = norm(delta).max(1e-6);
+            let ratio6 = (term.distance / distance).powi(6);
+            energy.van_der_waals += term.depth * (ratio6 * ratio6 - 2.0 * ratio6);
+            let derivative = 12.0 * term.depth * (ratio6 - ratio6 * ratio6) / distance;
+            let partial = scale(delta, derivative / distance);
+            add_gradient(gradient, a, partial);
+            add_gradient(gradient, b, scale(partial, -1.0));
+        }
+        energy
+    }
+
+    /// Energy of a conformer of the molecule.
+    pub fn energy(&self, conformer: &Conformer) -> UffEnergy {
+        let x: Vec<f64> = conformer.positions().iter().flatten().copied().collect();
+        self.evaluate(&x, &mut vec![0.0; x.len()])
+    }
+
+    /// Gradient of the total energy by the coordinates of every atom, in kcal/mol/Å.
+    pub fn gradient(&self, conformer: &Conformer) -> Vec<[f64; 3]> {
+        let x: Vec<f64> = conformer.positions().iter().flatten().copied().collect();
+        let mut gradient = vec![0.0; x.len()];
+        self.evaluate(&x, &mut gradient);
+        gradient
+            .chunks(3)
+            .map(|chunk| [chunk[0], chunk[1], chunk[2]])
+            .collect()
+    }
+
+    /// Minimize the energy of a conformer with the L-BFGS method, for at most `max_iterations`
+    /// steps, and return the final energy.
+    pub fn minimize(&self, conformer: &mut Conformer, max_iterations: usize) -> UffEnergy {
+        let mut x: Vec<f64> = conformer.positions().iter().flatten().copied().collect();
+        minimize(&mut x, max_iterations, 1e-4, 0.3, |x, gradient| {
+            gradient.iter_mut().for_each(|g| *g = 0.0);
+            self.evaluate(x, gradient).total()
+        });
+        *conformer = Conformer::new(
+            x.chunks(3)
+                .map(|chunk| [chunk[0], chunk[1], chunk[2]])
+                .collect(),
+        );
+        self.energy(conformer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::conformer::embed;
+    use crate::graph::{assert_close, molecule};
+
+    fn distance(conformer: &Conformer, a: usize, b: usize) -> f64 {
+        norm(sub(conformer.positions()[a], conformer.positions()[b]))
+    }
+
+    #[test]
+    fn atom_types() {
+        let graph = molecule("CC(=O)Nc1ccccc1C#N");
+        let uff = Uff::new(&graph).unwrap();
+        let labels: Vec<&str> = graph
+            .node_indices()
+            .take(11)
+            .map(|atom| uff.atom_type(atom))
+            .collect();
+        assert_eq!(
+            vec!["C_3", "C_2", "O_2", "N_R", "C_R", "C_R", "C_R", "C_R", "C_R", "C_R", "C_1"],
+            labels
+        );
+        assert_eq!("H_", uff.atom_type(NodeIndex::new(12)));
+        let graph = molecule("CS(=O)(=O)O.OP(=O)(O)O.[Na+]");
+        let error = Uff::new(&graph).err().unwrap();
+        assert_eq!(UffError::UnsupportedAtom(10), error);
+        let uff = Uff::new(&molecule("CS(=O)(=O)O.OP(=O)(O)O")).unwrap();
+        assert_eq!("S_3+6", uff.atom_type(NodeIndex::new(1)));
+        assert_eq!("P_3+5", uff.atom_type(NodeIndex::new(6)));
+    }
+
+    #[test]
+    fn reference_bond_parameters() {
+        // Published UFF values: C_3-C_3 1.514 Å and 699.5918 kcal/mol/Å², C_2=C_2 1.32883 Å
+        // and 1034.69 kcal/mol/Å².
+        let uff = Uff::new(&molecule("CC")).unwrap();
+        let bond = uff.bonds.iter().find(|bond| bond.atoms == [0, 1]).unwrap();
+        assert_close(1.514, bond.rest_length, 1e-4);
+        assert_close(699.5918, bond.force_constant, 1e-2);
+        // C-H is 1.109 Å.
+        let bond = uff.bonds.iter().find(|bond| bond.atoms == [0, 2]).unwrap();
+        assert_close(1.109, bond.rest_length, 1e-3);
+        let uff = Uff::new(&molecule("C=C")).unwrap();
+        let bond = uff.bonds.iter().find(|bond| bond.atoms == [0, 1]).unwrap();
+        assert_close(1.32883, bond.rest_length, 1e-4);
+        assert_close(1034.69, bond.force_constant, 1e-2);
+    }
+
+    /// Positions of ethane with the given H-C-C-H torsion between the hydrogens of both ends.
+    fn ethane(torsion: f64) -> Conformer {
+        let (cc, ch) = (1.514, 1.109);
+        let angle = 109.47f64.to_radians();
+        let mut positions = vec![[0.0, 0.0, 0.0], [cc, 0.0, 0.0]];
+        // Hydrogens of the first carbon, then of the second.
+        for (carbon, offset, direction) in [(0.0, 0.0, -1.0), (cc, torsion, 1.0)] {
+            for index in 0..3 {
+                let phi = offset + 2.0 * PI * index as f64 / 3.0;
+                positions.push([
+                    carbon - direction * ch * angle.cos(),
+                    ch * angle.sin() * phi.cos(),
+                    ch * angle.sin() * phi.sin(),
+                ]);
+            }
+        }
+        Conformer::new(positions)
+    }
+
+    #[test]
+    fn ethane_torsion_barrier() {
+        let uff = Uff::new(&molecule("CC")).unwrap();
+        let staggered = uff.energy(&ethane(PI / 3.0));
+        let eclipsed = uff.energy(&ethane(0.0));
+        // The barrier of the C_3-C_3 bond is V = 2.119 kcal/mol, spread over nine torsions.
+        assert_close(0.0, staggered.torsion, 1e-9);
+        assert_close(2.119, eclipsed.torsion, 1e-9);
+        assert_close(0.0, eclipsed.inversion, 1e-9);
+    }
+
+    #[test]
+    fn van_der_waals_minimum() {
+        // Two methanes: the C-C interaction has its minimum of -D = -0.105 kcal/mol at
+        // x = 3.851 Å.
+        let uff = Uff::new(&molecule("C.C")).unwrap();
+        let term = uff.vdw.iter().find(|term| term.atoms == [0, 1]).unwrap();
+        assert_close(3.851, term.distance, 1e-9);
+        assert_close(0.105, term.depth, 1e-9);
+        let mut positions = vec![[0.0; 3]; 10];
+        positions[1] = [3.851, 0.0, 0.0];
+        // Hydrogens far away.
+        for (index, position) in positions.iter_mut().enumerate().skip(2) {
+            *position = [0.0, 100.0 * index as f64, 0.0];
+        }
+        let single = Uff {
+            labels: uff.labels.clone(),
+            bonds: Vec::new(),
+            angles: Vec::new(),
+            torsions: Vec::new(),
+            inversions: Vec::new(),
+            vdw: uff
+                .vdw
+                .into_iter()
+                .filter(|term| term.atoms == [0, 1])
+                .collect(),
+        };
+        assert_close(
+            -0.105,
+            single.energy(&Conformer::new(positions)).van_der_waals,
+            1e-9,
+        );
+    }
+
+    #[test]
+    fn gradient_matches_energy() {
+        for smiles in &["CC(=O)Nc1ccccc1", "OCC=CC#N", "CSC(F)(Cl)Br"] {
+            let graph = molecule(smiles);
+            let uff = Uff::new(&graph).unwrap();
+            let conformer = embed(&graph, 3).unwrap();
+            let gradient = uff.gradient(&conformer);
+            let step = 1e-6;
+            for atom in 0..graph.node_count() {
+                for k in 0..3 {
+                    let mut positions = conformer.positions().to_vec();
+                    positions[atom][k] += step;
+                    let plus = uff.energy(&Conformer::new(positions.clone())).total();
+                    positions[atom][k] -= 2.0 * step;
+                    let minus = uff.energy(&Conformer::new(positions)).total();
+                    let numeric = (plus - minus) / (2.0 * step);
+                    assert!(
+                        (numeric - gradient[atom][k]).abs() < 1e-3 * numeric.abs().max(1.0),
+                        "{}: atom {} axis {}: {} != {}",
+                        smiles,
+                        atom,
+                        k,
+                        numeric,
+                        gradient[atom][k]
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn minimization() {
+        let graph = molecule("CC");
+        let uff = Uff::new(&graph).unwrap();
+        let mut conformer = embed(&graph, 1).unwrap();
+        let before = uff.energy(&conformer).total();
+        let after = uff.minimize(&mut conformer, 500).total();
+        assert!(after < before);
+        assert_close(1.514, distance(&conformer, 0, 1), 0.02);
+        assert_close(1.109, distance(&conformer, 0, 2), 0.01);
+
+        let graph = molecule("c1ccccc1");
+        let uff = Uff::new(&graph).unwrap();
+        let mut conformer = embed(&graph, 1).unwrap();
+        let energy = uff.minimize(&mut conformer, 500);
+        // Slightly longer than the natural 1.379 Å, from the repulsion between para carbons.
+        for atom in 0..6 {
+            assert_close(1.399, distance(&conformer, atom, (atom + 1) % 6), 0.005);
+        }
+        assert!(energy.inversion < 1e-3);
+        assert!(uff
+            .gradient(&conformer)
+            .iter()
+            .flatten()
+            .all(|g| g.abs() < 1e-2));
+    }
+}
@@ -0,0 +1,303 @@
+//! Chemical sanity checks of molecules that parse, but can't exist as written.
+//!
+//! [`validate`] flags:
+//!
+//! - Atoms with more bonds than their element allows, like the carbon of `C(C)(C)(C)(C)C`. The
+//!   largest valence of a main group element is taken from its number of valence electrons
+//!   corrected for its charge: up to four electrons allow as many bonds, more electrons allow
+//!   as many bonds as are missing to an octet, and elements from the third period on may also
+//!   use all of their electrons, as in `P(=O)(O)(O)O` or `S(=O)(=O)(O)O`.
+//! - Charges an element can't carry, like that of `[O+5]`: more positive charge than valence
+//!   electrons, more negative charge than fits in an octet, or more than four charges on a main
+//!   group element.
+//! - Aromatic atoms that are not part of a ring.
+//! - Bracket atoms with more hydrogens than their valence allows, like `[CH5]`.
+//!
+//! Aromatic bonds count as single bonds. Transition metals, lanthanides and actinides are only
+//! checked for aromaticity outside of rings, and wildcard atoms are not checked at all.
+
+use petgraph::graph::NodeIndex;
+use ptable::Element;
+
+use crate::graph::{Atom, MoleculeGraph};
+use crate::{parse, ParseError, Span};
+
+/// A problem found by [`validate`].
+#[derive(Debug, PartialEq, Eq, Copy, Clone, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum WarningKind {
+    /// The atom has a higher valence than its element allows with its charge.
+    Hypervalent { valence: u8, max_valence: u8 },
+    /// The element can't carry the charge of the atom.
+    ImpossibleCharge { charge: i8 },
+    /// The atom is aromatic, but not part of a ring.
+    AromaticOutsideRing,
+    /// The hydrogen count of a bracket atom exceeds what its valence allows next to its other
+    /// bonds.
+    TooManyHydrogens { hydrogens: u8, max_hydrogens: u8 },
+}
+
+/// A problem with an atom of a molecule.
+#[derive(Debug, PartialEq, Eq, Copy, Clone, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Warning {
+    pub kind: WarningKind,
+    /// Node index of the atom.
+    pub atom: usize,
+    /// Where the atom was written in the SMILES string, if the molecule was parsed from one.
+    pub span: Option<Span>,
+}
+
+impl std::fmt::Display for Warning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.kind {
+            WarningKind::Hypervalent {
+                valence,
+                max_valence,
+            } => write!(
+                f,
+                "atom {} has valence {}, at most {} is allowed",
+                self.atom, valence, max_valence
+            )?,
+            WarningKind::ImpossibleCharge { charge } => {
+                write!(f, "atom {} can't have charge {:+}", self.atom, charge)?
+            }
+            WarningKind::AromaticOutsideRing => {
+                write!(f, "atom {} is aromatic but not in a ring", self.atom)?
+            }
+            WarningKind::TooManyHydrogens {
+                hydrogens,
+                max_hydrogens,
+            } => write!(
+                f,
+                "atom {} has {} hydrogens, at most {} are allowed",
+                self.atom, hydrogens, max_hydrogens
+            )?,
+        }
+        if let Some(span) = self.span {
+            write!(f, " at position {}", span.start)?;
+        }
+        Ok(())
+    }
+}
+
+/// Check all atoms of a molecule, returning the warnings ordered by atom. Every atom gets at
+/// most one warning about its charge, hydrogens or valence.
+pub fn validate(graph: &MoleculeGraph) -> Vec<Warning> {
+    let ring_atoms = graph.ring_atoms();
+    let mut warnings = Vec::new();
+    for atom in graph.node_indices() {
+        let mut warn = |kind| {
+            warnings.push(Warning {
+                kind,
+                atom: atom.index(),
+                span: graph.span(atom),
+            })
+        };
+        if graph[atom].is_aromatic() && !ring_atoms[atom.index()] {
+            warn(WarningKind::AromaticOutsideRing);
+        }
+        if let Some(kind) = valence_warning(graph, atom) {
+            warn(kind);
+        }
+    }
+    warnings
+}
+
+/// Parse a SMILES string and check the molecule.
+pub fn validate_smiles(input: &[u8]) -> Result<Vec<Warning>, ParseError> {
+    Ok(validate(&MoleculeGraph::from_chain(parse(input)?)))
+}
+
+fn valence_warning(graph: &MoleculeGraph, atom: NodeIndex) -> Option<WarningKind> {
+    let element = graph[atom].element()?;
+    let charge = graph[atom].charge();
+    let (electrons, period) = valence_electrons(element)?;
+    let electrons = electrons as i8 - charge;
+    if !(0..=8).contains(&electrons) || charge.abs() > 4 {
+        return Some(WarningKind::ImpossibleCharge { charge });
+    }
+    let max_valence = match (element, electrons) {
+        (Element::Hydrogen, _) => 1 - charge.abs().min(1),
+        (_, 0..=4) => electrons,
+        (_, _) if period >= 3 => electrons,
+        (_, _) => 8 - electrons,
+    } as u8;
+
+    let valence = graph.bond_order_sum(atom);
+    if let Atom::Bracket(bracket) = &graph[atom] {
+        let hydrogens = bracket.hcount;
+        let others = valence - hydrogens;
+        if hydrogens > max_valence.saturating_sub(others) {
+            return Some(WarningKind::TooManyHydrogens {
+                hydrogens,
+                max_hydrogens: max_valence.saturating_sub(others),
+            });
+        }
+    }
+    if valence > max_valence {
+        return Some(WarningKind::Hypervalent {
+            valence,
+            max_valence,
+        });
+    }
+    None
+}
+
+/// The number of valence electrons and the period of a main group element.
+fn valence_electrons(element: Element) -> Option<(u8, u8)> {
+    let number = element.get_atomic_number() as u8;
+    match number {
+        1..=2 => Some((number, 1)),
+        3..=10 => Some((number - 2, 2)),
+        11..=18 => Some((number - 10, 3)),
+        19..=20 => Some((number - 18, 4)),
+        31..=36 => Some((number - 28, 4)),
+        37..=38 => Some((number - 36, 5)),
+        49..=54 => Some((number - 46, 5)),
+        55..=56 => Some((number - 54, 6)),
+        81..=86 => Some((number - 78, 6)),
+        87..=88 => Some((number - 86, 7)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kinds(smiles: &str) -> Vec<(usize, WarningKind)> {
+        validate(&MoleculeGraph::from_chain(
+            parse(smiles.as_bytes()).unwrap(),
+        ))
+        .into_iter()
+        .map(|warning| (warning.atom, warning.kind))
+        .collect()
+    }
+
+    #[test]
+    fn valid_molecules() {
+        for smiles in [
+            "CC(=O)Oc1ccccc1C(=O)O",
+            "C[N+](C)(C)C",
+            "[O-][N+](=O)c1ccccc1",
+            "OP(=O)(O)O",
+            "OS(=O)(=O)O",
+            "OCl(=O)(=O)=O",
+            "c1cc[nH]c1",
+            "[BH4-]",
+            "[CH3+]",
+            "[CH3-]",
+            "[Na+].[Cl-]",
+            "[Fe+2]",
+            "[H][H]",
+            "[H+]",
+            "*C",
+        ] {
+            assert_eq!(
+                Vec::<(usize, WarningKind)>::new(),
+                kinds(smiles),
+                "{}",
+                smiles
+            );
+        }
+    }
+
+    #[test]
+    fn hypervalent_atoms() {
+        let hypervalent = WarningKind::Hypervalent {
+            valence: 5,
+            max_valence: 4,
+        };
+        assert_eq!(vec![(0, hypervalent)], kinds("C(C)(C)(C)(C)C"));
+        assert_eq!(vec![(1, hypervalent)], kinds("C=C(C)(C)C"));
+        assert_eq!(
+            vec![(
+                1,
+                WarningKind::Hypervalent {
+                    valence: 5,
+                    max_valence: 3
+                }
+            )],
+            kinds("CN(=O)=O")
+        );
+        assert_eq!(
+            vec![(
+                0,
+                WarningKind::Hypervalent {
+                    valence: 4,
+                    max_valence: 3
+                }
+            )],
+            kinds("[O+](C)(C)(C)C")
+        );
+    }
+
+    #[test]
+    fn impossible_charges() {
+        assert_eq!(
+            vec![(0, WarningKind::ImpossibleCharge { charge: 5 })],
+            kinds("[O+5]")
+        );
+        assert_eq!(
+            vec![(0, WarningKind::ImpossibleCharge { charge: -2 })],
+            kinds("[F-2]")
+        );
+        assert_eq!(
+            vec![(0, WarningKind::ImpossibleCharge { charge: 2 })],
+            kinds("[Li+2]")
+        );
+    }
+
+    #[test]
+    fn aromatic_outside_ring() {
+        assert_eq!(
+            vec![
+                (1, WarningKind::AromaticOutsideRing),
+                (2, WarningKind::AromaticOutsideRing)
+            ],
+            kinds("Ccc")
+        );
+    }
+
+    #[test]
+    fn too_many_hydrogens() {
+        assert_eq!(
+            vec![(
+                0,
+                WarningKind::TooManyHydrogens {
+                    hydrogens: 5,
+                    max_hydrogens: 4
+                }
+            )],
+            kinds("[CH5]")
+        );
+        assert_eq!(
+            vec![(
+                1,
+                WarningKind::TooManyHydrogens {
+                    hydrogens: 2,
+                    max_hydrogens: 1
+                }
+            )],
+            kinds("C[OH2]")
+        );
+    }
+
+    #[test]
+    fn spans() {
+        let warnings = validate_smiles(b"CC(C)(C)(C)[O+5]").unwrap();
+        assert_eq!(
+            vec![(1, Some(1..2)), (5, Some(11..16))],
+            warnings
+                .iter()
+                .map(|warning| (warning.atom, warning.span.map(std::ops::Range::from)))
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(
+            "atom 5 can't have charge +5 at position 11",
+            warnings[1].to_string()
+        );
+        assert!(validate_smiles(b"C(").is_err());
+    }
+}
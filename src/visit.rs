@@ -0,0 +1,348 @@
+//! Traversal of a parsed [`Chain`] in SMILES order.
+//!
+//! [`walk_chain`] calls the methods of a [`Visitor`] for every atom, bond, dot, ring bond and
+//! branch of a chain, in the order in which they appear in the SMILES string, together with the
+//! depth of branches they are in. [`walk_chain_mut`] does the same with a [`VisitorMut`], which
+//! can change them.
+//!
+//! An atom is visited before its ring bonds and branches. A branch is entered and left at the
+//! depth of the atom it belongs to, and everything in it, including the bond or dot at its
+//! start, is visited at the next depth. Bonds and dots are visited before the atom they lead to,
+//! or after the branches of the last atom if they end a chain.
+//!
+//! Chains are walked iteratively, so neither long chains nor deeply nested branches overflow the
+//! stack.
+
+use crate::{build_chain, Atom, Bond, BondOrDot, Branch, BranchedAtom, Chain, Dot, RingBond, Span};
+
+/// Callbacks for [`walk_chain`]. All methods do nothing by default.
+pub trait Visitor {
+    /// An atom, before its ring bonds and branches.
+    fn visit_atom(&mut self, _atom: &BranchedAtom, _depth: usize) {}
+    /// An explicit bond to the next atom, to the first atom of a branch or at the end of a chain.
+    fn visit_bond(&mut self, _bond: &Bond, _depth: usize) {}
+    fn visit_dot(&mut self, _dot: &Dot, _depth: usize) {}
+    fn visit_ring_bond(&mut self, _ring_bond: &RingBond, _depth: usize) {}
+    /// A branch, before its contents.
+    fn enter_branch(&mut self, _branch: &Branch, _depth: usize) {}
+    /// A branch, after its contents.
+    fn leave_branch(&mut self, _branch: &Branch, _depth: usize) {}
+}
+
+/// Callbacks for [`walk_chain_mut`]. All methods do nothing by default.
+///
+/// Changes are seen by the rest of the walk: ring bonds and branches that are added to an atom
+/// in [`VisitorMut::visit_atom`] are visited next.
+pub trait VisitorMut {
+    /// An atom, before its ring bonds and branches.
+    fn visit_atom(&mut self, _atom: &mut BranchedAtom, _depth: usize) {}
+    /// An explicit bond to the next atom, to the first atom of a branch or at the end of a chain.
+    fn visit_bond(&mut self, _bond: &mut Bond, _depth: usize) {}
+    fn visit_dot(&mut self, _dot: &mut Dot, _depth: usize) {}
+    fn visit_ring_bond(&mut self, _ring_bond: &mut RingBond, _depth: usize) {}
+    /// A branch, before its contents.
+    fn enter_branch(&mut self, _branch: &mut Branch, _depth: usize) {}
+    /// A branch, after its contents.
+    fn leave_branch(&mut self, _branch: &mut Branch, _depth: usize) {}
+}
+
+/// Visit everything in a chain in SMILES order, starting at depth 0.
+pub fn walk_chain<V: Visitor + ?Sized>(visitor: &mut V, chain: &Chain) {
+    enum Step<'a> {
+        Chain(&'a Chain, usize),
+        BondOrDot(&'a BondOrDot, usize),
+        EnterBranch(&'a Branch, usize),
+        LeaveBranch(&'a Branch, usize),
+    }
+
+    let mut stack = vec![Step::Chain(chain, 0)];
+    while let Some(step) = stack.pop() {
+        let (chain, depth) = match step {
+            Step::Chain(chain, depth) => (chain, depth),
+            Step::BondOrDot(BondOrDot::Bond(bond), depth) => {
+                visitor.visit_bond(bond, depth);
+                continue;
+            }
+            Step::BondOrDot(BondOrDot::Dot(dot), depth) => {
+                visitor.visit_dot(dot, depth);
+                continue;
+            }
+            Step::EnterBranch(branch, depth) => {
+                visitor.enter_branch(branch, depth);
+                continue;
+            }
+            Step::LeaveBranch(branch, depth) => {
+                visitor.leave_branch(branch, depth);
+                continue;
+            }
+        };
+        let branched_atom = &chain.branched_atom;
+        visitor.visit_atom(branched_atom, depth);
+        for ring_bond in &branched_atom.ring_bonds {
+            visitor.visit_ring_bond(ring_bond, depth);
+        }
+
+        // What follows the atom is pushed in reverse order
+        if let Some(next) = &chain.chain {
+            stack.push(Step::Chain(next, depth));
+        }
+        if let Some(bond_or_dot) = &chain.bond_or_dot {
+            stack.push(Step::BondOrDot(bond_or_dot, depth));
+        }
+        for branch in branched_atom.branches.iter().rev() {
+            stack.push(Step::LeaveBranch(branch, depth));
+            stack.push(Step::Chain(&branch.chain, depth + 1));
+            if let Some(bond_or_dot) = &branch.bond_or_dot {
+                stack.push(Step::BondOrDot(bond_or_dot, depth + 1));
+            }
+            stack.push(Step::EnterBranch(branch, depth));
+        }
+    }
+}
+
+/// Visit everything in a chain in SMILES order, starting at depth 0, allowing changes.
+pub fn walk_chain_mut<V: VisitorMut + ?Sized>(visitor: &mut V, chain: &mut Chain) {
+    // The visited parts can't be borrowed while the walk continues below them, so the chain is
+    // taken apart into owned links while it is walked and put together again afterwards.
+    let mut stack = vec![Frame::new(std::mem::replace(chain, placeholder()), None, 0)];
+    loop {
+        let frame = stack.last_mut().unwrap();
+        let depth = frame.depth;
+        if let Some(mut branch) = frame.branches.next() {
+            visitor.enter_branch(&mut branch, depth);
+            if let Some(bond_or_dot) = &mut branch.bond_or_dot {
+                visit_bond_or_dot(visitor, bond_or_dot, depth + 1);
+            }
+            let chain = std::mem::replace(&mut branch.chain, placeholder());
+            stack.push(Frame::new(chain, Some(branch), depth + 1));
+        } else if !frame.bond_or_dot_visited {
+            frame.bond_or_dot_visited = true;
+            if let Some((_, Some(bond_or_dot))) = frame.links.last_mut() {
+                visit_bond_or_dot(visitor, bond_or_dot, depth);
+            }
+        } else if let Some(mut link) = frame.next.take() {
+            let mut branched_atom = std::mem::replace(&mut link.branched_atom, placeholder_atom());
+            visitor.visit_atom(&mut branched_atom, depth);
+            for ring_bond in &mut branched_atom.ring_bonds {
+                visitor.visit_ring_bond(ring_bond, depth);
+            }
+            frame.branches = std::mem::take(&mut branched_atom.branches).into_iter();
+            frame.bond_or_dot_visited = false;
+            frame.next = link.chain.take().map(|next| *next);
+            frame.links.push((branched_atom, link.bond_or_dot));
+        } else {
+            let frame = stack.pop().unwrap();
+            let walked = build_chain(frame.links).unwrap();
+            match frame.branch {
+                Some(mut branch) => {
+                    branch.chain = walked;
+                    let parent = stack.last_mut().unwrap();
+                    visitor.leave_branch(&mut branch, parent.depth);
+                    parent.links.last_mut().unwrap().0.branches.push(branch);
+                }
+                None => {
+                    *chain = walked;
+                    return;
+                }
+            }
+        }
+    }
+}
+
+/// A chain that is being walked by [`walk_chain_mut`].
+struct Frame {
+    /// The visited links, with the walked branches of the last one.
+    links: Vec<(BranchedAtom, Option<BondOrDot>)>,
+    /// The branches of the last link that are still to be walked.
+    branches: std::vec::IntoIter<Branch>,
+    bond_or_dot_visited: bool,
+    /// The links that are still to be visited.
+    next: Option<Chain>,
+    /// The branch of the chain, with a placeholder for the chain.
+    branch: Option<Branch>,
+    depth: usize,
+}
+
+impl Frame {
+    fn new(chain: Chain, branch: Option<Branch>, depth: usize) -> Self {
+        Frame {
+            links: Vec::new(),
+            branches: Vec::new().into_iter(),
+            bond_or_dot_visited: true,
+            next: Some(chain),
+            branch,
+            depth,
+        }
+    }
+}
+
+fn visit_bond_or_dot<V: VisitorMut + ?Sized>(
+    visitor: &mut V,
+    bond_or_dot: &mut BondOrDot,
+    depth: usize,
+) {
+    match bond_or_dot {
+        BondOrDot::Bond(bond) => visitor.visit_bond(bond, depth),
+        BondOrDot::Dot(dot) => visitor.visit_dot(dot, depth),
+    }
+}
+
+fn placeholder_atom() -> BranchedAtom {
+    BranchedAtom {
+        atom: Atom::Unknown,
+        ring_bonds: Vec::new(),
+        branches: Vec::new(),
+        span: Span::default(),
+    }
+}
+
+fn placeholder() -> Chain {
+    Chain {
+        chain: None,
+        bond_or_dot: None,
+        branched_atom: placeholder_atom(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parse, Atom};
+
+    /// Writes what is visited, with the depth before every atom.
+    #[derive(Default)]
+    struct Recorder(String);
+
+    impl Visitor for Recorder {
+        fn visit_atom(&mut self, atom: &BranchedAtom, depth: usize) {
+            let symbol = match atom.atom {
+                Atom::AliphaticOrganic(atom) => atom.element.get_symbol().to_string(),
+                Atom::AromaticOrganic(atom) => atom.element.get_symbol().to_lowercase(),
+                Atom::Bracket(_) => "[]".to_string(),
+                Atom::Unknown => "*".to_string(),
+            };
+            self.0 += &format!("{}{}", depth, symbol);
+        }
+
+        fn visit_bond(&mut self, bond: &Bond, _depth: usize) {
+            self.0 += match bond {
+                Bond::Double => "=",
+                Bond::Triple => "#",
+                _ => "-",
+            };
+        }
+
+        fn visit_dot(&mut self, _dot: &Dot, _depth: usize) {
+            self.0 += ".";
+        }
+
+        fn visit_ring_bond(&mut self, ring_bond: &RingBond, _depth: usize) {
+            self.0 += &ring_bond.ring_number.to_string();
+        }
+
+        fn enter_branch(&mut self, _branch: &Branch, depth: usize) {
+            self.0 += &format!("({}", depth);
+        }
+
+        fn leave_branch(&mut self, _branch: &Branch, depth: usize) {
+            self.0 += &format!("{})", depth);
+        }
+    }
+
+    fn record(smiles: &str) -> String {
+        let mut recorder = Recorder::default();
+        walk_chain(&mut recorder, &parse(smiles.as_bytes()).unwrap());
+        recorder.0
+    }
+
+    #[test]
+    fn smiles_order() {
+        assert_eq!("0C0C", record("CC"));
+        assert_eq!("0C0C1(0=1O0)0O", record("CC1(=O)O"));
+        assert_eq!("0C(0=1C(1.2N1)1C1O0)0[].0Cl", record("C(=C(.N)CO)[Na+].Cl"));
+        assert_eq!("0c10c0c0c0c0c1", record("c1ccccc1"));
+        assert_eq!("0C(01C=0)0C=", record("C(C=)C="));
+    }
+
+    #[test]
+    fn long_chain() {
+        let mut count = 0;
+        struct Counter<'a>(&'a mut usize);
+        impl Visitor for Counter<'_> {
+            fn visit_atom(&mut self, _atom: &BranchedAtom, _depth: usize) {
+                *self.0 += 1;
+            }
+        }
+        let chain = parse("C".repeat(100_000).as_bytes()).unwrap();
+        walk_chain(&mut Counter(&mut count), &chain);
+        assert_eq!(100_000, count);
+    }
+
+    #[test]
+    fn changes() {
+        /// Turns double bonds into single bonds and carbons into nitrogens.
+        struct Rewriter;
+        impl VisitorMut for Rewriter {
+            fn visit_atom(&mut self, atom: &mut BranchedAtom, _depth: usize) {
+                if let Atom::AliphaticOrganic(atom) = &mut atom.atom {
+                    if atom.element == ptable::Element::Carbon {
+                        atom.element = ptable::Element::Nitrogen;
+                    }
+                }
+            }
+
+            fn visit_bond(&mut self, bond: &mut Bond, _depth: usize) {
+                if *bond == Bond::Double {
+                    *bond = Bond::Single;
+                }
+            }
+        }
+
+        let mut chain = parse(b"C=C(=O)C").unwrap();
+        walk_chain_mut(&mut Rewriter, &mut chain);
+        let mut recorder = Recorder::default();
+        walk_chain(&mut recorder, &chain);
+        assert_eq!("0N-0N(0-1O0)0N", recorder.0);
+
+        struct Unchanged;
+        impl VisitorMut for Unchanged {}
+        let original = parse(b"C1C(=C(.N)C(O)=O)[Na+].Cl1C(C=)").unwrap();
+        let mut chain = original.clone();
+        walk_chain_mut(&mut Unchanged, &mut chain);
+        assert_eq!(original, chain);
+    }
+
+    #[test]
+    fn deeply_nested_branches() {
+        /// Counts the atoms and the largest depth, and turns carbons into nitrogens.
+        #[derive(Default)]
+        struct Depth(usize, usize);
+        impl Visitor for Depth {
+            fn visit_atom(&mut self, _atom: &BranchedAtom, depth: usize) {
+                self.0 += 1;
+                self.1 = self.1.max(depth);
+            }
+        }
+        impl VisitorMut for Depth {
+            fn visit_atom(&mut self, atom: &mut BranchedAtom, depth: usize) {
+                Visitor::visit_atom(self, atom, depth);
+                atom.atom = parse(b"N").unwrap().branched_atom.atom;
+            }
+        }
+
+        let nested =
+            |atom: &str| format!("{}{}{}", "C(".repeat(100_000), atom, ")".repeat(100_000));
+        let mut chain = parse(nested("C").as_bytes()).unwrap();
+        let mut depth = Depth::default();
+        walk_chain(&mut depth, &chain);
+        assert_eq!((100_001, 100_000), (depth.0, depth.1));
+
+        let mut depth = Depth::default();
+        walk_chain_mut(&mut depth, &mut chain);
+        assert_eq!((100_001, 100_000), (depth.0, depth.1));
+        assert_eq!(
+            parse(nested("C").replace('C', "N").as_bytes()).unwrap(),
+            chain
+        );
+    }
+}
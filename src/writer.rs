@@ -0,0 +1,489 @@
+//! Writing a [`MoleculeGraph`] as a SMILES string.
+//!
+//! Hydrogens that only count towards the hydrogen count of their atom are folded into it,
+//! either implicitly or as the hydrogen count of a bracket atom. Atoms are written in a
+//! depth-first traversal that starts at the atom with the lowest rank of every component and
+//! visits neighbors in the order of their ranks, so the same ranks always produce the same
+//! string.
+
+use petgraph::graph::{EdgeIndex, NodeIndex};
+use petgraph::visit::EdgeRef;
+use ptable::Element;
+
+use crate::graph::{implicit_hydrogen_count, MoleculeGraph};
+use crate::{Bond, Chirality};
+
+/// Write a graph as a SMILES string, visiting atoms in the order of their node index.
+///
+/// Bonds of an atom to itself can't be written in SMILES and are left out.
+pub fn write_smiles(graph: &MoleculeGraph) -> String {
+    let ranks: Vec<usize> = (0..graph.node_count()).collect();
+    write_smiles_ranked(graph, &ranks)
+}
+
+/// Write a graph as a SMILES string, visiting atoms in the order of the given ranks, indexed by
+/// node index.
+pub(crate) fn write_smiles_ranked(graph: &MoleculeGraph, ranks: &[usize]) -> String {
+    let traversal = Traversal::new(graph, ranks);
+    let mut writer = Writer {
+        graph,
+        traversal: &traversal,
+        output: String::new(),
+        ring_digits: vec![None; graph.edge_count()],
+        digits_in_use: [false; 100],
+    };
+    for (index, &start) in traversal.starts.iter().enumerate() {
+        if index > 0 {
+            writer.output.push('.');
+        }
+        writer.write_component(start);
+    }
+    writer.output
+}
+
+/// Whether an atom is a hydrogen that is written as part of the hydrogen count of its neighbor.
+///
+/// Hydrogens with an isotope or a directional bond have to be written as atoms of their own.
+fn is_folded_hydrogen(graph: &MoleculeGraph, atom: NodeIndex) -> bool {
+    graph.is_attached_hydrogen(atom)
+        && graph[atom].isotope().is_none()
+        && graph[atom].chirality().is_none()
+        && graph.edges(atom).all(|edge| *edge.weight() == Bond::Single)
+}
+
+/// The depth-first spanning forest of the atoms that are written, with the ring closures.
+struct Traversal {
+    skeleton: Vec<bool>,
+    starts: Vec<NodeIndex>,
+    parents: Vec<Option<NodeIndex>>,
+    children: Vec<Vec<NodeIndex>>,
+    ring_openings: Vec<Vec<EdgeIndex>>,
+    ring_closings: Vec<Vec<EdgeIndex>>,
+}
+
+impl Traversal {
+    fn new(graph: &MoleculeGraph, ranks: &[usize]) -> Self {
+        let count = graph.node_count();
+        let skeleton: Vec<bool> = graph
+            .node_indices()
+            .map(|node| !is_folded_hydrogen(graph, node))
+            .collect();
+        let neighbors: Vec<Vec<(NodeIndex, EdgeIndex)>> = graph
+            .node_indices()
+            .map(|node| {
+                let mut neighbors: Vec<(NodeIndex, EdgeIndex)> = graph
+                    .edges(node)
+                    .map(|edge| {
+                        let neighbor = graph.other_end(edge.id(), node);
+                        (neighbor, edge.id())
+                    })
+                    // A bond of an atom to itself can't be written in SMILES
+                    .filter(|&(neighbor, _)| neighbor != node && skeleton[neighbor.index()])
+                    .collect();
+                neighbors.sort_by_key(|&(neighbor, _)| ranks[neighbor.index()]);
+                neighbors
+            })
+            .collect();
+
+        let mut traversal = Traversal {
+            skeleton,
+            starts: Vec::new(),
+            parents: vec![None; count],
+            children: vec![Vec::new(); count],
+            ring_openings: vec![Vec::new(); count],
+            ring_closings: vec![Vec::new(); count],
+        };
+        let mut order: Vec<NodeIndex> = graph
+            .node_indices()
+            .filter(|node| traversal.skeleton[node.index()])
+            .collect();
+        order.sort_by_key(|node| ranks[node.index()]);
+
+        let mut visited = vec![false; count];
+        let mut recorded = vec![false; graph.edge_count()];
+        for start in order {
+            if visited[start.index()] {
+                continue;
+            }
+            visited[start.index()] = true;
+            traversal.starts.push(start);
+            let mut stack = vec![(start, 0)];
+            while let Some((atom, next)) = stack.pop() {
+                let (neighbor, edge) = match neighbors[atom.index()].get(next) {
+                    Some(&entry) => entry,
+                    None => continue,
+                };
+                stack.push((atom, next + 1));
+                if recorded[edge.index()] {
+                    continue;
+                }
+                recorded[edge.index()] = true;
+                if visited[neighbor.index()] {
+                    // An edge to an atom that was already visited closes a ring, which is opened
+                    // at that atom because it is written first.
+                    traversal.ring_openings[neighbor.index()].push(edge);
+                    traversal.ring_closings[atom.index()].push(edge);
+                } else {
+                    visited[neighbor.index()] = true;
+                    traversal.parents[neighbor.index()] = Some(atom);
+                    traversal.children[atom.index()].push(neighbor);
+                    stack.push((neighbor, 0));
+                }
+            }
+        }
+        traversal
+    }
+}
+
+enum Task {
+    Atom(NodeIndex),
+    OpenBranch,
+    CloseBranch,
+}
+
+struct Writer<'a> {
+    graph: &'a MoleculeGraph,
+    traversal: &'a Traversal,
+    output: String,
+    ring_digits: Vec<Option<usize>>,
+    digits_in_use: [bool; 100],
+}
+
+impl Writer<'_> {
+    fn write_component(&mut self, start: NodeIndex) {
+        let mut stack = vec![Task::Atom(start)];
+        while let Some(task) = stack.pop() {
+            let atom = match task {
+                Task::Atom(atom) => atom,
+                Task::OpenBranch => {
+                    self.output.push('(');
+                    continue;
+                }
+                Task::CloseBranch => {
+                    self.output.push(')');
+                    continue;
+                }
+            };
+            if let Some(parent) = self.traversal.parents[atom.index()] {
+                self.write_bond(parent, atom);
+            }
+            self.write_atom(atom);
+
+            // All children but the last one are written as branches.
+            let children = &self.traversal.children[atom.index()];
+            if let Some((&last, branches)) = children.split_last() {
+                stack.push(Task::Atom(last));
+                for &child in branches.iter().rev() {
+                    stack.push(Task::CloseBranch);
+                    stack.push(Task::Atom(child));
+                    stack.push(Task::OpenBranch);
+                }
+            }
+        }
+    }
+
+    fn write_atom(&mut self, atom: NodeIndex) {
+        let graph = self.graph;
+        let hydrogens: Vec<NodeIndex> = graph
+            .neighbors(atom)
+            .filter(|neighbor| !self.traversal.skeleton[neighbor.index()])
+            .collect();
+
+        // Ring bonds are written after the atom, closing ones first. A digit that is closed here
+        // is not reused for a ring that is opened at the same atom.
+        let mut ring_text = String::new();
+        let mut ring_partners = Vec::new();
+        let mut closed_here = Vec::new();
+        for &edge in &self.traversal.ring_closings[atom.index()] {
+            let digit = self.ring_digits[edge.index()].unwrap();
+            push_ring_digit(&mut ring_text, digit);
+            closed_here.push(digit);
+            ring_partners.push(graph.other_end(edge, atom));
+        }
+        for &edge in &self.traversal.ring_openings[atom.index()] {
+            let partner = graph.other_end(edge, atom);
+            let digit = (1..100)
+                .find(|&digit| !self.digits_in_use[digit] && !closed_here.contains(&digit))
+                .expect("too many open ring bonds");
+            self.digits_in_use[digit] = true;
+            self.ring_digits[edge.index()] = Some(digit);
+            ring_text.push_str(bond_symbol(graph, atom, partner));
+            push_ring_digit(&mut ring_text, digit);
+            ring_partners.push(partner);
+        }
+        for digit in closed_here {
+            self.digits_in_use[digit] = false;
+        }
+
+        // Neighbors in the order in which a parser sees them, to write tetrahedral chirality.
+        let neighbor_order: Vec<NodeIndex> = self.traversal.parents[atom.index()]
+            .into_iter()
+            .chain(hydrogens.iter().copied())
+            .chain(ring_partners)
+            .chain(self.traversal.children[atom.index()].iter().copied())
+            .collect();
+        let skeleton_bond_order_sum = graph.bond_order_sum(atom) - hydrogens.len() as u8;
+        let text = atom_text(
+            graph,
+            atom,
+            hydrogens.len() as u8,
+            skeleton_bond_order_sum,
+            &neighbor_order,
+        );
+        self.output.push_str(&text);
+        self.output.push_str(&ring_text);
+    }
+
+    fn write_bond(&mut self, from: NodeIndex, to: NodeIndex) {
+        let symbol = bond_symbol(self.graph, from, to);
+        self.output.push_str(symbol);
+    }
+}
+
+fn push_ring_digit(output: &mut String, digit: usize) {
+    if digit < 10 {
+        output.push_str(&digit.to_string());
+    } else {
+        output.push_str(&format!("%{}", digit));
+    }
+}
+
+/// The symbol of the bond between two atoms, written from `from` to `to`.
+fn bond_symbol(graph: &MoleculeGraph, from: NodeIndex, to: NodeIndex) -> &'static str {
+    let edge = graph.find_edge(from, to).unwrap();
+    let (source, _) = graph.edge_endpoints(edge).unwrap();
+    let aromatic = graph[from].is_aromatic() && graph[to].is_aromatic();
+    match graph[edge] {
+        Bond::Single if aromatic => "-",
+        Bond::Single => "",
+        Bond::Aromatic if aromatic => "",
+        Bond::Aromatic => ":",
+        Bond::Double => "=",
+        Bond::Triple => "#",
+        Bond::Quadruple => "$",
+        // Directional bonds are relative to the direction of the edge.
+        Bond::Up if source == from => "/",
+        Bond::Up => "\\",
+        Bond::Down if source == from => "\\",
+        Bond::Down => "/",
+    }
+}
+
+fn is_organic_subset(element: Element, aromatic: bool) -> bool {
+    match element {
+        Element::Boron
+        | Element::Carbon
+        | Element::Nitrogen
+        | Element::Oxygen
+        | Element::Phosphorus
+        | Element::Sulfur => true,
+        Element::Fluorine | Element::Chlorine | Element::Bromine | Element::Iodine => !aromatic,
+        _ => false,
+    }
+}
+
+fn atom_text(
+    graph: &MoleculeGraph,
+    atom: NodeIndex,
+    hydrogens: u8,
+    bond_order_sum: u8,
+    neighbor_order: &[NodeIndex],
+) -> String {
+    let graph_atom = &graph[atom];
+    let aromatic = graph_atom.is_aromatic();
+    let chirality = graph_atom
+        .chirality()
+        .map(|chirality| chirality_text(chirality, neighbor_order));
+    let symbol = match graph_atom.element() {
+        Some(element) if aromatic => element.get_symbol().to_lowercase(),
+        Some(element) => element.get_symbol().to_string(),
+        None => "*".to_string(),
+    };
+
+    let unbracketed = graph_atom.charge() == 0
+        && graph_atom.isotope().is_none()
+        && chirality.is_none()
+        && match graph_atom.element() {
+            Some(element) => {
+                is_organic_subset(element, aromatic)
+                    && implicit_hydrogen_count(element, bond_order_sum, aromatic) == hydrogens
+            }
+            None => hydrogens == 0,
+        };
+    if unbracketed {
+        return symbol;
+    }
+
+    let mut text = String::from("[");
+    if let Some(isotope) = graph_atom.isotope() {
+        text.push_str(&isotope.to_string());
+    }
+    text.push_str(&symbol);
+    if let Some(chirality) = chirality {
+        text.push_str(&chirality);
+    }
+    match hydrogens {
+        0 => {}
+        1 => text.push('H'),
+        count => text.push_str(&format!("H{}", count)),
+    }
+    match graph_atom.charge() {
+        0 => {}
+        1 => text.push('+'),
+        -1 => text.push('-'),
+        charge => text.push_str(&format!("{:+}", charge)),
+    }
+    text.push(']');
+    text
+}
+
+/// Chirality of an atom relative to the order in which its neighbors are written.
+///
+/// The graph stores tetrahedral chirality relative to the neighbors ordered by node index, so
+/// it is flipped if the written order is an odd permutation of that. Other chirality classes are
+/// written unchanged.
+fn chirality_text(chirality: Chirality, neighbor_order: &[NodeIndex]) -> String {
+    let inversions = neighbor_order
+        .iter()
+        .enumerate()
+        .map(|(i, a)| neighbor_order[i + 1..].iter().filter(|b| a > b).count())
+        .sum::<usize>();
+    let anticlockwise = match chirality {
+        Chirality::Anticlockwise => true,
+        Chirality::Clockwise => false,
+        Chirality::Tetrahedral(number) => return format!("@TH{}", number),
+        Chirality::Allenal(number) => return format!("@AL{}", number),
+        Chirality::SquarePlanar(number) => return format!("@SP{}", number),
+        Chirality::TrigonalBipyramidal(number) => return format!("@TB{}", number),
+        Chirality::Octahedral(number) => return format!("@OH{}", number),
+    };
+    if anticlockwise == (inversions % 2 == 0) {
+        "@".to_string()
+    } else {
+        "@@".to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::canon::canonical_smiles;
+    use crate::graph::molecule;
+
+    fn rewrite(smiles: &str) -> String {
+        write_smiles(&molecule(smiles))
+    }
+
+    #[test]
+    fn leaves_out_self_loops() {
+        let mut graph = molecule("CO");
+        graph.add_edge(NodeIndex::new(1), NodeIndex::new(1), Bond::Single);
+        assert_eq!("C[OH]", write_smiles(&graph));
+        assert_eq!("C[OH]", canonical_smiles(&graph));
+    }
+
+    #[test]
+    fn writes_in_input_order() {
+        for smiles in &[
+            "CCO",
+            "C(F)(Cl)Br",
+            "C1CC1",
+            "c1ccccc1",
+            "c1ccccc1-c1ccccc1",
+            "[NH4+]",
+            "[Na+].[Cl-]",
+            "CC(=O)Oc1ccccc1C(=O)O",
+            "C#N",
+            "[13CH4]",
+            "[2H]C",
+            "c1cc[nH]c1",
+            "[Fe+3]",
+            "[CH2]",
+            "*C",
+            "F/C=C/F",
+            "F/C=C\\F",
+            "N[C@@H](C)C(=O)O",
+            "N[C@H](C)C(=O)O",
+            "C12CC1C2",
+        ] {
+            assert_eq!(*smiles, rewrite(smiles));
+        }
+    }
+
+    #[test]
+    fn normalizes_notation() {
+        assert_eq!("CCO", rewrite("[CH3][CH2][OH]"));
+        assert_eq!("C1CCC1", rewrite("C%10CCC%10"));
+        assert_eq!("C1CC1", rewrite("C-1-C-C-1"));
+        assert_eq!("C(F)(Cl)Br", rewrite("[H]C(F)(Cl)Br"));
+        assert_eq!("[C@H](F)(Cl)Br", rewrite("[H][C@](F)(Cl)Br"));
+        assert_eq!("C=1CC1", rewrite("C1CC=1"));
+        assert_eq!("F/C=C/F", rewrite("F/C=C/1.F1"));
+    }
+
+    #[test]
+    fn round_trip() {
+        for smiles in &[
+            "CC(=O)Oc1ccccc1C(=O)O",
+            "CN1C=NC2=C1C(=O)N(C(=O)N2C)C",
+            "C1CC2CCC1CC2",
+            "C12C3C4C1C5C2C3C45",
+            "F/C=C/C=C\\Cl",
+            "O[C@H]1CC[C@@H](N)CC1",
+            "N[C@@H](Cc1ccccc1)C(=O)O",
+            "[O-][n+]1ccccc1",
+            "C1CCCCCCCCCC2CCCCCCCCCCC(CCCCCCCCCC1)CCCCCCCCCC2",
+        ] {
+            let graph = molecule(smiles);
+            let written = write_smiles(&graph);
+            assert_eq!(
+                canonical_smiles(&graph),
+                canonical_smiles(&molecule(&written)),
+                "{} -> {}",
+                smiles,
+                written
+            );
+        }
+    }
+
+    #[test]
+    fn canonical_is_independent_of_input_order() {
+        let groups: &[&[&str]] = &[
+            &["CCO", "OCC", "C(O)C"],
+            &["c1ccccc1O", "Oc1ccccc1", "c1cc(O)ccc1"],
+            &["CC(=O)O", "OC(C)=O", "O=C(O)C"],
+            &["N[C@@H](C)C(=O)O", "C[C@H](N)C(=O)O", "OC(=O)[C@H](C)N"],
+            &["F/C=C/F", "F\\C=C\\F", "C(\\F)=C/F"],
+            &["[Na+].[Cl-]", "[Cl-].[Na+]"],
+            &["C[C@H](O)[C@H](O)C", "C[C@@H](O)[C@@H](O)C"],
+            &["CC[C@H](C)CC", "CCC(C)CC"],
+            &[
+                "O[C@H]1CC[C@@H](N)CC1",
+                "O[C@@H]1CC[C@H](N)CC1",
+                "N[C@H]1CC[C@@H](O)CC1",
+            ],
+        ];
+        for group in groups {
+            let expected = canonical_smiles(&molecule(group[0]));
+            for smiles in group.iter() {
+                assert_eq!(expected, canonical_smiles(&molecule(smiles)), "{}", smiles);
+            }
+        }
+        assert_ne!(
+            canonical_smiles(&molecule("N[C@@H](C)C(=O)O")),
+            canonical_smiles(&molecule("N[C@H](C)C(=O)O"))
+        );
+        assert_ne!(
+            canonical_smiles(&molecule("F/C=C/F")),
+            canonical_smiles(&molecule("F/C=C\\F"))
+        );
+        assert_ne!(
+            canonical_smiles(&molecule("C[C@H](O)[C@@H](O)C")),
+            canonical_smiles(&molecule("C[C@@H](O)[C@H](O)C"))
+        );
+        assert_ne!(
+            canonical_smiles(&molecule("O[C@H]1CC[C@@H](N)CC1")),
+            canonical_smiles(&molecule("O[C@H]1CC[C@H](N)CC1"))
+        );
+    }
+}
@@ -11,8 +11,11 @@
 // LIMIT 5000
 
 use serde::Deserialize;
-use smiles_parser::chain;
+use smiles_parser::flat::parse_flat;
+use smiles_parser::lenient::parse_lenient;
+use smiles_parser::{chain, parse};
 
+#[allow(dead_code)]
 #[derive(Debug, Clone, Deserialize)]
 struct WikidataItem {
     pub item: String,
@@ -27,7 +30,7 @@ fn parse_wikidata_items() {
     let items: Vec<WikidataItem> = serde_json::from_str(&contents).unwrap();
 
     for item in items {
-        let res = chain(&item.smiles.as_bytes());
+        let res = chain(item.smiles.as_bytes());
         match res {
             Ok(_) => {
                 // println!("Correctly parse SMILES: {}", &item.smiles);
@@ -38,3 +41,20 @@ fn parse_wikidata_items() {
         }
     }
 }
+
+#[test]
+fn lenient_parse_matches_wikidata_items() {
+    let contents = std::fs::read_to_string("./tests/wikidata_molecules.json").unwrap();
+    let items: Vec<WikidataItem> = serde_json::from_str(&contents).unwrap();
+
+    for item in items {
+        let smiles = item.smiles.as_bytes();
+        let lenient = parse_lenient(smiles);
+        if parse_flat(smiles).is_ok() {
+            assert!(lenient.diagnostics.is_empty(), "{}", &item.smiles);
+            assert_eq!(parse(smiles).ok(), lenient.chain, "{}", &item.smiles);
+        } else {
+            assert!(!lenient.diagnostics.is_empty(), "{}", &item.smiles);
+        }
+    }
+}